@@ -1,5 +1,6 @@
 pub use self::ortho_camera::OrthoCamera;
 pub use self::perspective_camera::PerspectiveCamera;
 pub use self::perspective_camera::Direction as PerspectiveCameraDirection;
+pub use self::perspective_camera::Frustum;
 mod ortho_camera;
 mod perspective_camera;
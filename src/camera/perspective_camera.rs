@@ -24,7 +24,71 @@ pub enum Direction {
   YAlignedRight
 }
 
-#[derive(Clone, PartialEq)]
+/**
+** The six planes of a view frustum, xyz is the inward facing normal and w the
+** distance so a point is inside a plane when dot(normal, point) + w >= 0.
+**/
+#[derive(Clone, PartialEq, Debug)]
+pub struct Frustum {
+  planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+  // fov is vertical and in degrees, center is a point not a direction
+  pub fn new(position: Vector3<f32>, center: Vector3<f32>, up: Vector3<f32>, fov: f32, aspect: f32, near: f32, far: f32) -> Frustum {
+    let forward = (center - position).normalize();
+    let right = forward.cross(up).normalize();
+    let up = right.cross(forward);
+    let tan_y = (math::to_radians(fov) * 0.5).tan();
+    let tan_x = tan_y * aspect;
+    
+    let plane = |normal: Vector3<f32>, point: Vector3<f32>| {
+      let normal = normal.normalize();
+      normal.extend(-dot(normal, point))
+    };
+    
+    Frustum {
+      planes: [
+        plane(forward, position + forward*near),
+        plane(-forward, position + forward*far),
+        plane(forward*tan_x + right, position),
+        plane(forward*tan_x - right, position),
+        plane(forward*tan_y + up, position),
+        plane(forward*tan_y - up, position),
+      ],
+    }
+  }
+  
+  pub fn contains_sphere(&self, centre: Vector3<f32>, radius: f32) -> bool {
+    self.planes.iter().all(|plane| dot(plane.truncate(), centre) + plane.w >= -radius)
+  }
+  
+  // near, far, then the four sides
+  pub fn planes(&self) -> [Vector4<f32>; 6] {
+    self.planes
+  }
+  
+  // the near corners then the far corners, each going around the same way
+  pub fn corners(&self) -> [Vector3<f32>; 8] {
+    let intersect = |a: usize, b: usize, c: usize| {
+      let (a, b, c) = (self.planes[a], self.planes[b], self.planes[c]);
+      let (na, nb, nc) = (a.truncate(), b.truncate(), c.truncate());
+      
+      -(nb.cross(nc)*a.w + nc.cross(na)*b.w + na.cross(nb)*c.w) / dot(na, nb.cross(nc))
+    };
+    
+    let sides = [(2, 4), (3, 4), (3, 5), (2, 5)];
+    let mut corners = [Vector3::new(0.0, 0.0, 0.0); 8];
+    for (i, (x, y)) in sides.iter().enumerate() {
+      corners[i] = intersect(0, *x, *y);
+      corners[i+4] = intersect(1, *x, *y);
+    }
+    
+    corners
+  }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub struct PerspectiveCamera {
   position: Vector3<f32>,
   front: Vector3<f32>,
@@ -398,6 +462,10 @@ impl PerspectiveCamera {
     (self.position, self.position+self.front, self.up)
   }
   
+  pub fn get_frustum(&self, fov: f32, aspect: f32, near: f32, far: f32) -> Frustum {
+    Frustum::new(self.position, self.position+self.front, self.up, fov, aspect, near, far)
+  }
+  
   pub fn get_view_matrix(&self) -> Matrix4<f32> {
     Matrix4::look_at(Point3::from_vec(self.position), Point3::from_vec(self.position +
                      self.front), self.up)
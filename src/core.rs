@@ -3,6 +3,7 @@ use winit;
 use cgmath::{Vector4};
 
 use crate::ResourceManager;
+use crate::mesh_processing::ProcessingOptions;
 use crate::camera::PerspectiveCamera;
 use crate::drawcalls::DrawCall; 
 use crate::drawcalls::DrawType;
@@ -11,6 +12,11 @@ use crate::font::GenericFont;
 use crate::shaders::TextureShader;
 use crate::shaders::ModelShader;
 use crate::shaders::FinalShader;
+use crate::shaders::ShadowSettings;
+use crate::shaders::AmbientOcclusionSettings;
+use crate::shaders::AntiAliasing;
+use crate::shaders::DebugView;
+use crate::shaders::MaterialOverride;
 use crate::graphics;
 use crate::Settings;
 use crate::gltf_interpreter::ModelDetails;
@@ -30,6 +36,7 @@ use crate::ModelData;
 use cgmath::{Vector2, Vector3};
 use winit::dpi::{LogicalSize, LogicalPosition};
 
+use std::mem;
 use std::sync::Arc;
 use std::collections::HashMap;
 
@@ -59,6 +66,9 @@ pub struct CoreMaat {
   final_shader: FinalShader,
   
   resources: ResourceManager,
+  // waiting for their model or textures to finish loading
+  pending_material_overrides: Vec<(String, String, MaterialOverride)>,
+  unknown_override_textures: Vec<String>, // already reported, so they're only logged once
   
   current_frame: usize,
   max_frames: usize,
@@ -67,6 +77,7 @@ pub struct CoreMaat {
   
   mouse_position: Vector2<f32>,
   dpi: f32,
+  frame_stats: graphics::FrameStats,
   
   logs: Logs,
 }
@@ -80,7 +91,10 @@ impl CoreMaat {
     
     let (window, event_loop) = VkWindow::new(app_name, app_version, should_debug, &settings, &mut logs);
     
-    let resource_manager = ResourceManager::new();
+    let resource_manager = ResourceManager::new(ProcessingOptions {
+      optimise_vertex_cache: settings.vertex_cache_optimisation_enabled(),
+      generated_lods: settings.get_generated_lods(),
+    });
     
     let fences: Vec<Fence>;
     let mut semaphore_image_available: Vec<Semaphore> = Vec::new();
@@ -102,6 +116,7 @@ impl CoreMaat {
     
     let desired_texture_msaa = settings.get_texture_msaa();
     let desired_model_msaa = settings.get_model_msaa();
+    let anti_aliasing = AntiAliasing::from(settings.get_anti_aliasing());
     
     let texture_msaa;
     let model_msaa;
@@ -125,16 +140,23 @@ impl CoreMaat {
         texture_msaa = SampleCount::from(max_msaa);
       }
       
-      if desired_model_msaa < max_msaa {
+      // fxaa and taa replace the model msaa
+      if anti_aliasing.post_process() {
+        model_msaa = SampleCount::OneBit;
+      } else if desired_model_msaa < max_msaa {
         model_msaa = SampleCount::from(desired_model_msaa);
       } else {
         model_msaa = SampleCount::from(max_msaa);
       }
       
       settings.set_texture_msaa(texture_msaa.to_bits());
-      settings.set_model_msaa(model_msaa.to_bits());
       logs.system_msg(&format!("Using Msaa: {}x for 2D", texture_msaa.to_bits()));
-      logs.system_msg(&format!("Using Msaa: {}x for 3D", model_msaa.to_bits()));
+      if anti_aliasing.post_process() {
+        logs.system_msg(&format!("Using {:?} for 3D", anti_aliasing));
+      } else {
+        settings.set_model_msaa(model_msaa.to_bits());
+        logs.system_msg(&format!("Using Msaa: {}x for 3D", model_msaa.to_bits()));
+      }
       
       for _ in 0..image_views.len() {
         semaphore_image_available.push(Semaphore::new(Arc::clone(&device)));
@@ -149,7 +171,9 @@ impl CoreMaat {
                               .add_combined_image_samplers(100)
                               .add_uniform_buffers(80)
                               .add_storage_images(2)
+                              .add_storage_buffers(3)
                               .add_input_attachments(100)
+                              .free_descriptor_sets()
                               .build(Arc::clone(&device), image_views.len() as u32);
       
       dummy_image = ImageAttachment::create_dummy_texture(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &SampleCount::OneBit, &ImageViewType::Type2D, vk::FORMAT_R8G8B8A8_UNORM, &command_pool, graphics_queue);
@@ -169,7 +193,9 @@ impl CoreMaat {
      // buffer.destroy(Arc::clone(&device));
       
       texture_shader = TextureShader::new(Arc::clone(&instance), Arc::clone(&device), &current_extent, &format, &sampler, image_views, &dummy_image, &descriptor_set_pool, &command_pool, graphics_queue, &texture_msaa);
-      model_shader = ModelShader::new(Arc::clone(&instance), Arc::clone(&device), &current_extent, &format, &sampler, image_views, &dummy_image, &descriptor_set_pool, &command_pool, graphics_queue, &model_msaa);
+      let shadow_settings = ShadowSettings::new(settings.get_shadow_cascades(), settings.get_shadow_resolution(), settings.get_shadow_distance(), settings.get_light_shadow_budget(), settings.get_light_shadow_resolution());
+      let ambient_occlusion_settings = AmbientOcclusionSettings::new(settings.get_ssao_quality(), settings.get_ssao_samples(), settings.get_ssao_radius(), settings.get_ssao_intensity());
+      model_shader = ModelShader::new(Arc::clone(&instance), Arc::clone(&device), &current_extent, &format, &sampler, image_views, &dummy_image, &descriptor_set_pool, &command_pool, graphics_queue, &model_msaa, settings.alpha_to_coverage_enabled(), settings.gpu_culling_enabled(), settings.occlusion_culling_enabled(), shadow_settings, ambient_occlusion_settings, anti_aliasing);
       final_shader = FinalShader::new(Arc::clone(&instance), Arc::clone(&device), &current_extent, &format, &sampler, image_views, &dummy_image_snorm, &descriptor_set_pool, &command_pool, graphics_queue);
       /*
       let mut model_images = Vec::with_capacity(image_views.len());
@@ -232,6 +258,12 @@ impl CoreMaat {
         vk::ClearValue {
           color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 0.0] }
         },
+        vk::ClearValue {
+          color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 0.0] }
+        },
+        vk::ClearValue {
+          color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 0.0] }
+        },
       )
     } else {
       vec!(
@@ -256,6 +288,9 @@ impl CoreMaat {
         vk::ClearValue {
           color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 0.0] }
         },
+        vk::ClearValue {
+          color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 0.0] }
+        },
       )
     };
     
@@ -294,6 +329,8 @@ impl CoreMaat {
       final_shader,
       
       resources: resource_manager,
+      pending_material_overrides: Vec::new(),
+      unknown_override_textures: Vec::new(),
       
       current_frame: 0,
       max_frames,
@@ -302,11 +339,41 @@ impl CoreMaat {
       
       mouse_position: Vector2::new(0.0, 0.0),
       dpi: 1.0,
+      frame_stats: graphics::FrameStats::default(),
       
       logs,
     }, event_loop)
   }
   
+  fn add_pending_material_overrides(&mut self) {
+    let device = self.window.device();
+    let instance = self.window.instance();
+    
+    for (reference, model_reference, material) in mem::take(&mut self.pending_material_overrides) {
+      let mut textures = Vec::with_capacity(material.textures().len());
+      for (slot, texture_reference) in material.textures() {
+        match self.resources.get_texture(texture_reference.to_string()) {
+          Some(texture) => textures.push((*slot, texture)),
+          None => {
+            // nothing left loading and never added, it will never turn up
+            let unknown = !self.resources.has_texture(texture_reference) && self.resources.pending_objects_loaded();
+            if unknown && !self.unknown_override_textures.contains(texture_reference) {
+              println!("Error: material override {} uses texture {} which hasn't been added", reference, texture_reference);
+              self.unknown_override_textures.push(texture_reference.to_string());
+            }
+          },
+        }
+      }
+      
+      if !self.model_shader.has_model(&model_reference) || textures.len() != material.textures().len() {
+        self.pending_material_overrides.push((reference, model_reference, material));
+        continue;
+      }
+      
+      self.model_shader.add_material_override(Arc::clone(&instance), Arc::clone(&device), reference, model_reference, &material, textures, &self.descriptor_set_pool);
+    }
+  }
+  
   fn create_fences(device: Arc<Device>, num_fences: u32) -> Vec<Fence> {
     let mut fences: Vec<Fence> = Vec::with_capacity(num_fences as usize);
     
@@ -381,7 +448,7 @@ impl CoreRender for CoreMaat {
     let device = self.window.device();
     let instance = self.window.instance();
     let num_frames = self.fences.len() as u32;
-    self.model_shader.add_instanced_buffer(Arc::clone(&instance), Arc::clone(&device), num_frames, reference);
+    self.model_shader.add_instanced_buffer(Arc::clone(&instance), Arc::clone(&device), num_frames, reference, &self.descriptor_set_pool);
   }
   
   fn load_static_geometry(&mut self, _reference: String, _verticies: Vec<graphics::Vertex2d>, _indicies: Vec<u32>) {
@@ -479,13 +546,14 @@ impl CoreRender for CoreMaat {
     //
     { // Do drawcalls that most likely will only be called once and dont acutally draw
       let device = self.window.device();
+      self.model_shader.clear_lights();
       for draw in draw_calls {
         match draw.get_type() {
           DrawType::AddInstancedModelBuffer(ref info) => {
             let reference = info.clone();
             let instance = self.window.instance();
             let num_frames = self.fences.len() as u32;
-            self.model_shader.add_instanced_buffer(Arc::clone(&instance), Arc::clone(&device), num_frames, reference);
+            self.model_shader.add_instanced_buffer(Arc::clone(&instance), Arc::clone(&device), num_frames, reference, &self.descriptor_set_pool);
           },
           DrawType::LoadModel(ref info) => {
             let reference = info.clone();
@@ -496,10 +564,73 @@ impl CoreRender for CoreMaat {
             self.resources.unload_model_from_reference(Arc::clone(&device), reference.to_string());
             self.model_shader.remove_model(Arc::clone(&device), reference);
           },
+          DrawType::AddMaterialOverride(ref info) => {
+            let (reference, _, _) = info;
+            self.pending_material_overrides.retain(|(pending, _, _)| pending != reference);
+            self.pending_material_overrides.push(info.clone());
+          },
+          DrawType::RemoveMaterialOverride(ref reference) => {
+            self.pending_material_overrides.retain(|(pending, _, _)| pending != reference);
+            self.model_shader.remove_material_override(reference.to_string());
+          },
           DrawType::SetLight(ref info) => {
             let (position, colour, intensity) = info.clone();
             self.model_shader.set_light(position, colour, intensity);
           },
+          DrawType::AddLight(ref light) => {
+            self.model_shader.add_light(light.clone());
+          },
+          DrawType::SetEnvironmentMap(ref location) => {
+            let instance = self.window.instance();
+            self.model_shader.set_environment_map(Arc::clone(&instance), Arc::clone(&device), location.clone(), &self.command_pool, self.window.get_graphics_queue());
+          },
+          DrawType::SetSkyboxImages(ref locations) => {
+            let instance = self.window.instance();
+            self.model_shader.set_skybox_images(Arc::clone(&instance), Arc::clone(&device), locations.clone(), &self.command_pool, self.window.get_graphics_queue());
+          },
+          DrawType::SetSkyboxEquirectangular(ref location) => {
+            let instance = self.window.instance();
+            self.model_shader.set_skybox_equirectangular(Arc::clone(&instance), Arc::clone(&device), location.clone(), &self.command_pool, self.window.get_graphics_queue());
+          },
+          DrawType::SetSkyboxGradient(ref colours) => {
+            let (top, horizon, bottom) = *colours;
+            self.model_shader.set_skybox_gradient(top, horizon, bottom);
+          },
+          DrawType::ClearSkybox => {
+            self.model_shader.clear_skybox();
+          },
+          DrawType::SetFog(ref fog) => {
+            let (colour, density) = *fog;
+            self.model_shader.set_fog(colour, density);
+          },
+          DrawType::SetHeightFog(ref fog) => {
+            let (density, height, falloff) = *fog;
+            self.model_shader.set_height_fog(density, height, falloff);
+          },
+          DrawType::SetSkyScattering(sun_direction) => {
+            self.model_shader.set_sky_scattering(sun_direction);
+          },
+          DrawType::ClearFog => {
+            self.model_shader.clear_fog();
+          },
+          DrawType::DrawDebug(ref debug) => {
+            let (shapes, colour, depth_tested) = debug;
+            self.model_shader.add_debug_shapes(shapes, *colour, *depth_tested);
+          },
+          DrawType::SetExposure(exposure) => {
+            self.final_shader.set_exposure(exposure);
+          },
+          DrawType::SetTonemapper(tonemapper) => {
+            self.final_shader.set_tonemapper(tonemapper);
+          },
+          DrawType::SetBloomStrength(strength) => {
+            self.final_shader.set_bloom_strength(strength);
+          },
+          DrawType::SetDebugView(view) => {
+            self.model_shader.set_debug_view(view);
+            let view = self.model_shader.debug_view();
+            self.final_shader.set_debug_view(view != DebugView::Lit && view != DebugView::Wireframe);
+          },
           DrawType::SetCursorPosition(ref pos) => {
             let (x,y) = pos.clone();
             self.set_cursor_position(x,y);
@@ -507,6 +638,8 @@ impl CoreRender for CoreMaat {
           _ => {},
         }
       }
+      
+      self.add_pending_material_overrides();
     }
     
     if self.recreate_swapchain {
@@ -555,7 +688,7 @@ impl CoreRender for CoreMaat {
         match draw.get_type() {
           DrawType::DrawInstanced(ref references) => {
             let buffer_ref = references;
-            cmd = self.texture_shader.draw_instanced(Arc::clone(&device), cmd, i, buffer_ref.to_string());
+            cmd = self.texture_shader.draw_instanced(Arc::clone(&instance), Arc::clone(&device), cmd, buffer_ref.to_string());
           },
           DrawType::AddInstancedColoured(ref info) => {
             let (buffer_reference, position, scale, rotation, colour) = info.clone();
@@ -630,61 +763,115 @@ impl CoreRender for CoreMaat {
     }
       
       let device = self.window.device();
+      let instance = self.window.instance();
       let graphics_queue = self.window.get_graphics_queue();
       let window_size = vk::Extent2D { width: self.window_dimensions.width, height: self.window_dimensions.height };
       
       cmd = cmd.end_render_pass(Arc::clone(&device));
       
+      // Camera first so the shadow cascades and light budget follow this frame's view
+      for draw in &model_draw_calls {
+        if let DrawType::ModelCamera(ref info) = draw {
+          let (new_camera, move_direction, mouse_offset, set_move_speed, set_mouse_sensitivity) = info;
+            
+          if let Some(camera) = new_camera {
+            self.model_shader.set_camera(camera.clone());
+          }
+            
+          if let Some((direction, delta_time)) = move_direction {
+            self.model_shader.move_camera(direction.clone(), *delta_time);
+          }
+            
+          if let Some(offset) = mouse_offset {
+            self.model_shader.process_mouse_movement(offset.x, offset.y);
+          }
+            
+          if let Some(move_speed) = set_move_speed {
+            self.model_shader.set_camera_move_speed(*move_speed);
+          }
+            
+          if let Some(mouse_sensitivity) = set_mouse_sensitivity {
+            self.model_shader.set_mouse_sensitivity(*mouse_sensitivity);
+          }
+        }
+      }
+      
+      self.model_shader.begin_frame(Arc::clone(&device), i, window_size.width as f32, window_size.height as f32, &self.descriptor_set_pool);
+      
+      // The shadow pass draws instances from the same buffers as the model pass
+      for draw in &model_draw_calls {
+        if let DrawType::AddInstancedModel(ref info) = draw {
+          let (reference, model, colour, material, hologram) = info;
+          self.model_shader.add_instanced_model(*model, *colour, material, reference.to_string(), *hologram);
+        }
+      }
+      
+      // Shadow pass
+      cmd = self.model_shader.begin_shadow_pass(Arc::clone(&device), cmd, image_index, window_size.width as f32, window_size.height as f32);
+      
+      for draw in &model_draw_calls {
+        match draw {
+          DrawType::DrawModel(ref info) => {
+            let (reference, model, hologram, _material) = info;
+            cmd = self.model_shader.draw_model_shadow(Arc::clone(&device), cmd, *model, reference.to_string(), *hologram);
+          },
+          DrawType::DrawInstancedModel((ref reference, _)) => {
+            cmd = self.model_shader.draw_instanced_shadow(Arc::clone(&instance), Arc::clone(&device), cmd, reference.to_string());
+          },
+          _ => {}
+        }
+      }
+      
+      cmd = cmd.end_render_pass(Arc::clone(&device));
+      
+      // Instanced models culled on the gpu need culling before the model pass
+      if self.model_shader.gpu_culling_enabled() {
+        for draw in &model_draw_calls {
+          if let DrawType::DrawInstancedModel(ref info) = draw {
+            let (reference, material) = info;
+            cmd = self.model_shader.cull_instances(Arc::clone(&instance), Arc::clone(&device), cmd, reference.to_string(), material.clone());
+          }
+        }
+      }
+      
       // Model Shader
       cmd = self.model_shader.begin_renderpass(Arc::clone(&device), cmd, &self.model_clear_colour, &window_size, i);
       
-      cmd = cmd.set_viewport(Arc::clone(&device), 0.0, 0.0, window_size.width as f32, window_size.height as f32);
+      // taa moves the whole pass a fraction of a pixel each frame
+      let jitter = self.model_shader.jitter();
+      cmd = cmd.set_viewport(Arc::clone(&device), jitter.x, jitter.y, window_size.width as f32, window_size.height as f32);
       cmd = cmd.set_scissor(Arc::clone(&device), 0, 0, window_size.width, window_size.height);
       
+      cmd = self.model_shader.draw_skybox(Arc::clone(&device), cmd, window_size.width as f32, window_size.height as f32);
+      
       for draw in model_draw_calls {
         match draw {
           DrawType::DrawModel(ref info) => {
-            let (reference, position, scale, rotation, hologram) = info;
-            cmd = self.model_shader.draw_model(Arc::clone(&device), cmd, *position, *scale, *rotation, reference.to_string(), *hologram, window_size.width as f32, window_size.height as f32, delta_time);
-          },
-          DrawType::AddInstancedModel(ref info) => {
-            let (reference, position, scale, rotation, colour, hologram) = info;
-            self.model_shader.add_instanced_model(*position, *scale, *rotation, *colour, reference.to_string(), *hologram);
-          },
-          DrawType::DrawInstancedModel(ref reference) => {
-            cmd = self.model_shader.draw_instanced(Arc::clone(&device), cmd, reference.to_string(), window_size.width as f32, window_size.height as f32, delta_time);
+            let (reference, model, hologram, material) = info;
+            cmd = self.model_shader.draw_model(Arc::clone(&device), cmd, *model, reference.to_string(), *hologram, material.clone(), window_size.width as f32, window_size.height as f32, delta_time);
           },
-          DrawType::ModelCamera(ref info) => {
-            let (new_camera, move_direction, mouse_offset, set_move_speed, set_mouse_sensitivity) = info;
-            
-            if let Some(camera) = new_camera {
-              self.model_shader.set_camera(camera.clone());
-            }
-            
-            if let Some((direction, delta_time)) = move_direction {
-              self.model_shader.move_camera(direction.clone(), *delta_time);
-            }
-            
-            if let Some(offset) = mouse_offset {
-              self.model_shader.process_mouse_movement(offset.x, offset.y);
-            }
-            
-            if let Some(move_speed) = set_move_speed {
-              self.model_shader.set_camera_move_speed(*move_speed);
-            }
-            
-            if let Some(mouse_sensitivity) = set_mouse_sensitivity {
-              self.model_shader.set_mouse_sensitivity(*mouse_sensitivity);
-            }
+          DrawType::DrawInstancedModel(ref info) => {
+            let (reference, material) = info;
+            cmd = self.model_shader.draw_instanced(Arc::clone(&instance), Arc::clone(&device), cmd, reference.to_string(), material.clone(), window_size.width as f32, window_size.height as f32, delta_time);
           },
           _ => {}
         }
       }
       
       cmd = cmd.next_subpass(Arc::clone(&device));
-      cmd = self.model_shader.draw_deffered(Arc::clone(&device), cmd, image_index);
+      cmd = self.model_shader.draw_deffered(Arc::clone(&instance), Arc::clone(&device), cmd, image_index, window_size.width as f32, window_size.height as f32);
+      cmd = cmd.next_subpass(Arc::clone(&device));
+      cmd = self.model_shader.draw_transparent(Arc::clone(&device), cmd, image_index, window_size.width as f32, window_size.height as f32);
+      cmd = self.model_shader.draw_debug(Arc::clone(&device), cmd, image_index, window_size.width as f32, window_size.height as f32);
       cmd = cmd.end_render_pass(Arc::clone(&device));
       
+      cmd = self.model_shader.build_depth_pyramid(Arc::clone(&device), cmd, i);
+      cmd = self.model_shader.build_ambient_occlusion(Arc::clone(&device), cmd, i);
+      cmd = self.model_shader.build_bloom(Arc::clone(&device), cmd, i);
+      cmd = self.model_shader.build_anti_aliasing(Arc::clone(&device), cmd, i);
+      
+      self.frame_stats = self.model_shader.frame_stats();
+      
       // Final Shader
       cmd = self.final_shader.begin_renderpass(Arc::clone(&device), cmd, &self.final_clear_colour, &window_size, i);
       
@@ -693,9 +880,10 @@ impl CoreRender for CoreMaat {
       
       let texture_image = self.texture_shader.get_texture(self.current_frame);
       let model_image = self.model_shader.get_texture(self.current_frame);
+      let bloom_image = self.model_shader.get_bloom_texture();
       
-      cmd = self.final_shader.draw_to_screen(Arc::clone(&device), cmd, &model_image, &self.sampler, window_size.width as f32, window_size.height as f32, self.current_frame, false);
-      cmd = self.final_shader.draw_to_screen(Arc::clone(&device), cmd, &texture_image, &self.sampler, window_size.width as f32, window_size.height as f32, self.current_frame, true);
+      cmd = self.final_shader.draw_to_screen(Arc::clone(&device), cmd, &model_image, &bloom_image, &self.sampler, window_size.width as f32, window_size.height as f32, self.current_frame, false);
+      cmd = self.final_shader.draw_to_screen(Arc::clone(&device), cmd, &texture_image, &bloom_image, &self.sampler, window_size.width as f32, window_size.height as f32, self.current_frame, true);
       
       cmd = cmd.end_render_pass(Arc::clone(&device));
       cmd.end_command_buffer(Arc::clone(&device));
@@ -815,6 +1003,10 @@ impl CoreRender for CoreMaat {
     0
   }
   
+  fn frame_stats(&self) -> graphics::FrameStats {
+    self.frame_stats
+  }
+  
   fn force_window_resize(&mut self, new_size: Vector2<f32>, fullscreen: bool) {
     self.window.set_inner_size(LogicalSize::new(new_size.x, new_size.y));
     self.recreate_swapchain = true;
@@ -4,12 +4,19 @@ use crate::camera::PerspectiveCamera;
 use crate::camera::PerspectiveCameraDirection;
 use crate::camera::OrthoCamera;
 
+use crate::math;
 use crate::graphics;
+use crate::gltf_interpreter::{ModelLight, LightType};
+use crate::shaders::{Tonemapper, DebugShape, DebugView, MaterialOverride};
+use crate::shaders::box_edges;
+use crate::camera::Frustum;
+use crate::model_data::{ModelData, CollisionType};
 
 use cgmath::Vector2;
 use cgmath::Vector3;
 use cgmath::Vector4;
 use cgmath::Matrix4;
+use cgmath::{Quaternion, Rotation};
 
 #[derive(Clone, PartialEq)]
 pub enum DrawType {
@@ -21,9 +28,10 @@ pub enum DrawType {
   DrawSpriteSheet((String, Vector2<f32>, Vector2<f32>, f32, Vector3<i32>, Vector4<f32>)),
   // Position, Scale, Colour, Rotation
   DrawColoured((Vector2<f32>, Vector2<f32>, Vector4<f32>, f32)),
-  // Ref, position, scale, rotation
-  DrawModel((String, Vector3<f32>, Vector3<f32>, Vector3<f32>, bool)),
-  DrawInstancedModel(String),
+  // Ref, model, hologram, material override ref
+  DrawModel((String, Matrix4<f32>, bool, Option<String>)),
+  // buffer ref, material override ref
+  DrawInstancedModel((String, Option<String>)),
   // Ref, texture, position, scale, rotation
   DrawCustomShapeTextured((String, String, Vector2<f32>, Vector2<f32>, f32)),
   // Ref, position, scale, colour, rotation
@@ -36,8 +44,8 @@ pub enum DrawType {
   AddInstancedTextured((String, Vector2<f32>, Vector2<f32>, f32, f32)),
   // instanced buffer Ref, Position, Scale, Rotation, colour, SpriteDetails(x,y,rows)
   AddInstancedSpriteSheet((String, Vector2<f32>, Vector2<f32>, f32, Vector4<f32>, Vector3<i32>)),
-  //  reference, position, scale rotation colour hologram
-  AddInstancedModel((String, Vector3<f32>, Vector3<f32>, Vector3<f32>, Vector4<f32>, bool)),
+  //  reference, model, colour, material, hologram
+  AddInstancedModel((String, Matrix4<f32>, Vector4<f32>, MaterialOverride, bool)),
   // buffer ref
   DrawInstanced(String),
   
@@ -54,6 +62,10 @@ pub enum DrawType {
   UnloadFont(String),
   UnloadModel(String),
   
+  // Ref, model ref, material
+  AddMaterialOverride((String, String, MaterialOverride)),
+  RemoveMaterialOverride(String),
+  
   NewShape,
   UpdateShape((String, Vec<graphics::Vertex2d>, Vec<u32>)),
   RemoveShape,
@@ -63,6 +75,24 @@ pub enum DrawType {
   RemoveDrawcallSet,
   
   SetLight((Vector3<f32>, Vector3<f32>, f32)),
+  AddLight(ModelLight),
+  SetEnvironmentMap(Option<String>),
+  SetSkyboxImages(Vec<String>),
+  SetSkyboxEquirectangular(String),
+  SetSkyboxGradient((Vector3<f32>, Vector3<f32>, Vector3<f32>)),
+  ClearSkybox,
+  SetFog((Vector3<f32>, f32)),
+  SetHeightFog((f32, f32, f32)),
+  SetSkyScattering(Option<Vector3<f32>>),
+  ClearFog,
+  
+  // Shapes, colour, depth tested
+  DrawDebug((Vec<DebugShape>, Vector4<f32>, bool)),
+  
+  SetExposure(f32),
+  SetTonemapper(Tonemapper),
+  SetBloomStrength(f32),
+  SetDebugView(DebugView),
   
   SetTextureScale(f32),
   
@@ -97,14 +127,29 @@ pub struct DrawCall {
 impl DrawCall {
   pub fn draw_model(position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>, reference: String) -> DrawCall {
     DrawCall {
-      draw_type: DrawType::DrawModel((reference, position, scale, rotation, false)),
+      draw_type: DrawType::DrawModel((reference, math::calculate_model_euler(position, scale, rotation), false, None)),
+      coloured: true,
+    }
+  }
+  
+  pub fn draw_model_quaternion(position: Vector3<f32>, scale: Vector3<f32>, rotation: Quaternion<f32>, reference: String) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::DrawModel((reference, math::calculate_model_quaternion(position, scale, rotation), false, None)),
+      coloured: true,
+    }
+  }
+  
+  // the model matrix must be affine
+  pub fn draw_model_matrix(model: Matrix4<f32>, reference: String) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::DrawModel((reference, model, false, None)),
       coloured: true,
     }
   }
   
   pub fn draw_hologram_model(position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>, reference: String) -> DrawCall {
     DrawCall {
-      draw_type: DrawType::DrawModel((reference, position, scale, rotation, true)),
+      draw_type: DrawType::DrawModel((reference, math::calculate_model_euler(position, scale, rotation), true, None)),
       coloured: true,
     }
   }
@@ -182,35 +227,50 @@ impl DrawCall {
   
   pub fn add_instanced_model(buffer_reference: String, position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>) -> DrawCall {
     DrawCall {
-      draw_type: DrawType::AddInstancedModel((buffer_reference, position, scale, rotation, Vector4::new(1.0, 1.0, 1.0, 1.0), false)),
+      draw_type: DrawType::AddInstancedModel((buffer_reference, math::calculate_model_euler(position, scale, rotation), Vector4::new(1.0, 1.0, 1.0, 1.0), MaterialOverride::new(), false)),
+      coloured: true,
+    }
+  }
+  
+  pub fn add_instanced_model_quaternion(buffer_reference: String, position: Vector3<f32>, scale: Vector3<f32>, rotation: Quaternion<f32>) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::AddInstancedModel((buffer_reference, math::calculate_model_quaternion(position, scale, rotation), Vector4::new(1.0, 1.0, 1.0, 1.0), MaterialOverride::new(), false)),
+      coloured: true,
+    }
+  }
+  
+  // the model matrix must be affine
+  pub fn add_instanced_model_matrix(buffer_reference: String, model: Matrix4<f32>) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::AddInstancedModel((buffer_reference, model, Vector4::new(1.0, 1.0, 1.0, 1.0), MaterialOverride::new(), false)),
       coloured: true,
     }
   }
   
   pub fn add_instanced_hologram_model(buffer_reference: String, position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>) -> DrawCall {
     DrawCall {
-      draw_type: DrawType::AddInstancedModel((buffer_reference, position, scale, rotation, Vector4::new(1.0, 1.0, 1.0, 1.0), true)),
+      draw_type: DrawType::AddInstancedModel((buffer_reference, math::calculate_model_euler(position, scale, rotation), Vector4::new(1.0, 1.0, 1.0, 1.0), MaterialOverride::new(), true)),
       coloured: true,
     }
   }
   
   pub fn add_instanced_model_overwrite_colour(buffer_reference: String, position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>, colour: Vector3<f32>) -> DrawCall {
     DrawCall {
-      draw_type: DrawType::AddInstancedModel((buffer_reference, position, scale, rotation, Vector4::new(colour.x, colour.y, colour.z, -1.0), false)),
+      draw_type: DrawType::AddInstancedModel((buffer_reference, math::calculate_model_euler(position, scale, rotation), Vector4::new(colour.x, colour.y, colour.z, -1.0), MaterialOverride::new(), false)),
       coloured: true,
     }
   }
   
   pub fn add_instanced_hologram_model_overwrite_colour(buffer_reference: String, position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>, colour: Vector3<f32>) -> DrawCall {
     DrawCall {
-      draw_type: DrawType::AddInstancedModel((buffer_reference, position, scale, rotation, Vector4::new(colour.x, colour.y, colour.z, -1.0), true)),
+      draw_type: DrawType::AddInstancedModel((buffer_reference, math::calculate_model_euler(position, scale, rotation), Vector4::new(colour.x, colour.y, colour.z, -1.0), MaterialOverride::new(), true)),
       coloured: true,
     }
   }
   
   pub fn draw_instanced_model(buffer_reference: String) -> DrawCall {
     DrawCall {
-      draw_type: DrawType::DrawInstancedModel(buffer_reference),
+      draw_type: DrawType::DrawInstancedModel((buffer_reference, None)),
       coloured: true,
     }
   }
@@ -327,6 +387,255 @@ impl DrawCall {
     }
   }
   
+  /**
+  ** Adds a light for this frame only, lights imported with a model can be
+  ** passed straight through.
+  **/
+  pub fn add_light(light: ModelLight) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::AddLight(light),
+      coloured: false,
+    }
+  }
+  
+  pub fn add_point_light(position: Vector3<f32>, colour: Vector3<f32>, intensity: f32, range: f32) -> DrawCall {
+    DrawCall::add_light(ModelLight {
+      name: "".to_string(),
+      light_type: LightType::Point,
+      position,
+      direction: Vector3::new(0.0, -1.0, 0.0),
+      colour,
+      intensity,
+      range: Some(range),
+      cast_shadows: true,
+    })
+  }
+  
+  // cone angles are in radians from the centre of the cone
+  pub fn add_spot_light(position: Vector3<f32>, direction: Vector3<f32>, colour: Vector3<f32>, intensity: f32, range: f32, inner_cone_angle: f32, outer_cone_angle: f32) -> DrawCall {
+    DrawCall::add_light(ModelLight {
+      name: "".to_string(),
+      light_type: LightType::Spot(inner_cone_angle, outer_cone_angle),
+      position,
+      direction,
+      colour,
+      intensity,
+      range: Some(range),
+      cast_shadows: true,
+    })
+  }
+  
+  pub fn add_directional_light(direction: Vector3<f32>, colour: Vector3<f32>, intensity: f32) -> DrawCall {
+    DrawCall::add_light(ModelLight {
+      name: "".to_string(),
+      light_type: LightType::Directional,
+      position: Vector3::new(0.0, 0.0, 0.0),
+      direction,
+      colour,
+      intensity,
+      range: None,
+      cast_shadows: true,
+    })
+  }
+  
+  /**
+  ** Ambient light and reflections for models from an equirectangular image,
+  ** .hdr files keep their full range. Stays until changed or cleared.
+  **/
+  pub fn set_environment_map(location: String) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetEnvironmentMap(Some(location)),
+      coloured: false,
+    }
+  }
+  
+  pub fn clear_environment_map() -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetEnvironmentMap(None),
+      coloured: false,
+    }
+  }
+  
+  /**
+  ** Sky behind every model from six square images of the same size,
+  ** facing along +x, -x, +y, -y, +z and -z.
+  **/
+  pub fn set_skybox(right: String, left: String, top: String, bottom: String, front: String, back: String) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetSkyboxImages(vec!(right, left, top, bottom, front, back)),
+      coloured: false,
+    }
+  }
+  
+  pub fn set_skybox_equirectangular(location: String) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetSkyboxEquirectangular(location),
+      coloured: false,
+    }
+  }
+  
+  // procedural sky fading from top to horizon and from horizon to bottom below it
+  pub fn set_skybox_gradient(top: Vector3<f32>, horizon: Vector3<f32>, bottom: Vector3<f32>) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetSkyboxGradient((top, horizon, bottom)),
+      coloured: false,
+    }
+  }
+  
+  pub fn clear_skybox() -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::ClearSkybox,
+      coloured: false,
+    }
+  }
+  
+  // fog that thickens with distance from the camera, a density of 0.0 turns it off
+  pub fn set_fog(colour: Vector3<f32>, density: f32) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetFog((colour, density)),
+      coloured: false,
+    }
+  }
+  
+  /**
+  ** Fog with density at height that thins out exponentially by falloff
+  ** per unit above it, taking its colour from set_fog.
+  **/
+  pub fn set_height_fog(density: f32, height: f32, falloff: f32) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetHeightFog((density, height, falloff)),
+      coloured: false,
+    }
+  }
+  
+  /**
+  ** Sky lit by a sun shining along sun_direction, drawn when no skybox is
+  ** set. The fog takes the colour of this sky instead of its own.
+  **/
+  pub fn set_sky_scattering(sun_direction: Vector3<f32>) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetSkyScattering(Some(sun_direction)),
+      coloured: false,
+    }
+  }
+  
+  pub fn clear_sky_scattering() -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetSkyScattering(None),
+      coloured: false,
+    }
+  }
+  
+  // turns off both the distance and the height fog
+  pub fn clear_fog() -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::ClearFog,
+      coloured: false,
+    }
+  }
+  
+  fn draw_debug(shapes: Vec<DebugShape>, colour: Vector4<f32>, depth_test: bool) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::DrawDebug((shapes, colour, depth_test)),
+      coloured: false,
+    }
+  }
+  
+  /**
+  ** Debug lines are drawn over the lit scene for this frame only. With
+  ** depth_test they are hidden behind models, otherwise always on top.
+  **/
+  pub fn draw_line_3d(start: Vector3<f32>, end: Vector3<f32>, colour: Vector4<f32>, depth_test: bool) -> DrawCall {
+    DrawCall::draw_debug(vec!(DebugShape::Line(start, end)), colour, depth_test)
+  }
+  
+  pub fn draw_aabb(min: Vector3<f32>, max: Vector3<f32>, colour: Vector4<f32>, depth_test: bool) -> DrawCall {
+    DrawCall::draw_debug(vec!(DebugShape::Aabb(min, max)), colour, depth_test)
+  }
+  
+  // size is the full size of the box along its own axes
+  pub fn draw_obb(centre: Vector3<f32>, size: Vector3<f32>, rotation: Quaternion<f32>, colour: Vector4<f32>, depth_test: bool) -> DrawCall {
+    DrawCall::draw_debug(vec!(DebugShape::Obb(centre, size, rotation)), colour, depth_test)
+  }
+  
+  pub fn draw_sphere(centre: Vector3<f32>, radius: f32, colour: Vector4<f32>, depth_test: bool) -> DrawCall {
+    DrawCall::draw_debug(vec!(DebugShape::Sphere(centre, radius)), colour, depth_test)
+  }
+  
+  pub fn draw_frustum(frustum: Frustum, colour: Vector4<f32>, depth_test: bool) -> DrawCall {
+    DrawCall::draw_debug(vec!(DebugShape::Frustum(frustum)), colour, depth_test)
+  }
+  
+  // x is red, y green and z blue
+  pub fn draw_axes(position: Vector3<f32>, rotation: Quaternion<f32>, length: f32, depth_test: bool) -> DrawCall {
+    DrawCall::draw_debug(vec!(DebugShape::Axes(position, rotation, length)), Vector4::new(1.0, 1.0, 1.0, 1.0), depth_test)
+  }
+  
+  /**
+  ** Outlines every cube collision box of a model placed the same way
+  ** draw_model places it. Terrain collision isn't drawn.
+  **/
+  pub fn draw_collision_info(model_data: &ModelData, position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>, colour: Vector4<f32>, depth_test: bool) -> DrawCall {
+    let model = math::calculate_model_euler(position, scale, rotation);
+    let place = |point: Vector3<f32>| (model * point.extend(1.0)).truncate();
+    
+    let mut shapes = Vec::new();
+    for info in model_data.collision_info() {
+      if info.collision_type() != Some(CollisionType::Cube) {
+        continue;
+      }
+      
+      // w first, as loaded from the csv
+      let q = info.quaternion();
+      let box_rotation = Quaternion::new(q.x, q.y, q.z, q.w);
+      let half = info.size()*0.5;
+      let corners = (0..8).map(|i| {
+        let corner = Vector3::new(if i & 1 == 0 { -half.x } else { half.x },
+                                  if i & 2 == 0 { -half.y } else { half.y },
+                                  if i & 4 == 0 { -half.z } else { half.z });
+        place(info.offset_position() + box_rotation.rotate_vector(corner))
+      }).collect::<Vec<Vector3<f32>>>();
+      
+      shapes.extend(box_edges(&corners).into_iter().map(|(start, end)| DebugShape::Line(start, end)));
+    }
+    
+    DrawCall::draw_debug(shapes, colour, depth_test)
+  }
+  
+  // scales the model colour before it is tonemapped, 1.0 by default
+  pub fn set_exposure(exposure: f32) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetExposure(exposure),
+      coloured: false,
+    }
+  }
+  
+  pub fn set_tonemapper(tonemapper: Tonemapper) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetTonemapper(tonemapper),
+      coloured: false,
+    }
+  }
+  
+  // how much of the glow around bright colours is added, 0.0 turns it off
+  pub fn set_bloom_strength(strength: f32) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetBloomStrength(strength),
+      coloured: false,
+    }
+  }
+  
+  /**
+  ** Shows a single gbuffer attachment, wireframes or a heatmap instead of
+  ** the lit scene until it is set back to DebugView::Lit.
+  **/
+  pub fn set_debug_view(view: DebugView) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::SetDebugView(view),
+      coloured: false,
+    }
+  }
+  
   pub fn set_camera(camera: PerspectiveCamera) -> DrawCall {
     DrawCall {
       draw_type: DrawType::ModelCamera((Some(camera), None, None, None, None)),
@@ -446,6 +755,25 @@ impl DrawCall {
     }
   }
   
+  /**
+  ** Adds material values and textures a model can be drawn with instead of
+  ** its own, draws pick it with with_material_override. Adding the same
+  ** reference again replaces it.
+  **/
+  pub fn add_material_override(reference: String, model_reference: String, material: MaterialOverride) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::AddMaterialOverride((reference, model_reference, material)),
+      coloured: true,
+    }
+  }
+  
+  pub fn remove_material_override(reference: String) -> DrawCall {
+    DrawCall {
+      draw_type: DrawType::RemoveMaterialOverride(reference),
+      coloured: true,
+    }
+  }
+  
   pub fn unload_texture(reference: String) -> DrawCall {
     DrawCall {
       draw_type: DrawType::UnloadTexture(reference),
@@ -590,6 +918,43 @@ impl DrawCall {
     result
   }
   
+  /**
+  ** Stops a light added with add_light from casting shadows, lamps that
+  ** don't need them leave more of the shadow budget for those that do.
+  **/
+  pub fn without_shadows(mut self) -> DrawCall {
+    if let DrawType::AddLight(ref mut light) = self.draw_type {
+      light.cast_shadows = false;
+    }
+    self
+  }
+  
+  /**
+  ** Draws a draw_model or draw_instanced_model with a material override
+  ** added by add_material_override for the same model.
+  **/
+  pub fn with_material_override(mut self, material_reference: String) -> DrawCall {
+    match self.draw_type {
+      DrawType::DrawModel((_, _, _, ref mut material)) |
+      DrawType::DrawInstancedModel((_, ref mut material)) => {
+        *material = Some(material_reference);
+      },
+      _ => {},
+    }
+    self
+  }
+  
+  /**
+  ** Sets the material values of an instance added by add_instanced_model,
+  ** textures can only be swapped for the whole buffer by with_material_override.
+  **/
+  pub fn with_instance_material(mut self, instance_material: MaterialOverride) -> DrawCall {
+    if let DrawType::AddInstancedModel((_, _, _, ref mut material, _)) = self.draw_type {
+      *material = instance_material;
+    }
+    self
+  }
+  
   pub fn in_black_and_white(mut self) -> DrawCall {
     self.coloured = false;
     self
@@ -174,20 +174,28 @@ pub fn generate_terrain_from_image(reference: String, image: String) -> (ModelDe
     alpha_mode: AlphaMode::Blend,
     alpha_cutoff: 0.0,
     double_sided: true,
+    unlit: false,
+    emissive_strength: 1.0,
+    transmission_factor: 0.0,
   };
   
+  let bounding_box = FinalModel::vertex_bounds(&verticies);
   let f_model = FinalModel {
     vertices: VertexArray { morph_index: 0, vertex: verticies },
     indices: IndexArray { index: indicies.to_vec() },
     normals: NormalArray { normal: normals },
     tangents: TangentArray { tangent: Vec::new() },
     texcoords: TexCoordArray { texcoord: uvs },
+    texcoords_1: TexCoordArray { texcoord: Vec::new() },
     colours: ColourArray { colour: Vec::new() },
     material,
     topology: Topology::TriangleList,
     has_indices: true,
     has_normals: true,
     has_tangents: false,
+    lods: Vec::new(),
+    lod_level: None,
+    bounding_box,
   };
   
   let mut model_data = ModelData::new_terrain(reference.to_string(), size, heights);
@@ -195,6 +203,8 @@ pub fn generate_terrain_from_image(reference: String, image: String) -> (ModelDe
   (ModelDetails {
     models: vec!(f_model),
     size: Vector3::new(SIZE, 0.0, SIZE),
+    cameras: Vec::new(),
+    lights: Vec::new(),
   },
   model_data)
 }
@@ -270,24 +280,34 @@ pub fn generate_flat_terrain() -> ModelDetails {
     alpha_mode: AlphaMode::Blend,
     alpha_cutoff: 0.0,
     double_sided: true,
+    unlit: false,
+    emissive_strength: 1.0,
+    transmission_factor: 0.0,
   };
   
+  let bounding_box = FinalModel::vertex_bounds(&verticies);
   let f_model = FinalModel {
     vertices: VertexArray { morph_index: 0, vertex: verticies },
     indices: IndexArray { index: indicies.to_vec() },
     normals: NormalArray { normal: normals },
     tangents: TangentArray { tangent: Vec::new() },
     texcoords: TexCoordArray { texcoord: uvs },
+    texcoords_1: TexCoordArray { texcoord: Vec::new() },
     colours: ColourArray { colour: Vec::new() },
     material,
     topology: Topology::TriangleList,
     has_indices: true,
     has_normals: true,
     has_tangents: false,
+    lods: Vec::new(),
+    lod_level: None,
+    bounding_box,
   };
   
   ModelDetails {
     models: vec!(f_model),
     size: Vector3::new(SIZE, 0.0, SIZE),
+    cameras: Vec::new(),
+    lights: Vec::new(),
   }
 }
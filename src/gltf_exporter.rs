@@ -0,0 +1,436 @@
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::borrow::Cow;
+use std::path::Path;
+
+use crate::vulkan::vkenums::{AddressMode, Filter};
+use crate::gltf_interpreter::{ModelDetails, FinalModel, Material, SamplerInfo, AlphaMode, Topology,
+                              VertexArray, IndexArray, NormalArray, TangentArray, TexCoordArray, ColourArray};
+use crate::cgmath::{Vector4, Vector3};
+
+use gltf::json::Value;
+
+use base64::Engine;
+
+use image::ImageOutputFormat;
+
+// glTF enums
+const ARRAY_BUFFER: u64 = 34962;
+const ELEMENT_ARRAY_BUFFER: u64 = 34963;
+const FLOAT: u64 = 5126;
+const UNSIGNED_INT: u64 = 5125;
+const NEAREST: u64 = 9728;
+const LINEAR: u64 = 9729;
+const REPEAT: u64 = 10497;
+const CLAMP_TO_EDGE: u64 = 33071;
+const MIRRORED_REPEAT: u64 = 33648;
+
+/**
+** Builds up the json and the single binary buffer it points into.
+**/
+struct GltfWriter {
+  root: Value,
+  bin: Vec<u8>,
+  images_in_buffer: bool,
+}
+
+impl GltfWriter {
+  fn new(images_in_buffer: bool) -> GltfWriter {
+    let mut root = Value::Null;
+    root["asset"]["version"] = "2.0".into();
+    root["asset"]["generator"] = "maat_graphics".into();
+    for array in &["scenes", "nodes", "meshes", "materials", "textures", "images", "samplers", "accessors", "bufferViews", "buffers", "extensionsUsed"] {
+      root[*array] = Value::Array(Vec::new());
+    }
+    
+    GltfWriter {
+      root,
+      bin: Vec::new(),
+      images_in_buffer,
+    }
+  }
+  
+  fn push(&mut self, array: &str, value: Value) -> usize {
+    let array = self.root[array].as_array_mut().unwrap();
+    array.push(value);
+    array.len()-1
+  }
+  
+  fn use_extension(&mut self, extension: &str) {
+    let used = self.root["extensionsUsed"].as_array_mut().unwrap();
+    if !used.iter().any(|e| e == extension) {
+      used.push(extension.into());
+    }
+  }
+  
+  // buffer views have to start on a four byte boundary
+  fn align_bin(&mut self) {
+    let padding = (4 - self.bin.len() % 4) % 4;
+    self.bin.resize(self.bin.len() + padding, 0);
+  }
+  
+  fn add_buffer_view(&mut self, data: &[u8], target: Option<u64>) -> usize {
+    self.align_bin();
+    
+    let mut view = Value::Null;
+    view["buffer"] = 0.into();
+    view["byteOffset"] = self.bin.len().into();
+    view["byteLength"] = data.len().into();
+    if let Some(target) = target {
+      view["target"] = target.into();
+    }
+    
+    self.bin.extend_from_slice(data);
+    self.push("bufferViews", view)
+  }
+  
+  fn add_float_accessor<T: AsRef<[f32]>>(&mut self, values: &[T], accessor_type: &str, with_bounds: bool) -> usize {
+    let components = match accessor_type {
+      "VEC2" => 2,
+      "VEC3" => 3,
+      _ => 4,
+    };
+    
+    let mut data = Vec::with_capacity(values.len()*components*4);
+    let mut min = vec!(f32::MAX; components);
+    let mut max = vec!(f32::MIN; components);
+    for value in values {
+      for (i, v) in value.as_ref().iter().enumerate() {
+        data.extend_from_slice(&v.to_le_bytes());
+        min[i] = min[i].min(*v);
+        max[i] = max[i].max(*v);
+      }
+    }
+    
+    let mut accessor = Value::Null;
+    accessor["bufferView"] = self.add_buffer_view(&data, Some(ARRAY_BUFFER)).into();
+    accessor["componentType"] = FLOAT.into();
+    accessor["count"] = values.len().into();
+    accessor["type"] = accessor_type.into();
+    // positions are required to have bounds
+    if with_bounds {
+      accessor["min"] = min.into();
+      accessor["max"] = max.into();
+    }
+    
+    self.push("accessors", accessor)
+  }
+  
+  fn add_index_accessor(&mut self, indices: &[u32]) -> usize {
+    let mut data = Vec::with_capacity(indices.len()*4);
+    for index in indices {
+      data.extend_from_slice(&index.to_le_bytes());
+    }
+    
+    let mut accessor = Value::Null;
+    accessor["bufferView"] = self.add_buffer_view(&data, Some(ELEMENT_ARRAY_BUFFER)).into();
+    accessor["componentType"] = UNSIGNED_INT.into();
+    accessor["count"] = indices.len().into();
+    accessor["type"] = "SCALAR".into();
+    
+    self.push("accessors", accessor)
+  }
+  
+  fn add_texture(&mut self, texture: &Option<(Option<image::DynamicImage>, SamplerInfo)>) -> Option<usize> {
+    let (img, sampler_info) = match texture {
+      Some((Some(img), sampler_info)) => (img, sampler_info),
+      _ => return None,
+    };
+    
+    let mut png = Vec::new();
+    if let Err(e) = img.write_to(&mut png, ImageOutputFormat::PNG) {
+      println!("Error: Failed to encode texture for glTF export: {}", e);
+      return None;
+    }
+    
+    let mut image = Value::Null;
+    image["mimeType"] = "image/png".into();
+    if self.images_in_buffer {
+      image["bufferView"] = self.add_buffer_view(&png, None).into();
+    } else {
+      image["uri"] = ("data:image/png;base64,".to_string() + &base64::engine::general_purpose::STANDARD.encode(&png)).into();
+    }
+    let image = self.push("images", image);
+    
+    let mut sampler = Value::Null;
+    sampler["magFilter"] = filter(&sampler_info.mag_filter).into();
+    sampler["minFilter"] = filter(&sampler_info.min_filter).into();
+    sampler["wrapS"] = wrap(&sampler_info.s_wrap).into();
+    sampler["wrapT"] = wrap(&sampler_info.t_wrap).into();
+    let sampler = self.push("samplers", sampler);
+    
+    let mut texture = Value::Null;
+    texture["source"] = image.into();
+    texture["sampler"] = sampler.into();
+    Some(self.push("textures", texture))
+  }
+  
+  /**
+  ** A textureInfo for one of the material's textures, with its uv set and transform.
+  **/
+  fn texture_info(&mut self, model: &ModelDetails, model_index: usize, name: &str, texture: &Option<(Option<image::DynamicImage>, SamplerInfo)>) -> Option<Value> {
+    let index = self.add_texture(texture)?;
+    
+    let mut info = Value::Null;
+    info["index"] = index.into();
+    info["texCoord"] = model.texture_tex_coord(model_index, name).into();
+    
+    let m = model.texture_transform(model_index, name);
+    if m != [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0] {
+      // undo offset * rotation * scale, rotation is clockwise around z in the matrix
+      let angle = m[1].atan2(m[0]);
+      let scale_x = (m[0]*m[0] + m[1]*m[1]).sqrt();
+      let scale_y = -angle.sin()*m[4] + angle.cos()*m[5];
+      
+      let transform = &mut info["extensions"]["KHR_texture_transform"];
+      transform["offset"] = vec!(m[12], m[13]).into();
+      transform["rotation"] = (-angle).into();
+      transform["scale"] = vec!(scale_x, scale_y).into();
+      self.use_extension("KHR_texture_transform");
+    }
+    
+    Some(info)
+  }
+  
+  fn add_material(&mut self, model: &ModelDetails, model_index: usize) -> usize {
+    let material = &model.models[model_index].material;
+    
+    let mut value = Value::Null;
+    value["name"] = material.name.clone().into();
+    
+    let pbr = &mut value["pbrMetallicRoughness"];
+    let base_colour = material.base_colour_factor;
+    pbr["baseColorFactor"] = vec!(base_colour.x, base_colour.y, base_colour.z, base_colour.w).into();
+    pbr["metallicFactor"] = material.metallic_factor.into();
+    pbr["roughnessFactor"] = material.roughness_factor.into();
+    if let Some(info) = self.texture_info(model, model_index, "base_colour", &material.base_colour_texture) {
+      value["pbrMetallicRoughness"]["baseColorTexture"] = info;
+    }
+    if let Some(info) = self.texture_info(model, model_index, "metallic_roughness", &material.metallic_roughness_texture) {
+      value["pbrMetallicRoughness"]["metallicRoughnessTexture"] = info;
+    }
+    if let Some(mut info) = self.texture_info(model, model_index, "normal", &material.normal_texture) {
+      info["scale"] = material.normal_texture_scale.into();
+      value["normalTexture"] = info;
+    }
+    if let Some(mut info) = self.texture_info(model, model_index, "occlusion", &material.occlusion_texture) {
+      info["strength"] = material.occlusion_texture_strength.into();
+      value["occlusionTexture"] = info;
+    }
+    if let Some(info) = self.texture_info(model, model_index, "emissive", &material.emissive_texture) {
+      value["emissiveTexture"] = info;
+    }
+    
+    let emissive = material.emissive_factor;
+    value["emissiveFactor"] = vec!(emissive.x, emissive.y, emissive.z).into();
+    value["alphaMode"] = match material.alpha_mode {
+      AlphaMode::Opaque => "OPAQUE",
+      AlphaMode::Mask => "MASK",
+      AlphaMode::Blend => "BLEND",
+    }.into();
+    if let AlphaMode::Mask = material.alpha_mode {
+      value["alphaCutoff"] = material.alpha_cutoff.into();
+    }
+    value["doubleSided"] = material.double_sided.into();
+    
+    if material.unlit {
+      value["extensions"]["KHR_materials_unlit"] = Value::Object(Default::default());
+      self.use_extension("KHR_materials_unlit");
+    }
+    if material.emissive_strength != 1.0 {
+      value["extensions"]["KHR_materials_emissive_strength"]["emissiveStrength"] = material.emissive_strength.into();
+      self.use_extension("KHR_materials_emissive_strength");
+    }
+    if material.transmission_factor > 0.0 {
+      value["extensions"]["KHR_materials_transmission"]["transmissionFactor"] = material.transmission_factor.into();
+      self.use_extension("KHR_materials_transmission");
+    }
+    
+    self.push("materials", value)
+  }
+  
+  fn add_primitive(&mut self, model: &ModelDetails, model_index: usize) -> Option<Value> {
+    let final_model: &FinalModel = &model.models[model_index];
+    let vertex_count = final_model.vertices.vertex.len();
+    if vertex_count == 0 {
+      return None;
+    }
+    
+    let mut primitive = Value::Null;
+    primitive["attributes"]["POSITION"] = self.add_float_accessor(&final_model.vertices.vertex, "VEC3", true).into();
+    if final_model.normals.normal.len() == vertex_count {
+      primitive["attributes"]["NORMAL"] = self.add_float_accessor(&final_model.normals.normal, "VEC3", false).into();
+    }
+    if final_model.tangents.tangent.len() == vertex_count {
+      primitive["attributes"]["TANGENT"] = self.add_float_accessor(&final_model.tangents.tangent, "VEC4", false).into();
+    }
+    if final_model.texcoords.texcoord.len() == vertex_count {
+      primitive["attributes"]["TEXCOORD_0"] = self.add_float_accessor(&final_model.texcoords.texcoord, "VEC2", false).into();
+    }
+    if final_model.texcoords_1.texcoord.len() == vertex_count {
+      primitive["attributes"]["TEXCOORD_1"] = self.add_float_accessor(&final_model.texcoords_1.texcoord, "VEC2", false).into();
+    }
+    if final_model.colours.colour.len() == vertex_count {
+      primitive["attributes"]["COLOR_0"] = self.add_float_accessor(&final_model.colours.colour, "VEC4", false).into();
+    }
+    if !final_model.indices.index.is_empty() {
+      primitive["indices"] = self.add_index_accessor(&final_model.indices.index).into();
+    }
+    
+    primitive["mode"] = match final_model.topology {
+      Topology::PointList => 0,
+      Topology::LineList => 1,
+      Topology::_LineLoop => 2,
+      Topology::LineStrip => 3,
+      Topology::TriangleList => 4,
+      Topology::TriangleStrip => 5,
+      Topology::TriangleFan => 6,
+    }.into();
+    primitive["material"] = self.add_material(model, model_index).into();
+    
+    Some(primitive)
+  }
+  
+  fn write_model(&mut self, model: &ModelDetails) {
+    let primitives: Vec<Value> = (0..model.num_models()).filter_map(|i| self.add_primitive(model, i)).collect();
+    
+    // a mesh needs at least one primitive, without any geometry the node is left empty
+    let mut node = Value::Object(Default::default());
+    if !primitives.is_empty() {
+      let mut mesh = Value::Null;
+      mesh["primitives"] = Value::Array(primitives);
+      node["mesh"] = self.push("meshes", mesh).into();
+    }
+    let node = self.push("nodes", node);
+    
+    let mut scene = Value::Null;
+    scene["nodes"] = vec!(node).into();
+    self.push("scenes", scene);
+    self.root["scene"] = 0.into();
+    
+    // gltf doesn't allow empty arrays at the top level
+    if let Some(root) = self.root.as_object_mut() {
+      root.retain(|_, v| !v.as_array().map(|a| a.is_empty()).unwrap_or(false));
+    }
+  }
+  
+  fn finish_buffer(&mut self, uri: Option<String>) {
+    if self.bin.is_empty() {
+      return;
+    }
+    
+    self.align_bin();
+    
+    let mut buffer = Value::Null;
+    buffer["byteLength"] = self.bin.len().into();
+    if let Some(uri) = uri {
+      buffer["uri"] = uri.into();
+    }
+    self.root["buffers"] = vec!(buffer).into();
+  }
+}
+
+fn filter(filter: &Filter) -> u64 {
+  match filter {
+    Filter::Nearest => NEAREST,
+    Filter::Linear => LINEAR,
+  }
+}
+
+fn wrap(address_mode: &AddressMode) -> u64 {
+  match address_mode {
+    AddressMode::Repeat => REPEAT,
+    AddressMode::MirroredRepeat => MIRRORED_REPEAT,
+    _ => CLAMP_TO_EDGE,
+  }
+}
+
+fn to_io_error<E: std::fmt::Display>(e: E) -> io::Error {
+  io::Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+impl ModelDetails {
+  /**
+  ** A single opaque white primitive from a triangle list to save with
+  ** save_gltf or save_glb. Normals and texcoords can be empty.
+  **/
+  pub fn from_mesh(vertices: Vec<[f32; 3]>, normals: Vec<[f32; 3]>, texcoords: Vec<[f32; 2]>, indices: Vec<u32>) -> ModelDetails {
+    let material = Material {
+      base_colour_factor: Vector4::new(1.0, 1.0, 1.0, 1.0),
+      roughness_factor: 1.0,
+      alpha_mode: AlphaMode::Opaque,
+      ..Material::new()
+    };
+    
+    let bounding_box = FinalModel::vertex_bounds(&vertices);
+    let has_normals = !normals.is_empty();
+    let model = FinalModel {
+      vertices: VertexArray { morph_index: 0, vertex: vertices },
+      indices: IndexArray { index: indices },
+      normals: NormalArray { normal: normals },
+      tangents: TangentArray { tangent: Vec::new() },
+      texcoords: TexCoordArray { texcoord: texcoords },
+      texcoords_1: TexCoordArray { texcoord: Vec::new() },
+      colours: ColourArray { colour: Vec::new() },
+      material,
+      topology: Topology::TriangleList,
+      has_indices: true,
+      has_normals,
+      has_tangents: false,
+      lods: Vec::new(),
+      lod_level: None,
+      bounding_box,
+    };
+    
+    let (min, max) = bounding_box;
+    ModelDetails {
+      models: vec!(model),
+      size: Vector3::new(max.x - min.x, max.y - min.y, max.z - min.z),
+      cameras: Vec::new(),
+      lights: Vec::new(),
+    }
+  }
+  
+  /**
+  ** Writes the models as a .gltf with the vertex data in a .bin next to it, textures
+  ** are embedded in the .gltf as png data uris. There is no .bin without geometry.
+  **/
+  pub fn save_gltf(&self, location: &str) -> io::Result<()> {
+    let path = Path::new(location);
+    let bin_path = path.with_extension("bin");
+    let bin_name = bin_path.file_name().and_then(|n| n.to_str()).unwrap_or("model.bin").to_string();
+    
+    let mut writer = GltfWriter::new(false);
+    writer.write_model(self);
+    writer.finish_buffer(Some(bin_name));
+    
+    if !writer.bin.is_empty() {
+      fs::write(&bin_path, &writer.bin)?;
+    }
+    fs::write(path, gltf::json::serialize::to_vec_pretty(&writer.root).map_err(to_io_error)?)
+  }
+  
+  /**
+  ** Writes the models, and their textures, into a single binary .glb.
+  **/
+  pub fn save_glb(&self, location: &str) -> io::Result<()> {
+    let mut writer = GltfWriter::new(true);
+    writer.write_model(self);
+    writer.finish_buffer(None);
+    
+    let json = gltf::json::serialize::to_vec(&writer.root).map_err(to_io_error)?;
+    let glb = gltf::binary::Glb {
+      header: gltf::binary::Header {
+        magic: *b"glTF",
+        version: 2,
+        length: 0, // worked out when written
+      },
+      json: Cow::Owned(json),
+      bin: if writer.bin.is_empty() { None } else { Some(Cow::Owned(writer.bin)) },
+    };
+    
+    let file = fs::File::create(location)?;
+    glb.to_writer(io::BufWriter::new(file)).map_err(to_io_error)
+  }
+}
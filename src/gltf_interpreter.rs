@@ -2,6 +2,9 @@ use std::{fs, io};
 use std::path::Path;
 
 use crate::vulkan::vkenums::{AddressMode, Filter};
+use crate::camera::PerspectiveCamera;
+use crate::mesh_processing;
+use crate::mesh_processing::ProcessingOptions;
 use crate::math;
 
 use base64;
@@ -9,6 +12,9 @@ use base64;
 use gltf;
 use gltf::json::Value;
 use gltf::animation;
+use gltf::Semantic;
+use gltf::accessor::DataType;
+use gltf::accessor::sparse::IndexType;
 pub use gltf::material::AlphaMode;
 use gltf::texture::MagFilter;
 use gltf::texture::MinFilter;
@@ -22,6 +28,9 @@ use cgmath::Vector3;
 use cgmath::Vector4;
 use cgmath::Matrix4;
 use cgmath::Quaternion;
+use cgmath::Rad;
+use cgmath::SquareMatrix;
+use cgmath::InnerSpace;
 
 use image;
 use image::ImageFormat::{JPEG, PNG};
@@ -152,8 +161,9 @@ pub struct SamplerInfo {
 #[derive(Clone)]
 pub struct Texture {
   texture: String,
+  tex_coord: u32,
   
-  raw_transform: [f32; 16],
+  raw_transform: [f32; 16], // KHR_texture_transform, column major
 }
 
 #[derive(Clone)]
@@ -175,6 +185,9 @@ pub struct Material {
   pub alpha_mode: AlphaMode,
   pub alpha_cutoff: f32,
   pub double_sided: bool,
+  pub unlit: bool, // KHR_materials_unlit
+  pub emissive_strength: f32, // KHR_materials_emissive_strength
+  pub transmission_factor: f32, // KHR_materials_transmission
 }
 
 #[derive(Clone)]
@@ -184,19 +197,64 @@ pub struct FinalModel {
   pub normals: NormalArray,
   pub tangents: TangentArray,
   pub texcoords: TexCoordArray,
+  pub texcoords_1: TexCoordArray,
   pub colours: ColourArray,
   pub material: Material,
   pub topology: Topology,
   pub has_indices: bool,
   pub has_normals: bool,
   pub has_tangents: bool,
+  pub lods: Vec<IndexArray>, // generated, progressively simpler index lists over the same vertices
+  pub lod_level: Option<u32>, // artist lod from a _LOD<n> node name suffix, 0 is full detail
+  pub bounding_box: (Vector3<f32>, Vector3<f32>), // min and max of the vertices
 //  animation: Animation,
 }
 
+impl FinalModel {
+  pub fn vertex_bounds(vertices: &[[f32; 3]]) -> (Vector3<f32>, Vector3<f32>) {
+    if vertices.is_empty() {
+      return (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+    }
+    
+    let mut min_xyz = Vector3::new(vertices[0][0], vertices[0][1], vertices[0][2]);
+    let mut max_xyz = min_xyz;
+    for v in vertices {
+      min_xyz = Vector3::new(min_xyz.x.min(v[0]), min_xyz.y.min(v[1]), min_xyz.z.min(v[2]));
+      max_xyz = Vector3::new(max_xyz.x.max(v[0]), max_xyz.y.max(v[1]), max_xyz.z.max(v[2]));
+    }
+    
+    (min_xyz, max_xyz)
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LightType {
+  Point,
+  Spot(f32, f32), // inner cone angle, outer cone angle in radians
+  Directional,
+}
+
+/**
+** A KHR_lights_punctual light, positioned in the same space models are drawn in.
+**/
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelLight {
+  pub name: String,
+  pub light_type: LightType,
+  pub position: Vector3<f32>,
+  pub direction: Vector3<f32>,
+  pub colour: Vector3<f32>,
+  pub intensity: f32,
+  pub range: Option<f32>,
+  pub cast_shadows: bool, // point and spot lights only, the sun is always shadowed
+}
+
 #[derive(Clone)]
 pub struct ModelDetails {
   pub models: Vec<FinalModel>,
   pub size: Vector3<f32>,
+  pub cameras: Vec<PerspectiveCamera>,
+  pub lights: Vec<ModelLight>,
  // materials: Vec<Material>,
 }
 
@@ -217,12 +275,66 @@ impl Texture {
   pub fn _new() -> Texture {
     Texture {
       texture: "".to_string(),
+      tex_coord: 0,
       raw_transform: [1.0, 0.0, 0.0, 0.0, 
                       0.0, 1.0, 0.0, 0.0, 
                       0.0, 0.0, 1.0, 0.0, 
                       0.0, 0.0, 0.0, 1.0],
     }
   }
+  
+  /**
+  ** Reads the KHR_texture_transform extension of a raw textureInfo, offset * rotation * scale.
+  **/
+  pub fn from_raw_info(texture: String, info: &Value) -> Texture {
+    let transform = &info["extensions"]["KHR_texture_transform"];
+    
+    let offset = serde_to_f32(Some(transform["offset"].clone()));
+    let scale = serde_to_f32(Some(transform["scale"].clone()));
+    let scale = if transform["scale"].is_array() { scale } else { Vector4::new(1.0, 1.0, 0.0, 0.0) };
+    let rotation = transform["rotation"].as_f64().unwrap_or(0.0) as f32;
+    
+    // the extension is allowed to override which uv set the texture uses
+    let tex_coord = transform["texCoord"].as_u64().or(info["texCoord"].as_u64()).unwrap_or(0) as u32;
+    
+    Texture::new(texture, tex_coord, Vector2::new(offset.x, offset.y), rotation, Vector2::new(scale.x, scale.y))
+  }
+  
+  /**
+  ** A uv transform of offset * rotation * scale, rotation is counter clockwise in uv space.
+  **/
+  pub fn new(texture: String, tex_coord: u32, offset: Vector2<f32>, rotation: f32, scale: Vector2<f32>) -> Texture {
+    // glTF rotates uvs counter clockwise in uv space, which is clockwise around z here
+    let matrix = Matrix4::from_translation(Vector3::new(offset.x, offset.y, 0.0)) *
+                 Matrix4::from_angle_z(Rad(-rotation)) *
+                 Matrix4::from_nonuniform_scale(scale.x, scale.y, 1.0);
+    
+    let columns: [[f32; 4]; 4] = matrix.into();
+    let mut raw_transform = [0.0; 16];
+    for i in 0..4 {
+      for j in 0..4 {
+        raw_transform[i*4 + j] = columns[i][j];
+      }
+    }
+    
+    Texture {
+      texture,
+      tex_coord,
+      raw_transform,
+    }
+  }
+  
+  pub fn name(&self) -> &String {
+    &self.texture
+  }
+  
+  pub fn tex_coord(&self) -> u32 {
+    self.tex_coord
+  }
+  
+  pub fn transform(&self) -> [f32; 16] {
+    self.raw_transform
+  }
 }
 
 impl Material {
@@ -245,6 +357,9 @@ impl Material {
      alpha_mode: AlphaMode::Blend,
      alpha_cutoff: 0.5,
      double_sided: false,
+     unlit: false,
+     emissive_strength: 1.0,
+     transmission_factor: 0.0,
    }
  }
 }
@@ -281,7 +396,7 @@ fn serde_to_f32(value: Option<Value>) -> Vector4<f32> {
 }
 
 impl ModelDetails {
-  pub fn new(source: String) -> ModelDetails {
+  pub fn new(source: String, options: ProcessingOptions) -> ModelDetails {
     let source = &source;
     
     let mut points: Vec<Vec<f32>> = Vec::new();
@@ -312,6 +427,9 @@ impl ModelDetails {
     };
 //    let (gltf, buffers, images) = .unwrap();
     
+    // gltf doesn't keep extensions it doesn't know about, so they are read from the raw json
+    let raw_json = load_raw_json(source);
+    
     /*
     println!("{:?}", buffers);
     for scene in gltf.scenes() {
@@ -477,6 +595,7 @@ impl ModelDetails {
             normals: NormalArray { normal: Vec::new() },
             tangents: TangentArray { tangent: Vec::new() },
             texcoords: TexCoordArray { texcoord: Vec::new() },
+            texcoords_1: TexCoordArray { texcoord: Vec::new() },
             colours: ColourArray { colour: Vec::new() },
             material: Material::new(),
             topology: Topology::TriangleStrip, // default
@@ -484,28 +603,15 @@ impl ModelDetails {
             has_indices: false,
             has_normals: false,
             has_tangents: false,
+            lods: Vec::new(),
+            lod_level: node.name().and_then(lod_level_from_name),
+            bounding_box: (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)),
           });
           
-          let bounding_box = primitive.bounding_box();
-          
-          min_xyz.x = min_xyz.x.min(bounding_box.min[0]);
-          min_xyz.y = min_xyz.y.min(bounding_box.min[1]);
-          min_xyz.z = min_xyz.z.min(bounding_box.min[2]);
-          
-          max_xyz.x = max_xyz.x.max(bounding_box.max[0]);
-          max_xyz.y = max_xyz.y.max(bounding_box.max[1]);
-          max_xyz.z = max_xyz.z.max(bounding_box.max[2]);
-          
           //println!("- Primitive #{}", primitive.index());
           //println!("Material: {:?}", primitive.material().index());
           //println!("Material name: {:?}", primitive.material().name());
           //println!("Base Colour: {:?}", primitive.material().pbr_metallic_roughness().base_color_factor());
-          let mut texture_index = 0;
-          if let Some(info) = primitive.material().pbr_metallic_roughness().base_color_texture() {
-            texture_index = info.tex_coord();
-            //println!("Base Texture name: {:?}", info.texture().source().name());
-          }
-          
           models[index].topology = match primitive.mode() {
             gltf::mesh::Mode::Points => Topology::PointList,
             gltf::mesh::Mode::Lines => Topology::LineList,
@@ -540,8 +646,51 @@ impl ModelDetails {
           let emissive_factor = mat.emissive_factor();
           models[index].material.emissive_factor = Vector3::new(emissive_factor[0], emissive_factor[1], emissive_factor[2]);
           
+          let raw_material = match mat.index() {
+            Some(i) => &raw_json["materials"][i],
+            None => &Value::Null,
+          };
+          
+          models[index].material.textures = vec!(
+            Texture::from_raw_info("base_colour".to_string(), &raw_material["pbrMetallicRoughness"]["baseColorTexture"]),
+            Texture::from_raw_info("metallic_roughness".to_string(), &raw_material["pbrMetallicRoughness"]["metallicRoughnessTexture"]),
+            Texture::from_raw_info("normal".to_string(), &raw_material["normalTexture"]),
+            Texture::from_raw_info("occlusion".to_string(), &raw_material["occlusionTexture"]),
+            Texture::from_raw_info("emissive".to_string(), &raw_material["emissiveTexture"]),
+          );
+          
+          let extensions = &raw_material["extensions"];
+          models[index].material.unlit = extensions["KHR_materials_unlit"].is_object();
+          models[index].material.emissive_strength = extensions["KHR_materials_emissive_strength"]["emissiveStrength"].as_f64().unwrap_or(1.0) as f32;
+          models[index].material.transmission_factor = extensions["KHR_materials_transmission"]["transmissionFactor"].as_f64().unwrap_or(0.0) as f32;
+          
           let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-          if let Some(iter) = reader.read_positions() {
+          
+          // KHR_mesh_quantization allows integer attributes which the reader would read as floats
+          let quantized_positions = quantized_accessor(&primitive, Semantic::Positions);
+          let positions: Option<Vec<[f32; 3]>> = match quantized_positions {
+            Some(ref accessor) => Some(read_dequantized(accessor, &buffers).iter().map(|v| [v[0], v[1], v[2]]).collect()),
+            None => reader.read_positions().map(|iter| iter.collect()),
+          };
+          
+          if quantized_positions.is_some() {
+            for vertex_position in positions.iter().flatten() {
+              min_xyz = Vector3::new(min_xyz.x.min(vertex_position[0]), min_xyz.y.min(vertex_position[1]), min_xyz.z.min(vertex_position[2]));
+              max_xyz = Vector3::new(max_xyz.x.max(vertex_position[0]), max_xyz.y.max(vertex_position[1]), max_xyz.z.max(vertex_position[2]));
+            }
+          } else {
+            let bounding_box = primitive.bounding_box();
+            
+            min_xyz.x = min_xyz.x.min(bounding_box.min[0]);
+            min_xyz.y = min_xyz.y.min(bounding_box.min[1]);
+            min_xyz.z = min_xyz.z.min(bounding_box.min[2]);
+            
+            max_xyz.x = max_xyz.x.max(bounding_box.max[0]);
+            max_xyz.y = max_xyz.y.max(bounding_box.max[1]);
+            max_xyz.z = max_xyz.z.max(bounding_box.max[2]);
+          }
+          
+          if let Some(iter) = positions {
             let mut vertices = Vec::with_capacity(iter.len());
             for vertex_position in iter {
               let vertex = Vector3::new(vertex_position[0], vertex_position[1], vertex_position[2]);
@@ -550,9 +699,16 @@ impl ModelDetails {
               
               vertices.push([vertex.x, vertex.y, vertex.z]);
             }
+            models[index].bounding_box = FinalModel::vertex_bounds(&vertices);
             models[index].vertices.vertex = vertices;
           }
-          if let Some(iter) = reader.read_normals() {
+          
+          let normals: Option<Vec<[f32; 3]>> = match quantized_accessor(&primitive, Semantic::Normals) {
+            Some(ref accessor) => Some(read_dequantized(accessor, &buffers).iter().map(|v| [v[0], v[1], v[2]]).collect()),
+            None => reader.read_normals().map(|iter| iter.collect()),
+          };
+          
+          if let Some(iter) = normals {
             let mut normals = Vec::with_capacity(iter.len());
             for vertex_normal in iter {
               let normal = Vector4::new(vertex_normal[0], vertex_normal[1], vertex_normal[2], 1.0);
@@ -563,7 +719,12 @@ impl ModelDetails {
             }
             models[index].normals.normal = normals;
           }
-          if let Some(iter) = reader.read_tangents() {
+          let tangents: Option<Vec<[f32; 4]>> = match quantized_accessor(&primitive, Semantic::Tangents) {
+            Some(ref accessor) => Some(read_dequantized(accessor, &buffers)),
+            None => reader.read_tangents().map(|iter| iter.collect()),
+          };
+          
+          if let Some(iter) = tangents {
             let mut tangents = Vec::with_capacity(iter.len());
             for vertex_tangent in iter {
               let tangent = Vector4::new(vertex_tangent[0], vertex_tangent[1], vertex_tangent[2], vertex_tangent[3]);
@@ -583,14 +744,24 @@ impl ModelDetails {
             }
             models[index].indices.index = indices;
           }
-          if let Some(iter) = reader.read_tex_coords(texture_index) {
+          // each texture picks one of these with its tex_coord
+          for texture_index in 0..2 {
             let mut texcoords = Vec::new();
-            for vertex_texcoords in iter.into_f32() {
-              texcoords.push(vertex_texcoords);
+            if let Some(ref accessor) = quantized_accessor(&primitive, Semantic::TexCoords(texture_index)) {
+              texcoords = read_dequantized(accessor, &buffers).iter().map(|v| [v[0], v[1]]).collect();
+            } else if let Some(iter) = reader.read_tex_coords(texture_index) {
+              for vertex_texcoords in iter.into_f32() {
+                texcoords.push(vertex_texcoords);
+              }
+            }
+            
+            if texture_index == 0 {
+              models[index].texcoords.texcoord = texcoords;
+            } else {
+              models[index].texcoords_1.texcoord = texcoords;
             }
-            models[index].texcoords.texcoord = texcoords;
           }
-          if let Some(iter) = reader.read_colors(texture_index) {
+          if let Some(iter) = reader.read_colors(0) {
             let mut colours = Vec::new();
             for vertex_colour in iter.into_rgba_f32() {
               colours.push(vertex_colour);
@@ -603,9 +774,30 @@ impl ModelDetails {
     }
     //let mut materials: Vec<Material> = Vec::new();
     
+    // artist made lods replace the generated ones, primitives without a suffix still get them
+    for model in &mut models {
+      let options = if model.lod_level.is_some() {
+        ProcessingOptions { generated_lods: 0, ..options }
+      } else {
+        options
+      };
+      
+      mesh_processing::process_model(model, options);
+    }
+    
+    let mut cameras = Vec::new();
+    let mut lights = Vec::new();
+    for scene in gltf.scenes() {
+      for node in scene.nodes() {
+        collect_cameras_and_lights(&node, Matrix4::identity(), &mut cameras, &mut lights);
+      }
+    }
+    
     ModelDetails {
       models: models,
       size: Vector3::new(max_xyz.x - min_xyz.x, max_xyz.y - min_xyz.y, max_xyz.z - min_xyz.z),
+      cameras,
+      lights,
      // materials: materials,
     }
   }
@@ -614,6 +806,19 @@ impl ModelDetails {
     self.size
   }
   
+  // min and max corner of a primitive, in the same space as its vertices
+  pub fn bounding_box(&self, model_index: usize) -> (Vector3<f32>, Vector3<f32>) {
+    self.models[model_index].bounding_box
+  }
+  
+  pub fn cameras(&self) -> Vec<PerspectiveCamera> {
+    self.cameras.clone()
+  }
+  
+  pub fn lights(&self) -> Vec<ModelLight> {
+    self.lights.clone()
+  }
+  
   pub fn num_models(&self) -> usize {
     self.models.len()
   }
@@ -662,6 +867,18 @@ impl ModelDetails {
     self.models[model_index].texcoords.texcoord.clone()
   }
   
+  pub fn lods(&self, model_index: usize) -> Vec<Vec<u32>> {
+    self.models[model_index].lods.iter().map(|lod| lod.index.clone()).collect()
+  }
+  
+  pub fn lod_level(&self, model_index: usize) -> Option<u32> {
+    self.models[model_index].lod_level
+  }
+  
+  pub fn texcoords_1(&self, model_index: usize) -> Vec<[f32; 2]> {
+    self.models[model_index].texcoords_1.texcoord.clone()
+  }
+  
   pub fn colours(&self, model_index: usize) -> Vec<[f32; 4]> {
     self.models[model_index].colours.colour.clone()
   }
@@ -772,11 +989,225 @@ impl ModelDetails {
     [emissive.x, emissive.y, emissive.z]
   }
   
+  pub fn emissive_strength(&self, model_index: usize) -> f32 {
+    self.models[model_index].material.emissive_strength
+  }
+  
+  pub fn unlit(&self, model_index: usize) -> bool {
+    self.models[model_index].material.unlit
+  }
+  
+  pub fn transmission_factor(&self, model_index: usize) -> f32 {
+    self.models[model_index].material.transmission_factor
+  }
+  
+  /**
+  ** Returns the uv transform of a texture (base_colour, metallic_roughness, normal, occlusion or emissive), identity if it has none.
+  **/
+  pub fn texture_transform(&self, model_index: usize, texture: &str) -> [f32; 16] {
+    let mut transform = Texture::_new().transform();
+    for t in &self.models[model_index].material.textures {
+      if t.name() == texture {
+        transform = t.transform();
+      }
+    }
+    transform
+  }
+  
+  /**
+  ** Returns which uv set (0 or 1) a texture is sampled with.
+  **/
+  pub fn texture_tex_coord(&self, model_index: usize, texture: &str) -> u32 {
+    let mut tex_coord = 0;
+    for t in &self.models[model_index].material.textures {
+      if t.name() == texture {
+        tex_coord = t.tex_coord();
+      }
+    }
+    tex_coord
+  }
+  
   pub fn _get_primitive_topology(&self, model_index: usize) -> Topology {
     self.models[model_index].topology.clone()
   }
 }
 
+/**
+** Walks the node tree picking up cameras and lights, glTF points both down -z.
+** x is flipped to match the models. Only the placement of cameras is kept,
+** the renderer has its own field of view and clip planes.
+**/
+fn collect_cameras_and_lights(node: &gltf::Node, parent: Matrix4<f32>, cameras: &mut Vec<PerspectiveCamera>, lights: &mut Vec<ModelLight>) {
+  let transform = parent * Matrix4::from(node.transform().matrix());
+  let to_model_space = |v: Vector4<f32>| {
+    let v = (transform * v).truncate();
+    Vector3::new(-v.x, v.y, v.z)
+  };
+  
+  let position = to_model_space(Vector4::new(0.0, 0.0, 0.0, 1.0));
+  let front = to_model_space(Vector4::new(0.0, 0.0, -1.0, 0.0)).normalize();
+  
+  if let Some(camera) = node.camera() {
+    match camera.projection() {
+      gltf::camera::Projection::Perspective(_) => {
+        // the up vector is rebuilt from the yaw and pitch
+        let mut perspective_camera = PerspectiveCamera::default_vk();
+        perspective_camera.set_position(position);
+        perspective_camera.set_yaw(front.z.atan2(front.x).to_degrees());
+        perspective_camera.set_pitch(front.y.asin().to_degrees());
+        cameras.push(perspective_camera);
+      },
+      gltf::camera::Projection::Orthographic(_) => {
+        println!("Error: Orthographic glTF cameras are not supported");
+      }
+    }
+  }
+  
+  if let Some(light) = node.light() {
+    let light_type = match light.kind() {
+      gltf::khr_lights_punctual::Kind::Point => LightType::Point,
+      gltf::khr_lights_punctual::Kind::Spot { inner_cone_angle, outer_cone_angle } => LightType::Spot(inner_cone_angle, outer_cone_angle),
+      gltf::khr_lights_punctual::Kind::Directional => LightType::Directional,
+    };
+    
+    let colour = light.color();
+    lights.push(ModelLight {
+      name: light.name().unwrap_or("").to_string(),
+      light_type,
+      position,
+      direction: front,
+      colour: Vector3::new(colour[0], colour[1], colour[2]),
+      intensity: light.intensity(),
+      range: light.range(),
+      cast_shadows: true,
+    });
+  }
+  
+  for child in node.children() {
+    collect_cameras_and_lights(&child, transform, cameras, lights);
+  }
+}
+
+/**
+** Artist lods are named with a _LOD<n> suffix, like Tree_LOD0, Tree_LOD1.
+**/
+fn lod_level_from_name(name: &str) -> Option<u32> {
+  let lower = name.to_lowercase();
+  let lod_start = lower.rfind("_lod")?;
+  lower[lod_start + 4..].parse::<u32>().ok()
+}
+
+fn load_raw_json(source: &str) -> Value {
+  let data = match fs::read(source) {
+    Ok(data) => data,
+    Err(_) => return Value::Null,
+  };
+  
+  let json = if data.starts_with(b"glTF") {
+    match gltf::Glb::from_slice(&data) {
+      Ok(glb) => glb.json.into_owned(),
+      Err(_) => return Value::Null,
+    }
+  } else {
+    data
+  };
+  
+  gltf::json::deserialize::from_slice(&json).unwrap_or(Value::Null)
+}
+
+fn quantized_accessor<'a>(primitive: &gltf::Primitive<'a>, semantic: Semantic) -> Option<gltf::Accessor<'a>> {
+  primitive.get(&semantic).filter(|accessor| accessor.data_type() != DataType::F32)
+}
+
+/**
+** Reads an accessor of any component type into floats, normalising integers when the accessor says so.
+** Sparse accessors have their substituted values written over the base values (zeros when there is no view).
+**/
+fn read_dequantized(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Vec<[f32; 4]> {
+  let data_type = accessor.data_type();
+  let normalized = accessor.normalized();
+  let component_size = data_type.size();
+  let components = accessor.dimensions().multiplicity().min(4);
+  let element_size = component_size * accessor.dimensions().multiplicity();
+  
+  let mut values = vec![[0.0; 4]; accessor.count()];
+  if let Some(view) = accessor.view() {
+    let data = &buffers[view.buffer().index()].0;
+    let stride = view.stride().unwrap_or(element_size);
+    let start = view.offset() + accessor.offset();
+    
+    for (i, value) in values.iter_mut().enumerate() {
+      for (c, component) in value.iter_mut().enumerate().take(components) {
+        *component = read_component(data, data_type, normalized, start + i*stride + c*component_size);
+      }
+    }
+  }
+  
+  if let Some(sparse) = accessor.sparse() {
+    let indices = sparse.indices();
+    let index_view = indices.view();
+    let index_data = &buffers[index_view.buffer().index()].0;
+    let index_start = index_view.offset() + indices.offset() as usize;
+    
+    let sparse_values = sparse.values();
+    let value_view = sparse_values.view();
+    let value_data = &buffers[value_view.buffer().index()].0;
+    let value_start = value_view.offset() + sparse_values.offset() as usize;
+    
+    for i in 0..sparse.count() as usize {
+      let index = match indices.index_type() {
+        IndexType::U8 => index_data[index_start + i] as usize,
+        IndexType::U16 => {
+          let o = index_start + i*2;
+          u16::from_le_bytes([index_data[o], index_data[o+1]]) as usize
+        },
+        IndexType::U32 => {
+          let o = index_start + i*4;
+          u32::from_le_bytes([index_data[o], index_data[o+1], index_data[o+2], index_data[o+3]]) as usize
+        },
+      };
+      
+      if index >= values.len() {
+        println!("Error: sparse accessor {} index {} is out of range", accessor.index(), index);
+        continue;
+      }
+      
+      for (c, component) in values[index].iter_mut().enumerate().take(components) {
+        *component = read_component(value_data, data_type, normalized, value_start + i*element_size + c*component_size);
+      }
+    }
+  }
+  
+  values
+}
+
+fn read_component(data: &[u8], data_type: DataType, normalized: bool, o: usize) -> f32 {
+  match data_type {
+    DataType::I8 => {
+      let v = data[o] as i8 as f32;
+      if normalized { (v / 127.0).max(-1.0) } else { v }
+    },
+    DataType::U8 => {
+      let v = data[o] as f32;
+      if normalized { v / 255.0 } else { v }
+    },
+    DataType::I16 => {
+      let v = i16::from_le_bytes([data[o], data[o+1]]) as f32;
+      if normalized { (v / 32767.0).max(-1.0) } else { v }
+    },
+    DataType::U16 => {
+      let v = u16::from_le_bytes([data[o], data[o+1]]) as f32;
+      if normalized { v / 65535.0 } else { v }
+    },
+    DataType::U32 => {
+      u32::from_le_bytes([data[o], data[o+1], data[o+2], data[o+3]]) as f32
+    },
+    DataType::F32 => {
+      f32::from_le_bytes([data[o], data[o+1], data[o+2], data[o+3]])
+    },
+  }
+}
+
 fn texture_to_image(texture: gltf::Texture, buffers: &Vec<gltf::buffer::Data>, base_path: &Path) -> Option<image::DynamicImage> {
   let data = texture.source().source();
   let img = match data {
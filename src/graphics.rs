@@ -18,6 +18,18 @@ pub struct Vertex2d { pub position: [f32; 2], pub uv: [f32; 2] }
 #[derive(Clone, PartialEq)]
 pub struct Vertex3d { pub position: [f32; 3], pub normal: [f32; 3], pub tangent: [f32; 4], pub uv: [f32; 2], pub colour: [f32; 4] }
 
+/**
+** Counts from the model pass of the last frame, primitives are counted once for
+** each DrawModel they are part of and instances once for each model they add.
+**/
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct FrameStats {
+  pub primitives_drawn: u32,
+  pub primitives_culled: u32,
+  pub instances_drawn: u32,
+  pub instances_culled: u32,
+}
+
 pub const DEFAULT_TEXTURE: &str = "Arial";
 
 pub trait CoreRender {
@@ -82,6 +94,7 @@ pub trait CoreRender {
   fn set_camera(&mut self, camera: PerspectiveCamera);
   fn get_camera(&self) -> PerspectiveCamera;
   fn num_drawcalls(&self) -> u32;
+  fn frame_stats(&self) -> FrameStats;
   
   fn force_window_resize(&mut self, new_size: Vector2<f32>, fullscreen: bool);
 }
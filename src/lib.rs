@@ -113,6 +113,7 @@ pub use crate::core::CoreMaat;
 pub use crate::drawcalls::DrawCall;
 
 pub use self::model_data::{ModelData, CollisionInfo, CollisionType};
+pub use self::gltf_interpreter::{ModelDetails, ModelLight, LightType};
 
 use logs::Logs;
 
@@ -127,6 +128,9 @@ mod settings;
 mod drawcalls;
 mod core;
 mod gltf_interpreter;
+mod gltf_exporter;
+mod mesh_processing;
+mod obj_interpreter;
 mod font;
 mod threadpool;
 mod resource_manager;
@@ -1,6 +1,6 @@
 use cgmath::{Deg, Rad};
 use cgmath::{Vector2, Vector3, Vector4, Matrix4,
-             InnerSpace, Quaternion, Angle, Zero, Euler};
+             InnerSpace, Quaternion, Angle, Zero, Euler, Rotation3};
 
 use std::f64::consts::PI;
 
@@ -161,6 +161,23 @@ pub fn calculate_texture_model(translation: Vector3<f32>, size: Vector2<f32>, ro
   model
 }
 
+/**
+** The model matrix of an euler rotation in degrees, rotated about x, then y,
+** then z. It is scaled after it is rotated, as models always have been.
+**/
+pub fn calculate_model_euler(position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>) -> Matrix4<f32> {
+  let rotation = Quaternion::from_axis_angle(Vector3::unit_z(), Deg(rotation.z)) *
+                 Quaternion::from_axis_angle(Vector3::unit_y(), Deg(rotation.y)) *
+                 Quaternion::from_axis_angle(Vector3::unit_x(), Deg(rotation.x));
+  
+  Matrix4::from_translation(position) * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z) * Matrix4::from(rotation)
+}
+
+// scaled in model space then rotated, the order physics transforms use
+pub fn calculate_model_quaternion(position: Vector3<f32>, scale: Vector3<f32>, rotation: Quaternion<f32>) -> Matrix4<f32> {
+  Matrix4::from_translation(position) * Matrix4::from(rotation) * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+}
+
 pub fn barryCentric(p1: Vector3<f32>, p2: Vector3<f32>, p3: Vector3<f32>, pos: Vector2<f32>) -> f32 {
   let det = (p2.z - p3.z) * (p1.x - p3.x) + (p3.x - p2.x) * (p1.z - p3.z);
   let l1 = ((p2.z - p3.z) * (pos.x - p3.x) + (p3.x - p2.x) * (pos.y - p3.z)) / det;
@@ -217,6 +234,11 @@ pub fn vec3_lerp(current: Vector3<f32>, goal: Vector3<f32>, t: f32) -> Vector3<f
   Vector3::new(x, y, z)
 }
 
+// integer division that rounds up, u32::div_ceil needs rust 1.73
+pub fn div_round_up(value: u32, divisor: u32) -> u32 {
+  value / divisor + (value % divisor).min(1)
+}
+
 pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
   a + t * (b-a)
 }
@@ -0,0 +1,854 @@
+use crate::gltf_interpreter::{FinalModel, Topology, IndexArray};
+
+use cgmath::Vector3;
+use cgmath::InnerSpace;
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, BinaryHeap};
+
+// Forsyth "linear-speed vertex cache optimisation" tuning values
+const CACHE_SIZE: usize = 32;
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+// each generated lod aims for half the triangles of the one before it
+const LOD_REDUCTION: f32 = 0.5;
+const LOD_MIN_TRIANGLES: usize = 32;
+
+/**
+** What the loader thread does to meshes after reading them, set from Settings.
+**/
+#[derive(Clone, Copy)]
+pub struct ProcessingOptions {
+  pub optimise_vertex_cache: bool,
+  pub generated_lods: u32,
+}
+
+/**
+** Runs on the loader thread after a primitive has been read. Triangle primitives
+** end up as an indexed triangle list with duplicate vertices welded, normals and
+** MikkTSpace tangents generated when the file didn't have them, and optionally
+** their triangles reordered for the post transform vertex cache. Simplified lods
+** are added as extra index lists over the same vertices.
+**/
+pub fn process_model(model: &mut FinalModel, options: ProcessingOptions) {
+  match model.topology {
+    Topology::TriangleList | Topology::TriangleStrip | Topology::TriangleFan => {},
+    _ => { return; }
+  }
+  
+  let vertex_count = model.vertices.vertex.len();
+  if vertex_count == 0 {
+    return;
+  }
+  
+  discard_partial_attributes(model, vertex_count);
+  
+  if !model.has_indices {
+    model.indices.index = (0..vertex_count as u32).collect();
+    model.has_indices = true;
+  }
+  model.indices.index = triangle_list_indices(&model.indices.index, &model.topology);
+  model.topology = Topology::TriangleList;
+  
+  if !model.has_normals {
+    model.normals.normal = generate_smooth_normals(&model.vertices.vertex, &model.indices.index);
+    model.has_normals = true;
+  }
+  
+  if !model.has_tangents {
+    generate_tangents(model);
+  }
+  
+  weld_vertices(model);
+  
+  if options.optimise_vertex_cache {
+    model.indices.index = optimise_triangle_order(&model.indices.index, model.vertices.vertex.len());
+    optimise_vertex_fetch(model);
+  }
+  
+  generate_lods(model, options);
+}
+
+fn discard_partial_attributes(model: &mut FinalModel, vertex_count: usize) {
+  if model.normals.normal.len() != vertex_count {
+    model.normals.normal.clear();
+    model.has_normals = false;
+  }
+  if model.tangents.tangent.len() != vertex_count {
+    model.tangents.tangent.clear();
+    model.has_tangents = false;
+  }
+  if model.texcoords.texcoord.len() != vertex_count {
+    model.texcoords.texcoord.clear();
+  }
+  if model.texcoords_1.texcoord.len() != vertex_count {
+    model.texcoords_1.texcoord.clear();
+  }
+  if model.colours.colour.len() != vertex_count {
+    model.colours.colour.clear();
+  }
+}
+
+fn triangle_list_indices(indices: &[u32], topology: &Topology) -> Vec<u32> {
+  let mut list = Vec::with_capacity(indices.len()*3);
+  
+  match topology {
+    Topology::TriangleStrip => {
+      for i in 2..indices.len() {
+        let (a, b, c) = if i % 2 == 0 {
+          (indices[i-2], indices[i-1], indices[i])
+        } else {
+          (indices[i-1], indices[i-2], indices[i])
+        };
+        
+        if a != b && b != c && a != c {
+          list.extend_from_slice(&[a, b, c]);
+        }
+      }
+    },
+    Topology::TriangleFan => {
+      for i in 2..indices.len() {
+        list.extend_from_slice(&[indices[0], indices[i-1], indices[i]]);
+      }
+    },
+    _ => {
+      list.extend_from_slice(&indices[0..indices.len()/3*3]);
+    }
+  }
+  
+  list
+}
+
+fn float_bits(value: f32) -> u32 {
+  // 0.0 and -0.0 should weld together
+  if value == 0.0 {
+    0
+  } else {
+    value.to_bits()
+  }
+}
+
+fn position_key(position: [f32; 3]) -> [u32; 3] {
+  [float_bits(position[0]), float_bits(position[1]), float_bits(position[2])]
+}
+
+/**
+** Area weighted face normals, shared between all vertices in the same position
+** so that uv seams don't show up as hard edges.
+**/
+fn generate_smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+  let mut groups: HashMap<[u32; 3], usize> = HashMap::new();
+  let mut vertex_group = Vec::with_capacity(positions.len());
+  for position in positions {
+    let next_group = groups.len();
+    vertex_group.push(*groups.entry(position_key(*position)).or_insert(next_group));
+  }
+  
+  let mut group_normals = vec!(Vector3::new(0.0, 0.0, 0.0); groups.len());
+  for triangle in indices.chunks(3) {
+    let a = Vector3::from(positions[triangle[0] as usize]);
+    let b = Vector3::from(positions[triangle[1] as usize]);
+    let c = Vector3::from(positions[triangle[2] as usize]);
+    
+    let face_normal = (b-a).cross(c-a);
+    for vertex in triangle {
+      group_normals[vertex_group[*vertex as usize]] += face_normal;
+    }
+  }
+  
+  vertex_group.iter().map(|group| {
+    let normal = group_normals[*group];
+    if normal.magnitude2() > 0.0 {
+      normal.normalize().into()
+    } else {
+      [0.0, 1.0, 0.0]
+    }
+  }).collect()
+}
+
+struct TangentGeometry<'a> {
+  positions: &'a [[f32; 3]],
+  normals: &'a [[f32; 3]],
+  uvs: &'a [[f32; 2]],
+  indices: &'a [u32],
+  tangents: Vec<[f32; 4]>,
+}
+
+impl<'a> TangentGeometry<'a> {
+  fn vertex(&self, face: usize, vert: usize) -> usize {
+    self.indices[face*3 + vert] as usize
+  }
+}
+
+impl<'a> mikktspace::Geometry for TangentGeometry<'a> {
+  fn num_faces(&self) -> usize {
+    self.indices.len() / 3
+  }
+  
+  fn num_vertices_of_face(&self, _face: usize) -> usize {
+    3
+  }
+  
+  fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+    self.positions[self.vertex(face, vert)]
+  }
+  
+  fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+    self.normals[self.vertex(face, vert)]
+  }
+  
+  fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+    self.uvs[self.vertex(face, vert)]
+  }
+  
+  fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+    self.tangents[face*3 + vert] = tangent;
+  }
+}
+
+/**
+** MikkTSpace works per triangle corner, so the mesh is unwelded here and welded
+** back together afterwards, only splitting vertices where the tangents differ.
+**/
+fn generate_tangents(model: &mut FinalModel) {
+  // tangents follow the uv set the normal map is sampled with
+  let uv_set = model.material.textures.iter().find(|t| t.name() == "normal").map(|t| t.tex_coord()).unwrap_or(0);
+  
+  let corner_tangents = {
+    let uvs = if uv_set == 1 { &model.texcoords_1.texcoord } else { &model.texcoords.texcoord };
+    if uvs.is_empty() || model.indices.index.is_empty() {
+      return;
+    }
+    
+    let mut geometry = TangentGeometry {
+      positions: &model.vertices.vertex,
+      normals: &model.normals.normal,
+      uvs,
+      indices: &model.indices.index,
+      tangents: vec!([0.0, 0.0, 0.0, 1.0]; model.indices.index.len()),
+    };
+    
+    if !mikktspace::generate_tangents(&mut geometry) {
+      return;
+    }
+    
+    geometry.tangents
+  };
+  
+  let corners = model.indices.index.clone();
+  remap_vertices(model, &corners);
+  model.tangents.tangent = corner_tangents;
+  model.indices.index = (0..corners.len() as u32).collect();
+  model.has_tangents = true;
+}
+
+/**
+** Replaces every vertex attribute with the values at the given old vertex indices.
+**/
+fn remap_vertices(model: &mut FinalModel, old_vertices: &[u32]) {
+  fn gather<T: Copy>(values: &mut Vec<T>, old_vertices: &[u32]) {
+    if !values.is_empty() {
+      *values = old_vertices.iter().map(|v| values[*v as usize]).collect();
+    }
+  }
+  
+  gather(&mut model.vertices.vertex, old_vertices);
+  gather(&mut model.normals.normal, old_vertices);
+  gather(&mut model.tangents.tangent, old_vertices);
+  gather(&mut model.texcoords.texcoord, old_vertices);
+  gather(&mut model.texcoords_1.texcoord, old_vertices);
+  gather(&mut model.colours.colour, old_vertices);
+}
+
+fn weld_vertices(model: &mut FinalModel) {
+  let vertex_count = model.vertices.vertex.len();
+  
+  let mut unique: HashMap<[u32; 18], u32> = HashMap::with_capacity(vertex_count);
+  let mut kept_vertices = Vec::with_capacity(vertex_count);
+  let mut remap = Vec::with_capacity(vertex_count);
+  for i in 0..vertex_count {
+    let mut key = [0; 18];
+    let mut attributes = model.vertices.vertex[i].to_vec();
+    if let Some(normal) = model.normals.normal.get(i) {
+      attributes.extend_from_slice(normal);
+    }
+    if let Some(tangent) = model.tangents.tangent.get(i) {
+      attributes.extend_from_slice(tangent);
+    }
+    if let Some(uv) = model.texcoords.texcoord.get(i) {
+      attributes.extend_from_slice(uv);
+    }
+    if let Some(uv) = model.texcoords_1.texcoord.get(i) {
+      attributes.extend_from_slice(uv);
+    }
+    if let Some(colour) = model.colours.colour.get(i) {
+      attributes.extend_from_slice(colour);
+    }
+    for (k, value) in key.iter_mut().zip(attributes) {
+      *k = float_bits(value);
+    }
+    
+    let next_vertex = kept_vertices.len() as u32;
+    let new_index = *unique.entry(key).or_insert(next_vertex);
+    if new_index == next_vertex {
+      kept_vertices.push(i as u32);
+    }
+    remap.push(new_index);
+  }
+  
+  if kept_vertices.len() == vertex_count {
+    return;
+  }
+  
+  remap_vertices(model, &kept_vertices);
+  for index in &mut model.indices.index {
+    *index = remap[*index as usize];
+  }
+}
+
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: u32) -> f32 {
+  if remaining_triangles == 0 {
+    return -1.0;
+  }
+  
+  let mut score = 0.0;
+  if let Some(position) = cache_position {
+    if position < 3 {
+      score = LAST_TRIANGLE_SCORE;
+    } else {
+      let scaler = 1.0 / (CACHE_SIZE - 3) as f32;
+      score = (1.0 - (position - 3) as f32 * scaler).powf(CACHE_DECAY_POWER);
+    }
+  }
+  
+  score + VALENCE_BOOST_SCALE * (remaining_triangles as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+/**
+** Tom Forsyth's linear-speed vertex cache optimisation.
+**/
+fn optimise_triangle_order(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+  let triangle_count = indices.len() / 3;
+  
+  let mut remaining = vec!(0u32; vertex_count);
+  for index in indices {
+    remaining[*index as usize] += 1;
+  }
+  
+  // triangles using each vertex, packed into one array
+  let mut offsets = vec!(0usize; vertex_count + 1);
+  for v in 0..vertex_count {
+    offsets[v+1] = offsets[v] + remaining[v] as usize;
+  }
+  let mut vertex_triangles = vec!(0usize; indices.len());
+  let mut filled = offsets.clone();
+  for (i, index) in indices.iter().enumerate() {
+    vertex_triangles[filled[*index as usize]] = i / 3;
+    filled[*index as usize] += 1;
+  }
+  
+  let mut cache_position: Vec<Option<usize>> = vec!(None; vertex_count);
+  let mut vertex_scores: Vec<f32> = remaining.iter().map(|r| vertex_score(None, *r)).collect();
+  let mut triangle_added = vec!(false; triangle_count);
+  let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+  
+  let mut ordered = Vec::with_capacity(indices.len());
+  let mut best_triangle = None;
+  let mut next_unadded = 0;
+  
+  for _ in 0..triangle_count {
+    let triangle = match best_triangle {
+      Some(t) => t,
+      None => {
+        while triangle_added[next_unadded] {
+          next_unadded += 1;
+        }
+        next_unadded
+      }
+    };
+    
+    triangle_added[triangle] = true;
+    let corners = [indices[triangle*3], indices[triangle*3+1], indices[triangle*3+2]];
+    ordered.extend_from_slice(&corners);
+    
+    for vertex in &corners {
+      let v = *vertex as usize;
+      let start = offsets[v];
+      let end = start + remaining[v] as usize;
+      if let Some(position) = vertex_triangles[start..end].iter().position(|t| *t == triangle) {
+        vertex_triangles.swap(start + position, end - 1);
+        remaining[v] -= 1;
+      }
+    }
+    
+    let mut new_cache = corners.to_vec();
+    for vertex in &cache {
+      if !corners.contains(vertex) {
+        new_cache.push(*vertex);
+      }
+    }
+    
+    for (i, vertex) in new_cache.iter().enumerate() {
+      let v = *vertex as usize;
+      cache_position[v] = if i < CACHE_SIZE { Some(i) } else { None };
+      vertex_scores[v] = vertex_score(cache_position[v], remaining[v]);
+    }
+    
+    best_triangle = None;
+    let mut best_score = -1.0;
+    for vertex in &new_cache {
+      let v = *vertex as usize;
+      for t in &vertex_triangles[offsets[v]..offsets[v] + remaining[v] as usize] {
+        let score = vertex_scores[indices[t*3] as usize] +
+                    vertex_scores[indices[t*3+1] as usize] +
+                    vertex_scores[indices[t*3+2] as usize];
+        if score > best_score {
+          best_score = score;
+          best_triangle = Some(*t);
+        }
+      }
+    }
+    
+    new_cache.truncate(CACHE_SIZE);
+    cache = new_cache;
+  }
+  
+  ordered
+}
+
+/**
+** Orders the vertex buffer by first use in the index buffer.
+**/
+fn optimise_vertex_fetch(model: &mut FinalModel) {
+  let mut remap = vec!(u32::MAX; model.vertices.vertex.len());
+  let mut old_vertices = Vec::with_capacity(model.vertices.vertex.len());
+  for index in &mut model.indices.index {
+    let old = *index as usize;
+    if remap[old] == u32::MAX {
+      remap[old] = old_vertices.len() as u32;
+      old_vertices.push(old as u32);
+    }
+    *index = remap[old];
+  }
+  
+  remap_vertices(model, &old_vertices);
+}
+
+/**
+** Simplified index lists for the model, each roughly half the triangles of the
+** last. They all index the full resolution vertices.
+**/
+fn generate_lods(model: &mut FinalModel, options: ProcessingOptions) {
+  model.lods.clear();
+  if options.generated_lods == 0 || model.indices.index.len() < LOD_MIN_TRIANGLES*3 {
+    return;
+  }
+  
+  let vertex_count = model.vertices.vertex.len();
+  let mut simplifier = Simplifier::new(&model.vertices.vertex, &model.indices.index);
+  let mut target = simplifier.alive_triangles;
+  for _ in 0..options.generated_lods {
+    target = (target as f32 * LOD_REDUCTION) as usize;
+    let before = simplifier.alive_triangles;
+    simplifier.collapse_until(target);
+    
+    // not worth another buffer when the mesh can't be reduced any further
+    if simplifier.alive_triangles as f32 > before as f32 * 0.8 {
+      break;
+    }
+    
+    let mut indices = simplifier.indices();
+    if options.optimise_vertex_cache {
+      indices = optimise_triangle_order(&indices, vertex_count);
+    }
+    model.lods.push(IndexArray { index: indices });
+  }
+}
+
+/**
+** Symmetric 4x4 error quadric, a2 ab ac ad b2 bc bd c2 cd d2.
+**/
+#[derive(Clone, Copy)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+  fn from_plane(a: f64, b: f64, c: f64, d: f64, weight: f64) -> Quadric {
+    Quadric([a*a*weight, a*b*weight, a*c*weight, a*d*weight,
+             b*b*weight, b*c*weight, b*d*weight,
+             c*c*weight, c*d*weight,
+             d*d*weight])
+  }
+  
+  fn add(&self, other: &Quadric) -> Quadric {
+    let mut sum = self.0;
+    for (s, o) in sum.iter_mut().zip(other.0.iter()) {
+      *s += *o;
+    }
+    Quadric(sum)
+  }
+  
+  fn error(&self, p: [f32; 3]) -> f64 {
+    let q = &self.0;
+    let (x, y, z) = (p[0] as f64, p[1] as f64, p[2] as f64);
+    
+    q[0]*x*x + 2.0*q[1]*x*y + 2.0*q[2]*x*z + 2.0*q[3]*x +
+    q[4]*y*y + 2.0*q[5]*y*z + 2.0*q[6]*y +
+    q[7]*z*z + 2.0*q[8]*z +
+    q[9]
+  }
+}
+
+struct Collapse {
+  cost: f64,
+  from: usize,
+  to: usize,
+  from_version: u32,
+  to_version: u32,
+}
+
+impl PartialEq for Collapse {
+  fn eq(&self, other: &Collapse) -> bool {
+    self.cost == other.cost
+  }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+  fn partial_cmp(&self, other: &Collapse) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Collapse {
+  // cheapest collapse first out of the max heap
+  fn cmp(&self, other: &Collapse) -> Ordering {
+    other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+  }
+}
+
+/**
+** Quadric error half edge collapse. Vertices sharing a position are collapsed
+** together, so only positions move onto existing ones and the vertex buffer can
+** be shared between lods. Borders and uv seams are locked in place.
+**/
+struct Simplifier<'a> {
+  positions: &'a [[f32; 3]],
+  triangles: Vec<[u32; 3]>,
+  alive: Vec<bool>,
+  alive_triangles: usize,
+  
+  vertex_group: Vec<usize>,
+  group_triangles: Vec<Vec<usize>>,
+  quadrics: Vec<Quadric>,
+  locked: Vec<bool>,
+  collapsed: Vec<bool>,
+  version: Vec<u32>,
+  
+  heap: BinaryHeap<Collapse>,
+}
+
+impl<'a> Simplifier<'a> {
+  fn new(positions: &'a [[f32; 3]], indices: &[u32]) -> Simplifier<'a> {
+    let mut groups: HashMap<[u32; 3], usize> = HashMap::new();
+    let vertex_group: Vec<usize> = positions.iter().map(|p| {
+      let next_group = groups.len();
+      *groups.entry(position_key(*p)).or_insert(next_group)
+    }).collect();
+    let group_count = groups.len();
+    
+    let triangles: Vec<[u32; 3]> = indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+    let mut alive = vec!(true; triangles.len());
+    let mut group_triangles = vec!(Vec::new(); group_count);
+    let mut quadrics = vec!(Quadric([0.0; 10]); group_count);
+    let mut edges: HashMap<(usize, usize), u32> = HashMap::new();
+    let mut group_vertex: Vec<Option<u32>> = vec!(None; group_count);
+    let mut locked = vec!(false; group_count);
+    
+    for (t, triangle) in triangles.iter().enumerate() {
+      let g = [vertex_group[triangle[0] as usize], vertex_group[triangle[1] as usize], vertex_group[triangle[2] as usize]];
+      if g[0] == g[1] || g[1] == g[2] || g[0] == g[2] {
+        alive[t] = false;
+        continue;
+      }
+      
+      let p0 = Vector3::from(positions[triangle[0] as usize]).cast::<f64>().unwrap();
+      let p1 = Vector3::from(positions[triangle[1] as usize]).cast::<f64>().unwrap();
+      let p2 = Vector3::from(positions[triangle[2] as usize]).cast::<f64>().unwrap();
+      let cross = (p1-p0).cross(p2-p0);
+      let area = cross.magnitude();
+      if area > 0.0 {
+        let n = cross / area;
+        let plane = Quadric::from_plane(n.x, n.y, n.z, -n.dot(p0), area*0.5);
+        for group in &g {
+          quadrics[*group] = quadrics[*group].add(&plane);
+        }
+      }
+      
+      for i in 0..3 {
+        group_triangles[g[i]].push(t);
+        
+        // a position used by more than one vertex is on an attribute seam
+        match group_vertex[g[i]] {
+          Some(v) if v != triangle[i] => locked[g[i]] = true,
+          _ => group_vertex[g[i]] = Some(triangle[i]),
+        }
+        
+        let edge = (g[i].min(g[(i+1)%3]), g[i].max(g[(i+1)%3]));
+        *edges.entry(edge).or_insert(0) += 1;
+      }
+    }
+    
+    // border and non manifold edges
+    for ((a, b), count) in &edges {
+      if *count != 2 {
+        locked[*a] = true;
+        locked[*b] = true;
+      }
+    }
+    
+    let alive_triangles = alive.iter().filter(|a| **a).count();
+    let mut simplifier = Simplifier {
+      positions,
+      triangles,
+      alive,
+      alive_triangles,
+      vertex_group,
+      group_triangles,
+      quadrics,
+      locked,
+      collapsed: vec!(false; group_count),
+      version: vec!(0; group_count),
+      heap: BinaryHeap::new(),
+    };
+    
+    for (a, b) in edges.keys() {
+      simplifier.push_collapse(*a, *b);
+      simplifier.push_collapse(*b, *a);
+    }
+    
+    simplifier
+  }
+  
+  fn group_position(&self, group: usize) -> Option<[f32; 3]> {
+    self.group_triangles[group].iter().filter(|t| self.alive[**t]).flat_map(|t| self.triangles[*t].iter())
+                                      .find(|v| self.vertex_group[**v as usize] == group)
+                                      .map(|v| self.positions[*v as usize])
+  }
+  
+  fn push_collapse(&mut self, from: usize, to: usize) {
+    if self.locked[from] {
+      return;
+    }
+    
+    if let Some(position) = self.group_position(to) {
+      let cost = self.quadrics[from].add(&self.quadrics[to]).error(position);
+      self.heap.push(Collapse {
+        cost,
+        from,
+        to,
+        from_version: self.version[from],
+        to_version: self.version[to],
+      });
+    }
+  }
+  
+  fn collapse_until(&mut self, target_triangles: usize) {
+    while self.alive_triangles > target_triangles {
+      let collapse = match self.heap.pop() {
+        Some(c) => c,
+        None => break,
+      };
+      
+      if self.collapsed[collapse.from] || self.collapsed[collapse.to] ||
+         self.version[collapse.from] != collapse.from_version || self.version[collapse.to] != collapse.to_version {
+        continue;
+      }
+      
+      self.try_collapse(collapse.from, collapse.to);
+    }
+  }
+  
+  fn try_collapse(&mut self, from: usize, to: usize) -> bool {
+    let from_triangles: Vec<usize> = self.group_triangles[from].iter().cloned().filter(|t| self.alive[*t]).collect();
+    
+    // the vertex every triangle moving onto the edge should share
+    let mut target_vertex = None;
+    for t in &from_triangles {
+      for v in &self.triangles[*t] {
+        if self.vertex_group[*v as usize] == to {
+          if target_vertex.is_some() && target_vertex != Some(*v) {
+            return false;
+          }
+          target_vertex = Some(*v);
+        }
+      }
+    }
+    let target_vertex = match target_vertex {
+      Some(v) => v,
+      None => return false,
+    };
+    
+    // triangles that stay must not flip or collapse to a sliver
+    let target_position = Vector3::from(self.positions[target_vertex as usize]);
+    for t in &from_triangles {
+      let triangle = self.triangles[*t];
+      if triangle.iter().any(|v| self.vertex_group[*v as usize] == to) {
+        continue;
+      }
+      
+      let p: Vec<Vector3<f32>> = triangle.iter().map(|v| Vector3::from(self.positions[*v as usize])).collect();
+      let moved: Vec<Vector3<f32>> = triangle.iter().zip(p.iter()).map(|(v, p)| {
+        if self.vertex_group[*v as usize] == from { target_position } else { *p }
+      }).collect();
+      
+      let old_normal = (p[1]-p[0]).cross(p[2]-p[0]);
+      let new_normal = (moved[1]-moved[0]).cross(moved[2]-moved[0]);
+      if new_normal.magnitude2() <= old_normal.magnitude2()*1e-6 || old_normal.dot(new_normal) <= 0.0 {
+        return false;
+      }
+    }
+    
+    for t in from_triangles {
+      if self.triangles[t].iter().any(|v| self.vertex_group[*v as usize] == to) {
+        self.alive[t] = false;
+        self.alive_triangles -= 1;
+      } else {
+        for v in self.triangles[t].iter_mut() {
+          if self.vertex_group[*v as usize] == from {
+            *v = target_vertex;
+          }
+        }
+        self.group_triangles[to].push(t);
+      }
+    }
+    
+    self.collapsed[from] = true;
+    self.quadrics[to] = self.quadrics[to].add(&self.quadrics[from]);
+    self.version[to] += 1;
+    
+    let alive = &self.alive;
+    self.group_triangles[to].retain(|t| alive[*t]);
+    
+    let mut neighbours: Vec<usize> = self.group_triangles[to].iter().flat_map(|t| self.triangles[*t].iter())
+                                         .map(|v| self.vertex_group[*v as usize])
+                                         .filter(|g| *g != to).collect();
+    neighbours.sort_unstable();
+    neighbours.dedup();
+    for neighbour in neighbours {
+      self.push_collapse(neighbour, to);
+      self.push_collapse(to, neighbour);
+    }
+    
+    true
+  }
+  
+  fn indices(&self) -> Vec<u32> {
+    self.triangles.iter().zip(self.alive.iter()).filter(|(_, alive)| **alive).flat_map(|(t, _)| t.iter().cloned()).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::gltf_interpreter::{VertexArray, NormalArray, TangentArray, TexCoordArray, ColourArray, Material};
+  
+  fn model(vertices: Vec<[f32; 3]>, texcoords: Vec<[f32; 2]>, indices: Option<Vec<u32>>) -> FinalModel {
+    let bounding_box = FinalModel::vertex_bounds(&vertices);
+    FinalModel {
+      vertices: VertexArray { morph_index: 0, vertex: vertices },
+      has_indices: indices.is_some(),
+      indices: IndexArray { index: indices.unwrap_or_default() },
+      normals: NormalArray { normal: Vec::new() },
+      tangents: TangentArray { tangent: Vec::new() },
+      texcoords: TexCoordArray { texcoord: texcoords },
+      texcoords_1: TexCoordArray { texcoord: Vec::new() },
+      colours: ColourArray { colour: Vec::new() },
+      material: Material::new(),
+      topology: Topology::TriangleList,
+      has_normals: false,
+      has_tangents: false,
+      lods: Vec::new(),
+      lod_level: None,
+      bounding_box,
+    }
+  }
+  
+  // flat grid of size*size quads on the xz plane
+  fn grid(size: u32) -> FinalModel {
+    let mut vertices = Vec::new();
+    let mut texcoords = Vec::new();
+    for z in 0..=size {
+      for x in 0..=size {
+        vertices.push([x as f32, 0.0, z as f32]);
+        texcoords.push([x as f32 / size as f32, z as f32 / size as f32]);
+      }
+    }
+    
+    let mut indices = Vec::new();
+    for z in 0..size {
+      for x in 0..size {
+        let i = z*(size+1) + x;
+        indices.extend_from_slice(&[i, i+size+1, i+1, i+1, i+size+1, i+size+2]);
+      }
+    }
+    
+    model(vertices, texcoords, Some(indices))
+  }
+  
+  // triangles rotated to start at their smallest index so winding is kept, then sorted
+  fn triangle_set(indices: &[u32]) -> Vec<[u32; 3]> {
+    let mut triangles: Vec<[u32; 3]> = indices.chunks(3).map(|t| {
+      let first = (0..3).min_by_key(|i| t[*i]).unwrap();
+      [t[first], t[(first+1)%3], t[(first+2)%3]]
+    }).collect();
+    triangles.sort();
+    triangles
+  }
+  
+  #[test]
+  fn welded_quad_keeps_four_vertices() {
+    let corners = [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0]];
+    let uvs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+    let order = [0, 1, 2, 2, 1, 3];
+    let mut quad = model(order.iter().map(|i| corners[*i]).collect(),
+                         order.iter().map(|i| uvs[*i]).collect(), None);
+    
+    process_model(&mut quad, ProcessingOptions { optimise_vertex_cache: false, generated_lods: 0 });
+    
+    assert_eq!(quad.vertices.vertex.len(), 4);
+    assert_eq!(quad.normals.normal.len(), 4);
+    assert_eq!(quad.tangents.tangent.len(), 4);
+    assert_eq!(quad.indices.index.len(), 6);
+    assert!(quad.indices.index.iter().all(|i| (*i as usize) < 4));
+  }
+  
+  #[test]
+  fn vertex_cache_reordering_keeps_triangles() {
+    let mesh = grid(8);
+    let reordered = optimise_triangle_order(&mesh.indices.index, mesh.vertices.vertex.len());
+    
+    assert_eq!(triangle_set(&reordered), triangle_set(&mesh.indices.index));
+  }
+  
+  #[test]
+  fn simplification_never_adds_indices() {
+    let mut mesh = grid(16);
+    let full_detail = mesh.indices.index.len();
+    
+    process_model(&mut mesh, ProcessingOptions { optimise_vertex_cache: true, generated_lods: 4 });
+    
+    assert_eq!(mesh.indices.index.len(), full_detail);
+    assert!(!mesh.lods.is_empty());
+    let mut previous = full_detail;
+    for lod in &mesh.lods {
+      assert!(lod.index.len() <= previous);
+      assert_eq!(lod.index.len() % 3, 0);
+      assert!(lod.index.iter().all(|i| (*i as usize) < mesh.vertices.vertex.len()));
+      previous = lod.index.len();
+    }
+  }
+}
@@ -1,5 +1,7 @@
 
 use crate::cgmath::{Zero, Vector3, Vector4};
+use crate::camera::PerspectiveCamera;
+use crate::gltf_interpreter::ModelLight;
 
 use csv;
 
@@ -42,6 +44,8 @@ pub struct ModelData {
   location: String,
   size: Vector3<f32>,
   collision_info: Vec<CollisionInfo>,
+  cameras: Vec<PerspectiveCamera>,
+  lights: Vec<ModelLight>,
 }
 
 impl CollisionInfo {
@@ -102,6 +106,8 @@ impl ModelData {
       location,
       size: Vector3::zero(),
       collision_info: Vec::new(),
+      cameras: Vec::new(),
+      lights: Vec::new(),
     }
   }
   
@@ -116,6 +122,8 @@ impl ModelData {
       location: "".to_string(),
       size,
       collision_info,
+      cameras: Vec::new(),
+      lights: Vec::new(),
     }
   }
   
@@ -139,6 +147,30 @@ impl ModelData {
     self.size = size;
   }
   
+  /**
+  ** Cameras authored in the model file. Only their placement is imported,
+  ** the field of view, clip planes and aspect ratio of the file are ignored
+  ** and the cameras have the default move speed and mouse sensitivity.
+  **/
+  pub fn cameras(&self) -> &Vec<PerspectiveCamera> {
+    &self.cameras
+  }
+  
+  pub fn set_cameras(&mut self, cameras: Vec<PerspectiveCamera>) {
+    self.cameras = cameras;
+  }
+  
+  /**
+  ** Point, spot and directional lights authored in the model file.
+  **/
+  pub fn lights(&self) -> &Vec<ModelLight> {
+    &self.lights
+  }
+  
+  pub fn set_lights(&mut self, lights: Vec<ModelLight>) {
+    self.lights = lights;
+  }
+  
   pub fn get_terrain_data(&self) -> (String, Vec<Vec<f32>>) {
     let mut terrain_data = Vec::new();
     
@@ -0,0 +1,548 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+
+use crate::vulkan::vkenums::{AddressMode, Filter};
+use crate::gltf_interpreter::{ModelDetails, FinalModel, Material, Texture, SamplerInfo, AlphaMode, Topology,
+                              VertexArray, IndexArray, NormalArray, TangentArray, TexCoordArray, ColourArray};
+use crate::mesh_processing;
+use crate::mesh_processing::ProcessingOptions;
+
+use cgmath::Vector2;
+use cgmath::Vector3;
+use cgmath::Vector4;
+
+use image::GenericImageView;
+
+#[derive(Clone)]
+struct ObjTexture {
+  path: PathBuf,
+  offset: Vector2<f32>,
+  scale: Vector2<f32>,
+  clamp: bool,
+  bump_multiplier: f32,
+}
+
+#[derive(Clone)]
+struct ObjMaterial {
+  name: String,
+  diffuse: Option<[f32; 3]>,
+  dissolve: Option<f32>,
+  transparency: Option<f32>,
+  emissive: Option<[f32; 3]>,
+  shininess: Option<f32>,
+  roughness: Option<f32>,
+  metallic: Option<f32>,
+  illumination: u32,
+  diffuse_map: Option<ObjTexture>,
+  dissolve_map: Option<ObjTexture>,
+  emissive_map: Option<ObjTexture>,
+  normal_map: Option<ObjTexture>,
+  roughness_map: Option<ObjTexture>,
+  metallic_map: Option<ObjTexture>,
+}
+
+// one primitive per material, faces are unindexed until mesh processing welds them
+struct ObjPrimitive {
+  material: String,
+  vertices: Vec<[f32; 3]>,
+  normals: Vec<[f32; 3]>,
+  texcoords: Vec<[f32; 2]>,
+  colours: Vec<[f32; 4]>,
+  missing_normals: bool,
+  has_texcoords: bool,
+}
+
+impl ObjMaterial {
+  fn new(name: String) -> ObjMaterial {
+    ObjMaterial {
+      name,
+      diffuse: None,
+      dissolve: None,
+      transparency: None,
+      emissive: None,
+      shininess: None,
+      roughness: None,
+      metallic: None,
+      illumination: 2,
+      diffuse_map: None,
+      dissolve_map: None,
+      emissive_map: None,
+      normal_map: None,
+      roughness_map: None,
+      metallic_map: None,
+    }
+  }
+}
+
+impl ModelDetails {
+  /**
+  ** Loads a Wavefront OBJ and the MTL libraries it references. Faces are grouped by
+  ** material into FinalModels and MTL materials are mapped onto the PBR Material.
+  **/
+  pub fn from_obj(source: String, options: ProcessingOptions) -> ModelDetails {
+    let bytes = match fs::read(&source) {
+      Ok(bytes) => bytes,
+      Err(e) => {
+        // an empty model so a bad path doesn't take the loader thread down with it
+        println!("Error: Failed to read obj {}: {}", source, e);
+        return ModelDetails {
+          models: Vec::new(),
+          size: Vector3::new(0.0, 0.0, 0.0),
+          cameras: Vec::new(),
+          lights: Vec::new(),
+        };
+      }
+    };
+    let text = String::from_utf8_lossy(&bytes).replace("\\\r\n", " ").replace("\\\n", " ");
+    let base_path = Path::new(&source).parent().unwrap_or_else(|| Path::new("./")).to_path_buf();
+    
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut vertex_colours: Vec<[f32; 4]> = Vec::new();
+    let mut texcoords: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    
+    let mut materials: HashMap<String, ObjMaterial> = HashMap::new();
+    let mut primitives: Vec<ObjPrimitive> = Vec::new();
+    let mut current_primitive: Option<usize> = None;
+    let mut current_material = "".to_string();
+    
+    for line in text.lines() {
+      let line = line.split('#').next().unwrap_or("").trim();
+      let mut tokens = line.split_whitespace();
+      let keyword = match tokens.next() {
+        Some(k) => k,
+        None => continue,
+      };
+      let values: Vec<&str> = tokens.collect();
+      
+      match keyword {
+        "v" => {
+          let v = parse_floats(&values);
+          positions.push([v[0], v[1], v[2]]);
+          if values.len() >= 6 {
+            vertex_colours.push([v[3], v[4], v[5], 1.0]);
+          } else {
+            vertex_colours.push([1.0, 1.0, 1.0, 1.0]);
+          }
+        },
+        "vt" => {
+          let v = parse_floats(&values);
+          // obj uvs start from the bottom of the image
+          texcoords.push([v[0], 1.0 - v[1]]);
+        },
+        "vn" => {
+          let v = parse_floats(&values);
+          normals.push([v[0], v[1], v[2]]);
+        },
+        "mtllib" => {
+          let whole = base_path.join(values.join(" "));
+          let libraries = if whole.is_file() {
+            vec!(whole)
+          } else {
+            values.iter().map(|v| base_path.join(v)).collect()
+          };
+          
+          for library in libraries {
+            for material in load_mtl(&library) {
+              materials.insert(material.name.clone(), material);
+            }
+          }
+        },
+        "usemtl" => {
+          current_material = values.join(" ");
+          current_primitive = None;
+        },
+        "f" => {
+          let primitive = match current_primitive {
+            Some(p) => p,
+            None => {
+              let existing = primitives.iter().position(|p| p.material == current_material);
+              let p = existing.unwrap_or_else(|| {
+                primitives.push(ObjPrimitive {
+                  material: current_material.clone(),
+                  vertices: Vec::new(),
+                  normals: Vec::new(),
+                  texcoords: Vec::new(),
+                  colours: Vec::new(),
+                  missing_normals: false,
+                  has_texcoords: false,
+                });
+                primitives.len()-1
+              });
+              current_primitive = Some(p);
+              p
+            }
+          };
+          
+          let corners: Vec<(usize, Option<usize>, Option<usize>)> = values.iter().filter_map(|corner| {
+            let mut indices = corner.split('/');
+            let v = resolve_index(indices.next(), positions.len())?;
+            let t = resolve_index(indices.next(), texcoords.len());
+            let n = resolve_index(indices.next(), normals.len());
+            Some((v, t, n))
+          }).collect();
+          
+          // polygons are triangulated as a fan
+          let primitive = &mut primitives[primitive];
+          for i in 2..corners.len() {
+            for (v, t, n) in &[corners[0], corners[i-1], corners[i]] {
+              primitive.vertices.push(positions[*v]);
+              primitive.colours.push(vertex_colours[*v]);
+              match t {
+                Some(t) => {
+                  primitive.texcoords.push(texcoords[*t]);
+                  primitive.has_texcoords = true;
+                },
+                None => primitive.texcoords.push([0.0, 0.0]),
+              }
+              match n {
+                Some(n) => primitive.normals.push(normals[*n]),
+                None => {
+                  primitive.normals.push([0.0, 0.0, 0.0]);
+                  primitive.missing_normals = true;
+                }
+              }
+            }
+          }
+        },
+        _ => {
+          // groups, objects, smoothing groups, lines and points are ignored
+        }
+      }
+    }
+    
+    let has_vertex_colours = vertex_colours.iter().any(|c| *c != [1.0, 1.0, 1.0, 1.0]);
+    
+    let mut min_xyz = Vector3::new(0.0, 0.0, 0.0);
+    let mut max_xyz = Vector3::new(0.0, 0.0, 0.0);
+    for (i, position) in positions.iter().enumerate() {
+      if i == 0 {
+        min_xyz = Vector3::new(position[0], position[1], position[2]);
+        max_xyz = min_xyz;
+      }
+      min_xyz = Vector3::new(min_xyz.x.min(position[0]), min_xyz.y.min(position[1]), min_xyz.z.min(position[2]));
+      max_xyz = Vector3::new(max_xyz.x.max(position[0]), max_xyz.y.max(position[1]), max_xyz.z.max(position[2]));
+    }
+    
+    let mut images: HashMap<PathBuf, Option<image::DynamicImage>> = HashMap::new();
+    let mut models = Vec::with_capacity(primitives.len());
+    for primitive in primitives {
+      let obj_material = materials.get(&primitive.material).cloned().unwrap_or_else(|| ObjMaterial::new(primitive.material.clone()));
+      
+      let bounding_box = FinalModel::vertex_bounds(&primitive.vertices);
+      let mut model = FinalModel {
+        vertices: VertexArray { vertex: primitive.vertices, morph_index: 0 },
+        indices: IndexArray { index: Vec::new() },
+        normals: NormalArray { normal: if primitive.missing_normals { Vec::new() } else { primitive.normals } },
+        tangents: TangentArray { tangent: Vec::new() },
+        texcoords: TexCoordArray { texcoord: if primitive.has_texcoords { primitive.texcoords } else { Vec::new() } },
+        texcoords_1: TexCoordArray { texcoord: Vec::new() },
+        colours: ColourArray { colour: if has_vertex_colours { primitive.colours } else { Vec::new() } },
+        material: to_material(&obj_material, &mut images),
+        topology: Topology::TriangleList,
+        has_indices: false,
+        has_normals: !primitive.missing_normals,
+        has_tangents: false,
+        lods: Vec::new(),
+        lod_level: None,
+        bounding_box,
+      };
+      
+      mesh_processing::process_model(&mut model, options);
+      models.push(model);
+    }
+    
+    ModelDetails {
+      models,
+      size: max_xyz - min_xyz,
+      cameras: Vec::new(),
+      lights: Vec::new(),
+    }
+  }
+}
+
+fn parse_floats(values: &[&str]) -> Vec<f32> {
+  let mut floats: Vec<f32> = values.iter().map(|v| v.parse::<f32>().unwrap_or(0.0)).collect();
+  while floats.len() < 3 {
+    floats.push(0.0);
+  }
+  
+  floats
+}
+
+// obj indices start at 1, negative indices count back from the latest element
+fn resolve_index(index: Option<&str>, count: usize) -> Option<usize> {
+  let index = index?.parse::<i64>().ok()?;
+  let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+  
+  if resolved >= 0 && (resolved as usize) < count {
+    Some(resolved as usize)
+  } else {
+    None
+  }
+}
+
+fn load_mtl(location: &Path) -> Vec<ObjMaterial> {
+  let bytes = match fs::read(location) {
+    Ok(b) => b,
+    Err(e) => {
+      println!("Error: Failed to read mtl {:?}: {}", location, e);
+      return Vec::new();
+    }
+  };
+  let text = String::from_utf8_lossy(&bytes);
+  let base_path = location.parent().unwrap_or_else(|| Path::new("./"));
+  
+  let mut materials: Vec<ObjMaterial> = Vec::new();
+  for line in text.lines() {
+    let line = line.split('#').next().unwrap_or("").trim();
+    let mut tokens = line.split_whitespace();
+    let keyword = match tokens.next() {
+      Some(k) => k,
+      None => continue,
+    };
+    let values: Vec<&str> = tokens.collect();
+    
+    if keyword == "newmtl" {
+      materials.push(ObjMaterial::new(values.join(" ")));
+      continue;
+    }
+    
+    let material = match materials.last_mut() {
+      Some(m) => m,
+      None => continue,
+    };
+    
+    let first = values.first().and_then(|v| v.parse::<f32>().ok());
+    match keyword {
+      "Kd" => {
+        let v = parse_floats(&values);
+        material.diffuse = Some([v[0], v[1], v[2]]);
+      },
+      "Ke" => {
+        let v = parse_floats(&values);
+        material.emissive = Some([v[0], v[1], v[2]]);
+      },
+      "d" => material.dissolve = first,
+      "Tr" => material.transparency = first,
+      "Ns" => material.shininess = first,
+      "Pr" => material.roughness = first,
+      "Pm" => material.metallic = first,
+      "illum" => material.illumination = first.unwrap_or(2.0) as u32,
+      "map_Kd" => material.diffuse_map = parse_texture(&values, base_path),
+      "map_d" => material.dissolve_map = parse_texture(&values, base_path),
+      "map_Ke" => material.emissive_map = parse_texture(&values, base_path),
+      "norm" | "map_Bump" | "map_bump" | "bump" => material.normal_map = parse_texture(&values, base_path),
+      "map_Pr" => material.roughness_map = parse_texture(&values, base_path),
+      "map_Pm" => material.metallic_map = parse_texture(&values, base_path),
+      _ => {}
+    }
+  }
+  
+  materials
+}
+
+/**
+** Texture statements are options followed by a file name that may contain spaces.
+**/
+fn parse_texture(values: &[&str], base_path: &Path) -> Option<ObjTexture> {
+  let mut texture = ObjTexture {
+    path: PathBuf::new(),
+    offset: Vector2::new(0.0, 0.0),
+    scale: Vector2::new(1.0, 1.0),
+    clamp: false,
+    bump_multiplier: 1.0,
+  };
+  
+  let mut i = 0;
+  while i < values.len() && values[i].starts_with('-') {
+    let option = values[i];
+    i += 1;
+    
+    // options take up to three numbers
+    let mut numbers = Vec::new();
+    let max_numbers = match option {
+      "-o" | "-s" | "-t" => 3,
+      "-mm" => 2,
+      "-blendu" | "-blendv" | "-cc" | "-clamp" | "-imfchan" | "-type" => { i += 1; 0 },
+      _ => 1,
+    };
+    while numbers.len() < max_numbers && i < values.len() {
+      match values[i].parse::<f32>() {
+        Ok(n) => {
+          numbers.push(n);
+          i += 1;
+        },
+        Err(_) => break,
+      }
+    }
+    
+    match option {
+      "-o" => texture.offset = Vector2::new(*numbers.first().unwrap_or(&0.0), *numbers.get(1).unwrap_or(&0.0)),
+      "-s" => texture.scale = Vector2::new(*numbers.first().unwrap_or(&1.0), *numbers.get(1).unwrap_or(&1.0)),
+      "-bm" => texture.bump_multiplier = *numbers.first().unwrap_or(&1.0),
+      "-clamp" => texture.clamp = values[i-1] == "on",
+      _ => {}
+    }
+  }
+  
+  if i >= values.len() {
+    return None;
+  }
+  
+  texture.path = base_path.join(values[i..].join(" ").replace('\\', "/"));
+  Some(texture)
+}
+
+fn load_image(path: &Path, images: &mut HashMap<PathBuf, Option<image::DynamicImage>>) -> Option<image::DynamicImage> {
+  images.entry(path.to_path_buf()).or_insert_with(|| {
+    match image::open(path) {
+      Ok(img) => Some(img),
+      Err(e) => {
+        println!("Error: Failed to load obj texture {:?}: {}", path, e);
+        None
+      }
+    }
+  }).clone()
+}
+
+fn sampler_info(texture: &ObjTexture) -> SamplerInfo {
+  let wrap = if texture.clamp { AddressMode::ClampToEdge } else { AddressMode::Repeat };
+  
+  SamplerInfo {
+    mag_filter: Filter::Linear,
+    min_filter: Filter::Linear,
+    s_wrap: wrap.clone(),
+    t_wrap: wrap,
+  }
+}
+
+fn to_texture(name: &str, texture: &Option<ObjTexture>) -> Texture {
+  match texture {
+    Some(texture) => {
+      // the offset is in obj uv space, which is flipped vertically
+      let offset = Vector2::new(texture.offset.x, 1.0 - texture.scale.y - texture.offset.y);
+      Texture::new(name.to_string(), 0, offset, 0.0, texture.scale)
+    },
+    None => {
+      Texture::new(name.to_string(), 0, Vector2::new(0.0, 0.0), 0.0, Vector2::new(1.0, 1.0))
+    }
+  }
+}
+
+/**
+** Packs separate roughness and metallic maps into the green and blue channels of
+** a glTF style metallic roughness texture.
+**/
+fn metallic_roughness_image(roughness: Option<image::DynamicImage>, metallic: Option<image::DynamicImage>) -> Option<image::DynamicImage> {
+  let (width, height) = match (&roughness, &metallic) {
+    (Some(r), _) => r.dimensions(),
+    (None, Some(m)) => m.dimensions(),
+    (None, None) => return None,
+  };
+  
+  let roughness = roughness.map(|r| r.to_luma());
+  let metallic = metallic.map(|m| m.resize_exact(width, height, image::FilterType::Triangle).to_luma());
+  
+  let packed = image::RgbaImage::from_fn(width, height, |x, y| {
+    let r = roughness.as_ref().map(|r| r.get_pixel(x, y)[0]).unwrap_or(255);
+    let m = metallic.as_ref().map(|m| m.get_pixel(x, y)[0]).unwrap_or(255);
+    image::Rgba([255, r, m, 255])
+  });
+  
+  Some(image::DynamicImage::ImageRgba8(packed))
+}
+
+/**
+** Obj keeps alpha in its own map, the shaders read it from the base colour alpha.
+**/
+fn apply_dissolve(base_colour: Option<image::DynamicImage>, dissolve: &image::DynamicImage) -> image::DynamicImage {
+  let (width, height) = match &base_colour {
+    Some(img) => img.dimensions(),
+    None => dissolve.dimensions(),
+  };
+  
+  let alpha = dissolve.resize_exact(width, height, image::FilterType::Triangle).to_luma();
+  let mut rgba = match base_colour {
+    Some(img) => img.to_rgba(),
+    None => image::RgbaImage::from_pixel(width, height, image::Rgba([255, 255, 255, 255])),
+  };
+  
+  for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+    pixel[3] = alpha.get_pixel(x, y)[0];
+  }
+  
+  image::DynamicImage::ImageRgba8(rgba)
+}
+
+fn to_material(obj_material: &ObjMaterial, images: &mut HashMap<PathBuf, Option<image::DynamicImage>>) -> Material {
+  let mut material = Material::new();
+  material.name = obj_material.name.clone();
+  
+  let mut load_texture = |texture: &Option<ObjTexture>| {
+    texture.as_ref().and_then(|t| load_image(&t.path, images).map(|img| (img, sampler_info(t))))
+  };
+  
+  let base_colour_texture = load_texture(&obj_material.diffuse_map);
+  let dissolve_texture = load_texture(&obj_material.dissolve_map);
+  let emissive_texture = load_texture(&obj_material.emissive_map);
+  let normal_texture = load_texture(&obj_material.normal_map);
+  let roughness_texture = load_texture(&obj_material.roughness_map);
+  let metallic_texture = load_texture(&obj_material.metallic_map);
+  
+  let diffuse = obj_material.diffuse.unwrap_or([1.0, 1.0, 1.0]);
+  let alpha = obj_material.dissolve.or(obj_material.transparency.map(|t| 1.0 - t)).unwrap_or(1.0);
+  material.base_colour_factor = Vector4::new(diffuse[0], diffuse[1], diffuse[2], alpha);
+  material.alpha_mode = if alpha < 1.0 {
+    AlphaMode::Blend
+  } else if dissolve_texture.is_some() {
+    AlphaMode::Mask
+  } else {
+    AlphaMode::Opaque
+  };
+  material.base_colour_texture = match (base_colour_texture, dissolve_texture) {
+    (Some((img, sampler)), Some((dissolve, _))) => Some((Some(apply_dissolve(Some(img), &dissolve)), sampler)),
+    (None, Some((dissolve, sampler))) => Some((Some(apply_dissolve(None, &dissolve)), sampler)),
+    (Some((img, sampler)), None) => Some((Some(img), sampler)),
+    (None, None) => None,
+  };
+  
+  // Phong shininess to roughness, as used by most obj to PBR conversions
+  let shininess_roughness = obj_material.shininess.map(|ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt()).unwrap_or(1.0);
+  material.roughness_factor = if roughness_texture.is_some() {
+    obj_material.roughness.unwrap_or(1.0)
+  } else {
+    obj_material.roughness.unwrap_or(shininess_roughness)
+  };
+  material.metallic_factor = obj_material.metallic.unwrap_or(if metallic_texture.is_some() { 1.0 } else { 0.0 });
+  
+  let roughness_sampler = roughness_texture.as_ref().or(metallic_texture.as_ref()).map(|(_, sampler)| sampler.clone());
+  if let Some(sampler) = roughness_sampler {
+    let packed = metallic_roughness_image(roughness_texture.map(|(img, _)| img), metallic_texture.map(|(img, _)| img));
+    material.metallic_roughness_texture = Some((packed, sampler));
+  }
+  
+  if let Some((img, sampler)) = normal_texture {
+    material.normal_texture = Some((Some(img), sampler));
+    material.normal_texture_scale = obj_material.normal_map.as_ref().map(|t| t.bump_multiplier).unwrap_or(1.0);
+  }
+  
+  let emissive = obj_material.emissive.unwrap_or(if emissive_texture.is_some() { [1.0, 1.0, 1.0] } else { [0.0, 0.0, 0.0] });
+  material.emissive_factor = Vector3::new(emissive[0], emissive[1], emissive[2]);
+  material.emissive_texture = emissive_texture.map(|(img, sampler)| (Some(img), sampler));
+  
+  // illum 0 is a constant colour with no lighting
+  material.unlit = obj_material.illumination == 0;
+  
+  let metallic_roughness_map = if obj_material.roughness_map.is_some() { &obj_material.roughness_map } else { &obj_material.metallic_map };
+  material.textures = vec!(
+    to_texture("base_colour", if obj_material.diffuse_map.is_some() { &obj_material.diffuse_map } else { &obj_material.dissolve_map }),
+    to_texture("metallic_roughness", metallic_roughness_map),
+    to_texture("normal", &obj_material.normal_map),
+    to_texture("occlusion", &None),
+    to_texture("emissive", &obj_material.emissive_map),
+  );
+  
+  material
+}
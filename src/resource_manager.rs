@@ -10,6 +10,7 @@ use crate::vulkan::buffer::{Buffer};
 use crate::vulkan::pool::{CommandPool};
 
 use crate::gltf_interpreter::ModelDetails;
+use crate::mesh_processing::ProcessingOptions;
 use crate::font::GenericFont;
 
 use crate::{ModelData, CollisionInfo};
@@ -19,6 +20,7 @@ use crate::Logs;
 use cgmath::Vector3;
 
 use std::time;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::mpsc;
 use std::sync::Mutex;
@@ -106,6 +108,18 @@ impl LoadableObject {
   }
 }
 
+/**
+** Picks the importer from the file extension, anything that isn't obj is read as glTF.
+**/
+fn load_model_details(location: &str, options: ProcessingOptions) -> ModelDetails {
+  let extension = Path::new(location).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+  
+  match extension.as_deref() {
+    Some("obj") => ModelDetails::from_obj(location.to_string(), options),
+    _ => ModelDetails::new(location.to_string(), options),
+  }
+}
+
 pub struct ResourceManager {
   objects: Vec<LoadableObject>,
   pool: ThreadPool,
@@ -113,10 +127,11 @@ pub struct ResourceManager {
   tx: mpsc::Sender<usize>,
   rx: mpsc::Receiver<usize>,
   data: Vec<Arc<Mutex<Option<LoadableObject>>>>,
+  processing_options: ProcessingOptions,
 }
 
 impl ResourceManager {
-  pub fn new() -> ResourceManager {
+  pub fn new(processing_options: ProcessingOptions) -> ResourceManager {
     let (tx, rx) = mpsc::channel();
     
     ResourceManager {
@@ -126,6 +141,7 @@ impl ResourceManager {
       tx: tx,
       rx: rx,
       data: Vec::new(),
+      processing_options,
     }
   }
   
@@ -162,11 +178,15 @@ impl ResourceManager {
           //println!("Object recieved: {}", object.reference);
           
           let mut size = None;
+          let mut cameras = Vec::new();
+          let mut lights = Vec::new();
           let mut model_data = None;
           match &object.object_type {
             ObjectType::Model(Some(model), _, data) => {
               println!("reference: {} = {:?}", reference.to_string(), data);
               size = Some(model.get_size());
+              cameras = model.cameras();
+              lights = model.lights();
               model_data = data.clone();
             }
             _ => {}
@@ -187,6 +207,9 @@ impl ResourceManager {
                 m_data.set_size(size);
               }
               
+              m_data.set_cameras(cameras);
+              m_data.set_lights(lights);
+              
               m_data.load_collision_info();
             }
           }
@@ -277,6 +300,11 @@ impl ResourceManager {
     result
   }
   
+  // true for textures that have been added, whether or not they are loaded yet
+  pub fn has_texture(&self, reference: &str) -> bool {
+    self.objects.iter().any(|object| object.reference == reference && matches!(object.object_type, ObjectType::Texture(..)))
+  }
+  
   /**
   ** Returns None when resource isnt loaded yet otherwise returns a ModelDetails
   **/
@@ -606,7 +634,7 @@ ObjectType::Model(_, images, ..) => {
    // println!("loading model");
     
     let model_start_time = time::Instant::now();
-    let model = ModelDetails::new(location.to_string());
+    let model = load_model_details(&location, self.processing_options);
       
     let object = LoadableObject {
       loaded: true,
@@ -638,10 +666,11 @@ ObjectType::Model(_, images, ..) => {
     self.data.push(Arc::new(Mutex::new(None)));
     
     let (data, tx) = (self.data[index].clone(), self.tx.clone());
+    let processing_options = self.processing_options;
     self.pool.execute(move || {
       let mut data = data.lock().unwrap();
       let model_start_time = time::Instant::now();
-      let model = ModelDetails::new(location.to_string());
+      let model = load_model_details(&location, processing_options);
       
       let object = LoadableObject {
         loaded: true,
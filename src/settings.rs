@@ -12,12 +12,27 @@ const FALSE: &str = "False";
 const FULLSCREEN: &str = "Fullscreen";
 const TEXTURE_MSAA: &str = "TextureMsaa";
 const MODEL_MSAA: &str = "ModelMsaa";
+const ANTI_ALIASING: &str = "AntiAliasing";
+const ALPHA_TO_COVERAGE: &str = "AlphaToCoverage";
+const GPU_CULLING: &str = "GpuCulling";
+const OCCLUSION_CULLING: &str = "OcclusionCulling";
 const VSYNC: &str = "Vsync";
 const FORCE_DPI: &str = "ForceDpi";
 const DPI: &str = "Dpi";
 const TRIPLE_BUFFERING: &str = "TripleBuffer";
 const RESOLUTION: &str = "Resolution";
 const MAX_RESOLUTION: &str = "MaxMonitorResolution";
+const VERTEX_CACHE_OPTIMISATION: &str = "VertexCacheOptimisation";
+const GENERATED_LODS: &str = "GeneratedLods";
+const SHADOW_CASCADES: &str = "ShadowCascades";
+const SHADOW_RESOLUTION: &str = "ShadowResolution";
+const SHADOW_DISTANCE: &str = "ShadowDistance";
+const LIGHT_SHADOW_BUDGET: &str = "LightShadowBudget";
+const LIGHT_SHADOW_RESOLUTION: &str = "LightShadowResolution";
+const SSAO_QUALITY: &str = "SsaoQuality";
+const SSAO_SAMPLES: &str = "SsaoSamples";
+const SSAO_RADIUS: &str = "SsaoRadius";
+const SSAO_INTENSITY: &str = "SsaoIntensity";
 
 pub const RESOLUTIONS: [(u32, u32, &str); 99] = [
   (2160, 1080, "(1:2)"),
@@ -128,12 +143,27 @@ pub struct Settings {
   triple_buffer: bool,
   texture_msaa: u32,
   model_msaa: u32,
+  anti_aliasing: u32,
+  alpha_to_coverage: bool,
+  gpu_culling: bool,
+  occlusion_culling: bool,
   fullscreen: bool,
   _minimum_resolution: [u32; 2],
   max_monitor_resolution: [u32; 2],
   resolution: [u32; 2],
   force_dpi: bool,
   dpi: f32,
+  vertex_cache_optimisation: bool,
+  generated_lods: u32,
+  shadow_cascades: u32,
+  shadow_resolution: u32,
+  shadow_distance: f32,
+  light_shadow_budget: u32,
+  light_shadow_resolution: u32,
+  ssao_quality: u32,
+  ssao_samples: u32,
+  ssao_radius: f32,
+  ssao_intensity: f32,
 }
 
 impl Settings {
@@ -142,11 +172,26 @@ impl Settings {
     let mut triple_buffer = false;
     let mut texture_msaa = 1;
     let mut model_msaa = 1;
+    let mut anti_aliasing = 0;
+    let mut alpha_to_coverage = true;
+    let mut gpu_culling = false;
+    let mut occlusion_culling = false;
     let mut is_fullscreen = false;
     let mut resolution: [u32; 2] = [1280, 720];
     let mut max_monitor_resolution = [1920, 1080];
     let mut force_dpi = false;
     let mut dpi = 1.0;
+    let mut vertex_cache_optimisation = true;
+    let mut generated_lods = 0;
+    let mut shadow_cascades = 3;
+    let mut shadow_resolution = 2048;
+    let mut shadow_distance = 100.0;
+    let mut light_shadow_budget = 4;
+    let mut light_shadow_resolution = 512;
+    let mut ssao_quality = 0;
+    let mut ssao_samples = 0;
+    let mut ssao_radius = 0.5;
+    let mut ssao_intensity = 1.0;
     
     if let Ok(f) = File::open("./settings.ini") {
       println!("Settings file exists");
@@ -203,6 +248,44 @@ impl Settings {
                 model_msaa = s;
               }
             },
+            ANTI_ALIASING => {
+              if let Ok(mode) = v[1].parse::<u32>() {
+                anti_aliasing = mode;
+              }
+            },
+            ALPHA_TO_COVERAGE => {
+              match v[1] {
+                TRUE => {
+                  alpha_to_coverage = true;
+                },
+                FALSE => {
+                  alpha_to_coverage = false;
+                },
+                _ => {}
+              }
+            },
+            GPU_CULLING => {
+              match v[1] {
+                TRUE => {
+                  gpu_culling = true;
+                },
+                FALSE => {
+                  gpu_culling = false;
+                },
+                _ => {}
+              }
+            },
+            OCCLUSION_CULLING => {
+              match v[1] {
+                TRUE => {
+                  occlusion_culling = true;
+                },
+                FALSE => {
+                  occlusion_culling = false;
+                },
+                _ => {}
+              }
+            },
             VSYNC => {
               match v[1] {
                 TRUE => {
@@ -240,6 +323,67 @@ impl Settings {
               if let Ok(custom_dpi) = v[1].parse::<f32>() {
                 dpi = custom_dpi;
               }
+            },
+            VERTEX_CACHE_OPTIMISATION => {
+              match v[1] {
+                TRUE => {
+                  vertex_cache_optimisation = true;
+                },
+                FALSE => {
+                  vertex_cache_optimisation = false;
+                },
+                _ => {}
+              }
+            },
+            GENERATED_LODS => {
+              if let Ok(lods) = v[1].parse::<u32>() {
+                generated_lods = lods;
+              }
+            },
+            SHADOW_CASCADES => {
+              if let Ok(cascades) = v[1].parse::<u32>() {
+                shadow_cascades = cascades;
+              }
+            },
+            SHADOW_RESOLUTION => {
+              if let Ok(res) = v[1].parse::<u32>() {
+                shadow_resolution = res;
+              }
+            },
+            SHADOW_DISTANCE => {
+              if let Ok(distance) = v[1].parse::<f32>() {
+                shadow_distance = distance;
+              }
+            },
+            LIGHT_SHADOW_BUDGET => {
+              if let Ok(budget) = v[1].parse::<u32>() {
+                light_shadow_budget = budget;
+              }
+            },
+            LIGHT_SHADOW_RESOLUTION => {
+              if let Ok(res) = v[1].parse::<u32>() {
+                light_shadow_resolution = res;
+              }
+            },
+            SSAO_QUALITY => {
+              if let Ok(quality) = v[1].parse::<u32>() {
+                ssao_quality = quality;
+              }
+            },
+            SSAO_SAMPLES => {
+              if let Ok(samples) = v[1].parse::<u32>() {
+                ssao_samples = samples;
+              }
+            },
+            SSAO_RADIUS => {
+              if let Ok(radius) = v[1].parse::<f32>() {
+                ssao_radius = radius;
+              }
+            },
+            SSAO_INTENSITY => {
+              if let Ok(intensity) = v[1].parse::<f32>() {
+                ssao_intensity = intensity;
+              }
             }
             _ => {
               println!("Unknown setting: {:?}", v);
@@ -259,12 +403,27 @@ impl Settings {
       triple_buffer: triple_buffer,
       texture_msaa,
       model_msaa,
+      anti_aliasing,
+      alpha_to_coverage,
+      gpu_culling,
+      occlusion_culling,
       fullscreen: is_fullscreen,
       resolution,
       _minimum_resolution: [800, 640],
       max_monitor_resolution,
       force_dpi: force_dpi,
       dpi: dpi,
+      vertex_cache_optimisation,
+      generated_lods,
+      shadow_cascades,
+      shadow_resolution,
+      shadow_distance,
+      light_shadow_budget,
+      light_shadow_resolution,
+      ssao_quality,
+      ssao_samples,
+      ssao_radius,
+      ssao_intensity,
     }
   }
   
@@ -292,6 +451,30 @@ impl Settings {
       }
     };
     
+    let alpha_to_coverage = {
+      if self.alpha_to_coverage {
+        TRUE
+      } else {
+        FALSE
+      }
+    };
+    
+    let gpu_culling = {
+      if self.gpu_culling {
+        TRUE
+      } else {
+        FALSE
+      }
+    };
+    
+    let occlusion_culling = {
+      if self.occlusion_culling {
+        TRUE
+      } else {
+        FALSE
+      }
+    };
+    
     let force_dpi = {
       if self.force_dpi {
         TRUE
@@ -300,6 +483,14 @@ impl Settings {
       }
     };
     
+    let vertex_cache_optimisation = {
+      if self.vertex_cache_optimisation {
+        TRUE
+      } else {
+        FALSE
+      }
+    };
+    
     let data = RESOLUTION.to_owned() + SPACE + &self.resolution[0].to_string() + 
                   SPACE + &self.resolution[1].to_string() + NL +
                   MAX_RESOLUTION + SPACE + &self.max_monitor_resolution[0].to_string() + SPACE + &self.max_monitor_resolution[1].to_string() + NL +
@@ -308,8 +499,23 @@ impl Settings {
                   TRIPLE_BUFFERING  + SPACE + triple_buffer + NL + 
                   TEXTURE_MSAA      + SPACE + &self.texture_msaa.to_string() + NL + 
                   MODEL_MSAA        + SPACE + &self.model_msaa.to_string() + NL + 
+                  ANTI_ALIASING     + SPACE + &self.anti_aliasing.to_string() + NL + 
+                  ALPHA_TO_COVERAGE + SPACE + alpha_to_coverage + NL + 
+                  GPU_CULLING       + SPACE + gpu_culling + NL + 
+                  OCCLUSION_CULLING + SPACE + occlusion_culling + NL + 
                   FORCE_DPI         + SPACE + force_dpi + NL + 
-                  DPI               + SPACE + &self.dpi.to_string() + NL;
+                  DPI               + SPACE + &self.dpi.to_string() + NL + 
+                  VERTEX_CACHE_OPTIMISATION + SPACE + vertex_cache_optimisation + NL + 
+                  GENERATED_LODS    + SPACE + &self.generated_lods.to_string() + NL + 
+                  SHADOW_CASCADES   + SPACE + &self.shadow_cascades.to_string() + NL + 
+                  SHADOW_RESOLUTION + SPACE + &self.shadow_resolution.to_string() + NL + 
+                  SHADOW_DISTANCE   + SPACE + &self.shadow_distance.to_string() + NL + 
+                  LIGHT_SHADOW_BUDGET + SPACE + &self.light_shadow_budget.to_string() + NL + 
+                  LIGHT_SHADOW_RESOLUTION + SPACE + &self.light_shadow_resolution.to_string() + NL + 
+                  SSAO_QUALITY      + SPACE + &self.ssao_quality.to_string() + NL + 
+                  SSAO_SAMPLES      + SPACE + &self.ssao_samples.to_string() + NL + 
+                  SSAO_RADIUS       + SPACE + &self.ssao_radius.to_string() + NL + 
+                  SSAO_INTENSITY    + SPACE + &self.ssao_intensity.to_string() + NL;
     let f = File::create(SETTINGS_LOCATION).expect("Error: Failed to create settings file");
     let mut f = BufWriter::new(f);
     f.write_all(data.as_bytes()).expect("Unable to write data");
@@ -325,8 +531,23 @@ impl Settings {
                   TRIPLE_BUFFERING  + SPACE + FALSE + NL + 
                   TEXTURE_MSAA      + SPACE + "2"   + NL + 
                   MODEL_MSAA        + SPACE + "2"   + NL + 
+                  ANTI_ALIASING     + SPACE + "0"   + NL + 
+                  ALPHA_TO_COVERAGE + SPACE + TRUE  + NL + 
+                  GPU_CULLING       + SPACE + FALSE + NL + 
+                  OCCLUSION_CULLING + SPACE + FALSE + NL + 
                   FORCE_DPI         + SPACE + FALSE + NL + 
-                  DPI               + SPACE + "1"   + NL;
+                  DPI               + SPACE + "1"   + NL + 
+                  VERTEX_CACHE_OPTIMISATION + SPACE + TRUE + NL + 
+                  GENERATED_LODS    + SPACE + "0"   + NL + 
+                  SHADOW_CASCADES   + SPACE + "3"   + NL + 
+                  SHADOW_RESOLUTION + SPACE + "2048" + NL + 
+                  SHADOW_DISTANCE   + SPACE + "100" + NL + 
+                  LIGHT_SHADOW_BUDGET + SPACE + "4" + NL + 
+                  LIGHT_SHADOW_RESOLUTION + SPACE + "512" + NL + 
+                  SSAO_QUALITY      + SPACE + "0"   + NL + 
+                  SSAO_SAMPLES      + SPACE + "0"   + NL + 
+                  SSAO_RADIUS       + SPACE + "0.5" + NL + 
+                  SSAO_INTENSITY    + SPACE + "1"   + NL;
     let f = File::create(SETTINGS_LOCATION).expect("Error: Failed to create settings file");
     let mut f = BufWriter::new(f);
     f.write_all(data.as_bytes()).expect("Unable to write data");
@@ -366,6 +587,42 @@ impl Settings {
     self.model_msaa
   }
   
+  // 0 msaa, 1 fxaa, 2 taa, fxaa and taa turn off the model msaa
+  pub fn set_anti_aliasing(&mut self, mode: u32) {
+    self.anti_aliasing = mode;
+  }
+  
+  pub fn get_anti_aliasing(&self) -> u32 {
+    self.anti_aliasing
+  }
+  
+  // masked materials fade their edges over the msaa samples instead of cutting them
+  pub fn set_alpha_to_coverage(&mut self, enable: bool) {
+    self.alpha_to_coverage = enable;
+  }
+  
+  pub fn alpha_to_coverage_enabled(&self) -> bool {
+    self.alpha_to_coverage
+  }
+  
+  // instanced models are culled and drawn from a compute pass instead of on the cpu
+  pub fn set_gpu_culling(&mut self, enable: bool) {
+    self.gpu_culling = enable;
+  }
+  
+  pub fn gpu_culling_enabled(&self) -> bool {
+    self.gpu_culling
+  }
+  
+  // gpu culling also skips instances hidden behind last frame's depth
+  pub fn set_occlusion_culling(&mut self, enable: bool) {
+    self.occlusion_culling = enable;
+  }
+  
+  pub fn occlusion_culling_enabled(&self) -> bool {
+    self.occlusion_culling
+  }
+  
   pub fn is_fullscreen(&self) -> bool {
     self.fullscreen
   }
@@ -405,6 +662,99 @@ impl Settings {
   pub fn get_resolution(&self) -> [u32; 2] {
     self.resolution
   }
+  
+  pub fn vertex_cache_optimisation_enabled(&self) -> bool {
+    self.vertex_cache_optimisation
+  }
+  
+  pub fn set_vertex_cache_optimisation(&mut self, enable: bool) {
+    self.vertex_cache_optimisation = enable;
+  }
+  
+  pub fn set_generated_lods(&mut self, lods: u32) {
+    self.generated_lods = lods;
+  }
+  
+  pub fn get_generated_lods(&self) -> u32 {
+    self.generated_lods
+  }
+  
+  // 0 cascades turns sun shadows off
+  pub fn set_shadow_cascades(&mut self, cascades: u32) {
+    self.shadow_cascades = cascades;
+  }
+  
+  pub fn get_shadow_cascades(&self) -> u32 {
+    self.shadow_cascades
+  }
+  
+  pub fn set_shadow_resolution(&mut self, resolution: u32) {
+    self.shadow_resolution = resolution;
+  }
+  
+  pub fn get_shadow_resolution(&self) -> u32 {
+    self.shadow_resolution
+  }
+  
+  pub fn set_shadow_distance(&mut self, distance: f32) {
+    self.shadow_distance = distance;
+  }
+  
+  pub fn get_shadow_distance(&self) -> f32 {
+    self.shadow_distance
+  }
+  
+  // most point and spot lights given shadows each frame, 0 turns them off
+  pub fn set_light_shadow_budget(&mut self, budget: u32) {
+    self.light_shadow_budget = budget;
+  }
+  
+  pub fn get_light_shadow_budget(&self) -> u32 {
+    self.light_shadow_budget
+  }
+  
+  pub fn set_light_shadow_resolution(&mut self, resolution: u32) {
+    self.light_shadow_resolution = resolution;
+  }
+  
+  pub fn get_light_shadow_resolution(&self) -> u32 {
+    self.light_shadow_resolution
+  }
+  
+  // 0 off, 1 low, 2 medium, 3 high
+  pub fn set_ssao_quality(&mut self, quality: u32) {
+    self.ssao_quality = quality;
+  }
+  
+  pub fn get_ssao_quality(&self) -> u32 {
+    self.ssao_quality
+  }
+  
+  // 0 uses the quality level's sample count
+  pub fn set_ssao_samples(&mut self, samples: u32) {
+    self.ssao_samples = samples;
+  }
+  
+  pub fn get_ssao_samples(&self) -> u32 {
+    self.ssao_samples
+  }
+  
+  // world space distance surfaces are searched for occluders
+  pub fn set_ssao_radius(&mut self, radius: f32) {
+    self.ssao_radius = radius;
+  }
+  
+  pub fn get_ssao_radius(&self) -> f32 {
+    self.ssao_radius
+  }
+  
+  pub fn set_ssao_intensity(&mut self, intensity: f32) {
+    self.ssao_intensity = intensity;
+  }
+  
+  pub fn get_ssao_intensity(&self) -> f32 {
+    self.ssao_intensity
+  }
 }
 
 impl Drop for Settings {
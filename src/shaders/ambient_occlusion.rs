@@ -0,0 +1,314 @@
+use vk;
+
+use crate::vulkan::vkenums::{ImageType, ImageUsage, ImageViewType, ImageTiling, ImageLayout, ImageAspect, SampleCount, ShaderStage, Filter, AddressMode, MipmapMode, VkBool, Access, PipelineStage};
+
+use crate::vulkan::{Instance, Device, Shader, Pipeline, PipelineBuilder, DescriptorSet, UpdateDescriptorSets, DescriptorSetBuilder, ImageAttachment, Sampler, SamplerBuilder};
+use crate::vulkan::buffer::{Buffer, BufferUsage, UniformData, CommandBufferBuilder};
+use crate::vulkan::pool::DescriptorPool;
+use crate::shaders::lights::{ClusterView, CLUSTER_NEAR, CLUSTER_FAR};
+use crate::math;
+
+use cgmath::Vector4;
+
+use std::sync::Arc;
+
+const MAX_SAMPLES: u32 = 64;
+const QUALITY_SAMPLES: [u32; 4] = [0, 8, 16, 32]; // off, low, medium, high
+const VIEW_SIZE: usize = 16;
+const GROUP_SIZE: u32 = 8;
+
+#[derive(Clone, Copy)]
+pub struct AmbientOcclusionSettings {
+  samples: u32,
+  radius: f32,
+  intensity: f32,
+}
+
+impl AmbientOcclusionSettings {
+  // quality 0 turns it off, 0 samples uses the quality's count
+  pub fn new(quality: u32, samples: u32, radius: f32, intensity: f32) -> AmbientOcclusionSettings {
+    let quality = (quality as usize).min(QUALITY_SAMPLES.len()-1);
+    let samples = if quality == 0 {
+      0
+    } else if samples == 0 {
+      QUALITY_SAMPLES[quality]
+    } else {
+      samples.min(MAX_SAMPLES)
+    };
+    
+    AmbientOcclusionSettings {
+      samples,
+      radius: radius.max(0.01),
+      intensity: intensity.max(0.0),
+    }
+  }
+  
+  pub fn enabled(&self) -> bool {
+    self.samples > 0
+  }
+}
+
+/**
+** Screen space ambient occlusion from the position and normal gbuffers.
+** It is built after the model pass, blurred, and read by the next frame's
+** deffered subpass through the view it was built from, the same way the
+** occlusion culling depth pyramid lags a frame.
+**/
+pub struct AmbientOcclusion {
+  settings: AmbientOcclusionSettings,
+  
+  occlusion_shader: Shader,
+  occlusion_pipeline: Pipeline,
+  source_sets: Vec<DescriptorSet>, // per frame, position and normal gbuffers into occlusion
+  blur_shader: Shader,
+  blur_pipeline: Pipeline,
+  blur_set: DescriptorSet,
+  sampler: Sampler,
+  occlusion: ImageAttachment, // occlusion and view depth, a single texel when off
+  blurred: ImageAttachment,
+  images_ready: bool, // false until the images are in the general layout
+  
+  view_buffer: Buffer<f32>, // per frame, the view blurred was built from
+  frame_view: Option<ClusterView>,
+  occlusion_view: Option<ClusterView>,
+}
+
+impl AmbientOcclusion {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, settings: AmbientOcclusionSettings, position_images: &[ImageAttachment], normal_images: &[ImageAttachment], extent: &vk::Extent2D, descriptor_set_pool: &DescriptorPool) -> AmbientOcclusion {
+    let num_frames = position_images.len();
+    
+    let occlusion_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/ComputeAmbientOcclusion.spv"));
+    let blur_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/ComputeAmbientOcclusionBlur.spv"));
+    
+    let occlusion_layout = || {
+      DescriptorSetBuilder::new()
+        .compute_combined_image_sampler(0)
+        .compute_combined_image_sampler(1)
+        .compute_storage_image(2)
+        .build(Arc::clone(&device), descriptor_set_pool, 1)
+    };
+    
+    let mut source_sets = Vec::new();
+    if settings.enabled() {
+      for _ in 0..num_frames {
+        source_sets.push(occlusion_layout());
+      }
+    }
+    
+    let occlusion_push_constant_size = UniformData::new()
+                                         .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                         .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                         .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                         .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                         .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                         .size_non_aligned();
+    
+    let occlusion_set_layout = occlusion_layout();
+    let occlusion_pipeline = PipelineBuilder::new()
+                               .compute_shader(*occlusion_shader.get_shader())
+                               .descriptor_set_layout(occlusion_set_layout.layouts_clone())
+                               .push_constants(ShaderStage::Compute, occlusion_push_constant_size as u32)
+                               .build_compute(Arc::clone(&device));
+    occlusion_set_layout.destroy(Arc::clone(&device));
+    
+    let blur_set = DescriptorSetBuilder::new()
+                     .compute_combined_image_sampler(0)
+                     .compute_storage_image(1)
+                     .build(Arc::clone(&device), descriptor_set_pool, 1);
+    
+    let blur_pipeline = PipelineBuilder::new()
+                          .compute_shader(*blur_shader.get_shader())
+                          .descriptor_set_layout(blur_set.layouts_clone())
+                          .build_compute(Arc::clone(&device));
+    
+    let sampler = SamplerBuilder::new()
+                    .min_filter(Filter::Nearest)
+                    .mag_filter(Filter::Nearest)
+                    .address_mode(AddressMode::ClampToEdge)
+                    .mipmap_mode(MipmapMode::Nearest)
+                    .anisotropy(VkBool::False)
+                    .max_anisotropy(1.0)
+                    .build(Arc::clone(&device));
+    
+    let (occlusion, blurred) = AmbientOcclusion::create_images(Arc::clone(&instance), Arc::clone(&device), settings.enabled(), extent);
+    
+    let view_buffer = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), num_frames as u32, VIEW_SIZE as u64);
+    
+    let ambient_occlusion = AmbientOcclusion {
+      settings,
+      
+      occlusion_shader,
+      occlusion_pipeline,
+      source_sets,
+      blur_shader,
+      blur_pipeline,
+      blur_set,
+      sampler,
+      occlusion,
+      blurred,
+      images_ready: false,
+      
+      view_buffer,
+      frame_view: None,
+      occlusion_view: None,
+    };
+    
+    ambient_occlusion.update_sets(Arc::clone(&device), position_images, normal_images);
+    
+    ambient_occlusion
+  }
+  
+  fn create_images(instance: Arc<Instance>, device: Arc<Device>, enabled: bool, extent: &vk::Extent2D) -> (ImageAttachment, ImageAttachment) {
+    let (width, height) = if enabled { (extent.width, extent.height) } else { (1, 1) };
+    
+    let create = || {
+      ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::storage_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &vk::FORMAT_R32G32_SFLOAT, width, height)
+    };
+    
+    (create(), create())
+  }
+  
+  fn update_sets(&self, device: Arc<Device>, position_images: &[ImageAttachment], normal_images: &[ImageAttachment]) {
+    for ((position, normal), descriptor_set) in position_images.iter().zip(normal_images).zip(&self.source_sets) {
+      UpdateDescriptorSets::new()
+        .add_sampled_image(0, position, ImageLayout::ShaderReadOnlyOptimal, &self.sampler)
+        .add_sampled_image(1, normal, ImageLayout::ShaderReadOnlyOptimal, &self.sampler)
+        .add_storage_image(2, &self.occlusion, ImageLayout::General)
+        .finish_update(Arc::clone(&device), descriptor_set);
+    }
+    
+    UpdateDescriptorSets::new()
+      .add_sampled_image(0, &self.occlusion, ImageLayout::General, &self.sampler)
+      .add_storage_image(1, &self.blurred, ImageLayout::General)
+      .finish_update(Arc::clone(&device), &self.blur_set);
+  }
+  
+  // sampled by the deffered subpass in the general layout
+  pub fn blurred_image(&self) -> &ImageAttachment {
+    &self.blurred
+  }
+  
+  pub fn sampler(&self) -> &Sampler {
+    &self.sampler
+  }
+  
+  pub fn view_buffer(&self) -> &Buffer<f32> {
+    &self.view_buffer
+  }
+  
+  // moves the images to the general layout the first time, outside of a render pass
+  pub fn prepare(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    if !self.images_ready {
+      cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderWrite, &ImageLayout::Undefined, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.occlusion);
+      cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderRead, &ImageLayout::Undefined, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::FragmentShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.blurred);
+      self.images_ready = true;
+    }
+    
+    cmd
+  }
+  
+  /**
+  ** Uploads the view the occlusion was last built from for the deffered
+  ** subpass to reproject onto, view is this frame's and is used by build.
+  **/
+  pub fn update_view(&mut self, device: Arc<Device>, current_buffer: usize, view: ClusterView) {
+    let mut data = match &self.occlusion_view {
+      Some(occlusion_view) => {
+        UniformData::new()
+          .add_vector4(occlusion_view.position.extend(1.0))
+          .add_vector4(occlusion_view.forward.extend(0.0))
+          .add_vector4(occlusion_view.right)
+          .add_vector4(occlusion_view.up)
+      },
+      None => {
+        UniformData::new()
+          .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+          .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+          .add_vector4(Vector4::new(0.0, 0.0, 0.0, 1.0))
+          .add_vector4(Vector4::new(0.0, 0.0, 0.0, 1.0))
+      },
+    };
+    
+    self.view_buffer.fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, data.build_non_aligned());
+    
+    if self.settings.enabled() {
+      self.frame_view = Some(view);
+    }
+  }
+  
+  /**
+  ** Builds and blurs the occlusion from this frame's gbuffers once the model
+  ** pass has ended, for the next frame's deffered subpass.
+  **/
+  pub fn build(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize, position_image: &ImageAttachment, normal_image: &ImageAttachment) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    let view = match self.frame_view.take() {
+      Some(view) => view,
+      None => return cmd,
+    };
+    
+    for gbuffer in &[position_image, normal_image] {
+      cmd = cmd.image_barrier(Arc::clone(&device), &Access::ColourAttachmentWrite, &Access::ShaderRead, &ImageLayout::ShaderReadOnlyOptimal, &ImageLayout::ShaderReadOnlyOptimal, &ImageAspect::Colour, PipelineStage::ColorAttachmentOutput, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, gbuffer);
+    }
+    
+    let (width, height) = self.occlusion.get_size();
+    let settings = Vector4::new(self.settings.radius, self.settings.samples as f32, self.settings.intensity, 0.0);
+    
+    let push_constant_data = UniformData::new()
+                               .add_vector4(view.position.extend(CLUSTER_NEAR))
+                               .add_vector4(view.forward.extend(CLUSTER_FAR))
+                               .add_vector4(view.right)
+                               .add_vector4(view.up)
+                               .add_vector4(settings);
+    
+    // the last frame's blur is done reading it
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderWrite, &ImageLayout::General, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.occlusion);
+    cmd = cmd.push_constants(Arc::clone(&device), &self.occlusion_pipeline, ShaderStage::Compute, push_constant_data);
+    cmd = cmd.compute_dispatch(Arc::clone(&device), &self.occlusion_pipeline, vec!(*self.source_sets[current_buffer].set(0)), math::div_round_up(width, GROUP_SIZE), math::div_round_up(height, GROUP_SIZE), 1);
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderWrite, &Access::ShaderRead, &ImageLayout::General, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.occlusion);
+    
+    // this frame's deffered subpass is done reading it
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderWrite, &ImageLayout::General, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::FragmentShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.blurred);
+    cmd = cmd.compute_dispatch(Arc::clone(&device), &self.blur_pipeline, vec!(*self.blur_set.set(0)), math::div_round_up(width, GROUP_SIZE), math::div_round_up(height, GROUP_SIZE), 1);
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderWrite, &Access::ShaderRead, &ImageLayout::General, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::FragmentShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.blurred);
+    
+    self.occlusion_view = Some(view);
+    
+    cmd
+  }
+  
+  // the device must be idle
+  pub fn recreate(&mut self, instance: Arc<Instance>, device: Arc<Device>, position_images: &[ImageAttachment], normal_images: &[ImageAttachment], extent: &vk::Extent2D) {
+    self.occlusion.destroy(Arc::clone(&device));
+    self.blurred.destroy(Arc::clone(&device));
+    
+    let (occlusion, blurred) = AmbientOcclusion::create_images(Arc::clone(&instance), Arc::clone(&device), self.settings.enabled(), extent);
+    self.occlusion = occlusion;
+    self.blurred = blurred;
+    self.images_ready = false;
+    self.frame_view = None;
+    self.occlusion_view = None;
+    
+    self.update_sets(Arc::clone(&device), position_images, normal_images);
+  }
+  
+  pub fn destroy(&self, device: Arc<Device>) {
+    self.occlusion.destroy(Arc::clone(&device));
+    self.blurred.destroy(Arc::clone(&device));
+    
+    for descriptor_set in &self.source_sets {
+      descriptor_set.destroy(Arc::clone(&device));
+    }
+    
+    self.blur_set.destroy(Arc::clone(&device));
+    self.view_buffer.destroy(Arc::clone(&device));
+    self.sampler.destroy(Arc::clone(&device));
+    self.occlusion_pipeline.destroy(Arc::clone(&device));
+    self.blur_pipeline.destroy(Arc::clone(&device));
+    self.occlusion_shader.destroy(Arc::clone(&device));
+    self.blur_shader.destroy(Arc::clone(&device));
+  }
+}
@@ -0,0 +1,253 @@
+use vk;
+
+use crate::vulkan::vkenums::{ImageType, ImageUsage, ImageViewType, ImageTiling, ImageLayout, ImageAspect, SampleCount, ShaderStage, Filter, AddressMode, MipmapMode, VkBool, Access, PipelineStage};
+
+use crate::vulkan::{Instance, Device, Shader, Pipeline, PipelineBuilder, DescriptorSet, UpdateDescriptorSets, DescriptorSetBuilder, ImageAttachment, Sampler, SamplerBuilder};
+use crate::vulkan::buffer::{UniformData, CommandBufferBuilder};
+use crate::vulkan::pool::DescriptorPool;
+use crate::math;
+
+use cgmath::{Vector2, Vector4};
+
+use std::sync::Arc;
+
+const RESOLVED_FORMAT: vk::Format = vk::FORMAT_R16G16B16A16_SFLOAT;
+const GROUP_SIZE: u32 = 8;
+const JITTER_SAMPLES: usize = 8;
+// how much of each new frame goes into the history
+const TAA_CURRENT_WEIGHT: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AntiAliasing {
+  Msaa,
+  Fxaa,
+  Taa,
+}
+
+impl AntiAliasing {
+  // 0 msaa, 1 fxaa, 2 taa
+  pub fn from(mode: u32) -> AntiAliasing {
+    match mode {
+      1 => AntiAliasing::Fxaa,
+      2 => AntiAliasing::Taa,
+      _ => AntiAliasing::Msaa,
+    }
+  }
+  
+  // resolved after the model pass instead of by multisampling it
+  pub fn post_process(self) -> bool {
+    self != AntiAliasing::Msaa
+  }
+}
+
+// halton base 2 and 3, centred on the pixel
+fn halton(index: usize, base: usize) -> f32 {
+  let mut result = 0.0;
+  let mut fraction = 1.0;
+  let mut i = index;
+  while i > 0 {
+    fraction /= base as f32;
+    result += fraction * (i % base) as f32;
+    i /= base;
+  }
+  
+  result
+}
+
+/**
+** Fxaa or taa over the hdr model colour once the model pass has ended, the
+** result replaces the model colour in the final shader. Taa jitters the
+** model pass viewport a fraction of a pixel each frame and blends the frame
+** into the history reprojected by the velocity the model pass wrote, so it
+** writes into two images in turn.
+**/
+pub struct PostAntiAliasing {
+  mode: AntiAliasing,
+  
+  shader: Shader,
+  pipeline: Pipeline,
+  sets: Vec<Vec<DescriptorSet>>, // per frame, per output
+  sampler: Sampler,
+  outputs: Vec<ImageAttachment>,
+  current: usize, // the output written last
+  outputs_ready: bool, // false until the outputs are in the shader read only layout
+  history_valid: bool,
+  
+  frame: usize,
+}
+
+impl PostAntiAliasing {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, mode: AntiAliasing, colour_images: &[ImageAttachment], velocity_images: &[ImageAttachment], extent: &vk::Extent2D, descriptor_set_pool: &DescriptorPool) -> PostAntiAliasing {
+    let shader = if mode == AntiAliasing::Taa {
+      Shader::new(Arc::clone(&device), include_bytes!("./sprv/ComputeTaa.spv"))
+    } else {
+      Shader::new(Arc::clone(&device), include_bytes!("./sprv/ComputeFxaa.spv"))
+    };
+    
+    let num_outputs = if mode == AntiAliasing::Taa { 2 } else { 1 };
+    
+    let mut sets = Vec::with_capacity(colour_images.len());
+    for _ in 0..colour_images.len() {
+      let mut frame_sets = Vec::with_capacity(num_outputs);
+      for _ in 0..num_outputs {
+        frame_sets.push(DescriptorSetBuilder::new()
+                          .compute_combined_image_sampler(0)
+                          .compute_combined_image_sampler(1)
+                          .compute_combined_image_sampler(2)
+                          .compute_storage_image(3)
+                          .build(Arc::clone(&device), descriptor_set_pool, 1));
+      }
+      sets.push(frame_sets);
+    }
+    
+    let push_constant_size = UniformData::new()
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .size_non_aligned();
+    
+    let pipeline = PipelineBuilder::new()
+                     .compute_shader(*shader.get_shader())
+                     .descriptor_set_layout(sets[0][0].layouts_clone())
+                     .push_constants(ShaderStage::Compute, push_constant_size as u32)
+                     .build_compute(Arc::clone(&device));
+    
+    let sampler = SamplerBuilder::new()
+                    .min_filter(Filter::Linear)
+                    .mag_filter(Filter::Linear)
+                    .address_mode(AddressMode::ClampToEdge)
+                    .mipmap_mode(MipmapMode::Nearest)
+                    .anisotropy(VkBool::False)
+                    .max_anisotropy(1.0)
+                    .build(Arc::clone(&device));
+    
+    let outputs = PostAntiAliasing::create_outputs(Arc::clone(&instance), Arc::clone(&device), num_outputs, extent);
+    
+    let anti_aliasing = PostAntiAliasing {
+      mode,
+      
+      shader,
+      pipeline,
+      sets,
+      sampler,
+      outputs,
+      current: 0,
+      outputs_ready: false,
+      history_valid: false,
+      
+      frame: 0,
+    };
+    
+    anti_aliasing.update_sets(Arc::clone(&device), colour_images, velocity_images);
+    
+    anti_aliasing
+  }
+  
+  fn create_outputs(instance: Arc<Instance>, device: Arc<Device>, num_outputs: usize, extent: &vk::Extent2D) -> Vec<ImageAttachment> {
+    (0..num_outputs).map(|_| {
+      ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::storage_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &RESOLVED_FORMAT, extent.width, extent.height)
+    }).collect()
+  }
+  
+  fn update_sets(&self, device: Arc<Device>, colour_images: &[ImageAttachment], velocity_images: &[ImageAttachment]) {
+    let num_outputs = self.outputs.len();
+    
+    for ((colour, velocity), frame_sets) in colour_images.iter().zip(velocity_images).zip(&self.sets) {
+      for (i, descriptor_set) in frame_sets.iter().enumerate() {
+        UpdateDescriptorSets::new()
+          .add_sampled_image(0, colour, ImageLayout::ShaderReadOnlyOptimal, &self.sampler)
+          .add_sampled_image(1, velocity, ImageLayout::ShaderReadOnlyOptimal, &self.sampler)
+          .add_sampled_image(2, &self.outputs[(i+1) % num_outputs], ImageLayout::ShaderReadOnlyOptimal, &self.sampler)
+          .add_storage_image(3, &self.outputs[i], ImageLayout::General)
+          .finish_update(Arc::clone(&device), descriptor_set);
+      }
+    }
+  }
+  
+  // replaces the model colour in the final shader, in the shader read only layout
+  pub fn output(&self) -> &ImageAttachment {
+    &self.outputs[self.current]
+  }
+  
+  // offset in pixels the model pass viewport is moved by this frame
+  pub fn jitter(&self) -> Vector2<f32> {
+    if self.mode != AntiAliasing::Taa {
+      return Vector2::new(0.0, 0.0);
+    }
+    
+    let index = self.frame % JITTER_SAMPLES + 1;
+    Vector2::new(halton(index, 2) - 0.5, halton(index, 3) - 0.5)
+  }
+  
+  // moves the outputs to the shader read only layout the first time, outside of a render pass
+  pub fn prepare(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    if !self.outputs_ready {
+      for output in &self.outputs {
+        cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderRead, &ImageLayout::Undefined, &ImageLayout::ShaderReadOnlyOptimal, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, output);
+      }
+      
+      self.outputs_ready = true;
+    }
+    
+    cmd
+  }
+  
+  pub fn build(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize, colour_image: &ImageAttachment, velocity_image: &ImageAttachment) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    let next = (self.current+1) % self.outputs.len();
+    
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ColourAttachmentWrite, &Access::ShaderRead, &ImageLayout::ShaderReadOnlyOptimal, &ImageLayout::ShaderReadOnlyOptimal, &ImageAspect::Colour, PipelineStage::ColorAttachmentOutput, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, colour_image);
+    if self.mode == AntiAliasing::Taa {
+      cmd = cmd.image_barrier(Arc::clone(&device), &Access::ColourAttachmentWrite, &Access::ShaderRead, &ImageLayout::ShaderReadOnlyOptimal, &ImageLayout::ShaderReadOnlyOptimal, &ImageAspect::Colour, PipelineStage::ColorAttachmentOutput, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, velocity_image);
+    }
+    
+    let settings = Vector4::new(if self.history_valid { 1.0 } else { 0.0 }, TAA_CURRENT_WEIGHT, 0.0, 0.0);
+    let push_constant_data = UniformData::new().add_vector4(settings);
+    
+    // the final shader and the last resolve are done reading it
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderWrite, &ImageLayout::ShaderReadOnlyOptimal, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::AllCommands, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.outputs[next]);
+    
+    let (width, height) = self.outputs[next].get_size();
+    cmd = cmd.push_constants(Arc::clone(&device), &self.pipeline, ShaderStage::Compute, push_constant_data);
+    cmd = cmd.compute_dispatch(Arc::clone(&device), &self.pipeline, vec!(*self.sets[current_buffer][next].set(0)), math::div_round_up(width, GROUP_SIZE), math::div_round_up(height, GROUP_SIZE), 1);
+    
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderWrite, &Access::ShaderRead, &ImageLayout::General, &ImageLayout::ShaderReadOnlyOptimal, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::AllCommands, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.outputs[next]);
+    
+    self.current = next;
+    self.frame += 1;
+    self.history_valid = true;
+    
+    cmd
+  }
+  
+  // the device must be idle
+  pub fn recreate(&mut self, instance: Arc<Instance>, device: Arc<Device>, colour_images: &[ImageAttachment], velocity_images: &[ImageAttachment], extent: &vk::Extent2D) {
+    for output in &self.outputs {
+      output.destroy(Arc::clone(&device));
+    }
+    
+    self.outputs = PostAntiAliasing::create_outputs(Arc::clone(&instance), Arc::clone(&device), self.outputs.len(), extent);
+    self.current = 0;
+    self.outputs_ready = false;
+    self.history_valid = false;
+    
+    self.update_sets(Arc::clone(&device), colour_images, velocity_images);
+  }
+  
+  pub fn destroy(&self, device: Arc<Device>) {
+    for output in &self.outputs {
+      output.destroy(Arc::clone(&device));
+    }
+    
+    for frame_sets in &self.sets {
+      for descriptor_set in frame_sets {
+        descriptor_set.destroy(Arc::clone(&device));
+      }
+    }
+    
+    self.sampler.destroy(Arc::clone(&device));
+    self.pipeline.destroy(Arc::clone(&device));
+    self.shader.destroy(Arc::clone(&device));
+  }
+}
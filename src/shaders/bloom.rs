@@ -0,0 +1,216 @@
+use vk;
+
+use crate::vulkan::vkenums::{ImageType, ImageUsage, ImageViewType, ImageTiling, ImageLayout, ImageAspect, SampleCount, ShaderStage, Filter, AddressMode, MipmapMode, VkBool, Access, PipelineStage};
+
+use crate::vulkan::{Instance, Device, Shader, Pipeline, PipelineBuilder, DescriptorSet, UpdateDescriptorSets, DescriptorSetBuilder, ImageAttachment, Sampler, SamplerBuilder};
+use crate::vulkan::buffer::{UniformData, CommandBufferBuilder};
+use crate::vulkan::pool::DescriptorPool;
+use crate::math;
+
+use cgmath::Vector4;
+
+use std::sync::Arc;
+
+const BLOOM_FORMAT: vk::Format = vk::FORMAT_R16G16B16A16_SFLOAT;
+const BLOOM_LEVELS: usize = 6;
+const BLOOM_THRESHOLD: f32 = 1.0;
+const BLOOM_KNEE: f32 = 0.5;
+const GROUP_SIZE: u32 = 8;
+
+/**
+** Bloom from the hdr model colour once the model pass has ended. The first
+** level is half the screen and only keeps what is over the threshold, each
+** level after it is half the one before. They are then blurred back up into
+** the first level, which the final shader adds over the colour.
+**/
+pub struct Bloom {
+  downsample_shader: Shader,
+  downsample_pipeline: Pipeline,
+  source_sets: Vec<DescriptorSet>, // per frame, hdr colour into the first level
+  downsample_sets: Vec<DescriptorSet>, // level to the next smaller level
+  upsample_shader: Shader,
+  upsample_pipeline: Pipeline,
+  upsample_sets: Vec<DescriptorSet>, // level to the next larger level
+  sampler: Sampler,
+  levels: Vec<ImageAttachment>,
+  levels_ready: bool, // false until the levels are in the general layout
+}
+
+impl Bloom {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, colour_images: &[ImageAttachment], extent: &vk::Extent2D, descriptor_set_pool: &DescriptorPool) -> Bloom {
+    let downsample_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/ComputeBloomDownsample.spv"));
+    let upsample_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/ComputeBloomUpsample.spv"));
+    
+    let layout = || {
+      DescriptorSetBuilder::new()
+        .compute_combined_image_sampler(0)
+        .compute_storage_image(1)
+        .build(Arc::clone(&device), descriptor_set_pool, 1)
+    };
+    
+    let source_sets = colour_images.iter().map(|_| layout()).collect::<Vec<DescriptorSet>>();
+    let downsample_sets = (1..BLOOM_LEVELS).map(|_| layout()).collect::<Vec<DescriptorSet>>();
+    let upsample_sets = (1..BLOOM_LEVELS).map(|_| layout()).collect::<Vec<DescriptorSet>>();
+    
+    let downsample_push_constant_size = UniformData::new()
+                                          .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                          .size_non_aligned();
+    
+    let downsample_pipeline = PipelineBuilder::new()
+                                .compute_shader(*downsample_shader.get_shader())
+                                .descriptor_set_layout(source_sets[0].layouts_clone())
+                                .push_constants(ShaderStage::Compute, downsample_push_constant_size as u32)
+                                .build_compute(Arc::clone(&device));
+    
+    let upsample_pipeline = PipelineBuilder::new()
+                              .compute_shader(*upsample_shader.get_shader())
+                              .descriptor_set_layout(upsample_sets[0].layouts_clone())
+                              .build_compute(Arc::clone(&device));
+    
+    let sampler = SamplerBuilder::new()
+                    .min_filter(Filter::Linear)
+                    .mag_filter(Filter::Linear)
+                    .address_mode(AddressMode::ClampToEdge)
+                    .mipmap_mode(MipmapMode::Nearest)
+                    .anisotropy(VkBool::False)
+                    .max_anisotropy(1.0)
+                    .build(Arc::clone(&device));
+    
+    let levels = Bloom::create_levels(Arc::clone(&instance), Arc::clone(&device), extent);
+    
+    let bloom = Bloom {
+      downsample_shader,
+      downsample_pipeline,
+      source_sets,
+      downsample_sets,
+      upsample_shader,
+      upsample_pipeline,
+      upsample_sets,
+      sampler,
+      levels,
+      levels_ready: false,
+    };
+    
+    bloom.update_sets(Arc::clone(&device), colour_images);
+    
+    bloom
+  }
+  
+  fn create_levels(instance: Arc<Instance>, device: Arc<Device>, extent: &vk::Extent2D) -> Vec<ImageAttachment> {
+    let mut levels = Vec::with_capacity(BLOOM_LEVELS);
+    
+    let mut width = extent.width;
+    let mut height = extent.height;
+    for _ in 0..BLOOM_LEVELS {
+      width = (width/2).max(1);
+      height = (height/2).max(1);
+      
+      levels.push(ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::storage_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &BLOOM_FORMAT, width, height));
+    }
+    
+    levels
+  }
+  
+  fn update_sets(&self, device: Arc<Device>, colour_images: &[ImageAttachment]) {
+    for (colour, descriptor_set) in colour_images.iter().zip(&self.source_sets) {
+      UpdateDescriptorSets::new()
+        .add_sampled_image(0, colour, ImageLayout::ShaderReadOnlyOptimal, &self.sampler)
+        .add_storage_image(1, &self.levels[0], ImageLayout::General)
+        .finish_update(Arc::clone(&device), descriptor_set);
+    }
+    
+    for i in 1..BLOOM_LEVELS {
+      UpdateDescriptorSets::new()
+        .add_sampled_image(0, &self.levels[i-1], ImageLayout::General, &self.sampler)
+        .add_storage_image(1, &self.levels[i], ImageLayout::General)
+        .finish_update(Arc::clone(&device), &self.downsample_sets[i-1]);
+      
+      UpdateDescriptorSets::new()
+        .add_sampled_image(0, &self.levels[i], ImageLayout::General, &self.sampler)
+        .add_storage_image(1, &self.levels[i-1], ImageLayout::General)
+        .finish_update(Arc::clone(&device), &self.upsample_sets[i-1]);
+    }
+  }
+  
+  // sampled by the final shader in the general layout
+  pub fn image(&self) -> &ImageAttachment {
+    &self.levels[0]
+  }
+  
+  // moves the levels to the general layout the first time, outside of a render pass
+  pub fn prepare(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    if !self.levels_ready {
+      for level in &self.levels {
+        cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderWrite, &ImageLayout::Undefined, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, level);
+      }
+      
+      self.levels_ready = true;
+    }
+    
+    cmd
+  }
+  
+  /**
+  ** Thresholds and downsamples the model colour down the levels, then blurs
+  ** each level back up into the one above it.
+  **/
+  pub fn build(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize, colour_image: &ImageAttachment) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ColourAttachmentWrite, &Access::ShaderRead, &ImageLayout::ShaderReadOnlyOptimal, &ImageLayout::ShaderReadOnlyOptimal, &ImageAspect::Colour, PipelineStage::ColorAttachmentOutput, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, colour_image);
+    
+    // the last frame's blur and final shader are done reading the levels
+    cmd = cmd.memory_barrier(Arc::clone(&device), PipelineStage::ComputeShader, PipelineStage::ComputeShader, &Access::ShaderRead, &Access::ShaderWrite);
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderWrite, &ImageLayout::General, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::FragmentShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.levels[0]);
+    
+    for i in 0..BLOOM_LEVELS {
+      let descriptor_set = if i == 0 { &self.source_sets[current_buffer] } else { &self.downsample_sets[i-1] };
+      let first_level = if i == 0 { 1.0 } else { 0.0 };
+      let push_constant_data = UniformData::new()
+                                 .add_vector4(Vector4::new(BLOOM_THRESHOLD, BLOOM_KNEE, first_level, 0.0));
+      
+      let (width, height) = self.levels[i].get_size();
+      cmd = cmd.push_constants(Arc::clone(&device), &self.downsample_pipeline, ShaderStage::Compute, push_constant_data);
+      cmd = cmd.compute_dispatch(Arc::clone(&device), &self.downsample_pipeline, vec!(*descriptor_set.set(0)), math::div_round_up(width, GROUP_SIZE), math::div_round_up(height, GROUP_SIZE), 1);
+      cmd = cmd.memory_barrier(Arc::clone(&device), PipelineStage::ComputeShader, PipelineStage::ComputeShader, &Access::ShaderWrite, &Access::ShaderRead);
+    }
+    
+    for i in (1..BLOOM_LEVELS).rev() {
+      let (width, height) = self.levels[i-1].get_size();
+      cmd = cmd.compute_dispatch(Arc::clone(&device), &self.upsample_pipeline, vec!(*self.upsample_sets[i-1].set(0)), math::div_round_up(width, GROUP_SIZE), math::div_round_up(height, GROUP_SIZE), 1);
+      cmd = cmd.memory_barrier(Arc::clone(&device), PipelineStage::ComputeShader, PipelineStage::ComputeShader, &Access::ShaderWrite, &Access::ShaderRead);
+    }
+    
+    cmd.image_barrier(Arc::clone(&device), &Access::ShaderWrite, &Access::ShaderRead, &ImageLayout::General, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::FragmentShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.levels[0])
+  }
+  
+  // the device must be idle
+  pub fn recreate(&mut self, instance: Arc<Instance>, device: Arc<Device>, colour_images: &[ImageAttachment], extent: &vk::Extent2D) {
+    for level in &self.levels {
+      level.destroy(Arc::clone(&device));
+    }
+    
+    self.levels = Bloom::create_levels(Arc::clone(&instance), Arc::clone(&device), extent);
+    self.levels_ready = false;
+    
+    self.update_sets(Arc::clone(&device), colour_images);
+  }
+  
+  pub fn destroy(&self, device: Arc<Device>) {
+    for level in &self.levels {
+      level.destroy(Arc::clone(&device));
+    }
+    
+    for descriptor_set in self.source_sets.iter().chain(&self.downsample_sets).chain(&self.upsample_sets) {
+      descriptor_set.destroy(Arc::clone(&device));
+    }
+    
+    self.sampler.destroy(Arc::clone(&device));
+    self.downsample_pipeline.destroy(Arc::clone(&device));
+    self.upsample_pipeline.destroy(Arc::clone(&device));
+    self.downsample_shader.destroy(Arc::clone(&device));
+    self.upsample_shader.destroy(Arc::clone(&device));
+  }
+}
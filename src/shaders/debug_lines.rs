@@ -0,0 +1,274 @@
+use vk;
+
+use crate::camera::Frustum;
+use crate::vulkan::vkenums::{ShaderStage, VertexInputRate, BlendFactor};
+
+use crate::vulkan::{Instance, Device, RenderPass, Shader, Pipeline, PipelineBuilder, DescriptorSet};
+use crate::vulkan::buffer::{Buffer, BufferUsage, UniformData, CommandBufferBuilder};
+
+use cgmath::{Vector3, Vector4, Quaternion, Rotation};
+
+use std::mem;
+use std::sync::Arc;
+
+const MAX_DEBUG_LINES: usize = 16384; // per frame, tested and overlay lines together
+const SPHERE_SEGMENTS: usize = 24;
+
+/**
+** A shape outlined by the debug lines, in world space.
+** Obb size is the full size of the box before it is rotated.
+**/
+#[derive(Clone, PartialEq, Debug)]
+pub enum DebugShape {
+  Line(Vector3<f32>, Vector3<f32>),
+  Aabb(Vector3<f32>, Vector3<f32>), // min, max
+  Obb(Vector3<f32>, Vector3<f32>, Quaternion<f32>), // centre, size, rotation
+  Sphere(Vector3<f32>, f32), // centre, radius
+  Frustum(Frustum),
+  Axes(Vector3<f32>, Quaternion<f32>, f32), // position, rotation, length, x red, y green and z blue
+}
+
+#[derive(Clone)]
+pub struct DebugVertex {
+  pos: Vector3<f32>,
+  colour: Vector4<f32>,
+}
+
+impl DebugVertex {
+  pub fn vertex_input_binding() -> vk::VertexInputBindingDescription {
+    vk::VertexInputBindingDescription {
+      binding: 0,
+      stride: (mem::size_of::<DebugVertex>()) as u32,
+      inputRate: VertexInputRate::Vertex.to_bits(),
+    }
+  }
+  
+  pub fn vertex_input_attributes() -> Vec<vk::VertexInputAttributeDescription> {
+    let mut vertex_input_attribute_descriptions: Vec<vk::VertexInputAttributeDescription> = Vec::with_capacity(2);
+    
+    vertex_input_attribute_descriptions.push(
+      vk::VertexInputAttributeDescription {
+        location: 0,
+        binding: 0,
+        format: vk::FORMAT_R32G32B32_SFLOAT,
+        offset: offset_of!(DebugVertex, pos) as u32,
+      }
+    );
+    
+    vertex_input_attribute_descriptions.push(
+      vk::VertexInputAttributeDescription {
+        location: 1,
+        binding: 0,
+        format: vk::FORMAT_R32G32B32A32_SFLOAT,
+        offset: offset_of!(DebugVertex, colour) as u32,
+      }
+    );
+    
+    vertex_input_attribute_descriptions
+  }
+}
+
+// the twelve edges of a box, corners are numbered with x in the first bit, y in the second and z in the third
+pub fn box_edges(corners: &[Vector3<f32>]) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+  let mut edges = Vec::with_capacity(12);
+  for i in 0..8 {
+    for bit in &[1, 2, 4] {
+      if i & bit == 0 {
+        edges.push((corners[i], corners[i | bit]));
+      }
+    }
+  }
+  
+  edges
+}
+
+/**
+** Immediate mode lines drawn over the lit scene at the end of the forward
+** subpass, either tested against the model depth or on top of everything.
+** Shapes are added every frame and cleared once they are drawn.
+**/
+pub struct DebugLines {
+  vertex_shader: Shader,
+  fragment_shader: Shader,
+  pipeline: Pipeline, // depth tested
+  overlay_pipeline: Pipeline,
+  tested: Vec<DebugVertex>, // cleared every frame
+  overlay: Vec<DebugVertex>, // cleared every frame
+  vertex_buffer: Buffer<DebugVertex>, // per frame, the tested lines then the overlay lines
+  warned: bool, // the line limit is only printed once
+}
+
+impl DebugLines {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, render_pass: &RenderPass, subpass: u32, descriptor_set: &DescriptorSet, num_frames: u32) -> DebugLines {
+    let vertex_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkDebugLineVert.spv"));
+    let fragment_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkDebugLineFrag.spv"));
+    
+    let push_constant_size = UniformData::new()
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .size_non_aligned();
+    
+    let mut pipelines = Vec::with_capacity(2);
+    for depth_tested in &[true, false] {
+      let mut pipeline = PipelineBuilder::new()
+                           .vertex_shader(*vertex_shader.get_shader())
+                           .fragment_shader(*fragment_shader.get_shader())
+                           .push_constants(ShaderStage::Vertex, push_constant_size as u32)
+                           .render_pass(render_pass.clone())
+                           .subpass(subpass)
+                           .descriptor_set_layout(descriptor_set.layouts_clone())
+                           .vertex_binding(vec!(DebugVertex::vertex_input_binding()))
+                           .vertex_attributes(DebugVertex::vertex_input_attributes())
+                           .topology_line_list()
+                           .polygon_mode_fill()
+                           .cull_mode_none()
+                           .alpha_blend_factors(BlendFactor::One, BlendFactor::OneMinusSrcAlpha)
+                           .front_face_counter_clockwise();
+      
+      if *depth_tested {
+        pipeline = pipeline.enable_depth_test();
+      }
+      
+      pipelines.push(pipeline.build(Arc::clone(&device)));
+    }
+    
+    let overlay_pipeline = pipelines.remove(1);
+    let pipeline = pipelines.remove(0);
+    
+    let vertex_buffer = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::vertex_buffer(), num_frames, (MAX_DEBUG_LINES*2) as u64);
+    
+    DebugLines {
+      vertex_shader,
+      fragment_shader,
+      pipeline,
+      overlay_pipeline,
+      tested: Vec::new(),
+      overlay: Vec::new(),
+      vertex_buffer,
+      warned: false,
+    }
+  }
+  
+  // colour is as it should appear on screen
+  pub fn add_shape(&mut self, shape: &DebugShape, colour: Vector4<f32>, depth_tested: bool) {
+    let mut lines = Vec::new();
+    
+    match shape {
+      DebugShape::Line(start, end) => {
+        lines.push((*start, *end, colour));
+      },
+      DebugShape::Aabb(min, max) => {
+        let corners = (0..8).map(|i| {
+          Vector3::new(if i & 1 == 0 { min.x } else { max.x },
+                       if i & 2 == 0 { min.y } else { max.y },
+                       if i & 4 == 0 { min.z } else { max.z })
+        }).collect::<Vec<Vector3<f32>>>();
+        lines.extend(box_edges(&corners).into_iter().map(|(start, end)| (start, end, colour)));
+      },
+      DebugShape::Obb(centre, size, rotation) => {
+        let half = size*0.5;
+        let corners = (0..8).map(|i| {
+          let corner = Vector3::new(if i & 1 == 0 { -half.x } else { half.x },
+                                    if i & 2 == 0 { -half.y } else { half.y },
+                                    if i & 4 == 0 { -half.z } else { half.z });
+          centre + rotation.rotate_vector(corner)
+        }).collect::<Vec<Vector3<f32>>>();
+        lines.extend(box_edges(&corners).into_iter().map(|(start, end)| (start, end, colour)));
+      },
+      DebugShape::Sphere(centre, radius) => {
+        // a circle around each axis
+        let step = std::f32::consts::PI*2.0 / SPHERE_SEGMENTS as f32;
+        for axis in 0..3 {
+          let point = |i: usize| {
+            let (sin, cos) = (i as f32 * step).sin_cos();
+            let offset = match axis {
+              0 => Vector3::new(0.0, cos, sin),
+              1 => Vector3::new(cos, 0.0, sin),
+              _ => Vector3::new(cos, sin, 0.0),
+            };
+            centre + offset*(*radius)
+          };
+          
+          for i in 0..SPHERE_SEGMENTS {
+            lines.push((point(i), point(i+1), colour));
+          }
+        }
+      },
+      DebugShape::Frustum(frustum) => {
+        let corners = frustum.corners();
+        for i in 0..4 {
+          let next = (i+1)%4;
+          lines.push((corners[i], corners[next], colour));
+          lines.push((corners[i+4], corners[next+4], colour));
+          lines.push((corners[i], corners[i+4], colour));
+        }
+      },
+      DebugShape::Axes(position, rotation, length) => {
+        let axes = [(Vector3::unit_x(), Vector4::new(1.0, 0.0, 0.0, colour.w)),
+                    (Vector3::unit_y(), Vector4::new(0.0, 1.0, 0.0, colour.w)),
+                    (Vector3::unit_z(), Vector4::new(0.0, 0.0, 1.0, colour.w))];
+        for (axis, axis_colour) in &axes {
+          lines.push((*position, position + rotation.rotate_vector(*axis)*(*length), *axis_colour));
+        }
+      },
+    }
+    
+    for (start, end, colour) in lines {
+      self.add_line(start, end, colour, depth_tested);
+    }
+  }
+  
+  fn add_line(&mut self, start: Vector3<f32>, end: Vector3<f32>, colour: Vector4<f32>, depth_tested: bool) {
+    if self.tested.len() + self.overlay.len() >= MAX_DEBUG_LINES*2 {
+      if !self.warned {
+        println!("Error: More than {} debug lines in a frame, the rest are skipped", MAX_DEBUG_LINES);
+        self.warned = true;
+      }
+      return;
+    }
+    
+    let vertices = if depth_tested { &mut self.tested } else { &mut self.overlay };
+    vertices.push(DebugVertex { pos: start, colour });
+    vertices.push(DebugVertex { pos: end, colour });
+  }
+  
+  /**
+  ** Draws and clears the lines added this frame, inside the subpass the
+  ** pipelines were made for. push_constant_data is the camera VkDebugLine.vert
+  ** takes, position and fov, center and aspect, then up.
+  **/
+  pub fn draw(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize, push_constant_data: UniformData, descriptor_set: &DescriptorSet) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    let num_tested = self.tested.len() as u32;
+    let num_overlay = self.overlay.len() as u32;
+    if num_tested + num_overlay == 0 {
+      return cmd;
+    }
+    
+    let mut vertices = mem::take(&mut self.tested);
+    vertices.append(&mut self.overlay);
+    self.vertex_buffer.fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, vertices);
+    
+    for (pipeline, first_vertex, vertex_count) in &[(&self.pipeline, 0, num_tested), (&self.overlay_pipeline, num_tested, num_overlay)] {
+      if *vertex_count == 0 {
+        continue;
+      }
+      
+      cmd = cmd.push_constants(Arc::clone(&device), pipeline, ShaderStage::Vertex, push_constant_data.clone());
+      let offset = *first_vertex as u64 * mem::size_of::<DebugVertex>() as u64;
+      cmd = cmd.draw_with_offset(Arc::clone(&device), self.vertex_buffer.internal_object(current_buffer), offset, *vertex_count, pipeline, vec!(*descriptor_set.set(0)));
+    }
+    
+    cmd
+  }
+  
+  pub fn destroy(&self, device: Arc<Device>) {
+    self.vertex_buffer.destroy(Arc::clone(&device));
+    self.pipeline.destroy(Arc::clone(&device));
+    self.overlay_pipeline.destroy(Arc::clone(&device));
+    self.vertex_shader.destroy(Arc::clone(&device));
+    self.fragment_shader.destroy(Arc::clone(&device));
+  }
+}
@@ -0,0 +1,447 @@
+use vk;
+
+use crate::vulkan::vkenums::{ImageType, ImageUsage, ImageViewType, SampleCount, ImageTiling, AttachmentLoadOp, AttachmentStoreOp, ImageLayout, ShaderStage, Filter, AddressMode, MipmapMode, VkBool};
+
+use crate::vulkan::{Instance, Device, RenderPass, Shader, Pipeline, PipelineBuilder, DescriptorSet, UpdateDescriptorSets, DescriptorSetBuilder, ImageAttachment, AttachmentInfo, SubpassInfo, RenderPassBuilder, Sampler, SamplerBuilder};
+use crate::vulkan::buffer::{Buffer, UniformData, Framebuffer, CommandBuffer, CommandBufferBuilder};
+use crate::vulkan::pool::{DescriptorPool, CommandPool};
+use crate::shaders::FinalShader;
+use crate::shaders::FinalVertex;
+
+use cgmath::Vector4;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+pub const ENVIRONMENT_FORMAT: vk::Format = vk::FORMAT_R16G16B16A16_SFLOAT;
+const ENVIRONMENT_SIZE: u32 = 256;
+const ENVIRONMENT_MIPS: u32 = 9;
+const IRRADIANCE_SIZE: u32 = 32;
+// mip of the environment the irradiance integral samples, roughly the size of its steps
+const IRRADIANCE_LOD: f32 = 3.0;
+const PREFILTERED_SIZE: u32 = 128;
+const PREFILTERED_MIPS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 256;
+const MIN_SKY_SIZE: u32 = 256;
+const MAX_SKY_SIZE: u32 = 2048;
+
+/**
+** Image based lighting from an equirectangular environment, hdr files are
+** read as they are and anything else is treated as srgb. The environment is
+** drawn into a cube which is convolved into an irradiance cube for ambient
+** diffuse and a prefiltered cube with a roughness per mip for ambient specular,
+** alongside a brdf lookup table that never changes.
+**
+** The cubes are created once and only redrawn, so descriptor sets holding
+** them stay valid when the environment changes. Without an environment they
+** are cleared to black.
+**/
+pub struct EnvironmentMap {
+  location: Option<String>,
+  loaded: bool, // false when location failed to load, so it isn't retried every frame
+  render_pass: RenderPass,
+  cube_pipeline: Pipeline,
+  irradiance_pipeline: Pipeline,
+  prefilter_pipeline: Pipeline,
+  brdf_pipeline: Pipeline,
+  vertex_shader: Shader,
+  cube_shader: Shader,
+  irradiance_shader: Shader,
+  prefilter_shader: Shader,
+  brdf_shader: Shader,
+  source_descriptor_set: DescriptorSet,
+  cube_descriptor_set: DescriptorSet,
+  vertex_buffer: Buffer<FinalVertex>,
+  sampler: Sampler,
+  environment: ImageAttachment,
+  irradiance: ImageAttachment,
+  prefiltered: ImageAttachment,
+  brdf_lut: ImageAttachment,
+}
+
+impl EnvironmentMap {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, descriptor_set_pool: &DescriptorPool, command_pool: &CommandPool, graphics_queue: &vk::Queue) -> EnvironmentMap {
+    let vertex_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelDefferedVert.spv"));
+    let cube_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkEnvironmentCubeFrag.spv"));
+    let irradiance_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkEnvironmentIrradianceFrag.spv"));
+    let prefilter_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkEnvironmentPrefilterFrag.spv"));
+    let brdf_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkEnvironmentBrdfFrag.spv"));
+    
+    let colour_attachment = AttachmentInfo::new()
+                                .format(ENVIRONMENT_FORMAT)
+                                .multisample(&SampleCount::OneBit)
+                                .load(AttachmentLoadOp::Clear)
+                                .store(AttachmentStoreOp::Store)
+                                .stencil_load(AttachmentLoadOp::DontCare)
+                                .stencil_store(AttachmentStoreOp::DontCare)
+                                .initial_layout(ImageLayout::Undefined)
+                                .final_layout(ImageLayout::ShaderReadOnlyOptimal)
+                                .image_usage(ImageLayout::ColourAttachmentOptimal);
+    
+    let subpass = SubpassInfo::new().add_colour_attachment(0);
+    
+    let render_pass = RenderPassBuilder::new()
+                        .add_attachment(colour_attachment)
+                        .add_subpass(subpass)
+                        .build(Arc::clone(&device));
+    
+    let source_descriptor_set = DescriptorSetBuilder::new()
+                                  .fragment_combined_image_sampler(0)
+                                  .build(Arc::clone(&device), descriptor_set_pool, 1);
+    let cube_descriptor_set = DescriptorSetBuilder::new()
+                                .fragment_combined_image_sampler(0)
+                                .build(Arc::clone(&device), descriptor_set_pool, 1);
+    
+    let cube_pipeline = EnvironmentMap::create_pipeline(Arc::clone(&device), &vertex_shader, &cube_shader, &render_pass, &source_descriptor_set);
+    let irradiance_pipeline = EnvironmentMap::create_pipeline(Arc::clone(&device), &vertex_shader, &irradiance_shader, &render_pass, &cube_descriptor_set);
+    let prefilter_pipeline = EnvironmentMap::create_pipeline(Arc::clone(&device), &vertex_shader, &prefilter_shader, &render_pass, &cube_descriptor_set);
+    let brdf_pipeline = EnvironmentMap::create_pipeline(Arc::clone(&device), &vertex_shader, &brdf_shader, &render_pass, &source_descriptor_set);
+    
+    let vertex_buffer = FinalShader::create_vertex_buffer(Arc::clone(&instance), Arc::clone(&device), command_pool, graphics_queue);
+    
+    let sampler = SamplerBuilder::new()
+                    .min_filter(Filter::Linear)
+                    .mag_filter(Filter::Linear)
+                    .address_mode(AddressMode::ClampToEdge)
+                    .mipmap_mode(MipmapMode::Linear)
+                    .anisotropy(VkBool::False)
+                    .max_anisotropy(1.0)
+                    .max_lod(ENVIRONMENT_MIPS as f32)
+                    .build(Arc::clone(&device));
+    
+    let usage = ImageUsage::colour_attachment_transfer_sampled();
+    let environment = ImageAttachment::create_cube_attachment(Arc::clone(&instance), Arc::clone(&device), &usage, &ENVIRONMENT_FORMAT, ENVIRONMENT_SIZE, ENVIRONMENT_MIPS);
+    let irradiance = ImageAttachment::create_cube_attachment(Arc::clone(&instance), Arc::clone(&device), &usage, &ENVIRONMENT_FORMAT, IRRADIANCE_SIZE, 1);
+    let prefiltered = ImageAttachment::create_cube_attachment(Arc::clone(&instance), Arc::clone(&device), &usage, &ENVIRONMENT_FORMAT, PREFILTERED_SIZE, PREFILTERED_MIPS);
+    let brdf_lut = ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::colour_attachment_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &ENVIRONMENT_FORMAT, BRDF_LUT_SIZE, BRDF_LUT_SIZE);
+    
+    UpdateDescriptorSets::new()
+      .add_sampled_image(0, &environment, ImageLayout::ShaderReadOnlyOptimal, &sampler)
+     .finish_update(Arc::clone(&device), &cube_descriptor_set);
+    
+    let environment_map = EnvironmentMap {
+      location: None,
+      loaded: false,
+      render_pass,
+      cube_pipeline,
+      irradiance_pipeline,
+      prefilter_pipeline,
+      brdf_pipeline,
+      vertex_shader,
+      cube_shader,
+      irradiance_shader,
+      prefilter_shader,
+      brdf_shader,
+      source_descriptor_set,
+      cube_descriptor_set,
+      vertex_buffer,
+      sampler,
+      environment,
+      irradiance,
+      prefiltered,
+      brdf_lut,
+    };
+    
+    environment_map.draw_brdf_lut(Arc::clone(&device), command_pool, graphics_queue);
+    environment_map.clear(Arc::clone(&device), command_pool, graphics_queue);
+    
+    environment_map
+  }
+  
+  pub fn enabled(&self) -> bool {
+    self.loaded
+  }
+  
+  pub fn location(&self) -> &Option<String> {
+    &self.location
+  }
+  
+  pub fn irradiance(&self) -> &ImageAttachment {
+    &self.irradiance
+  }
+  
+  pub fn prefiltered(&self) -> &ImageAttachment {
+    &self.prefiltered
+  }
+  
+  pub fn brdf_lut(&self) -> &ImageAttachment {
+    &self.brdf_lut
+  }
+  
+  pub fn sampler(&self) -> &Sampler {
+    &self.sampler
+  }
+  
+  // mip of the prefiltered cube holding fully rough reflections
+  pub fn max_lod(&self) -> f32 {
+    (PREFILTERED_MIPS-1) as f32
+  }
+  
+  /**
+  ** Redraws every cube from the image at location, or clears them with None.
+  ** The device must be idle as the cubes may be in use by earlier frames.
+  **/
+  pub fn set(&mut self, instance: Arc<Instance>, device: Arc<Device>, location: Option<String>, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    if location == self.location {
+      return;
+    }
+    
+    self.location = location.clone();
+    self.loaded = false;
+    
+    let equirectangular = match &location {
+      Some(location) => self.upload_equirectangular(Arc::clone(&instance), Arc::clone(&device), location, command_pool, graphics_queue),
+      None => None,
+    };
+    
+    let equirectangular = match equirectangular {
+      Some(equirectangular) => equirectangular,
+      None => {
+        self.clear(Arc::clone(&device), command_pool, graphics_queue);
+        return;
+      }
+    };
+    
+    self.draw_cube(Arc::clone(&device), &self.environment, 1, Some((&self.cube_pipeline, &self.source_descriptor_set)), |_mip| {
+      Vector4::new(0.0, 0.0, ENVIRONMENT_SIZE as f32, 0.0)
+    }, command_pool, graphics_queue);
+    self.environment.generate_cube_mipmaps(Arc::clone(&device), command_pool, graphics_queue);
+    
+    self.draw_cube(Arc::clone(&device), &self.irradiance, 1, Some((&self.irradiance_pipeline, &self.cube_descriptor_set)), |_mip| {
+      Vector4::new(0.0, IRRADIANCE_LOD, 0.0, 0.0)
+    }, command_pool, graphics_queue);
+    
+    self.draw_cube(Arc::clone(&device), &self.prefiltered, PREFILTERED_MIPS, Some((&self.prefilter_pipeline, &self.cube_descriptor_set)), |mip| {
+      Vector4::new(0.0, mip as f32 / (PREFILTERED_MIPS-1) as f32, ENVIRONMENT_SIZE as f32, 0.0)
+    }, command_pool, graphics_queue);
+    
+    equirectangular.destroy(Arc::clone(&device));
+    
+    self.loaded = true;
+  }
+  
+  /**
+  ** A cube holding the image at location with faces about a quarter of its
+  ** width, for drawing as a sky. None if the image can't be loaded.
+  **/
+  pub fn create_cube_from_equirectangular(&self, instance: Arc<Instance>, device: Arc<Device>, location: &str, command_pool: &CommandPool, graphics_queue: &vk::Queue) -> Option<ImageAttachment> {
+    let equirectangular = self.upload_equirectangular(Arc::clone(&instance), Arc::clone(&device), location, command_pool, graphics_queue)?;
+    
+    let size = (equirectangular.get_size().0 / 4).next_power_of_two().clamp(MIN_SKY_SIZE, MAX_SKY_SIZE);
+    let cube = ImageAttachment::create_cube_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageUsage::colour_attachment_sampled(), &ENVIRONMENT_FORMAT, size, 1);
+    
+    self.draw_cube(Arc::clone(&device), &cube, 1, Some((&self.cube_pipeline, &self.source_descriptor_set)), |_mip| {
+      Vector4::new(0.0, 0.0, size as f32, 0.0)
+    }, command_pool, graphics_queue);
+    
+    equirectangular.destroy(Arc::clone(&device));
+    
+    Some(cube)
+  }
+  
+  // loads the image and points the source descriptor set at it
+  fn upload_equirectangular(&self, instance: Arc<Instance>, device: Arc<Device>, location: &str, command_pool: &CommandPool, graphics_queue: &vk::Queue) -> Option<ImageAttachment> {
+    let (data, width, height) = match EnvironmentMap::load_equirectangular(location) {
+      Some(pixels) => pixels,
+      None => {
+        println!("Error: Failed to load equirectangular image {}", location);
+        return None;
+      }
+    };
+    
+    let equirectangular = ImageAttachment::create_texture_from_pixels(Arc::clone(&instance), Arc::clone(&device), data, width, height, &ImageType::Type2D, &ImageTiling::Optimal, &SampleCount::OneBit, &ImageViewType::Type2D, ENVIRONMENT_FORMAT, command_pool, graphics_queue);
+    
+    UpdateDescriptorSets::new()
+      .add_sampled_image(0, &equirectangular, ImageLayout::ShaderReadOnlyOptimal, &self.sampler)
+     .finish_update(Arc::clone(&device), &self.source_descriptor_set);
+    
+    Some(equirectangular)
+  }
+  
+  fn clear(&self, device: Arc<Device>, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    let no_info = |_mip| Vector4::new(0.0, 0.0, 0.0, 0.0);
+    
+    self.draw_cube(Arc::clone(&device), &self.environment, ENVIRONMENT_MIPS, None, no_info, command_pool, graphics_queue);
+    self.draw_cube(Arc::clone(&device), &self.irradiance, 1, None, no_info, command_pool, graphics_queue);
+    self.draw_cube(Arc::clone(&device), &self.prefiltered, PREFILTERED_MIPS, None, no_info, command_pool, graphics_queue);
+  }
+  
+  fn draw_brdf_lut(&self, device: Arc<Device>, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    let command_buffer = Arc::new(CommandBuffer::begin_single_time_command(Arc::clone(&device), command_pool));
+    let cmd = CommandBufferBuilder::primary_one_time_submit(Arc::clone(&command_buffer));
+    
+    let (cmd, framebuffer) = self.draw_face(Arc::clone(&device), cmd, self.brdf_lut.get_image_view(), BRDF_LUT_SIZE, Some((&self.brdf_pipeline, &self.source_descriptor_set, Vector4::new(0.0, 0.0, 0.0, 0.0))));
+    cmd.destroy();
+    
+    command_buffer.end_single_time_command(Arc::clone(&device), command_pool, graphics_queue);
+    framebuffer.destroy(Arc::clone(&device));
+  }
+  
+  /**
+  ** Draws the six faces of every mip level below mips, info gives the push
+  ** constants for a mip with the face filled in. No pipeline only clears.
+  **/
+  fn draw_cube<F: Fn(u32) -> Vector4<f32>>(&self, device: Arc<Device>, cube: &ImageAttachment, mips: u32, pipeline: Option<(&Pipeline, &DescriptorSet)>, info: F, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    let command_buffer = Arc::new(CommandBuffer::begin_single_time_command(Arc::clone(&device), command_pool));
+    let mut cmd = CommandBufferBuilder::primary_one_time_submit(Arc::clone(&command_buffer));
+    
+    let mut temporaries = Vec::new();
+    for mip in 0..mips {
+      let size = (cube.get_size().0 >> mip).max(1);
+      for face in 0..6 {
+        let view = cube.create_face_view(Arc::clone(&device), face, mip);
+        let draw = pipeline.map(|(pipeline, descriptor_set)| {
+          let mut face_info = info(mip);
+          face_info.x = face as f32;
+          (pipeline, descriptor_set, face_info)
+        });
+        
+        let (new_cmd, framebuffer) = self.draw_face(Arc::clone(&device), cmd, view, size, draw);
+        cmd = new_cmd;
+        temporaries.push((framebuffer, view));
+      }
+    }
+    cmd.destroy();
+    
+    command_buffer.end_single_time_command(Arc::clone(&device), command_pool, graphics_queue);
+    
+    for (framebuffer, view) in temporaries {
+      framebuffer.destroy(Arc::clone(&device));
+      ImageAttachment::destroy_view(Arc::clone(&device), view);
+    }
+  }
+  
+  // the framebuffer has to outlive the command buffer
+  fn draw_face(&self, device: Arc<Device>, cmd: CommandBufferBuilder, view: vk::ImageView, size: u32, draw: Option<(&Pipeline, &DescriptorSet, Vector4<f32>)>) -> (CommandBufferBuilder, Framebuffer) {
+    let extent = vk::Extent2D { width: size, height: size };
+    let framebuffer = Framebuffer::new_with_imageviews(Arc::clone(&device), &self.render_pass, &extent, vec!(view));
+    
+    let clear_values = vec!(vk::ClearValue { color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] } });
+    
+    let mut cmd = cmd.begin_render_pass(Arc::clone(&device), &clear_values, &self.render_pass, framebuffer.internal_object(), &extent);
+    
+    if let Some((pipeline, descriptor_set, info)) = draw {
+      cmd = cmd.set_viewport(Arc::clone(&device), 0.0, 0.0, size as f32, size as f32);
+      cmd = cmd.set_scissor(Arc::clone(&device), 0, 0, size, size);
+      cmd = cmd.push_constants(Arc::clone(&device), pipeline, ShaderStage::Fragment, UniformData::new().add_vector4(info));
+      cmd = cmd.draw(Arc::clone(&device), self.vertex_buffer.internal_object(0), 3, pipeline, vec!(*descriptor_set.set(0)), Vec::with_capacity(0));
+    }
+    
+    let cmd = cmd.end_render_pass(Arc::clone(&device));
+    
+    (cmd, framebuffer)
+  }
+  
+  /**
+  ** Linear rgba half floats for the whole image, hdr files keep their
+  ** range and everything else is converted from srgb.
+  **/
+  fn load_equirectangular(location: &str) -> Option<(Vec<u8>, u32, u32)> {
+    let is_hdr = Path::new(location).extension().map(|extension| extension.to_string_lossy().to_lowercase() == "hdr").unwrap_or(false);
+    
+    let (width, height, pixels) = if is_hdr {
+      let file = File::open(location).ok()?;
+      let decoder = image::hdr::HDRDecoder::new(BufReader::new(file)).ok()?;
+      let metadata = decoder.metadata();
+      let pixels: Vec<[f32; 3]> = decoder.read_image_hdr().ok()?.iter().map(|pixel| pixel.data).collect();
+      (metadata.width, metadata.height, pixels)
+    } else {
+      let image = image::open(location).ok()?.to_rgba();
+      let (width, height) = image.dimensions();
+      let pixels: Vec<[f32; 3]> = image.pixels().map(|pixel| {
+        [srgb_to_linear(pixel.data[0]), srgb_to_linear(pixel.data[1]), srgb_to_linear(pixel.data[2])]
+      }).collect();
+      (width, height, pixels)
+    };
+    
+    Some((rgb_to_half_floats(&pixels), width, height))
+  }
+  
+  fn create_pipeline(device: Arc<Device>, vertex_shader: &Shader, fragment_shader: &Shader, render_pass: &RenderPass, descriptor_set: &DescriptorSet) -> Pipeline {
+    let push_constant_size = UniformData::new()
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .size_non_aligned();
+    
+    PipelineBuilder::new()
+      .vertex_shader(*vertex_shader.get_shader())
+      .fragment_shader(*fragment_shader.get_shader())
+      .push_constants(ShaderStage::Fragment, push_constant_size as u32)
+      .render_pass(render_pass.clone())
+      .descriptor_set_layout(descriptor_set.layouts_clone())
+      .vertex_binding(vec!(FinalVertex::vertex_input_binding()))
+      .vertex_attributes(FinalVertex::vertex_input_attributes())
+      .topology_triangle_list()
+      .polygon_mode_fill()
+      .cull_mode_none()
+      .front_face_counter_clockwise()
+      .disable_blend()
+      .build(Arc::clone(&device))
+  }
+  
+  pub fn destroy(&self, device: Arc<Device>) {
+    self.environment.destroy(Arc::clone(&device));
+    self.irradiance.destroy(Arc::clone(&device));
+    self.prefiltered.destroy(Arc::clone(&device));
+    self.brdf_lut.destroy(Arc::clone(&device));
+    self.sampler.destroy(Arc::clone(&device));
+    self.vertex_buffer.destroy(Arc::clone(&device));
+    
+    self.source_descriptor_set.destroy(Arc::clone(&device));
+    self.cube_descriptor_set.destroy(Arc::clone(&device));
+    
+    self.cube_pipeline.destroy(Arc::clone(&device));
+    self.irradiance_pipeline.destroy(Arc::clone(&device));
+    self.prefilter_pipeline.destroy(Arc::clone(&device));
+    self.brdf_pipeline.destroy(Arc::clone(&device));
+    
+    self.vertex_shader.destroy(Arc::clone(&device));
+    self.cube_shader.destroy(Arc::clone(&device));
+    self.irradiance_shader.destroy(Arc::clone(&device));
+    self.prefilter_shader.destroy(Arc::clone(&device));
+    self.brdf_shader.destroy(Arc::clone(&device));
+    
+    self.render_pass.destroy(Arc::clone(&device));
+  }
+}
+
+// linear rgb to the rgba half floats of ENVIRONMENT_FORMAT
+pub fn rgb_to_half_floats(pixels: &[[f32; 3]]) -> Vec<u8> {
+  let mut data = Vec::with_capacity(pixels.len()*8);
+  for pixel in pixels {
+    for channel in &[pixel[0], pixel[1], pixel[2], 1.0] {
+      data.extend_from_slice(&f32_to_f16(*channel).to_le_bytes());
+    }
+  }
+  
+  data
+}
+
+pub fn srgb_to_linear(value: u8) -> f32 {
+  (value as f32 / 255.0).powf(2.2)
+}
+
+// negatives and nans go to black, anything too bright for a half float is clamped to its largest value
+fn f32_to_f16(value: f32) -> u16 {
+  if value.is_nan() || value <= 0.0 {
+    return 0;
+  }
+  
+  let bits = value.to_bits();
+  let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+  let mantissa = bits & 0x7fffff;
+  
+  if exponent >= 31 {
+    0x7bff
+  } else if exponent <= 0 {
+    if exponent < -10 {
+      0
+    } else {
+      // subnormal, put the implicit bit back before shifting down
+      ((mantissa | 0x800000) >> (14 - exponent)) as u16
+    }
+  } else {
+    ((exponent as u32) << 10 | (mantissa >> 13)) as u16
+  }
+}
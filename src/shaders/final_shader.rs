@@ -12,6 +12,27 @@ use cgmath::{Vector2, Vector4};
 use std::mem;
 use std::sync::Arc;
 
+// how the hdr model texture is brought into the screen's range
+// must match the debug view check in tonemap in VkFinal.frag
+const NO_TONEMAPPING: f32 = -1.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tonemapper {
+  Reinhard,
+  Aces,
+  Uncharted,
+}
+
+impl Tonemapper {
+  fn to_index(self) -> f32 {
+    match self {
+      Tonemapper::Reinhard => 0.0,
+      Tonemapper::Aces => 1.0,
+      Tonemapper::Uncharted => 2.0,
+    }
+  }
+}
+
 #[derive(Clone)]
 pub struct FinalVertex {
   pos: Vector2<f32>,
@@ -65,6 +86,11 @@ pub struct FinalShader {
   
   vertex_shader: Shader,
   fragment_shader: Shader,
+  
+  exposure: f32,
+  tonemapper: Tonemapper,
+  bloom_strength: f32,
+  debug_view: bool, // the model texture is shown as it is
 }
 
 impl FinalShader {
@@ -96,18 +122,22 @@ impl FinalShader {
     for i in 0..image_views.len() {
       descriptor_sets.push(DescriptorSetBuilder::new()
         .fragment_combined_image_sampler(0)
+        .fragment_combined_image_sampler(1)
         .build(Arc::clone(&device), &descriptor_set_pool, 1));
       
       UpdateDescriptorSets::new()
        .add_sampled_image(0, texture_image, ImageLayout::ShaderReadOnlyOptimal, sampler)
+       .add_sampled_image(1, texture_image, ImageLayout::ShaderReadOnlyOptimal, sampler)
        .finish_update(Arc::clone(&device), &descriptor_sets[i]);
        
       ds.push(DescriptorSetBuilder::new()
         .fragment_combined_image_sampler(0)
+        .fragment_combined_image_sampler(1)
         .build(Arc::clone(&device), &descriptor_set_pool, 1));
       
       UpdateDescriptorSets::new()
        .add_sampled_image(0, texture_image, ImageLayout::ShaderReadOnlyOptimal, sampler)
+       .add_sampled_image(1, texture_image, ImageLayout::ShaderReadOnlyOptimal, sampler)
        .finish_update(Arc::clone(&device), &ds[i]);
     }
     
@@ -129,9 +159,32 @@ impl FinalShader {
       
       vertex_shader,
       fragment_shader,
+      
+      exposure: 1.0,
+      tonemapper: Tonemapper::Aces,
+      bloom_strength: 0.1,
+      debug_view: false,
     }
   }
   
+  pub fn set_exposure(&mut self, exposure: f32) {
+    self.exposure = exposure.max(0.0);
+  }
+  
+  pub fn set_tonemapper(&mut self, tonemapper: Tonemapper) {
+    self.tonemapper = tonemapper;
+  }
+  
+  // how much of the bloom is added over the model texture, 0 turns it off
+  pub fn set_bloom_strength(&mut self, strength: f32) {
+    self.bloom_strength = strength.max(0.0);
+  }
+  
+  // the model debug views skip exposure, bloom and tonemapping
+  pub fn set_debug_view(&mut self, enabled: bool) {
+    self.debug_view = enabled;
+  }
+  
   pub fn recreate(&mut self, device: Arc<Device>, image_views: &Vec<vk::ImageView>, new_extent: &vk::Extent2D, textures: Vec<(String, ImageAttachment)>, sampler: &Sampler) {
     for i in 0..self.framebuffers.len() {
       self.framebuffers[i].destroy(Arc::clone(&device));
@@ -143,9 +196,11 @@ impl FinalShader {
       self.framebuffers.push(Framebuffer::new(Arc::clone(&device), &self.renderpass, &new_extent, &image_views[i]));
       UpdateDescriptorSets::new()
          .add_sampled_image(0, &textures[0].1, ImageLayout::ShaderReadOnlyOptimal, sampler)
+         .add_sampled_image(1, &textures[0].1, ImageLayout::ShaderReadOnlyOptimal, sampler)
          .finish_update(Arc::clone(&device), &self.descriptor_sets[i]);
       UpdateDescriptorSets::new()
          .add_sampled_image(0, &textures[0].1, ImageLayout::ShaderReadOnlyOptimal, sampler)
+         .add_sampled_image(1, &textures[0].1, ImageLayout::ShaderReadOnlyOptimal, sampler)
          .finish_update(Arc::clone(&device), &self.ds[i]);
     }
   }
@@ -210,7 +265,7 @@ impl FinalShader {
     cmd.begin_render_pass(Arc::clone(&device), clear_value, &self.renderpass, &self.framebuffers[current_buffer].internal_object(), &window_size)
   }
   
-  pub fn draw_to_screen(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, texture_image: &ImageAttachment, sampler: &Sampler, window_width: f32, window_height: f32, current_buffer: usize, is_model_texture: bool) -> CommandBufferBuilder {
+  pub fn draw_to_screen(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, texture_image: &ImageAttachment, bloom_image: &ImageAttachment, sampler: &Sampler, window_width: f32, window_height: f32, current_buffer: usize, is_model_texture: bool) -> CommandBufferBuilder {
     let mut cmd = cmd;
     
     let mut ui = 0.0;
@@ -219,10 +274,12 @@ impl FinalShader {
       ui = 1.0;
       UpdateDescriptorSets::new()
          .add_sampled_image(0, texture_image, ImageLayout::ShaderReadOnlyOptimal, sampler)
+         .add_sampled_image(1, texture_image, ImageLayout::ShaderReadOnlyOptimal, sampler)
          .finish_update(Arc::clone(&device), &self.descriptor_sets[current_buffer]);
     } else {
       UpdateDescriptorSets::new()
          .add_sampled_image(0, texture_image, ImageLayout::ShaderReadOnlyOptimal, sampler)
+         .add_sampled_image(1, bloom_image, ImageLayout::General, sampler)
          .finish_update(Arc::clone(&device), &self.ds[current_buffer]);
     }
     
@@ -232,10 +289,16 @@ impl FinalShader {
     let right = window_width;
     let projection_details = Vector4::new(right, top, 0.0, 0.0);
     
+    let tonemapping = if self.debug_view {
+      Vector4::new(ui, 1.0, NO_TONEMAPPING, 0.0)
+    } else {
+      Vector4::new(ui, self.exposure, self.tonemapper.to_index(), self.bloom_strength)
+    };
+    
     let push_constant_data = UniformData::new()
                                .add_vector4(model)
                                .add_vector4(projection_details)
-                               .add_vector4(Vector4::new(ui, 0.0, 0.0, 0.0));
+                               .add_vector4(tonemapping);
     
     cmd = cmd.push_constants(Arc::clone(&device), &self.pipeline, ShaderStage::Vertex, push_constant_data);
     
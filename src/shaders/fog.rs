@@ -0,0 +1,92 @@
+use crate::vulkan::{Instance, Device};
+use crate::vulkan::buffer::{Buffer, BufferUsage, UniformData};
+
+use cgmath::{Vector3, Vector4, InnerSpace};
+
+use std::sync::Arc;
+
+// 3 vec4s, must match Fog in VkModelDeffered.frag and VkModelForward.frag
+const FOG_SIZE: usize = 12;
+
+/**
+** Distance and height fog over the lit colour of the deffered and forward
+** subpasses. The distance fog thickens evenly with distance, the height fog
+** is thickest at its height and thins out exponentially above it. With the
+** atmosphere on the fog takes the colour of the sky around the sun instead
+** of its own, and the sky is drawn when no skybox is set.
+**/
+pub struct Fog {
+  colour: Vector3<f32>,
+  density: f32,
+  height_density: f32,
+  height: f32,
+  height_falloff: f32,
+  sun_direction: Option<Vector3<f32>>, // the way the sunlight travels
+  
+  buffer: Buffer<f32>, // per frame
+}
+
+impl Fog {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, num_frames: u32) -> Fog {
+    let buffer = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), num_frames, FOG_SIZE as u64);
+    
+    Fog {
+      colour: Vector3::new(0.0, 0.0, 0.0),
+      density: 0.0,
+      height_density: 0.0,
+      height: 0.0,
+      height_falloff: 1.0,
+      sun_direction: None,
+      
+      buffer,
+    }
+  }
+  
+  pub fn buffer(&self) -> &Buffer<f32> {
+    &self.buffer
+  }
+  
+  pub fn atmosphere(&self) -> Option<Vector3<f32>> {
+    self.sun_direction
+  }
+  
+  // colour is as it should appear on screen
+  pub fn set_distance(&mut self, colour: Vector3<f32>, density: f32) {
+    self.colour = colour;
+    self.density = density.max(0.0);
+  }
+  
+  pub fn set_height(&mut self, density: f32, height: f32, falloff: f32) {
+    self.height_density = density.max(0.0);
+    self.height = height;
+    self.height_falloff = falloff.max(0.0001);
+  }
+  
+  pub fn set_atmosphere(&mut self, sun_direction: Option<Vector3<f32>>) {
+    self.sun_direction = sun_direction.filter(|direction| direction.magnitude() > 0.0).map(|direction| direction.normalize());
+  }
+  
+  pub fn clear(&mut self) {
+    self.density = 0.0;
+    self.height_density = 0.0;
+  }
+  
+  pub fn update(&mut self, device: Arc<Device>, current_buffer: usize) {
+    let colour = Vector3::new(self.colour.x.powf(2.2), self.colour.y.powf(2.2), self.colour.z.powf(2.2));
+    let (atmosphere, sun_direction) = match self.sun_direction {
+      Some(direction) => (1.0, direction),
+      None => (0.0, Vector3::new(0.0, -1.0, 0.0)),
+    };
+    
+    let mut data = UniformData::new()
+                     .add_vector4(colour.extend(self.density))
+                     .add_vector4(Vector4::new(self.height_density, self.height, self.height_falloff, atmosphere))
+                     .add_vector4(sun_direction.extend(0.0));
+    
+    self.buffer.fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, data.build_non_aligned());
+  }
+  
+  pub fn destroy(&self, device: Arc<Device>) {
+    self.buffer.destroy(Arc::clone(&device));
+  }
+}
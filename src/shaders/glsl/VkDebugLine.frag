@@ -0,0 +1,9 @@
+#version 450
+
+layout(location = 0) in vec4 v_colour;
+
+layout(location = 0) out vec4 outColour;
+
+void main() {
+  outColour = vec4(pow(v_colour.rgb, vec3(2.2)), v_colour.a);
+}
@@ -0,0 +1,61 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 colour;
+
+layout(location = 0) out vec4 v_colour;
+
+layout(push_constant) uniform PushConstants {
+  vec4 c_position; // x, y, z, fov
+  vec4 c_center;   // x, y, z, aspect
+  vec4 c_up;       // x, y, z, _
+} push_constants;
+
+const float M_PI = 3.141592653589793;
+
+float cot(float value) {
+  return 1.0 / tan(value);
+}
+
+float to_radians(float degree) {
+  return degree * (M_PI/180.0);
+}
+
+mat4 create_perspective_matrix(float fov, float aspect, float near, float far) {
+  float f = cot(to_radians(fov) / 2.0);
+  
+  mat4 perspective = mat4(
+                      vec4(f / aspect, 0.0,   0.0,                               0.0),
+                      vec4(0.0,        f,     0.0,                               0.0),
+                      vec4(0.0,        0.0,   (far + near) / (near - far),      -1.0),
+                      vec4(0.0,        0.0,   (2.0 * far * near) / (near - far), 0.0)
+                    );
+                
+  return perspective;
+}
+
+// center is a point not a direction
+mat4 create_view_matrix(vec3 eye, vec3 center, vec3 up) {
+  vec3 dir = center - eye;
+  
+  vec3 f = normalize(dir);
+  vec3 s = normalize(cross(f, up));
+  vec3 u = cross(s,f);
+  
+  mat4 look_at_matrix = mat4(vec4(s.x,           u.x,        -f.x,         0.0), 
+                             vec4(s.y,           u.y,        -f.y,         0.0), 
+                             vec4(s.z,           u.z,        -f.z,         0.0), 
+                             vec4(-dot(eye, s), -dot(eye, u), dot(eye, f), 1.0));
+  
+  return look_at_matrix;
+}
+
+// lines are already in world space, projected the same as VkModel.vert so they depth test against the models
+void main() {
+  mat4 projection = create_perspective_matrix(push_constants.c_position.w, push_constants.c_center.w, 0.1, 1080.0);
+  mat4 view = create_view_matrix(push_constants.c_position.xyz, push_constants.c_center.xyz, push_constants.c_up.xyz);
+  
+  v_colour = colour;
+  
+  gl_Position = projection * view * vec4(position, 1.0);
+}
@@ -0,0 +1,67 @@
+#version 450
+
+layout(location = 0) in vec2 uvs;
+
+layout(location = 0) out vec4 outColour;
+
+const float M_PI = 3.141592653589793;
+const uint SAMPLE_COUNT = 512;
+
+float radical_inverse(uint bits) {
+  return float(bitfieldReverse(bits)) * 2.3283064365386963e-10;
+}
+
+vec2 hammersley(uint i, uint n) {
+  return vec2(float(i) / float(n), radical_inverse(i));
+}
+
+vec3 importance_sample_ggx(vec2 xi, vec3 N, float roughness) {
+  float alpha = roughness*roughness;
+  
+  float phi = 2.0*M_PI*xi.x;
+  float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha*alpha - 1.0)*xi.y));
+  float sin_theta = sqrt(1.0 - cos_theta*cos_theta);
+  vec3 H = vec3(cos(phi)*sin_theta, sin(phi)*sin_theta, cos_theta);
+  
+  vec3 up = abs(N.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+  vec3 tangent = normalize(cross(up, N));
+  vec3 bitangent = cross(N, tangent);
+  
+  return normalize(tangent*H.x + bitangent*H.y + N*H.z);
+}
+
+float G_SchlickGGX(float dotNV, float roughness) {
+  float k = (roughness*roughness) / 2.0;
+  return dotNV / (dotNV * (1.0 - k) + k);
+}
+
+// scale and bias to F0 of the split sum, x is NdotV and y is roughness
+void main() {
+  float NdotV = max(uvs.x, 0.001);
+  float roughness = uvs.y;
+  
+  vec3 V = vec3(sqrt(1.0 - NdotV*NdotV), 0.0, NdotV);
+  vec3 N = vec3(0.0, 0.0, 1.0);
+  
+  float A = 0.0;
+  float B = 0.0;
+  for (uint i = 0; i < SAMPLE_COUNT; ++i) {
+    vec3 H = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), N, roughness);
+    vec3 L = normalize(2.0*dot(V, H)*H - V);
+    
+    float NdotL = max(L.z, 0.0);
+    float NdotH = max(H.z, 0.0);
+    float VdotH = max(dot(V, H), 0.0);
+    
+    if (NdotL > 0.0) {
+      float G = G_SchlickGGX(NdotL, roughness) * G_SchlickGGX(NdotV, roughness);
+      float G_Vis = (G * VdotH) / (NdotH * NdotV);
+      float Fc = pow(1.0 - VdotH, 5.0);
+      
+      A += (1.0 - Fc) * G_Vis;
+      B += Fc * G_Vis;
+    }
+  }
+  
+  outColour = vec4(A / float(SAMPLE_COUNT), B / float(SAMPLE_COUNT), 0.0, 1.0);
+}
@@ -0,0 +1,52 @@
+#version 450
+
+layout(location = 0) in vec2 uvs;
+
+layout(location = 0) out vec4 outColour;
+
+layout(binding = 0) uniform sampler2D equirectangular;
+
+layout(push_constant) uniform PushConstants {
+  vec4 info; // face, _, face size, _
+} push_constants;
+
+const float M_PI = 3.141592653589793;
+
+// direction through a texel of a cube face, matches the vulkan cube face layout
+vec3 face_direction(float face, vec2 uv) {
+  vec2 st = uv*2.0 - 1.0;
+  
+  int f = int(face);
+  if (f == 0) {
+    return normalize(vec3(1.0, -st.y, -st.x));
+  } else if (f == 1) {
+    return normalize(vec3(-1.0, -st.y, st.x));
+  } else if (f == 2) {
+    return normalize(vec3(st.x, 1.0, st.y));
+  } else if (f == 3) {
+    return normalize(vec3(st.x, -1.0, -st.y));
+  } else if (f == 4) {
+    return normalize(vec3(st.x, -st.y, 1.0));
+  }
+  
+  return normalize(vec3(-st.x, -st.y, -1.0));
+}
+
+vec2 equirectangular_uv(vec3 direction) {
+  return vec2(atan(direction.z, direction.x) / (2.0*M_PI) + 0.5, acos(clamp(direction.y, -1.0, 1.0)) / M_PI);
+}
+
+void main() {
+  float texel = 1.0 / push_constants.info.z;
+  
+  // 2x2 samples per texel as the source is usually much larger than a face
+  vec3 colour = vec3(0.0);
+  for (int x = 0; x < 2; ++x) {
+    for (int y = 0; y < 2; ++y) {
+      vec2 offset = (vec2(x, y) - 0.5) * 0.5 * texel;
+      colour += texture(equirectangular, equirectangular_uv(face_direction(push_constants.info.x, uvs + offset))).rgb;
+    }
+  }
+  
+  outColour = vec4(colour / 4.0, 1.0);
+}
@@ -0,0 +1,56 @@
+#version 450
+
+layout(location = 0) in vec2 uvs;
+
+layout(location = 0) out vec4 outColour;
+
+layout(binding = 0) uniform samplerCube environment;
+
+layout(push_constant) uniform PushConstants {
+  vec4 info; // face, environment lod to sample, _, _
+} push_constants;
+
+const float M_PI = 3.141592653589793;
+const float SAMPLE_DELTA = 0.05;
+
+// direction through a texel of a cube face, matches the vulkan cube face layout
+vec3 face_direction(float face, vec2 uv) {
+  vec2 st = uv*2.0 - 1.0;
+  
+  int f = int(face);
+  if (f == 0) {
+    return normalize(vec3(1.0, -st.y, -st.x));
+  } else if (f == 1) {
+    return normalize(vec3(-1.0, -st.y, st.x));
+  } else if (f == 2) {
+    return normalize(vec3(st.x, 1.0, st.y));
+  } else if (f == 3) {
+    return normalize(vec3(st.x, -1.0, -st.y));
+  } else if (f == 4) {
+    return normalize(vec3(st.x, -st.y, 1.0));
+  }
+  
+  return normalize(vec3(-st.x, -st.y, -1.0));
+}
+
+// cosine weighted sum of the hemisphere around N
+void main() {
+  vec3 N = face_direction(push_constants.info.x, uvs);
+  vec3 up = abs(N.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+  vec3 right = normalize(cross(up, N));
+  up = cross(N, right);
+  
+  vec3 irradiance = vec3(0.0);
+  float num_samples = 0.0;
+  for (float phi = 0.0; phi < 2.0*M_PI; phi += SAMPLE_DELTA) {
+    for (float theta = 0.0; theta < 0.5*M_PI; theta += SAMPLE_DELTA) {
+      vec3 tangent = vec3(sin(theta)*cos(phi), sin(theta)*sin(phi), cos(theta));
+      vec3 direction = tangent.x*right + tangent.y*up + tangent.z*N;
+      
+      irradiance += textureLod(environment, direction, push_constants.info.y).rgb * cos(theta) * sin(theta);
+      num_samples += 1.0;
+    }
+  }
+  
+  outColour = vec4(M_PI * irradiance / num_samples, 1.0);
+}
@@ -0,0 +1,99 @@
+#version 450
+
+layout(location = 0) in vec2 uvs;
+
+layout(location = 0) out vec4 outColour;
+
+layout(binding = 0) uniform samplerCube environment;
+
+layout(push_constant) uniform PushConstants {
+  vec4 info; // face, roughness, environment face size, _
+} push_constants;
+
+const float M_PI = 3.141592653589793;
+const uint SAMPLE_COUNT = 512;
+
+// direction through a texel of a cube face, matches the vulkan cube face layout
+vec3 face_direction(float face, vec2 uv) {
+  vec2 st = uv*2.0 - 1.0;
+  
+  int f = int(face);
+  if (f == 0) {
+    return normalize(vec3(1.0, -st.y, -st.x));
+  } else if (f == 1) {
+    return normalize(vec3(-1.0, -st.y, st.x));
+  } else if (f == 2) {
+    return normalize(vec3(st.x, 1.0, st.y));
+  } else if (f == 3) {
+    return normalize(vec3(st.x, -1.0, -st.y));
+  } else if (f == 4) {
+    return normalize(vec3(st.x, -st.y, 1.0));
+  }
+  
+  return normalize(vec3(-st.x, -st.y, -1.0));
+}
+
+float radical_inverse(uint bits) {
+  return float(bitfieldReverse(bits)) * 2.3283064365386963e-10;
+}
+
+vec2 hammersley(uint i, uint n) {
+  return vec2(float(i) / float(n), radical_inverse(i));
+}
+
+vec3 importance_sample_ggx(vec2 xi, vec3 N, float roughness) {
+  float alpha = roughness*roughness;
+  
+  float phi = 2.0*M_PI*xi.x;
+  float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha*alpha - 1.0)*xi.y));
+  float sin_theta = sqrt(1.0 - cos_theta*cos_theta);
+  vec3 H = vec3(cos(phi)*sin_theta, sin(phi)*sin_theta, cos_theta);
+  
+  vec3 up = abs(N.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+  vec3 tangent = normalize(cross(up, N));
+  vec3 bitangent = cross(N, tangent);
+  
+  return normalize(tangent*H.x + bitangent*H.y + N*H.z);
+}
+
+float D_GGX(float dotNH, float roughness) {
+  float alpha = roughness * roughness;
+  float alpha2 = alpha * alpha;
+  float denom = dotNH * dotNH * (alpha2 - 1.0) + 1.0;
+  return (alpha2)/(M_PI * denom*denom); 
+}
+
+// split sum prefilter with the view along the normal, unlikely samples
+// read blurrier environment mips so bright spots don't alias
+void main() {
+  vec3 N = face_direction(push_constants.info.x, uvs);
+  float roughness = push_constants.info.y;
+  
+  if (roughness == 0.0) {
+    outColour = vec4(textureLod(environment, N, 0.0).rgb, 1.0);
+    return;
+  }
+  
+  float size = push_constants.info.z;
+  float texel_solid_angle = 4.0*M_PI / (6.0*size*size);
+  
+  vec3 colour = vec3(0.0);
+  float total_weight = 0.0;
+  for (uint i = 0; i < SAMPLE_COUNT; ++i) {
+    vec3 H = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), N, roughness);
+    vec3 L = normalize(2.0*dot(N, H)*H - N);
+    
+    float NdotL = dot(N, L);
+    if (NdotL > 0.0) {
+      float NdotH = max(dot(N, H), 0.0);
+      float pdf = D_GGX(NdotH, roughness) * 0.25 + 0.0001;
+      float sample_solid_angle = 1.0 / (float(SAMPLE_COUNT) * pdf);
+      float lod = max(0.5*log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+      
+      colour += textureLod(environment, L, lod).rgb * NdotL;
+      total_weight += NdotL;
+    }
+  }
+  
+  outColour = vec4(colour / max(total_weight, 0.0001), 1.0);
+}
@@ -2,10 +2,12 @@
 
 layout(location = 0) in vec2 uvs;
 layout(location = 1) in float drawing_ui;
+layout(location = 2) flat in vec3 tonemapping; // exposure, tonemapper, bloom strength
 
 layout(location = 0) out vec4 outColour;
 
 layout(set = 0, binding = 0) uniform sampler2D texture_image;
+layout(set = 0, binding = 1) uniform sampler2D bloom_image;
 
 vec4 when_eq(vec4 x, vec4 y) {
   return 1.0 - abs(sign(x - y));
@@ -47,6 +49,44 @@ vec4 not(vec4 a) {
   return 1.0 - a;
 }
 
+vec3 reinhard(vec3 colour) {
+  return colour / (colour + 1.0);
+}
+
+// Narkowicz's fit of the aces filmic curve
+vec3 aces(vec3 colour) {
+  return clamp((colour * (2.51 * colour + 0.03)) / (colour * (2.43 * colour + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 uncharted_curve(vec3 x) {
+  float a = 0.15;
+  float b = 0.50;
+  float c = 0.10;
+  float d = 0.20;
+  float e = 0.02;
+  float f = 0.30;
+  return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+vec3 uncharted(vec3 colour) {
+  float exposure_bias = 2.0;
+  vec3 white_scale = 1.0 / uncharted_curve(vec3(11.2));
+  return uncharted_curve(colour * exposure_bias) * white_scale;
+}
+
+// 0 reinhard, 1 aces, 2 uncharted
+vec3 tonemap(vec3 colour, float tonemapper) {
+  if (tonemapper < -0.5) { // the model debug views
+    return min(colour, vec3(1.0));
+  } else if (tonemapper < 0.5) {
+    return reinhard(colour);
+  } else if (tonemapper < 1.5) {
+    return aces(colour);
+  }
+  
+  return uncharted(colour);
+}
+
 void main() {
   vec4 final_colour = texture(texture_image, uvs);
   // Allows 3D to draw
@@ -57,6 +97,11 @@ void main() {
   // Fixes 2D black transparency issue
   if (drawing_ui > 0.0) {
     final_colour.a = 1.0;
+  } else {
+    // the model texture is hdr, bloom and exposure are applied before it is brought back to the screen's range
+    vec3 bloom = texture(bloom_image, uvs).rgb;
+    vec3 colour = (final_colour.rgb + bloom * tonemapping.z) * tonemapping.x;
+    final_colour.rgb = pow(tonemap(max(colour, vec3(0.0)), tonemapping.y), vec3(0.4545));
   }
   
   outColour = final_colour;
@@ -5,11 +5,12 @@ layout(location = 1) in vec2 uv;
 
 layout(location = 0) out vec2 uvs;
 layout(location = 1) out float drawing_ui;
+layout(location = 2) flat out vec3 tonemapping;
 
 layout(push_constant) uniform PushConstants {
   vec4 model; // x, y, scale_x, scale_y
   vec4 projection; // right, bottom, _, _
-  vec4 ui_enabled; // false < 0.5 < true, _, _, _
+  vec4 ui_enabled; // false < 0.5 < true, exposure, tonemapper, bloom strength
 } push_constants;
 
 mat4 create_translation_matrix(vec2 pos, vec2 scale) {
@@ -51,5 +52,6 @@ void main() {
   
   uvs = uv;
   drawing_ui = push_constants.ui_enabled.x;
+  tonemapping = push_constants.ui_enabled.yzw;
   gl_Position = projection * model * vec4(position, 0.0, 1.0);
 }
@@ -6,10 +6,16 @@ layout(location = 2) in vec4 v_base_colour_factor;
 layout(location = 3) in vec4 v_alpha_cutoff; // alpha, cutoff, _, use emissive
 layout(location = 4) in vec3 v_normal;
 layout(location = 5) in vec3 v_world_pos;
-layout(location = 6) in vec3 v_camera_pos;
+layout(location = 6) in vec4 v_clip; // this frame's x, y, w and last frame's w
+layout(location = 7) in vec4 v_uvs_base_mro; // base uv, metallic_roughness uv
+layout(location = 8) in vec4 v_uvs_normal_occlusion; // normal uv, occlusion uv
+layout(location = 9) in vec2 v_uvs_emissive;
 layout(location = 10) in vec3 v_scanline;
 layout(location = 11) in vec4 v_use_textures;
 layout(location = 12) in vec2 v_mr;
+layout(location = 13) in vec4 v_emissive_factor; // r, g, b, _
+layout(location = 14) in vec4 v_extensions; // unlit, transmission, _, _
+layout(location = 15) in vec2 v_previous_clip;
 
 layout(location = 0) out vec4 outColour;
 layout(location = 1) out vec4 outAlbedo;
@@ -17,6 +23,7 @@ layout(location = 2) out vec4 outMro;
 layout(location = 3) out vec4 outOcclusion;
 layout(location = 4) out vec4 outNormal;
 layout(location = 5) out vec4 outPosition;
+layout(location = 6) out vec4 outVelocity; // uv this frame less uv last frame
 
 layout(set = 0, binding = 1) uniform sampler2D base_texture;
 layout(set = 0, binding = 2) uniform sampler2D mro_texture;
@@ -24,6 +31,9 @@ layout(set = 0, binding = 3) uniform sampler2D normal_texture;
 layout(set = 0, binding = 4) uniform sampler2D occlusion_texture;
 layout(set = 0, binding = 5) uniform sampler2D emissive_texture;
 
+// 1.0 on the pipelines masked materials use when alpha to coverage is on
+layout(constant_id = 0) const float alpha_to_coverage = 0.0;
+
 const float M_PI = 3.141592653589793;
 
 const vec3 sun_dir = vec3(-0.2, 1.0, -0.2);
@@ -67,24 +77,28 @@ void main() {
   vec4 use_occlusion_texture = when_gt(vec4(v_use_textures.w), vec4(0.0));
   vec4 use_emissive_texture = when_gt(vec4(v_alpha_cutoff.w), vec4(0.0));
   
-  mro = use_mro_texture * texture(mro_texture, uvs) + 
+  vec4 use_unlit = when_gt(vec4(v_extensions.x), vec4(0.0));
+  
+  mro = use_mro_texture * texture(mro_texture, v_uvs_base_mro.zw) + 
         not(use_mro_texture) * vec4(0.0, v_mr.y, v_mr.x, 0.0);
   
-  emissive = use_emissive_texture * texture(emissive_texture, uvs) + 
-             not(use_emissive_texture) * vec4(0.0);
+  emissive = use_emissive_texture * texture(emissive_texture, v_uvs_emissive) + 
+             not(use_emissive_texture) * vec4(1.0);
+  emissive.rgb *= v_emissive_factor.rgb;
   
-  normal = use_normal_texture * texture(normal_texture, uvs) + 
+  normal = use_normal_texture * texture(normal_texture, v_uvs_normal_occlusion.xy) + 
            not(use_normal_texture) * vec4(normalize(v_normal), 0.0);
   
-  base_colour = use_base_texture.rgb      * texture(base_texture, uvs).rgb + 
+  base_colour = use_base_texture.rgb      * texture(base_texture, v_uvs_base_mro.xy).rgb + 
                 not(use_base_texture).rgb * base_colour;
   
-  alpha = use_base_texture.a    * texture(base_texture, uvs).a + 
+  alpha = use_base_texture.a    * texture(base_texture, v_uvs_base_mro.xy).a + 
           not(use_base_texture).a * alpha;
   
   base_colour *= v_base_colour_factor.rgb;
   base_colour *= v_colour.rgb;
   alpha *= v_base_colour_factor.a;
+  alpha *= 1.0 - v_extensions.y; // transmission approximated as transparency
  // base_colour *= 0.02;
   base_colour += emissive.rgb;
   
@@ -95,19 +109,23 @@ void main() {
   brightness_factor = level / light_levels;
   
   //base_colour *= brightness_factor;
-  base_colour.rgb = base_colour*max(brightness_factor, 0.02);
+  base_colour.rgb = use_unlit.rgb      * base_colour + 
+                    not(use_unlit).rgb * base_colour*max(brightness_factor, 0.02);
   
   float alpha_cutoff = v_alpha_cutoff.x;
   float alpha_mask = v_alpha_cutoff.y;
+  float coverage = 0.0;
   
   if (alpha_mask == 1.0) { //opaque
     alpha = 1.0;
   } else if (alpha_mask == 2.0) { // mask
-    if (alpha < alpha_cutoff) { // draw nothing
+    if (alpha_to_coverage > 0.0) {
+      // sharpened around the cutoff so the edge fades over about a pixel
+      coverage = clamp((alpha - alpha_cutoff) / max(fwidth(alpha), 0.0001) + 0.5, 0.0, 1.0);
+    } else if (alpha < alpha_cutoff) { // draw nothing
       discard;
-    } else {
-      alpha = alpha_cutoff;
     }
+    alpha = 1.0;
   }
   
   float halpha = hologram_alpha(v_scanline.x, v_scanline.y);
@@ -116,10 +134,20 @@ void main() {
   alpha = use_scanline.a      * halpha + 
           not(use_scanline).a * alpha;
   
+  // the deffered pass replaces the colour, only the alpha is read for coverage
+  outColour = vec4(0.0, 0.0, 0.0, coverage);
   outAlbedo = vec4(base_colour, alpha);
   outMro = mro;
-  outOcclusion = texture(occlusion_texture, uvs);
-  outNormal = normal;//vec4(normalize(v_normal), 1.0);
+  outOcclusion = texture(occlusion_texture, v_uvs_normal_occlusion.zw);
+  // unlit materials write no normal so the deffered pass adds no lighting
+  outNormal = not(use_unlit) * normal;//vec4(normalize(v_normal), 1.0);
   outPosition = vec4(v_world_pos, 1.0);
+  
+  // behind last frame's camera there is nothing to reproject, the history uv lands off screen
+  vec2 velocity = vec2(2.0);
+  if (v_clip.w > 0.0) {
+    velocity = (v_clip.xy / v_clip.z - v_previous_clip / v_clip.w) * 0.5;
+  }
+  outVelocity = vec4(velocity, 0.0, 1.0);
   // occlusion
 }
@@ -5,6 +5,7 @@ layout(location = 1) in vec3 normal;
 layout(location = 2) in vec2 uv;
 layout(location = 3) in vec4 colour;
 layout(location = 4) in vec4 tangent;
+layout(location = 9) in vec2 uv_1;
 
 layout(location = 0) out vec2 uvs;
 layout(location = 1) out vec4 v_colour;
@@ -12,225 +13,76 @@ layout(location = 2) out vec4 v_base_colour_factor;
 layout(location = 3) out vec4 v_alpha_cutoff; // alpha, cutoff, _, use emissive
 layout(location = 4) out vec3 v_normal;
 layout(location = 5) out vec3 v_world_pos;
-layout(location = 6) out vec3 v_camera_pos;
+layout(location = 6) out vec4 v_clip; // this frame's x, y, w and last frame's w
+layout(location = 7) out vec4 v_uvs_base_mro; // base uv, metallic_roughness uv
+layout(location = 8) out vec4 v_uvs_normal_occlusion; // normal uv, occlusion uv
+layout(location = 9) out vec2 v_uvs_emissive;
 layout(location = 10) out vec3 v_scanline;
 layout(location = 11) out vec4 v_use_textures;
 layout(location = 12) out vec2 v_mr;
+layout(location = 13) out vec4 v_emissive_factor; // r, g, b, _
+layout(location = 14) out vec4 v_extensions; // unlit, transmission, _, _
+layout(location = 15) out vec2 v_previous_clip; // last frame's w is in v_clip
 
 layout(set = 0, binding = 0) uniform UniformBuffer {
   vec4 use_textures; //base, metallic_roughness, normal, occlusion
   vec4 emissive_alpha; //use_emissive, normal_scale, alpha_cutoff, alpha_mask
   vec4 base_colour_factor; // r, g, b, a
   vec4 mro_factors; // metallic_factor, roughness_factor, occlusion_string, _
-  vec4 emissive_factor; // r, g, b, _
+  vec4 emissive_factor; // r, g, b, emissive_strength
+  vec4 extensions; // unlit, transmission, _, _
+  vec4 uv_transforms[10]; // two rows each, uv set in w: base, metallic_roughness, normal, occlusion, emissive
 } uniforms;
 
+layout (std430, set = 1, binding = 0) readonly buffer Camera {
+  mat4 view_projection;
+  mat4 previous_view_projection; // last frame's, the velocity is taken from it
+} camera;
+
 layout(push_constant) uniform PushConstants {
-  vec4 c_position; // x, y, z, fov
-  vec4 c_center;   // x, y, z, aspect
-  vec4 c_up;       // x, y, z, x_scale
-  vec4 model;      // x, y, z, y_scale
-  vec4 rotation;   // x_rot, y_rot, z_rot, z_scale
+  mat3x4 model; // the rows of the model matrix, it is affine
+  mat3x4 previous_model; // last frame's
   vec4 hologram_scanline; // hologram_enabled, scanline, _, _
 } push_constants;
 
-const float M_PI = 3.141592653589793;
-
-float cot(float value) {
-  return 1.0 / tan(value);
-}
-
-float to_radians(float degree) {
-  return degree * (M_PI/180.0);
-}
-
-vec3 to_radians(vec3 degrees) {
-  return vec3(to_radians(degrees.x), to_radians(degrees.y), to_radians(degrees.z));
-}
-
-mat4 create_perspective_matrix(float fov, float aspect, float near, float far) {
-  float f = cot(to_radians(fov) / 2.0);
-  
-  mat4 perspective = mat4(
-                      vec4(f / aspect, 0.0,   0.0,                               0.0),
-                      vec4(0.0,        f,     0.0,                               0.0),
-                      vec4(0.0,        0.0,   (far + near) / (near - far),      -1.0),
-                      vec4(0.0,        0.0,   (2.0 * far * near) / (near - far), 0.0)
-                    );
-                
-  return perspective;
-}
-
-// center is a point not a direction
-mat4 create_view_matrix(vec3 eye, vec3 center, vec3 up) {
-  vec3 dir = center - eye;
-  
-  vec3 f = normalize(dir);
-  vec3 s = normalize(cross(f, up));
-  vec3 u = cross(s,f);
-  
-  mat4 look_at_matrix = mat4(vec4(s.x,           u.x,        -f.x,         0.0), 
-                             vec4(s.y,           u.y,        -f.y,         0.0), 
-                             vec4(s.z,           u.z,        -f.z,         0.0), 
-                             vec4(-dot(eye, s), -dot(eye, u), dot(eye, f), 1.0));
-  
-  return look_at_matrix;
-}
-
-vec4 quaternion_normalise(vec4 q) {
-  float n = sqrt(q.x *q.x + q.y*q.y + q.z*q.z + q.w*q.w);
-  
-  return vec4(q.x/n, q.y/n, q.z/n, q.w/n);
-}
-
-vec4 quaternion_from_rotation(vec3 axis, float deg_rotation) {
-  vec4 q = vec4(0.0);
-  
-  float h_angle = (deg_rotation*0.5) * M_PI / 180.0;
-  q.x = axis.x * sin(h_angle);
-  q.y = axis.y * sin(h_angle);
-  q.z = axis.z * sin(h_angle);
-  q.w = cos(h_angle);
-  
-  return q;
-}
-
-vec4 quaternion_conj(vec4 q) {
-  return vec4(-q.xyz, q.w);
+vec2 transform_uv(int texture) {
+  vec4 row = uniforms.uv_transforms[texture*2]; // w is the uv set
+  vec3 u = vec3(row.w > 0.5 ? uv_1 : uv, 1.0);
+  return vec2(dot(row.xyz, u), dot(uniforms.uv_transforms[texture*2+1].xyz, u));
 }
 
-vec4 quaternion_from_position(vec3 pos) {
-  return vec4(pos, 0.0);
-}
+// the inverse transpose of the model matrix from its cofactors, so scaled models keep their normals
+vec3 model_normal(mat3x4 model, vec3 normal) {
+  vec3 r0 = model[0].xyz;
+  vec3 r1 = model[1].xyz;
+  vec3 r2 = model[2].xyz;
+  float flip = sign(dot(r0, cross(r1, r2)));
 
-vec4 quaternion_inverse(vec4 q) {
-  vec4 q_1 = vec4(0.0);
-  
-  float n = sqrt(q.x *q.x + q.y*q.y + q.z*q.z + q.w*q.w);
-  q_1 = vec4(-q.xyz, q.w) / n;
-  //q_1 = vec4(-q.x/n, -q.y/n, -q.z/n, w/n);.
-  return q_1;
-}
-
-vec4 quaternion_mul(vec4 q1, vec4 q2) {
-  vec4 qr;
-  qr.x = (q1.w * q2.x) + (q1.x * q2.w) + (q1.y * q2.z) - (q1.z * q2.y);
-  qr.y = (q1.w * q2.y) - (q1.x * q2.z) + (q1.y * q2.w) + (q1.z * q2.x);
-  qr.z = (q1.w * q2.z) + (q1.x * q2.y) - (q1.y * q2.x) + (q1.z * q2.w);
-  qr.w = (q1.w * q2.w) - (q1.x * q2.x) - (q1.y * q2.y) - (q1.z * q2.z);
-  return qr;
-}
-
-vec3 rotate_vertex_position(vec3 position, vec3 axis, float angle) { 
-  vec4 q = quaternion_from_rotation(axis, angle);
-  vec3 v = position.xyz;
-  return v + 2.0 * cross(q.xyz, cross(q.xyz, v) + q.w * v);
-}
-
-
-mat4 create_rotation_matrix_from_quaternion(vec3 axis, float deg_rotation) {
-  vec4 q = quaternion_from_rotation(axis, deg_rotation);
-  float s = sin(to_radians(deg_rotation) * 0.5);
-  mat4 rotation_matrix = mat4(
-          vec4(1.0 - 2.0*s*(q.y*q.y + q.z*q.z),       2.0*s*(q.x*q.y - q.z*q.w),       2.0*s*(q.x*q.z + q.y*q.w), 0.0),
-          vec4(      2.0*s*(q.x*q.y + q.z*q.w), 1.0 - 2.0*s*(q.x*q.x + q.z*q.z),       2.0*s*(q.y*q.z - q.x*q.w), 0.0),
-          vec4(      2.0*s*(q.x*q.z - q.y*q.w),       2.0*s*(q.y*q.z + q.x*q.w), 1.0 - 2.0*s*(q.x*q.x + q.y*q.y), 0.0),
-          vec4(0.0, 0.0, 0.0, 1.0));
-  
-  return rotation_matrix;
-}
-
-vec3 rotate_vector_by_angle(vec3 pos, vec3 rotation) {
-  vec3 rotated_pos = rotate_vertex_position(pos, vec3(1.0, 0.0, 0.0), rotation.x);
-  rotated_pos = rotate_vertex_position(rotated_pos, vec3(0.0, 1.0, 0.0), rotation.y);
-  rotated_pos = rotate_vertex_position(rotated_pos, vec3(0.0, 0.0, 1.0), rotation.z);
-  
-  return rotated_pos;
-}
-/*
-mat4 create_entire_rotation_matrix(vec3 rotations) {
-  mat4 rot_x = create_rotation_matrix_from_quaternion(vec3(1.0, 0.0, 0.0), rotations.x);
-  mat4 rot_y = create_rotation_matrix_from_quaternion(vec3(0.0, 1.0, 0.0), rotations.y);
-  mat4 rot_z = create_rotation_matrix_from_quaternion(vec3(0.0, 0.0, 1.0), rotations.z);
-  
-  return rot_y*rot_z*rot_x;
-}*/
-/*
-mat4 create_rotation_matrix(vec3 deg_rotation) {
-  vec3 rotation = to_radians(deg_rotation);
-  
-  float s = sin(rotation.x);
-  float c = cos(rotation.x);
-
-  mat4 rot_x = mat4(vec4(1.0,  0.0, 0.0, 0.0), 
-                    vec4(0.0,  c,   s,   0.0), 
-                    vec4(0.0, -s,   c,   0.0), 
-                    vec4(0.0,  0.0, 0.0, 1.0));
-  
-  s = sin(rotation.y);
-  c = cos(rotation.y);
-  
-  mat4 rot_y = mat4(vec4(c,   0.0, -s,   0.0), 
-                    vec4(0.0, 1.0,  0.0, 0.0), 
-                    vec4(s,   0.0,  c,   0.0), 
-                    vec4(0.0, 0.0,  0.0, 1.0));
-  
-  s = sin(rotation.z);
-  c = cos(rotation.z);
-  
-  mat4 rot_z = mat4(vec4(c,   s,   0.0, 0.0), 
-                    vec4(-s,  c,   0.0, 0.0), 
-                    vec4(0.0, 0.0, 1.0, 0.0), 
-                    vec4(0.0, 0.0, 0.0, 1.0));
-  
-  mat4 rotation_matrix = rot_y*rot_z*rot_x;
-  
-  return rotation_matrix;
-}*/
-
-mat4 create_translation_matrix(vec3 pos) {
-  mat4 translate_matrix = mat4(vec4(1.0, 0.0, 0.0, 0.0), 
-                               vec4(0.0, 1.0, 0.0, 0.0), 
-                               vec4(0.0, 0.0, 1.0, 0.0), 
-                               vec4(pos,           1.0));
-  
-  return translate_matrix;
-}
-
-mat4 create_scale_matrix(vec3 scale) {
-  mat4 scale_matrix = mat4(vec4(scale.x, 0.0,     0.0,     0.0), 
-                               vec4(0.0,     scale.y, 0.0,     0.0), 
-                               vec4(0.0,     0.0,     scale.z, 0.0), 
-                               vec4(0.0,     0.0,     0.0,     1.0));
-  
-  return scale_matrix;
+  return normal * mat3(cross(r1, r2), cross(r2, r0), cross(r0, r1)) * flip;
 }
 
 void main() {
-  vec3 model_scale = vec3(push_constants.c_up.w, push_constants.model.w, push_constants.rotation.w);
-  
-  mat4 projection = create_perspective_matrix(push_constants.c_position.w, push_constants.c_center.w, 0.1, 1080.0);
-  mat4 view = create_view_matrix(push_constants.c_position.xyz, push_constants.c_center.xyz, push_constants.c_up.xyz);
-  mat4 model = create_translation_matrix(push_constants.model.xyz);
-  mat4 scale = create_scale_matrix(model_scale);
-  vec3 rotation = push_constants.rotation.xyz;
-  
-  vec3 local_pos = vec3(model * scale * vec4(rotate_vector_by_angle(position, rotation), 1.0));
-  
-  vec4 rotated_normal = vec4(rotate_vector_by_angle(vec3(-normal.x, normal.y, normal.z), rotation), 1.0);
+  vec3 world_pos = vec4(position, 1.0) * push_constants.model;
+  vec3 world_normal = model_normal(push_constants.model, vec3(-normal.x, normal.y, normal.z));
   
   uvs = uv;
+  v_uvs_base_mro = vec4(transform_uv(0), transform_uv(1));
+  v_uvs_normal_occlusion = vec4(transform_uv(2), transform_uv(3));
+  v_uvs_emissive = transform_uv(4);
+  v_emissive_factor = vec4(uniforms.emissive_factor.rgb * uniforms.emissive_factor.w, 0.0);
+  v_extensions = uniforms.extensions;
   v_colour = colour;
   v_alpha_cutoff = vec4(uniforms.emissive_alpha.z, uniforms.emissive_alpha.w, 0.0, uniforms.emissive_alpha.x);
   v_base_colour_factor = uniforms.base_colour_factor;
-  v_world_pos = local_pos;
-  v_normal = rotated_normal.xyz;
-  
-  v_camera_pos = push_constants.c_position.rgb;
+  v_world_pos = world_pos;
+  v_normal = world_normal;
   
   v_use_textures = uniforms.use_textures;
-  v_scanline = vec3(push_constants.hologram_scanline.y, local_pos.y, push_constants.hologram_scanline.x);
+  v_scanline = vec3(push_constants.hologram_scanline.y, world_pos.y, push_constants.hologram_scanline.x);
   v_mr = vec2(uniforms.mro_factors.x, uniforms.mro_factors.y);
   
-  gl_Position = projection * view * vec4(local_pos, 1.0);
+  vec4 previous_clip = camera.previous_view_projection * vec4(vec4(position, 1.0) * push_constants.previous_model, 1.0);
+  gl_Position = camera.view_projection * vec4(world_pos, 1.0);
+  v_clip = vec4(gl_Position.xyw, previous_clip.w);
+  v_previous_clip = previous_clip.xy;
 }
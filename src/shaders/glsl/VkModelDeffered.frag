@@ -1,21 +1,96 @@
 #version 450
+#extension GL_GOOGLE_include_directive : require
 
 layout(location = 0) in vec2 uvs;
-layout(location = 1) in vec3 v_camera_pos;
-layout(location = 2) in vec3 v_light_positions[3];
-layout(location = 5) in vec3 v_light_colours[3];
-layout(location = 8) in float v_light_intensity[3];
-layout(location = 11) in vec3 v_sun_direction;
-layout(location = 12) in vec4 v_sun_colour;
 
 layout(location = 0) out vec4 outColour;
 
+// Must match CLUSTER_X, CLUSTER_Y and CLUSTER_Z in shaders/lights.rs
+const uint CLUSTER_X = 16;
+const uint CLUSTER_Y = 9;
+const uint CLUSTER_Z = 24;
+const uint CLUSTER_COUNT = CLUSTER_X*CLUSTER_Y*CLUSTER_Z;
+
+const float LIGHT_POINT = 0.0;
+const float LIGHT_SPOT = 1.0;
+
+struct Light {
+  vec4 position; // x, y, z, range
+  vec4 colour; // r, g, b, intensity
+  vec4 direction; // x, y, z, type
+  vec4 cone; // cos inner, cos outer, first shadow tile or -1, _
+};
+
 layout (input_attachment_index = 1, binding = 1) uniform subpassInput colour_texture;
 layout (input_attachment_index = 2, binding = 2) uniform subpassInput mro_texture;
 layout (input_attachment_index = 3, binding = 3) uniform subpassInput occlusion_texture;
 layout (input_attachment_index = 4, binding = 4) uniform subpassInput normal_texture;
 layout (input_attachment_index = 5, binding = 5) uniform subpassInput position_texture;
 
+layout (std430, binding = 6) readonly buffer Lights {
+  Light lights[];
+};
+
+// directional lights are first in lights and aren't in any cluster
+layout (std430, binding = 7) readonly buffer LightClusters {
+  uvec2 clusters[CLUSTER_COUNT]; // offset, count
+  uint light_indices[];
+};
+
+struct ShadowTile {
+  mat4 matrix;
+  vec4 rect; // uv offset, uv scale in the atlas
+};
+
+// the sun is the first directional light and its cascades are the first tiles
+layout (std430, binding = 8) readonly buffer Shadows {
+  vec4 cascade_splits; // view depth each cascade ends at
+  vec4 shadow_info; // cascade count, atlas texel width, atlas texel height, _
+  ShadowTile tiles[];
+} shadows;
+
+layout (binding = 9) uniform sampler2D shadow_atlas;
+
+// black until an environment map is set
+layout (binding = 10) uniform samplerCube irradiance_map;
+layout (binding = 11) uniform samplerCube prefiltered_map;
+layout (binding = 12) uniform sampler2D brdf_lut;
+
+// built from last frame's gbuffers, r is the occlusion and g the view depth it was found at
+layout (binding = 13) uniform sampler2D ambient_occlusion_map;
+layout (std430, binding = 14) readonly buffer AmbientOcclusionView {
+  vec4 position; // x, y, z, occlusion built
+  vec4 forward; // x, y, z, _
+  vec4 right; // x, y, z, tan(fov/2)*aspect
+  vec4 up; // x, y, z, tan(fov/2)
+} ambient_occlusion_view;
+
+layout (std430, binding = 15) readonly buffer Fog {
+  vec4 colour; // r, g, b, distance density
+  vec4 height; // density, height, falloff, atmosphere on
+  vec4 sun_direction; // x, y, z, _
+} fog;
+
+layout(push_constant) uniform PushConstants {
+  vec4 camera_position; // x, y, z, near
+  vec4 camera_forward; // x, y, z, far
+  vec4 camera_right; // x, y, z, tan(fov/2)*aspect
+  vec4 camera_up; // x, y, z, tan(fov/2)
+  vec4 light_counts; // num lights, num directional, environment map on, prefiltered max lod
+  vec4 debug_view; // view, _, _, _
+} push_constants;
+
+// must match DebugView in shaders/model_shader.rs, 0 is lit
+const float DEBUG_ALBEDO = 1.0;
+const float DEBUG_MRO = 2.0;
+const float DEBUG_EMISSIVE = 3.0;
+const float DEBUG_NORMAL = 4.0;
+const float DEBUG_POSITION = 5.0;
+const float DEBUG_DEPTH = 6.0;
+const float DEBUG_WIREFRAME = 7.0;
+const float DEBUG_OVERDRAW = 8.0;
+const float DEBUG_LIGHT_COUNT = 9.0;
+
 const float M_PI = 3.141592653589793;
 
 float cot(float value) {
@@ -43,14 +118,19 @@ float G_SchlicksmithGGX(float dotNL, float dotNV, float roughness) {
   return GL * GV;
 }
 
-vec3 F_Schlick(float cosTheta, float metallic) {
-  vec3 F0 = mix(vec3(0.04), vec3(0.2, 0.2, 0.2), metallic);
+// dielectrics reflect 4%, metals reflect their albedo
+vec3 F_Schlick(float cosTheta, vec3 F0) {
   vec3 F = F0 + (1.0 - F0) * pow(1.0 - cosTheta, 5.0); 
   
   return F; 
 }
 
-vec3 BRDF(vec3 L, vec3 V, vec3 N, float metallic, float roughness, vec3 light_position, vec3 light_colour, float intensity, vec3 world_pos) {
+// rough surfaces lose some of the glancing angle reflection of ambient light
+vec3 F_SchlickRoughness(float cosTheta, vec3 F0, float roughness) {
+  return F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(1.0 - cosTheta, 5.0);
+}
+
+vec3 BRDF(vec3 L, vec3 V, vec3 N, vec3 F0, float roughness, vec3 radiance) {
   vec3 H = normalize(V+L);
   float dotNV = clamp(dot(N, V), 0.0, 1.0);
   float dotNL = clamp(dot(N, L), 0.0, 1.0);
@@ -59,13 +139,6 @@ vec3 BRDF(vec3 L, vec3 V, vec3 N, float metallic, float roughness, vec3 light_po
   
   vec3 colour = vec3(0.0);
   
-  float distance = length(light_position-world_pos);
-  
-  float attenuation = 1.0;
-  attenuation *= 1.0 / max(distance * distance, 0.01*0.01);
-  
-  vec3 radiance = light_colour * intensity * attenuation;
-  
   if (dotNL > 0.0 && dotNV > 0.0) {
     float rr = max(0.05, roughness);
     
@@ -73,7 +146,7 @@ vec3 BRDF(vec3 L, vec3 V, vec3 N, float metallic, float roughness, vec3 light_po
     
     float G = G_SchlicksmithGGX(dotNL, dotNV, roughness);
     
-    vec3 F = F_Schlick(dotNV, metallic);
+    vec3 F = F_Schlick(dotNV, F0);
     
     vec3 spec = D *F * G / (4.0 * dotNL * dotNV);
     
@@ -83,6 +156,248 @@ vec3 BRDF(vec3 L, vec3 V, vec3 N, float metallic, float roughness, vec3 light_po
   return colour;
 }
 
+// 3x3 pcf around a point in a tile, depth and uv are in the tile's clip space
+float sample_tile(ShadowTile tile, vec3 shadow_pos) {
+  vec2 texel = shadows.shadow_info.yz;
+  vec2 edge = 1.5 * texel / tile.rect.zw;
+  
+  vec2 uv = clamp(shadow_pos.xy * 0.5 + 0.5, edge, vec2(1.0) - edge);
+  uv = tile.rect.xy + uv * tile.rect.zw;
+  
+  float lit = 0.0;
+  for (int x = -1; x <= 1; ++x) {
+    for (int y = -1; y <= 1; ++y) {
+      float closest_depth = texture(shadow_atlas, uv + vec2(x, y) * texel).r;
+      lit += shadow_pos.z <= closest_depth ? 1.0 : 0.0;
+    }
+  }
+  
+  return lit / 9.0;
+}
+
+// the cascade covering world_pos, 1.0 is fully lit
+float sun_shadow(vec3 world_pos, vec3 N, vec3 L) {
+  uint num_cascades = uint(shadows.shadow_info.x);
+  float depth = dot(world_pos - push_constants.camera_position.xyz, push_constants.camera_forward.xyz);
+  
+  uint cascade = 0;
+  while (cascade < num_cascades && depth > shadows.cascade_splits[cascade]) {
+    cascade += 1;
+  }
+  
+  if (cascade >= num_cascades) {
+    return 1.0;
+  }
+  
+  ShadowTile tile = shadows.tiles[cascade];
+  mat4 light_matrix = tile.matrix;
+  float resolution = tile.rect.z / shadows.shadow_info.y;
+  
+  // rows of the matrix are the light's axes scaled by 1/radius and 1/depth range
+  float world_texel = 2.0 / (resolution * length(vec3(light_matrix[0][0], light_matrix[1][0], light_matrix[2][0])));
+  float depth_scale = length(vec3(light_matrix[0][2], light_matrix[1][2], light_matrix[2][2]));
+  
+  float NdotL = clamp(dot(N, L), 0.0, 1.0);
+  vec3 offset_pos = world_pos + N * world_texel * 1.5 * (1.0 - NdotL);
+  vec4 light_pos = light_matrix * vec4(offset_pos, 1.0);
+  
+  float bias = world_texel * depth_scale;
+  
+  return sample_tile(tile, vec3(light_pos.xy, light_pos.z - bias));
+}
+
+// point lights pick the cube face world_pos falls in, 1.0 is fully lit
+float light_shadow(Light light, vec3 world_pos, vec3 N, vec3 L) {
+  int first_tile = int(light.cone.z);
+  if (first_tile < 0) {
+    return 1.0;
+  }
+  
+  vec3 from_light = world_pos - light.position.xyz;
+  
+  int face = 0;
+  if (light.direction.w == LIGHT_POINT) {
+    vec3 axis = abs(from_light);
+    if (axis.x >= axis.y && axis.x >= axis.z) {
+      face = from_light.x > 0.0 ? 0 : 1;
+    } else if (axis.y >= axis.z) {
+      face = from_light.y > 0.0 ? 2 : 3;
+    } else {
+      face = from_light.z > 0.0 ? 4 : 5;
+    }
+  }
+  
+  ShadowTile tile = shadows.tiles[first_tile + face];
+  mat4 light_matrix = tile.matrix;
+  float resolution = tile.rect.z / shadows.shadow_info.y;
+  
+  // texels grow with distance, the first row is the light's right axis scaled by cot(fov/2)
+  float focal = length(vec3(light_matrix[0][0], light_matrix[1][0], light_matrix[2][0]));
+  float world_texel = 2.0 * length(from_light) / (focal * resolution);
+  
+  float NdotL = clamp(dot(N, L), 0.0, 1.0);
+  vec3 offset_pos = world_pos + N * world_texel * 1.5 * (1.0 - NdotL) + L * world_texel;
+  vec4 light_pos = light_matrix * vec4(offset_pos, 1.0);
+  
+  if (light_pos.w <= 0.0) {
+    return 1.0;
+  }
+  
+  return sample_tile(tile, light_pos.xyz / light_pos.w);
+}
+
+// KHR_lights_punctual inverse square falloff windowed to reach zero at the range
+vec3 light_contribution(Light light, vec3 V, vec3 N, vec3 F0, float roughness, vec3 world_pos) {
+  vec3 to_light = light.position.xyz - world_pos;
+  float distance = length(to_light);
+  vec3 L = to_light / max(distance, 0.0001);
+  
+  float range_window = clamp(1.0 - pow(distance / light.position.w, 4.0), 0.0, 1.0);
+  float attenuation = range_window*range_window / max(distance * distance, 0.01*0.01);
+  
+  if (light.direction.w == LIGHT_SPOT) {
+    float cd = dot(light.direction.xyz, -L);
+    attenuation *= smoothstep(light.cone.y, light.cone.x, cd);
+  }
+  
+  vec3 radiance = light.colour.rgb * light.colour.w * attenuation;
+  
+  if (attenuation > 0.0) {
+    radiance *= light_shadow(light, world_pos, N, L);
+  }
+  
+  return BRDF(L, V, N, F0, roughness, radiance);
+}
+
+// diffuse from the irradiance map and specular from the prefiltered map split sum
+vec3 ambient_contribution(vec3 V, vec3 N, vec3 albedo, vec3 F0, float metallic, float roughness) {
+  float dotNV = clamp(dot(N, V), 0.0, 1.0);
+  vec3 R = reflect(-V, N);
+  
+  vec3 F = F_SchlickRoughness(dotNV, F0, roughness);
+  vec3 kD = (vec3(1.0) - F) * (1.0 - metallic);
+  
+  vec3 diffuse = texture(irradiance_map, N).rgb * albedo;
+  
+  vec3 prefiltered = textureLod(prefiltered_map, R, roughness * push_constants.light_counts.w).rgb;
+  vec2 brdf = texture(brdf_lut, vec2(dotNV, roughness)).rg;
+  vec3 specular = prefiltered * (F * brdf.x + brdf.y);
+  
+  return kD * diffuse + specular;
+}
+
+// reprojected into the view the occlusion was built from, 1.0 where that view didn't see world_pos
+float ambient_occlusion(vec3 world_pos) {
+  if (ambient_occlusion_view.position.w < 0.5) {
+    return 1.0;
+  }
+  
+  vec3 relative = world_pos - ambient_occlusion_view.position.xyz;
+  float depth = dot(relative, ambient_occlusion_view.forward.xyz);
+  if (depth <= 0.0) {
+    return 1.0;
+  }
+  
+  vec2 ndc = vec2(dot(relative, ambient_occlusion_view.right.xyz) / (depth*ambient_occlusion_view.right.w),
+                  dot(relative, ambient_occlusion_view.up.xyz) / (depth*ambient_occlusion_view.up.w));
+  vec2 uv = ndc*0.5 + 0.5;
+  if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+    return 1.0;
+  }
+  
+  vec2 occlusion = texture(ambient_occlusion_map, uv).rg;
+  
+  // something else was in front of it
+  if (abs(occlusion.g - depth) > depth*0.05) {
+    return 1.0;
+  }
+  
+  return occlusion.r;
+}
+
+uint cluster_index(vec3 world_pos) {
+  vec3 relative = world_pos - push_constants.camera_position.xyz;
+  float near = push_constants.camera_position.w;
+  float far = push_constants.camera_forward.w;
+  
+  float depth = max(dot(relative, push_constants.camera_forward.xyz), near);
+  vec2 ndc = vec2(dot(relative, push_constants.camera_right.xyz) / (depth*push_constants.camera_right.w),
+                  dot(relative, push_constants.camera_up.xyz) / (depth*push_constants.camera_up.w));
+  
+  uvec2 tile = uvec2(clamp((ndc*0.5 + 0.5) * vec2(CLUSTER_X, CLUSTER_Y), vec2(0.0), vec2(CLUSTER_X-1, CLUSTER_Y-1)));
+  uint slice = uint(clamp(log(depth/near) / log(far/near) * float(CLUSTER_Z), 0.0, float(CLUSTER_Z-1)));
+  
+  return (slice*CLUSTER_Y + tile.y)*CLUSTER_X + tile.x;
+}
+
+#include "include/atmosphere.glsl"
+
+// fog between the camera and world_pos blended over colour
+vec3 apply_fog(vec3 colour, vec3 world_pos, vec3 camera_pos) {
+  float density = fog.colour.w;
+  float height_density = fog.height.x;
+  if (density <= 0.0 && height_density <= 0.0) {
+    return colour;
+  }
+  
+  vec3 ray = world_pos - camera_pos;
+  float distance = length(ray);
+  vec3 direction = ray / max(distance, 0.0001);
+  
+  // height fog thins exponentially above its height, integrated along the ray
+  float falloff = fog.height.z;
+  float height_depth = height_density * exp(-falloff * (camera_pos.y - fog.height.y));
+  float climb = falloff * ray.y;
+  if (abs(climb) > 0.0001) {
+    height_depth *= (1.0 - exp(-climb)) / climb * distance;
+  } else {
+    height_depth *= distance;
+  }
+  
+  float amount = 1.0 - exp(-(density*distance + height_depth));
+  
+  vec3 fog_colour = fog.colour.rgb;
+  if (fog.height.w > 0.0) {
+    fog_colour = atmosphere(direction, -fog.sun_direction.xyz);
+  }
+  
+  return mix(colour, fog_colour, clamp(amount, 0.0, 1.0));
+}
+
+// blue through green and yellow to red as amount goes from 0 to 1
+vec3 heatmap(float amount) {
+  float x = 4.0*clamp(amount, 0.0, 1.0);
+  return clamp(vec3(x - 2.0, 2.0 - abs(x - 2.0), 2.0 - x), 0.0, 1.0);
+}
+
+// a single gbuffer attachment as data, the final pass takes the gamma back off
+vec3 debug_colour(float view, vec4 albedo, vec3 world_pos, vec3 N, float view_depth) {
+  vec3 colour = vec3(0.0);
+  
+  if (view == DEBUG_ALBEDO) {
+    return albedo.rgb;
+  } else if (view == DEBUG_MRO) {
+    colour = subpassLoad(mro_texture).rgb;
+  } else if (view == DEBUG_EMISSIVE) {
+    colour = subpassLoad(occlusion_texture).rgb;
+  } else if (view == DEBUG_NORMAL) {
+    colour = length(N) > 0.0 ? normalize(N)*0.5 + 0.5 : vec3(0.0);
+  } else if (view == DEBUG_POSITION) {
+    colour = fract(world_pos * 0.1); // repeats every 10 units
+  } else if (view == DEBUG_DEPTH) {
+    float near = push_constants.camera_position.w;
+    float far = push_constants.camera_forward.w;
+    colour = vec3(log(view_depth/near) / log(far/near));
+  } else if (view == DEBUG_OVERDRAW) {
+    colour = heatmap((albedo.a - 1.0) / 7.0); // a single fragment is blue, 8 or more red
+  } else if (view == DEBUG_LIGHT_COUNT) {
+    uvec2 cluster = clusters[cluster_index(world_pos)];
+    colour = heatmap((float(cluster.y) + push_constants.light_counts.y) / 16.0);
+  }
+  
+  return pow(colour, vec3(2.2));
+}
+
 /*
 float getLinearDepth(vec2 coord) {
     float depth = texture2D(gBufferTexture2, coord).r * 2.0 - 1.0;
@@ -98,27 +413,54 @@ void main() {
   }
   
   vec3 world_pos = subpassLoad(position_texture).rgb;
+  
+  // depth for the transparent subpass, only attached when the opaque depth is multisampled
+  float near = push_constants.camera_position.w;
+  float far = push_constants.camera_forward.w;
+  float view_depth = max(dot(world_pos - push_constants.camera_position.xyz, push_constants.camera_forward.xyz), near);
+  gl_FragDepth = (far + near) / (far - near) - 2.0*far*near / ((far - near)*view_depth);
   vec3 N = vec3(subpassLoad(normal_texture).rgb);
-  vec3 V = normalize(v_camera_pos.xyz - world_pos);
   
-  vec3 Lo = vec3(0.0);
+  float view = push_constants.debug_view.x;
+  if (view > 0.5 && view != DEBUG_WIREFRAME) {
+    outColour = vec4(debug_colour(view, base_colour, world_pos, N, view_depth), 1.0);
+    return;
+  }
   
-  vec3 L[3];
-  L[0] = normalize(v_light_positions[0] - world_pos);
-  L[1] = normalize(v_light_positions[1] - world_pos);
-  L[2] = normalize(v_light_positions[2] - world_pos);
+  vec3 V = normalize(push_constants.camera_position.xyz - world_pos);
+  
+  vec3 Lo = vec3(0.0);
   
   vec4 mro_colour = subpassLoad(mro_texture);
+  float metallic = mro_colour.b;
+  float roughness = mro_colour.g;
+  vec3 F0 = mix(vec3(0.04), base_colour.rgb, metallic);
   
-  for(int i = 0; i < 3; ++i) {
-    Lo += BRDF(L[i], V, N, mro_colour.b, mro_colour.g, v_light_positions[i], v_light_colours[i], v_light_intensity[i], world_pos);
+  uint num_directional = uint(push_constants.light_counts.y);
+  for (uint i = 0; i < num_directional; ++i) {
+    vec3 L = normalize(-lights[i].direction.xyz);
+    vec3 radiance = lights[i].colour.rgb * lights[i].colour.w;
+    if (i == 0) {
+      radiance *= sun_shadow(world_pos, N, L);
+    }
+    Lo += BRDF(L, V, N, F0, roughness, radiance);
   }
   
-  base_colour.rgb += Lo;
+  uvec2 cluster = clusters[cluster_index(world_pos)];
+  for (uint i = 0; i < cluster.y; ++i) {
+    Lo += light_contribution(lights[light_indices[cluster.x + i]], V, N, F0, roughness, world_pos);
+  }
   
-  base_colour.rgb = pow(base_colour.rgb, vec3(0.4545));
+  // the environment replaces the flat albedo ambient, unlit materials have no normal and keep it
+  if (push_constants.light_counts.z > 0.0 && length(N) > 0.0) {
+    N = normalize(N);
+    base_colour.rgb = ambient_contribution(V, N, base_colour.rgb, F0, metallic, roughness);
+  }
+  
+  base_colour.rgb *= ambient_occlusion(world_pos);
+  
+  base_colour.rgb += Lo;
+  base_colour.rgb = apply_fog(base_colour.rgb, world_pos, push_constants.camera_position.xyz);
   
   outColour = base_colour;
 }
-
-
@@ -0,0 +1,445 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) in vec2 uvs;
+layout(location = 1) in vec4 v_colour;
+layout(location = 2) in vec4 v_base_colour_factor;
+layout(location = 3) in vec4 v_alpha_cutoff; // alpha, cutoff, _, use emissive
+layout(location = 4) in vec3 v_normal;
+layout(location = 5) in vec3 v_world_pos;
+layout(location = 7) in vec4 v_uvs_base_mro; // base uv, metallic_roughness uv
+layout(location = 8) in vec4 v_uvs_normal_occlusion; // normal uv, occlusion uv
+layout(location = 9) in vec2 v_uvs_emissive;
+layout(location = 10) in vec3 v_scanline;
+layout(location = 11) in vec4 v_use_textures;
+layout(location = 12) in vec2 v_mr;
+layout(location = 13) in vec4 v_emissive_factor; // r, g, b, _
+layout(location = 14) in vec4 v_extensions; // unlit, transmission, _, _
+
+layout(location = 0) out vec4 outColour;
+
+// Must match CLUSTER_X, CLUSTER_Y and CLUSTER_Z in shaders/lights.rs
+const uint CLUSTER_X = 16;
+const uint CLUSTER_Y = 9;
+const uint CLUSTER_Z = 24;
+const uint CLUSTER_COUNT = CLUSTER_X*CLUSTER_Y*CLUSTER_Z;
+
+const float LIGHT_POINT = 0.0;
+const float LIGHT_SPOT = 1.0;
+
+struct Light {
+  vec4 position; // x, y, z, range
+  vec4 colour; // r, g, b, intensity
+  vec4 direction; // x, y, z, type
+  vec4 cone; // cos inner, cos outer, first shadow tile or -1, _
+};
+
+layout(set = 0, binding = 1) uniform sampler2D base_texture;
+layout(set = 0, binding = 2) uniform sampler2D mro_texture;
+layout(set = 0, binding = 3) uniform sampler2D normal_texture;
+layout(set = 0, binding = 4) uniform sampler2D occlusion_texture;
+layout(set = 0, binding = 5) uniform sampler2D emissive_texture;
+
+layout (std430, set = 2, binding = 0) readonly buffer Lights {
+  Light lights[];
+};
+
+// directional lights are first in lights and aren't in any cluster
+layout (std430, set = 2, binding = 1) readonly buffer LightClusters {
+  uvec2 clusters[CLUSTER_COUNT]; // offset, count
+  uint light_indices[];
+};
+
+struct ShadowTile {
+  mat4 matrix;
+  vec4 rect; // uv offset, uv scale in the atlas
+};
+
+// the sun is the first directional light and its cascades are the first tiles
+layout (std430, set = 2, binding = 2) readonly buffer Shadows {
+  vec4 cascade_splits; // view depth each cascade ends at
+  vec4 shadow_info; // cascade count, atlas texel width, atlas texel height, _
+  ShadowTile tiles[];
+} shadows;
+
+layout (set = 2, binding = 3) uniform sampler2D shadow_atlas;
+
+// black until an environment map is set
+layout (set = 2, binding = 4) uniform samplerCube irradiance_map;
+layout (set = 2, binding = 5) uniform samplerCube prefiltered_map;
+layout (set = 2, binding = 6) uniform sampler2D brdf_lut;
+
+// the view and light counts the deffered pass was pushed
+layout (std430, set = 2, binding = 7) readonly buffer Camera {
+  vec4 camera_position; // x, y, z, near
+  vec4 camera_forward; // x, y, z, far
+  vec4 camera_right; // x, y, z, tan(fov/2)*aspect
+  vec4 camera_up; // x, y, z, tan(fov/2)
+  vec4 light_counts; // num lights, num directional, environment map on, prefiltered max lod
+  vec4 debug_view; // view, _, _, _
+} camera;
+
+layout (std430, set = 2, binding = 8) readonly buffer Fog {
+  vec4 colour; // r, g, b, distance density
+  vec4 height; // density, height, falloff, atmosphere on
+  vec4 sun_direction; // x, y, z, _
+} fog;
+
+const float M_PI = 3.141592653589793;
+
+const vec3 sun_dir = vec3(-0.2, 1.0, -0.2);
+
+vec4 when_gt(vec4 x, vec4 y) {
+  return max(sign(x - y), 0.0);
+}
+
+vec4 not(vec4 a) {
+  return 1.0 - a;
+}
+
+float hologram_alpha(float scanline, float y_offset) {
+  
+  float blah = 0.25f * sin(10.0*y_offset + scanline*-15.0);
+  blah += 0.4f;
+  
+  float n_offset = (y_offset+1.0f)/2;
+  
+  float alpha = cos(M_PI*n_offset - (scanline*M_PI)) + 1;
+  alpha = 0.017-alpha;
+  alpha = alpha *100;
+  
+  alpha = max(blah, alpha);
+  alpha = alpha * 0.8;
+  
+  return alpha;
+}
+
+float D_GGX(float dotNH, float roughness) {
+  float alpha = roughness * roughness;
+  float alpha2 = alpha * alpha;
+  float denom = dotNH * dotNH * (alpha2 - 1.0) + 1.0;
+  //float denom = (dotNH * (alpha2) - dotNH) * dotNH + 1.0;
+  return (alpha2)/(M_PI * denom*denom); 
+}
+
+float G_SchlicksmithGGX(float dotNL, float dotNV, float roughness) {
+  float r = (roughness + 1.0);
+  float k = (r*r) / 8.0;
+  float GL = dotNL / (dotNL * (1.0 - k) + k);
+  float GV = dotNV / (dotNV * (1.0 - k) + k);
+  
+  return GL * GV;
+}
+
+// dielectrics reflect 4%, metals reflect their albedo
+vec3 F_Schlick(float cosTheta, vec3 F0) {
+  vec3 F = F0 + (1.0 - F0) * pow(1.0 - cosTheta, 5.0); 
+  
+  return F; 
+}
+
+// rough surfaces lose some of the glancing angle reflection of ambient light
+vec3 F_SchlickRoughness(float cosTheta, vec3 F0, float roughness) {
+  return F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(1.0 - cosTheta, 5.0);
+}
+
+vec3 BRDF(vec3 L, vec3 V, vec3 N, vec3 F0, float roughness, vec3 radiance) {
+  vec3 H = normalize(V+L);
+  float dotNV = clamp(dot(N, V), 0.0, 1.0);
+  float dotNL = clamp(dot(N, L), 0.0, 1.0);
+  float dotLH = clamp(dot(L, H), 0.0, 1.0);
+  float dotNH = clamp(dot(N, H), 0.0, 1.0);
+  
+  vec3 colour = vec3(0.0);
+  
+  if (dotNL > 0.0 && dotNV > 0.0) {
+    float rr = max(0.05, roughness);
+    
+    float D = D_GGX(dotNH, roughness);
+    
+    float G = G_SchlicksmithGGX(dotNL, dotNV, roughness);
+    
+    vec3 F = F_Schlick(dotNV, F0);
+    
+    vec3 spec = D *F * G / (4.0 * dotNL * dotNV);
+    
+    colour += spec * radiance * dotNL;
+  }
+  
+  return colour;
+}
+
+// 3x3 pcf around a point in a tile, depth and uv are in the tile's clip space
+float sample_tile(ShadowTile tile, vec3 shadow_pos) {
+  vec2 texel = shadows.shadow_info.yz;
+  vec2 edge = 1.5 * texel / tile.rect.zw;
+  
+  vec2 uv = clamp(shadow_pos.xy * 0.5 + 0.5, edge, vec2(1.0) - edge);
+  uv = tile.rect.xy + uv * tile.rect.zw;
+  
+  float lit = 0.0;
+  for (int x = -1; x <= 1; ++x) {
+    for (int y = -1; y <= 1; ++y) {
+      float closest_depth = texture(shadow_atlas, uv + vec2(x, y) * texel).r;
+      lit += shadow_pos.z <= closest_depth ? 1.0 : 0.0;
+    }
+  }
+  
+  return lit / 9.0;
+}
+
+// the cascade covering world_pos, 1.0 is fully lit
+float sun_shadow(vec3 world_pos, vec3 N, vec3 L) {
+  uint num_cascades = uint(shadows.shadow_info.x);
+  float depth = dot(world_pos - camera.camera_position.xyz, camera.camera_forward.xyz);
+  
+  uint cascade = 0;
+  while (cascade < num_cascades && depth > shadows.cascade_splits[cascade]) {
+    cascade += 1;
+  }
+  
+  if (cascade >= num_cascades) {
+    return 1.0;
+  }
+  
+  ShadowTile tile = shadows.tiles[cascade];
+  mat4 light_matrix = tile.matrix;
+  float resolution = tile.rect.z / shadows.shadow_info.y;
+  
+  // rows of the matrix are the light's axes scaled by 1/radius and 1/depth range
+  float world_texel = 2.0 / (resolution * length(vec3(light_matrix[0][0], light_matrix[1][0], light_matrix[2][0])));
+  float depth_scale = length(vec3(light_matrix[0][2], light_matrix[1][2], light_matrix[2][2]));
+  
+  float NdotL = clamp(dot(N, L), 0.0, 1.0);
+  vec3 offset_pos = world_pos + N * world_texel * 1.5 * (1.0 - NdotL);
+  vec4 light_pos = light_matrix * vec4(offset_pos, 1.0);
+  
+  float bias = world_texel * depth_scale;
+  
+  return sample_tile(tile, vec3(light_pos.xy, light_pos.z - bias));
+}
+
+// point lights pick the cube face world_pos falls in, 1.0 is fully lit
+float light_shadow(Light light, vec3 world_pos, vec3 N, vec3 L) {
+  int first_tile = int(light.cone.z);
+  if (first_tile < 0) {
+    return 1.0;
+  }
+  
+  vec3 from_light = world_pos - light.position.xyz;
+  
+  int face = 0;
+  if (light.direction.w == LIGHT_POINT) {
+    vec3 axis = abs(from_light);
+    if (axis.x >= axis.y && axis.x >= axis.z) {
+      face = from_light.x > 0.0 ? 0 : 1;
+    } else if (axis.y >= axis.z) {
+      face = from_light.y > 0.0 ? 2 : 3;
+    } else {
+      face = from_light.z > 0.0 ? 4 : 5;
+    }
+  }
+  
+  ShadowTile tile = shadows.tiles[first_tile + face];
+  mat4 light_matrix = tile.matrix;
+  float resolution = tile.rect.z / shadows.shadow_info.y;
+  
+  // texels grow with distance, the first row is the light's right axis scaled by cot(fov/2)
+  float focal = length(vec3(light_matrix[0][0], light_matrix[1][0], light_matrix[2][0]));
+  float world_texel = 2.0 * length(from_light) / (focal * resolution);
+  
+  float NdotL = clamp(dot(N, L), 0.0, 1.0);
+  vec3 offset_pos = world_pos + N * world_texel * 1.5 * (1.0 - NdotL) + L * world_texel;
+  vec4 light_pos = light_matrix * vec4(offset_pos, 1.0);
+  
+  if (light_pos.w <= 0.0) {
+    return 1.0;
+  }
+  
+  return sample_tile(tile, light_pos.xyz / light_pos.w);
+}
+
+// KHR_lights_punctual inverse square falloff windowed to reach zero at the range
+vec3 light_contribution(Light light, vec3 V, vec3 N, vec3 F0, float roughness, vec3 world_pos) {
+  vec3 to_light = light.position.xyz - world_pos;
+  float distance = length(to_light);
+  vec3 L = to_light / max(distance, 0.0001);
+  
+  float range_window = clamp(1.0 - pow(distance / light.position.w, 4.0), 0.0, 1.0);
+  float attenuation = range_window*range_window / max(distance * distance, 0.01*0.01);
+  
+  if (light.direction.w == LIGHT_SPOT) {
+    float cd = dot(light.direction.xyz, -L);
+    attenuation *= smoothstep(light.cone.y, light.cone.x, cd);
+  }
+  
+  vec3 radiance = light.colour.rgb * light.colour.w * attenuation;
+  
+  if (attenuation > 0.0) {
+    radiance *= light_shadow(light, world_pos, N, L);
+  }
+  
+  return BRDF(L, V, N, F0, roughness, radiance);
+}
+
+// diffuse from the irradiance map and specular from the prefiltered map split sum
+vec3 ambient_contribution(vec3 V, vec3 N, vec3 albedo, vec3 F0, float metallic, float roughness) {
+  float dotNV = clamp(dot(N, V), 0.0, 1.0);
+  vec3 R = reflect(-V, N);
+  
+  vec3 F = F_SchlickRoughness(dotNV, F0, roughness);
+  vec3 kD = (vec3(1.0) - F) * (1.0 - metallic);
+  
+  vec3 diffuse = texture(irradiance_map, N).rgb * albedo;
+  
+  vec3 prefiltered = textureLod(prefiltered_map, R, roughness * camera.light_counts.w).rgb;
+  vec2 brdf = texture(brdf_lut, vec2(dotNV, roughness)).rg;
+  vec3 specular = prefiltered * (F * brdf.x + brdf.y);
+  
+  return kD * diffuse + specular;
+}
+
+uint cluster_index(vec3 world_pos) {
+  vec3 relative = world_pos - camera.camera_position.xyz;
+  float near = camera.camera_position.w;
+  float far = camera.camera_forward.w;
+  
+  float depth = max(dot(relative, camera.camera_forward.xyz), near);
+  vec2 ndc = vec2(dot(relative, camera.camera_right.xyz) / (depth*camera.camera_right.w),
+                  dot(relative, camera.camera_up.xyz) / (depth*camera.camera_up.w));
+  
+  uvec2 tile = uvec2(clamp((ndc*0.5 + 0.5) * vec2(CLUSTER_X, CLUSTER_Y), vec2(0.0), vec2(CLUSTER_X-1, CLUSTER_Y-1)));
+  uint slice = uint(clamp(log(depth/near) / log(far/near) * float(CLUSTER_Z), 0.0, float(CLUSTER_Z-1)));
+  
+  return (slice*CLUSTER_Y + tile.y)*CLUSTER_X + tile.x;
+}
+
+#include "include/atmosphere.glsl"
+
+// fog between the camera and world_pos blended over colour
+vec3 apply_fog(vec3 colour, vec3 world_pos, vec3 camera_pos) {
+  float density = fog.colour.w;
+  float height_density = fog.height.x;
+  if (density <= 0.0 && height_density <= 0.0) {
+    return colour;
+  }
+  
+  vec3 ray = world_pos - camera_pos;
+  float distance = length(ray);
+  vec3 direction = ray / max(distance, 0.0001);
+  
+  // height fog thins exponentially above its height, integrated along the ray
+  float falloff = fog.height.z;
+  float height_depth = height_density * exp(-falloff * (camera_pos.y - fog.height.y));
+  float climb = falloff * ray.y;
+  if (abs(climb) > 0.0001) {
+    height_depth *= (1.0 - exp(-climb)) / climb * distance;
+  } else {
+    height_depth *= distance;
+  }
+  
+  float amount = 1.0 - exp(-(density*distance + height_depth));
+  
+  vec3 fog_colour = fog.colour.rgb;
+  if (fog.height.w > 0.0) {
+    fog_colour = atmosphere(direction, -fog.sun_direction.xyz);
+  }
+  
+  return mix(colour, fog_colour, clamp(amount, 0.0, 1.0));
+}
+
+void main() {
+  vec3 base_colour = vec3(1.0);
+  float alpha = v_colour.a;
+  vec4 mro = vec4(1.0);
+  vec4 emissive = vec4(1.0);
+  vec4 normal = vec4(1.0);
+  
+  vec4 use_base_texture = when_gt(vec4(v_use_textures.x), vec4(0.0));
+  vec4 use_mro_texture = when_gt(vec4(v_use_textures.y), vec4(0.0));
+  vec4 use_normal_texture = when_gt(vec4(v_use_textures.z), vec4(0.0));
+  vec4 use_occlusion_texture = when_gt(vec4(v_use_textures.w), vec4(0.0));
+  vec4 use_emissive_texture = when_gt(vec4(v_alpha_cutoff.w), vec4(0.0));
+  
+  vec4 use_unlit = when_gt(vec4(v_extensions.x), vec4(0.0));
+  
+  mro = use_mro_texture * texture(mro_texture, v_uvs_base_mro.zw) + 
+        not(use_mro_texture) * vec4(0.0, v_mr.y, v_mr.x, 0.0);
+  
+  emissive = use_emissive_texture * texture(emissive_texture, v_uvs_emissive) + 
+             not(use_emissive_texture) * vec4(1.0);
+  emissive.rgb *= v_emissive_factor.rgb;
+  
+  normal = use_normal_texture * texture(normal_texture, v_uvs_normal_occlusion.xy) + 
+           not(use_normal_texture) * vec4(normalize(v_normal), 0.0);
+  
+  base_colour = use_base_texture.rgb      * texture(base_texture, v_uvs_base_mro.xy).rgb + 
+                not(use_base_texture).rgb * base_colour;
+  
+  alpha = use_base_texture.a    * texture(base_texture, v_uvs_base_mro.xy).a + 
+          not(use_base_texture).a * alpha;
+  
+  base_colour *= v_base_colour_factor.rgb;
+  base_colour *= v_colour.rgb;
+  alpha *= v_base_colour_factor.a;
+  alpha *= 1.0 - v_extensions.y; // transmission approximated as transparency
+ // base_colour *= 0.02;
+  base_colour += emissive.rgb;
+  
+  // Cell shading
+  float light_levels = 6.0;
+  float brightness_factor = max(dot(normalize(normal.xyz), normalize(sun_dir)), 0.0);
+  float level = floor(brightness_factor * light_levels); 
+  brightness_factor = level / light_levels;
+  
+  //base_colour *= brightness_factor;
+  base_colour.rgb = use_unlit.rgb      * base_colour + 
+                    not(use_unlit).rgb * base_colour*max(brightness_factor, 0.02);
+  
+  float halpha = hologram_alpha(v_scanline.x, v_scanline.y);
+  vec4 use_scanline = when_gt(vec4(v_scanline.z), vec4(0.0));
+  
+  alpha = use_scanline.a      * halpha + 
+          not(use_scanline).a * alpha;
+  
+  if (alpha <= 0.0) {
+    discard;
+  }
+  
+  // lit as the deffered pass would have, unlit materials have no normal
+  vec3 world_pos = v_world_pos;
+  vec3 N = not(use_unlit).xyz * normal.xyz;
+  vec3 V = normalize(camera.camera_position.xyz - world_pos);
+  
+  vec3 Lo = vec3(0.0);
+  
+  float metallic = mro.b;
+  float roughness = mro.g;
+  vec3 F0 = mix(vec3(0.04), base_colour, metallic);
+  
+  uint num_directional = uint(camera.light_counts.y);
+  for (uint i = 0; i < num_directional; ++i) {
+    vec3 L = normalize(-lights[i].direction.xyz);
+    vec3 radiance = lights[i].colour.rgb * lights[i].colour.w;
+    if (i == 0) {
+      radiance *= sun_shadow(world_pos, N, L);
+    }
+    Lo += BRDF(L, V, N, F0, roughness, radiance);
+  }
+  
+  uvec2 cluster = clusters[cluster_index(world_pos)];
+  for (uint i = 0; i < cluster.y; ++i) {
+    Lo += light_contribution(lights[light_indices[cluster.x + i]], V, N, F0, roughness, world_pos);
+  }
+  
+  // the environment replaces the flat albedo ambient, unlit materials have no normal and keep it
+  if (camera.light_counts.z > 0.0 && length(N) > 0.0) {
+    N = normalize(N);
+    base_colour = ambient_contribution(V, N, base_colour, F0, metallic, roughness);
+  }
+  
+  base_colour += Lo;
+  base_colour = apply_fog(base_colour, world_pos, camera.camera_position.xyz);
+  
+  outColour = vec4(base_colour, alpha);
+}
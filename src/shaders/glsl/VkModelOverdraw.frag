@@ -0,0 +1,20 @@
+#version 450
+
+layout(location = 0) out vec4 outColour;
+layout(location = 1) out vec4 outAlbedo;
+layout(location = 2) out vec4 outMro;
+layout(location = 3) out vec4 outOcclusion;
+layout(location = 4) out vec4 outNormal;
+layout(location = 5) out vec4 outPosition;
+layout(location = 6) out vec4 outVelocity;
+
+// blended additively, the deffered pass reads how many fragments landed on each pixel from the albedo
+void main() {
+  outColour = vec4(0.0);
+  outAlbedo = vec4(1.0);
+  outMro = vec4(0.0);
+  outOcclusion = vec4(0.0);
+  outNormal = vec4(0.0);
+  outPosition = vec4(0.0);
+  outVelocity = vec4(0.0);
+}
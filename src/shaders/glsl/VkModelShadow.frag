@@ -0,0 +1,20 @@
+#version 450
+
+layout(location = 0) in vec2 v_uvs;
+layout(location = 1) in vec4 v_alpha_cutoff; // alpha, cutoff, mask, use base texture
+
+layout(set = 0, binding = 1) uniform sampler2D base_texture;
+
+// Depth only, masked materials still cut holes in their shadow
+void main() {
+  if (v_alpha_cutoff.z == 2.0) {
+    float alpha = v_alpha_cutoff.x;
+    if (v_alpha_cutoff.w > 0.0) {
+      alpha *= texture(base_texture, v_uvs).a;
+    }
+    
+    if (alpha < v_alpha_cutoff.y) {
+      discard;
+    }
+  }
+}
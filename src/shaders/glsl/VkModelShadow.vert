@@ -0,0 +1,39 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 2) in vec2 uv;
+layout(location = 3) in vec4 colour;
+layout(location = 9) in vec2 uv_1;
+
+layout(location = 0) out vec2 v_uvs;
+layout(location = 1) out vec4 v_alpha_cutoff; // alpha, cutoff, mask, use base texture
+
+layout(set = 0, binding = 0) uniform UniformBuffer {
+  vec4 use_textures; //base, metallic_roughness, normal, occlusion
+  vec4 emissive_alpha; //use_emissive, normal_scale, alpha_cutoff, alpha_mask
+  vec4 base_colour_factor; // r, g, b, a
+  vec4 mro_factors; // metallic_factor, roughness_factor, occlusion_string, _
+  vec4 emissive_factor; // r, g, b, emissive_strength
+  vec4 extensions; // unlit, transmission, _, _
+  vec4 uv_transforms[10]; // two rows each, uv set in w: base, metallic_roughness, normal, occlusion, emissive
+} uniforms;
+
+layout(push_constant) uniform PushConstants {
+  mat4 light_matrix; // view and projection of the atlas tile
+  mat3x4 model;      // the rows of the model matrix, it is affine
+} push_constants;
+
+vec2 transform_uv(int texture) {
+  vec4 row = uniforms.uv_transforms[texture*2]; // w is the uv set
+  vec3 u = vec3(row.w > 0.5 ? uv_1 : uv, 1.0);
+  return vec2(dot(row.xyz, u), dot(uniforms.uv_transforms[texture*2+1].xyz, u));
+}
+
+void main() {
+  vec3 world_pos = vec4(position, 1.0) * push_constants.model;
+  
+  v_uvs = transform_uv(0);
+  v_alpha_cutoff = vec4(colour.a * uniforms.base_colour_factor.a, uniforms.emissive_alpha.z, uniforms.emissive_alpha.w, uniforms.use_textures.x);
+  
+  gl_Position = push_constants.light_matrix * vec4(world_pos, 1.0);
+}
@@ -0,0 +1,77 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) in vec2 uvs;
+
+layout(location = 0) out vec4 outColour;
+layout(location = 1) out vec4 outAlbedo;
+layout(location = 2) out vec4 outMro;
+layout(location = 3) out vec4 outOcclusion;
+layout(location = 4) out vec4 outNormal;
+layout(location = 5) out vec4 outPosition;
+layout(location = 6) out vec4 outVelocity;
+
+layout(set = 0, binding = 0) uniform samplerCube sky;
+
+const float SKY_CUBE = 0.0;
+const float SKY_GRADIENT = 1.0;
+const float SKY_ATMOSPHERE = 2.0;
+
+const float M_PI = 3.141592653589793;
+
+layout(push_constant) uniform PushConstants {
+  vec4 camera_position; // x, y, z, far
+  vec4 camera_forward; // x, y, z, sky type
+  vec4 camera_right; // x, y, z, tan(fov/2)*aspect
+  vec4 camera_up; // x, y, z, tan(fov/2)
+  vec4 top; // r, g, b, _ or the direction to the sun for the atmosphere
+  vec4 horizon; // r, g, b, _
+  vec4 bottom; // r, g, b, _
+} push_constants;
+
+vec3 gradient(vec3 direction) {
+  vec3 top = pow(push_constants.top.rgb, vec3(2.2));
+  vec3 horizon = pow(push_constants.horizon.rgb, vec3(2.2));
+  vec3 bottom = pow(push_constants.bottom.rgb, vec3(2.2));
+  
+  float height = direction.y;
+  if (height >= 0.0) {
+    return mix(horizon, top, pow(height, 0.5));
+  }
+  
+  return mix(horizon, bottom, pow(-height, 0.5));
+}
+
+#include "include/atmosphere.glsl"
+
+void main() {
+  // matches the projection in VkModel.vert, which keeps the sky turning with the camera
+  vec2 ndc = uvs * 2.0 - 1.0;
+  vec3 direction = normalize(push_constants.camera_forward.xyz + 
+                             ndc.x * push_constants.camera_right.xyz * push_constants.camera_right.w + 
+                             ndc.y * push_constants.camera_up.xyz * push_constants.camera_up.w);
+  
+  vec3 colour = vec3(0.0);
+  if (push_constants.camera_forward.w == SKY_GRADIENT) {
+    colour = gradient(direction);
+  } else if (push_constants.camera_forward.w == SKY_ATMOSPHERE) {
+    vec3 sun = push_constants.top.xyz;
+    colour = atmosphere(direction, sun);
+    
+    // the sun itself, dimmed by the same air its light comes through
+    float sun_disc = smoothstep(0.9997, 0.9999, dot(direction, sun));
+    colour += sun_disc * SUN_INTENSITY * air_transmittance(sun.y);
+  } else {
+    colour = texture(sky, direction).rgb;
+  }
+  
+  // the deffered pass leaves anything without a normal unlit
+  outColour = vec4(colour, 1.0);
+  outAlbedo = vec4(colour, 1.0);
+  outMro = vec4(0.0);
+  outOcclusion = vec4(1.0);
+  outNormal = vec4(0.0);
+  outPosition = vec4(push_constants.camera_position.xyz + direction * push_constants.camera_position.w, 1.0);
+  // taa keeps the history of the sky where it is
+  outVelocity = vec4(0.0);
+}
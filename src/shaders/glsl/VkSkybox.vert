@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 uv;
+
+layout(location = 0) out vec2 uvs;
+
+// a single triangle over the screen on the far plane
+void main() {
+  uvs = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+  gl_Position = vec4(uvs * 2.0f - 1.0f, 1.0f, 1.0f);
+}
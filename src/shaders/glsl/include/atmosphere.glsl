@@ -0,0 +1,28 @@
+// single scattering sky shared by the skybox and the fog of the model passes,
+// the including shader defines M_PI
+
+// zenith optical depths of the air and the haze, per km times their scale heights
+const vec3 AIR_RAYLEIGH = vec3(0.0058, 0.0135, 0.0331) * 8.0;
+const float AIR_MIE = 0.021 * 1.2;
+const float SUN_INTENSITY = 20.0;
+
+// light left after crossing the air along a direction, the air is thickest toward the horizon
+vec3 air_transmittance(float height) {
+  return exp(-(AIR_RAYLEIGH + vec3(AIR_MIE)) / (max(height, 0.0) + 0.1));
+}
+
+// light scattered toward the eye from the sun, a single scattering slab of air and haze
+vec3 atmosphere(vec3 direction, vec3 sun) {
+  const float g = 0.76;
+  
+  vec3 extinction = AIR_RAYLEIGH + vec3(AIR_MIE);
+  vec3 sunlight = SUN_INTENSITY * air_transmittance(sun.y) * smoothstep(-0.1, 0.05, sun.y);
+  
+  float mu = dot(direction, sun);
+  float rayleigh_phase = 3.0 / (16.0*M_PI) * (1.0 + mu*mu);
+  float mie_phase = 1.0 / (4.0*M_PI) * (1.0 - g*g) / pow(1.0 + g*g - 2.0*g*mu, 1.5);
+  
+  vec3 scattering = AIR_RAYLEIGH*rayleigh_phase + vec3(AIR_MIE*mie_phase);
+  
+  return sunlight * scattering / extinction * (1.0 - air_transmittance(direction.y));
+}
@@ -0,0 +1,489 @@
+use vk;
+
+use crate::vulkan::vkenums::{ImageType, ImageUsage, ImageViewType, ImageTiling, ImageLayout, ImageAspect, SampleCount, ShaderStage, Filter, AddressMode, MipmapMode, VkBool, Access, PipelineStage};
+
+use crate::vulkan::{Instance, Device, Shader, Pipeline, PipelineBuilder, DescriptorSet, UpdateDescriptorSets, DescriptorSetBuilder, ImageAttachment, Sampler, SamplerBuilder};
+use crate::vulkan::buffer::{Buffer, BufferUsage, UniformData, CommandBufferBuilder};
+use crate::vulkan::pool::DescriptorPool;
+use crate::camera::Frustum;
+use crate::shaders::lights::ClusterView;
+use crate::shaders::model_shader::{LOD_SCREEN_SIZES, INSTANCE_SIZE};
+use crate::math;
+
+use cgmath::{Vector3, Vector4};
+
+use std::mem;
+use std::sync::Arc;
+
+pub const MAX_LODS: usize = LOD_SCREEN_SIZES.len() + 1;
+const HIZ_LEVELS: usize = 8; // must match instance_cull.comp
+
+const MAX_DRAWS: usize = 512; // lods times primitives of a model
+const COMMAND_SIZE: usize = 5; // u32s in a VkDrawIndexedIndirectCommand
+const CULLED_CAPACITY: usize = 256; // instances each lod has room for to start with, grows with the instances culled
+const VIEW_SIZE: usize = 44;
+const CULL_GROUP_SIZE: u32 = 64;
+const PYRAMID_GROUP_SIZE: u32 = 8;
+
+const NEAR: f32 = 0.1;
+const FAR: f32 = 1080.0;
+
+/**
+** The gpu side of an instanced buffer, per frame the instances that survive
+** culling bucketed by lod and a draw command for each lod and primitive.
+**/
+struct CulledBuffer {
+  reference: String,
+  culled: Buffer<f32>,
+  capacity: usize, // instances each lod has room for in culled
+  commands: Buffer<f32>, // u32s stored as their bits
+  descriptor_set: DescriptorSet,
+  submitted: Vec<Option<(u32, usize, usize)>>, // per frame, instances, lods and primitives of the last cull
+  instances: u32, // culled this frame
+}
+
+/**
+** Culls instanced models on the gpu. Each instance is tested against the
+** camera frustum and, with occlusion culling, against a depth pyramid built
+** from the previous frame's positions. Survivors are written out by lod with
+** the instance counts of their draw commands so the model pass draws them
+** indirectly without reading anything back.
+**/
+pub struct InstanceCulling {
+  occlusion: bool,
+  num_frames: usize,
+  current_buffer: usize, // set by begin_frame
+  
+  cull_shader: Shader,
+  cull_pipeline: Pipeline,
+  cull_layout: DescriptorSet, // the layout every buffer's descriptor set shares
+  view_buffer: Buffer<f32>,
+  buffers: Vec<CulledBuffer>,
+  
+  pyramid_shader: Shader,
+  pyramid_pipeline: Pipeline,
+  source_sets: Vec<DescriptorSet>, // per frame, position gbuffer into the first level
+  level_sets: Vec<DescriptorSet>, // each level into the next
+  sampler: Sampler,
+  hiz: Vec<ImageAttachment>,
+  hiz_ready: bool, // false until the levels are in the general layout
+  frame_view: Option<ClusterView>,
+  hiz_view: Option<ClusterView>, // camera the pyramid was built from
+}
+
+impl InstanceCulling {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, position_images: &[ImageAttachment], occlusion: bool, extent: &vk::Extent2D, descriptor_set_pool: &DescriptorPool) -> InstanceCulling {
+    let num_frames = position_images.len();
+    
+    let cull_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/ComputeInstanceCull.spv"));
+    let pyramid_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/ComputeDepthPyramid.spv"));
+    
+    let cull_layout = InstanceCulling::cull_descriptor_set(Arc::clone(&device), descriptor_set_pool, 1);
+    
+    let cull_push_constant_size = UniformData::new()
+                                    .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                    .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                    .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                    .size_non_aligned();
+    
+    let cull_pipeline = PipelineBuilder::new()
+                          .compute_shader(*cull_shader.get_shader())
+                          .descriptor_set_layout(cull_layout.layouts_clone())
+                          .push_constants(ShaderStage::Compute, cull_push_constant_size as u32)
+                          .build_compute(Arc::clone(&device));
+    
+    let view_buffer = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), num_frames as u32, VIEW_SIZE as u64);
+    
+    let pyramid_layout = || {
+      DescriptorSetBuilder::new()
+        .compute_combined_image_sampler(0)
+        .compute_storage_image(1)
+        .build(Arc::clone(&device), descriptor_set_pool, 1)
+    };
+    
+    let mut source_sets = Vec::new();
+    let mut level_sets = Vec::new();
+    if occlusion {
+      for _ in 0..num_frames {
+        source_sets.push(pyramid_layout());
+      }
+      for _ in 1..HIZ_LEVELS {
+        level_sets.push(pyramid_layout());
+      }
+    }
+    
+    let pyramid_push_constant_size = UniformData::new()
+                                       .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                       .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                                       .size_non_aligned();
+    
+    let pyramid_set_layout = pyramid_layout();
+    let pyramid_pipeline = PipelineBuilder::new()
+                             .compute_shader(*pyramid_shader.get_shader())
+                             .descriptor_set_layout(pyramid_set_layout.layouts_clone())
+                             .push_constants(ShaderStage::Compute, pyramid_push_constant_size as u32)
+                             .build_compute(Arc::clone(&device));
+    pyramid_set_layout.destroy(Arc::clone(&device));
+    
+    let sampler = SamplerBuilder::new()
+                    .min_filter(Filter::Nearest)
+                    .mag_filter(Filter::Nearest)
+                    .address_mode(AddressMode::ClampToEdge)
+                    .mipmap_mode(MipmapMode::Nearest)
+                    .anisotropy(VkBool::False)
+                    .max_anisotropy(1.0)
+                    .build(Arc::clone(&device));
+    
+    let hiz = InstanceCulling::create_pyramid(Arc::clone(&instance), Arc::clone(&device), occlusion, extent);
+    
+    let culling = InstanceCulling {
+      occlusion,
+      num_frames,
+      current_buffer: 0,
+      
+      cull_shader,
+      cull_pipeline,
+      cull_layout,
+      view_buffer,
+      buffers: Vec::new(),
+      
+      pyramid_shader,
+      pyramid_pipeline,
+      source_sets,
+      level_sets,
+      sampler,
+      hiz,
+      hiz_ready: false,
+      frame_view: None,
+      hiz_view: None,
+    };
+    
+    culling.update_pyramid_sets(Arc::clone(&device), position_images);
+    
+    culling
+  }
+  
+  fn cull_descriptor_set(device: Arc<Device>, descriptor_set_pool: &DescriptorPool, num_sets: u32) -> DescriptorSet {
+    let mut builder = DescriptorSetBuilder::new()
+                        .compute_storage_buffer(0)
+                        .compute_storage_buffer(1)
+                        .compute_storage_buffer(2)
+                        .compute_storage_buffer(3);
+    
+    for level in 0..HIZ_LEVELS {
+      builder = builder.compute_combined_image_sampler(4 + level as u32);
+    }
+    
+    builder.build(Arc::clone(&device), descriptor_set_pool, num_sets)
+  }
+  
+  // each level is half the one before, the first half the screen, a single texel when occlusion culling is off
+  fn create_pyramid(instance: Arc<Instance>, device: Arc<Device>, occlusion: bool, extent: &vk::Extent2D) -> Vec<ImageAttachment> {
+    let (mut width, mut height) = if occlusion { (extent.width, extent.height) } else { (1, 1) };
+    
+    let mut hiz = Vec::with_capacity(HIZ_LEVELS);
+    for _ in 0..HIZ_LEVELS {
+      width = math::div_round_up(width, 2).max(1);
+      height = math::div_round_up(height, 2).max(1);
+      
+      hiz.push(ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::storage_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &vk::FORMAT_R32_SFLOAT, width, height));
+    }
+    
+    hiz
+  }
+  
+  fn update_pyramid_sets(&self, device: Arc<Device>, position_images: &[ImageAttachment]) {
+    for (position, descriptor_set) in position_images.iter().zip(&self.source_sets) {
+      UpdateDescriptorSets::new()
+        .add_sampled_image(0, position, ImageLayout::ShaderReadOnlyOptimal, &self.sampler)
+        .add_storage_image(1, &self.hiz[0], ImageLayout::General)
+        .finish_update(Arc::clone(&device), descriptor_set);
+    }
+    
+    for (level, descriptor_set) in self.level_sets.iter().enumerate() {
+      UpdateDescriptorSets::new()
+        .add_sampled_image(0, &self.hiz[level], ImageLayout::General, &self.sampler)
+        .add_storage_image(1, &self.hiz[level+1], ImageLayout::General)
+        .finish_update(Arc::clone(&device), descriptor_set);
+    }
+  }
+  
+  fn update_cull_set(&self, device: Arc<Device>, instance_buffer: &Buffer<f32>, buffer: &CulledBuffer) {
+    let mut update = UpdateDescriptorSets::new()
+                       .add_storage_buffer(0, instance_buffer)
+                       .add_storage_buffer(1, &buffer.culled)
+                       .add_storage_buffer(2, &buffer.commands)
+                       .add_storage_buffer(3, &self.view_buffer);
+    
+    for (level, hiz) in self.hiz.iter().enumerate() {
+      update = update.add_sampled_image(4 + level as u32, hiz, ImageLayout::General, &self.sampler);
+    }
+    
+    update.finish_update(Arc::clone(&device), &buffer.descriptor_set);
+  }
+  
+  // the device must be idle
+  pub fn update_instance_buffer(&self, device: Arc<Device>, reference: &str, instance_buffer: &Buffer<f32>) {
+    if let Some(buffer) = self.buffers.iter().find(|buffer| buffer.reference == reference) {
+      UpdateDescriptorSets::new()
+        .add_storage_buffer(0, instance_buffer)
+        .finish_update(Arc::clone(&device), &buffer.descriptor_set);
+    }
+  }
+  
+  // instance_buffer needs a set for every frame and to be usable as a storage buffer
+  pub fn add_buffer(&mut self, instance: Arc<Instance>, device: Arc<Device>, descriptor_set_pool: &DescriptorPool, reference: String, instance_buffer: &Buffer<f32>) {
+    if self.buffers.iter().any(|buffer| buffer.reference == reference) {
+      return;
+    }
+    
+    let num_frames = self.num_frames as u32;
+    let culled = Buffer::device_local_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::vertex_storage_buffer(), num_frames, (MAX_LODS*CULLED_CAPACITY*INSTANCE_SIZE) as u64);
+    let commands = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::indirect_storage_buffer(), num_frames, (MAX_DRAWS*COMMAND_SIZE) as u64);
+    let descriptor_set = InstanceCulling::cull_descriptor_set(Arc::clone(&device), descriptor_set_pool, num_frames);
+    
+    let buffer = CulledBuffer {
+      reference,
+      culled,
+      capacity: CULLED_CAPACITY,
+      commands,
+      descriptor_set,
+      submitted: vec![None; self.num_frames],
+      instances: 0,
+    };
+    
+    self.update_cull_set(Arc::clone(&device), instance_buffer, &buffer);
+    self.buffers.push(buffer);
+  }
+  
+  /**
+  ** Uploads the camera for this frame's culls. view is the camera the model
+  ** pass is drawn with and becomes the depth pyramid's view once it is built.
+  **/
+  pub fn begin_frame(&mut self, device: Arc<Device>, current_buffer: usize, frustum: &Frustum, view: ClusterView, fov: f32) {
+    self.current_buffer = current_buffer;
+    
+    for buffer in &mut self.buffers {
+      buffer.instances = 0;
+    }
+    
+    let mut data = UniformData::new();
+    for plane in frustum.planes().iter() {
+      data = data.add_vector4(*plane);
+    }
+    
+    data = data.add_vector4(view.position.extend(1.0 / (fov.to_radians()*0.5).tan()));
+    
+    data = match (&self.hiz_view, self.hiz_ready) {
+      (Some(hiz_view), true) => {
+        data.add_vector4(hiz_view.position.extend(NEAR))
+            .add_vector4(hiz_view.forward.extend(1.0))
+            .add_vector4(hiz_view.right)
+            .add_vector4(hiz_view.up)
+      },
+      _ => {
+        data.add_vector4(Vector4::new(0.0, 0.0, 0.0, NEAR))
+            .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+            .add_vector4(Vector4::new(0.0, 0.0, 0.0, 1.0))
+            .add_vector4(Vector4::new(0.0, 0.0, 0.0, 1.0))
+      },
+    };
+    
+    self.view_buffer.fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, data.build_non_aligned());
+    self.frame_view = Some(view);
+  }
+  
+  /**
+  ** Instances drawn and culled the last time this frame's buffers were used.
+  ** The frame's fence must have been waited on.
+  **/
+  fn read_counts(&mut self, device: Arc<Device>, buffer: usize) -> (u32, u32) {
+    let current_buffer = self.current_buffer;
+    let buffer = &mut self.buffers[buffer];
+    
+    let (submitted, lods, primitives) = match buffer.submitted[current_buffer].take() {
+      Some(submitted) => submitted,
+      None => return (0, 0),
+    };
+    
+    let commands = buffer.commands.read_buffer(Arc::clone(&device), current_buffer);
+    let drawn = (0..lods).map(|lod| commands[lod*primitives*COMMAND_SIZE + 1].to_bits()).sum::<u32>().min(submitted);
+    
+    (drawn, submitted - drawn)
+  }
+  
+  /**
+  ** Culls the instances in instance_buffer's current frame. index_counts has
+  ** an entry for each primitive of each lod, 0 for ones the gpu mustn't draw.
+  ** Returns the cmd and the instances drawn and culled by the last cull that
+  ** used this frame's buffers, so the counts lag by the frames in flight.
+  ** The culled instances grow to fit, waiting for the device to be idle.
+  **/
+  pub fn cull(&mut self, instance: Arc<Instance>, device: Arc<Device>, cmd: CommandBufferBuilder, reference: &str, num_instances: u32, sphere: (Vector3<f32>, f32), lod_radius: f32, lods: usize, index_counts: Vec<u32>) -> (CommandBufferBuilder, u32, u32) {
+    let mut cmd = cmd;
+    
+    let idx = match self.buffers.iter().position(|buffer| buffer.reference == reference) {
+      Some(idx) => idx,
+      None => return (cmd, 0, 0),
+    };
+    
+    let (drawn, culled) = self.read_counts(Arc::clone(&device), idx);
+    
+    if num_instances == 0 || index_counts.is_empty() {
+      return (cmd, drawn, culled);
+    }
+    
+    if index_counts.len() > MAX_DRAWS {
+      println!("Error: {} has {} lods and primitives, gpu culling supports {}", reference, index_counts.len(), MAX_DRAWS);
+      return (cmd, drawn, culled);
+    }
+    
+    if !self.hiz_ready {
+      for hiz in &self.hiz {
+        cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderRead, &ImageLayout::Undefined, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, hiz);
+      }
+      self.hiz_ready = true;
+    }
+    
+    let current_buffer = self.current_buffer;
+    let primitives = index_counts.len() / lods;
+    let mut commands = Vec::with_capacity(index_counts.len()*COMMAND_SIZE);
+    for index_count in &index_counts {
+      // index count, instance count, first index, vertex offset, first instance
+      commands.extend_from_slice(&[f32::from_bits(*index_count), 0.0, 0.0, 0.0, 0.0]);
+    }
+    
+    let buffer = &mut self.buffers[idx];
+    if num_instances as usize > buffer.capacity {
+      device.wait();
+      buffer.culled.destroy(Arc::clone(&device));
+      
+      buffer.capacity = (num_instances as usize).next_power_of_two();
+      buffer.culled = Buffer::device_local_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::vertex_storage_buffer(), self.num_frames as u32, (MAX_LODS*buffer.capacity*INSTANCE_SIZE) as u64);
+      
+      UpdateDescriptorSets::new()
+        .add_storage_buffer(1, &buffer.culled)
+        .finish_update(Arc::clone(&device), &buffer.descriptor_set);
+    }
+    
+    buffer.commands.fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, commands);
+    buffer.submitted[current_buffer] = Some((num_instances, lods, primitives));
+    buffer.instances = num_instances;
+    
+    let (centre, radius) = sphere;
+    let push_constant_data = UniformData::new()
+                               .add_vector4(centre.extend(radius))
+                               .add_vector4(Vector4::new(num_instances as f32, lods as f32, primitives as f32, lod_radius))
+                               .add_vector4(Vector4::new(buffer.capacity as f32, 0.0, 0.0, 0.0));
+    
+    let descriptor_set = *buffer.descriptor_set.set(current_buffer);
+    
+    cmd = cmd.push_constants(Arc::clone(&device), &self.cull_pipeline, ShaderStage::Compute, push_constant_data);
+    cmd = cmd.compute_dispatch(Arc::clone(&device), &self.cull_pipeline, vec!(descriptor_set), math::div_round_up(num_instances, CULL_GROUP_SIZE), 1, 1);
+    
+    cmd = cmd.memory_barrier(Arc::clone(&device), PipelineStage::ComputeShader, PipelineStage::DrawIndirect, &Access::ShaderWrite, &Access::IndirectCommandRead);
+    cmd = cmd.memory_barrier(Arc::clone(&device), PipelineStage::ComputeShader, PipelineStage::VertexInput, &Access::ShaderWrite, &Access::VertexAttributeRead);
+    cmd = cmd.memory_barrier(Arc::clone(&device), PipelineStage::ComputeShader, PipelineStage::Host, &Access::ShaderWrite, &Access::HostRead);
+    
+    (cmd, drawn, culled)
+  }
+  
+  /**
+  ** The culled instances, draw commands and instances each lod has room for
+  ** of a buffer for this frame, None if nothing was culled into it.
+  **/
+  pub fn draw_buffers(&self, reference: &str) -> Option<(&vk::Buffer, &vk::Buffer, usize)> {
+    self.buffers.iter()
+                .find(|buffer| buffer.reference == reference && buffer.instances > 0)
+                .map(|buffer| (buffer.culled.internal_object(self.current_buffer), buffer.commands.internal_object(self.current_buffer), buffer.capacity))
+  }
+  
+  // where the instances of a lod start in the culled buffer
+  pub fn lod_offset(lod: usize, capacity: usize) -> u64 {
+    (lod*capacity*INSTANCE_SIZE*mem::size_of::<f32>()) as u64
+  }
+  
+  // where the command of a lod and primitive starts in the command buffer
+  pub fn command_offset(lod: usize, primitive: usize, primitives: usize) -> u64 {
+    ((lod*primitives + primitive)*COMMAND_SIZE*mem::size_of::<u32>()) as u64
+  }
+  
+  /**
+  ** Builds the depth pyramid from this frame's positions for the next frame
+  ** to test against, each texel is the furthest depth under it.
+  **/
+  pub fn build_depth_pyramid(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, position_image: &ImageAttachment) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    if !self.occlusion {
+      return cmd;
+    }
+    
+    let view = match self.frame_view.take() {
+      Some(view) => view,
+      None => return cmd,
+    };
+    
+    cmd = cmd.image_barrier(Arc::clone(&device), &Access::ColourAttachmentWrite, &Access::ShaderRead, &ImageLayout::ShaderReadOnlyOptimal, &ImageLayout::ShaderReadOnlyOptimal, &ImageAspect::Colour, PipelineStage::ColorAttachmentOutput, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, position_image);
+    
+    for level in 0..HIZ_LEVELS {
+      let descriptor_set = if level == 0 { &self.source_sets[self.current_buffer] } else { &self.level_sets[level-1] };
+      let (width, height) = self.hiz[level].get_size();
+      
+      let push_constant_data = UniformData::new()
+                                 .add_vector4(view.position.extend(FAR))
+                                 .add_vector4(view.forward.extend(if level == 0 { 1.0 } else { 0.0 }));
+      
+      // the last frame's culls are done reading it
+      cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderRead, &Access::ShaderWrite, &ImageLayout::Undefined, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.hiz[level]);
+      cmd = cmd.push_constants(Arc::clone(&device), &self.pyramid_pipeline, ShaderStage::Compute, push_constant_data);
+      cmd = cmd.compute_dispatch(Arc::clone(&device), &self.pyramid_pipeline, vec!(*descriptor_set.set(0)), math::div_round_up(width, PYRAMID_GROUP_SIZE), math::div_round_up(height, PYRAMID_GROUP_SIZE), 1);
+      cmd = cmd.image_barrier(Arc::clone(&device), &Access::ShaderWrite, &Access::ShaderRead, &ImageLayout::General, &ImageLayout::General, &ImageAspect::Colour, PipelineStage::ComputeShader, PipelineStage::ComputeShader, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED, &self.hiz[level]);
+    }
+    
+    self.hiz_ready = true;
+    self.hiz_view = Some(view);
+    
+    cmd
+  }
+  
+  // the device must be idle, instance_buffers are in the order they were added
+  pub fn recreate(&mut self, instance: Arc<Instance>, device: Arc<Device>, position_images: &[ImageAttachment], instance_buffers: Vec<&Buffer<f32>>, extent: &vk::Extent2D) {
+    for hiz in &self.hiz {
+      hiz.destroy(Arc::clone(&device));
+    }
+    
+    self.hiz = InstanceCulling::create_pyramid(Arc::clone(&instance), Arc::clone(&device), self.occlusion, extent);
+    self.hiz_ready = false;
+    self.hiz_view = None;
+    
+    self.update_pyramid_sets(Arc::clone(&device), position_images);
+    for (instance_buffer, buffer) in instance_buffers.iter().zip(&self.buffers) {
+      self.update_cull_set(Arc::clone(&device), instance_buffer, buffer);
+    }
+  }
+  
+  pub fn destroy(&self, device: Arc<Device>) {
+    for buffer in &self.buffers {
+      buffer.culled.destroy(Arc::clone(&device));
+      buffer.commands.destroy(Arc::clone(&device));
+      buffer.descriptor_set.destroy(Arc::clone(&device));
+    }
+    
+    for hiz in &self.hiz {
+      hiz.destroy(Arc::clone(&device));
+    }
+    
+    for descriptor_set in self.source_sets.iter().chain(&self.level_sets) {
+      descriptor_set.destroy(Arc::clone(&device));
+    }
+    
+    self.view_buffer.destroy(Arc::clone(&device));
+    self.cull_layout.destroy(Arc::clone(&device));
+    self.sampler.destroy(Arc::clone(&device));
+    self.cull_pipeline.destroy(Arc::clone(&device));
+    self.pyramid_pipeline.destroy(Arc::clone(&device));
+    self.cull_shader.destroy(Arc::clone(&device));
+    self.pyramid_shader.destroy(Arc::clone(&device));
+  }
+}
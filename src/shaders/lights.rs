@@ -0,0 +1,344 @@
+use crate::gltf_interpreter::{ModelLight, LightType};
+
+use cgmath::{Vector3, Vector4, InnerSpace};
+
+// screen is split into CLUSTER_X*CLUSTER_Y tiles and CLUSTER_Z exponential depth slices
+pub const CLUSTER_X: usize = 16;
+pub const CLUSTER_Y: usize = 9;
+pub const CLUSTER_Z: usize = 24;
+pub const CLUSTER_COUNT: usize = CLUSTER_X*CLUSTER_Y*CLUSTER_Z;
+
+// room the light buffers start with, they grow when a frame needs more
+pub const INITIAL_LIGHTS: usize = 1024;
+pub const INITIAL_LIGHT_INDICES: usize = 64*1024;
+pub const LIGHT_SIZE: usize = 16; // floats per light in the light buffer
+
+pub const CLUSTER_NEAR: f32 = 0.1;
+pub const CLUSTER_FAR: f32 = 1080.0;
+
+// lights without a range stop contributing once intensity/distance^2 drops below this
+const LIGHT_CUTOFF: f32 = 0.01;
+// widest cone a spot light's depth map covers, wider cones are cut at its edge
+const MAX_SPOT_SHADOW_FOV: f32 = 2.8;
+
+// min and max cluster on each axis, inclusive
+type ClusterBounds = ([usize; 3], [usize; 3]);
+
+#[derive(Clone)]
+pub struct Light {
+  light_type: LightType,
+  pos: Vector3<f32>,
+  direction: Vector3<f32>,
+  colour: Vector3<f32>,
+  intensity: f32,
+  range: f32,
+  cast_shadows: bool,
+  shadow_tile: Option<usize>,
+}
+
+impl Light {
+  pub fn new() -> Light {
+    Light {
+      light_type: LightType::Point,
+      pos: Vector3::new(0.0, 0.0, 0.0),
+      direction: Vector3::new(0.0, -1.0, 0.0),
+      colour: Vector3::new(1.0, 1.0, 1.0),
+      intensity: 100.0,
+      range: Light::range_from_intensity(100.0),
+      // the set_light light is unshadowed so added lights get the whole shadow budget
+      cast_shadows: false,
+      shadow_tile: None,
+    }
+  }
+  
+  pub fn off() -> Light {
+    Light {
+      intensity: 0.0,
+      colour: Vector3::new(0.0, 0.0, 0.0),
+      range: 0.0,
+      .. Light::new()
+    }
+  }
+  
+  pub fn from_model_light(light: &ModelLight) -> Light {
+    let direction = if light.direction.magnitude2() > 0.0 {
+      light.direction.normalize()
+    } else {
+      Vector3::new(0.0, -1.0, 0.0)
+    };
+    
+    Light {
+      light_type: light.light_type.clone(),
+      pos: light.position,
+      direction,
+      colour: light.colour,
+      intensity: light.intensity,
+      range: light.range.unwrap_or_else(|| Light::range_from_intensity(light.intensity)),
+      cast_shadows: light.cast_shadows,
+      shadow_tile: None,
+    }
+  }
+  
+  pub fn update(&mut self, position: Vector3<f32>, colour: Vector3<f32>, intensity: f32) {
+    self.light_type = LightType::Point;
+    self.pos = position;
+    self.colour = colour;
+    self.intensity = intensity;
+    self.range = Light::range_from_intensity(intensity);
+  }
+  
+  fn range_from_intensity(intensity: f32) -> f32 {
+    (intensity.max(0.0) / LIGHT_CUTOFF).sqrt()
+  }
+  
+  pub fn position(&self) -> Vector3<f32> {
+    self.pos
+  }
+  
+  pub fn range(&self) -> f32 {
+    self.range
+  }
+  
+  /**
+  ** Point and spot lights that want shadows, the sun is shadowed through
+  ** the cascades instead.
+  **/
+  pub fn casts_shadows(&self) -> bool {
+    self.cast_shadows && self.is_visible() && !self.is_directional()
+  }
+  
+  /**
+  ** Direction and field of view of each depth map the light needs, the six
+  ** faces of a cube for a point light or the cone of a spot light.
+  **/
+  pub fn shadow_faces(&self) -> Vec<(Vector3<f32>, f32)> {
+    match self.light_type {
+      LightType::Spot(_, outer) => {
+        vec!((self.direction, (outer*2.0).clamp(0.1, MAX_SPOT_SHADOW_FOV)))
+      },
+      _ => {
+        let fov = std::f32::consts::FRAC_PI_2;
+        vec!((Vector3::new(1.0, 0.0, 0.0), fov), (Vector3::new(-1.0, 0.0, 0.0), fov),
+             (Vector3::new(0.0, 1.0, 0.0), fov), (Vector3::new(0.0, -1.0, 0.0), fov),
+             (Vector3::new(0.0, 0.0, 1.0), fov), (Vector3::new(0.0, 0.0, -1.0), fov))
+      },
+    }
+  }
+  
+  // first tile of the light's depth maps in the shadow atlas
+  pub fn set_shadow_tile(&mut self, tile: Option<usize>) {
+    self.shadow_tile = tile;
+  }
+  
+  fn is_directional(&self) -> bool {
+    self.light_type == LightType::Directional
+  }
+  
+  fn is_visible(&self) -> bool {
+    self.intensity > 0.0 && (self.is_directional() || self.range > 0.0)
+  }
+  
+  /**
+  ** Sphere that bounds everything the light can reach, spots use the sphere
+  ** around their cone rather than their full range.
+  **/
+  pub fn bounding_sphere(&self) -> (Vector3<f32>, f32) {
+    match self.light_type {
+      LightType::Spot(_, outer) => {
+        let angle = outer.clamp(0.0, std::f32::consts::FRAC_PI_2);
+        if angle <= std::f32::consts::FRAC_PI_4 {
+          let radius = self.range / (2.0*angle.cos()*angle.cos());
+          (self.pos + self.direction*radius, radius)
+        } else {
+          (self.pos + self.direction*(self.range*angle.cos()), self.range*angle.sin())
+        }
+      },
+      _ => (self.pos, self.range),
+    }
+  }
+  
+  /**
+  ** Layout matches the Light struct in VkModelDeffered.frag
+  **/
+  fn data(&self) -> [f32; LIGHT_SIZE] {
+    let (light_type, cos_inner, cos_outer) = match self.light_type {
+      LightType::Point => (0.0, -1.0, -1.0),
+      LightType::Spot(inner, outer) => (1.0, inner.cos(), outer.cos()),
+      LightType::Directional => (2.0, -1.0, -1.0),
+    };
+    let shadow_tile = self.shadow_tile.map(|tile| tile as f32).unwrap_or(-1.0);
+    
+    [self.pos.x,       self.pos.y,       self.pos.z,       self.range,
+     self.colour.x,    self.colour.y,    self.colour.z,    self.intensity,
+     self.direction.x, self.direction.y, self.direction.z, light_type,
+     cos_inner,        cos_outer,        shadow_tile,      0.0]
+  }
+}
+
+/**
+** Direction of the sun, the first visible directional light. It is also the
+** first light in the light buffer so the deffered pass knows which one is shadowed.
+**/
+pub fn sun_direction(lights: &[Light]) -> Option<Vector3<f32>> {
+  lights.iter().find(|light| light.is_visible() && light.is_directional()).map(|light| light.direction)
+}
+
+/**
+** View of the camera the clusters are built for, right.w and up.w are the
+** tangents of the half fov scaled to the aspect ratio.
+**/
+#[derive(Clone, Copy)]
+pub struct ClusterView {
+  pub position: Vector3<f32>,
+  pub forward: Vector3<f32>,
+  pub right: Vector4<f32>,
+  pub up: Vector4<f32>,
+}
+
+impl ClusterView {
+  pub fn new(position: Vector3<f32>, center: Vector3<f32>, up: Vector3<f32>, fov: f32, aspect: f32) -> ClusterView {
+    let forward = (center-position).normalize();
+    let right = forward.cross(up).normalize();
+    let up = right.cross(forward);
+    let tan_half_fov = (fov.to_radians()*0.5).tan();
+    
+    ClusterView {
+      position,
+      forward,
+      right: right.extend(tan_half_fov*aspect),
+      up: up.extend(tan_half_fov),
+    }
+  }
+  
+  /**
+  ** Distance from the camera to the nearest point of a sphere,
+  ** None if the sphere can't be seen.
+  **/
+  pub fn distance_to(&self, centre: Vector3<f32>, radius: f32) -> Option<f32> {
+    self.cluster_bounds(centre, radius).map(|_| ((centre - self.position).magnitude() - radius).max(0.0))
+  }
+  
+  fn depth_slice(depth: f32) -> usize {
+    let depth = depth.max(CLUSTER_NEAR);
+    let slice = (depth/CLUSTER_NEAR).ln() / (CLUSTER_FAR/CLUSTER_NEAR).ln() * CLUSTER_Z as f32;
+    (slice.max(0.0) as usize).min(CLUSTER_Z-1)
+  }
+  
+  fn tile(ndc: f32, tiles: usize) -> usize {
+    let tile = (ndc*0.5 + 0.5) * tiles as f32;
+    (tile.max(0.0) as usize).min(tiles-1)
+  }
+  
+  /**
+  ** The range of clusters a sphere touches,
+  ** None if it is entirely behind the camera, past the far plane or off screen.
+  **/
+  fn cluster_bounds(&self, centre: Vector3<f32>, radius: f32) -> Option<ClusterBounds> {
+    let relative = centre - self.position;
+    let x = relative.dot(self.right.truncate());
+    let y = relative.dot(self.up.truncate());
+    let z = relative.dot(self.forward);
+    
+    if z + radius < CLUSTER_NEAR || z - radius > CLUSTER_FAR {
+      return None;
+    }
+    
+    let near = (z - radius).max(CLUSTER_NEAR);
+    let far = (z + radius).min(CLUSTER_FAR);
+    
+    // bounds of the sphere's box over the nearest and furthest depth it covers
+    let ndc = |min: f32, max: f32, scale: f32| {
+      let low = min / if min < 0.0 { near } else { far };
+      let high = max / if max > 0.0 { near } else { far };
+      (low/scale, high/scale)
+    };
+    
+    let (x_min, x_max) = ndc(x - radius, x + radius, self.right.w);
+    let (y_min, y_max) = ndc(y - radius, y + radius, self.up.w);
+    
+    if x_min > 1.0 || x_max < -1.0 || y_min > 1.0 || y_max < -1.0 {
+      return None;
+    }
+    
+    Some(([ClusterView::tile(x_min, CLUSTER_X), ClusterView::tile(y_min, CLUSTER_Y), ClusterView::depth_slice(near)],
+          [ClusterView::tile(x_max, CLUSTER_X), ClusterView::tile(y_max, CLUSTER_Y), ClusterView::depth_slice(far)]))
+  }
+}
+
+/**
+** Per frame light list binned into view space clusters, directional lights
+** go first in the light buffer and touch every cluster so they aren't binned.
+**/
+pub struct LightClusters {
+  pub lights: Vec<f32>,
+  pub clusters: Vec<f32>,
+  pub num_lights: u32,
+  pub num_directional: u32,
+}
+
+impl LightClusters {
+  pub fn new(lights: &[Light], view: &ClusterView) -> LightClusters {
+    let mut sorted: Vec<&Light> = lights.iter().filter(|light| light.is_visible() && light.is_directional()).collect();
+    let num_directional = sorted.len();
+    sorted.extend(lights.iter().filter(|light| light.is_visible() && !light.is_directional()));
+    
+    let mut light_data = Vec::with_capacity(sorted.len()*LIGHT_SIZE);
+    for light in &sorted {
+      light_data.extend_from_slice(&light.data());
+    }
+    
+    let bounds: Vec<(u32, ClusterBounds)> = sorted.iter().enumerate().skip(num_directional).filter_map(|(i, light)| {
+      let (centre, radius) = light.bounding_sphere();
+      view.cluster_bounds(centre, radius).map(|bounds| (i as u32, bounds))
+    }).collect();
+    
+    let cluster_index = |x: usize, y: usize, z: usize| (z*CLUSTER_Y + y)*CLUSTER_X + x;
+    
+    let mut counts = vec![0u32; CLUSTER_COUNT];
+    for (_, (min, max)) in &bounds {
+      for z in min[2]..=max[2] {
+        for y in min[1]..=max[1] {
+          for x in min[0]..=max[0] {
+            counts[cluster_index(x, y, z)] += 1;
+          }
+        }
+      }
+    }
+    
+    // offset and count per cluster
+    let mut offsets = vec![0u32; CLUSTER_COUNT];
+    let mut total = 0;
+    for i in 0..CLUSTER_COUNT {
+      offsets[i] = total;
+      total += counts[i];
+    }
+    
+    let mut indices = vec![0u32; total as usize];
+    let mut filled = vec![0u32; CLUSTER_COUNT];
+    for (light, (min, max)) in &bounds {
+      for z in min[2]..=max[2] {
+        for y in min[1]..=max[1] {
+          for x in min[0]..=max[0] {
+            let cluster = cluster_index(x, y, z);
+            indices[(offsets[cluster] + filled[cluster]) as usize] = *light;
+            filled[cluster] += 1;
+          }
+        }
+      }
+    }
+    
+    let mut clusters = Vec::with_capacity(CLUSTER_COUNT*2 + indices.len());
+    for i in 0..CLUSTER_COUNT {
+      clusters.push(f32::from_bits(offsets[i]));
+      clusters.push(f32::from_bits(counts[i]));
+    }
+    clusters.extend(indices.iter().map(|index| f32::from_bits(*index)));
+    
+    LightClusters {
+      lights: light_data,
+      clusters,
+      num_lights: sorted.len() as u32,
+      num_directional: num_directional as u32,
+    }
+  }
+}
@@ -0,0 +1,129 @@
+use cgmath::{Vector2, Vector3, Vector4};
+
+// where each value sits in the material uniform of VkModel.vert
+const USE_TEXTURES: usize = 0;
+const EMISSIVE_ALPHA: usize = 1;
+const BASE_COLOUR_FACTOR: usize = 2;
+const MRO_FACTORS: usize = 3;
+const EMISSIVE_FACTOR: usize = 4;
+
+/**
+** A texture of a primitive's material, in the order of its samplers.
+**/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextureSlot {
+  BaseColour,
+  MetallicRoughness,
+  Normal,
+  Occlusion,
+  Emissive,
+}
+
+impl TextureSlot {
+  pub(crate) fn index(self) -> usize {
+    match self {
+      TextureSlot::BaseColour => 0,
+      TextureSlot::MetallicRoughness => 1,
+      TextureSlot::Normal => 2,
+      TextureSlot::Occlusion => 3,
+      TextureSlot::Emissive => 4,
+    }
+  }
+  
+  // turns the texture on in the material uniform of a primitive
+  pub(crate) fn enable(self, material: &mut [Vector4<f32>]) {
+    match self {
+      TextureSlot::Emissive => material[EMISSIVE_ALPHA].x = 1.0,
+      slot => material[USE_TEXTURES][slot.index()] = 1.0,
+    }
+  }
+}
+
+/**
+** Material values to draw a model with in place of its own, anything not set
+** is left as the model has it. Textures are references to loaded textures
+** and only apply to a named override, instances take the values alone.
+**/
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MaterialOverride {
+  base_colour_factor: Option<Vector4<f32>>,
+  metallic: Option<f32>,
+  roughness: Option<f32>,
+  emissive: Option<(Vector3<f32>, f32)>, // colour, strength
+  textures: Vec<(TextureSlot, String)>,
+}
+
+impl MaterialOverride {
+  pub fn new() -> MaterialOverride {
+    MaterialOverride {
+      base_colour_factor: None,
+      metallic: None,
+      roughness: None,
+      emissive: None,
+      textures: Vec::new(),
+    }
+  }
+  
+  pub fn base_colour_factor(mut self, colour: Vector4<f32>) -> MaterialOverride {
+    self.base_colour_factor = Some(colour);
+    self
+  }
+  
+  pub fn metallic(mut self, metallic: f32) -> MaterialOverride {
+    self.metallic = Some(metallic);
+    self
+  }
+  
+  pub fn roughness(mut self, roughness: f32) -> MaterialOverride {
+    self.roughness = Some(roughness);
+    self
+  }
+  
+  pub fn emissive(mut self, colour: Vector3<f32>, strength: f32) -> MaterialOverride {
+    self.emissive = Some((colour, strength));
+    self
+  }
+  
+  pub fn texture(mut self, slot: TextureSlot, texture_reference: String) -> MaterialOverride {
+    self.textures.retain(|(s, _)| *s != slot);
+    self.textures.push((slot, texture_reference));
+    self
+  }
+  
+  pub(crate) fn textures(&self) -> &Vec<(TextureSlot, String)> {
+    &self.textures
+  }
+  
+  // writes the values over the material uniform of a primitive
+  pub(crate) fn apply(&self, material: &mut [Vector4<f32>]) {
+    if let Some(colour) = self.base_colour_factor {
+      material[BASE_COLOUR_FACTOR] = colour;
+    }
+    if let Some(metallic) = self.metallic {
+      material[MRO_FACTORS].x = metallic;
+    }
+    if let Some(roughness) = self.roughness {
+      material[MRO_FACTORS].y = roughness;
+    }
+    if let Some((colour, strength)) = self.emissive {
+      material[EMISSIVE_FACTOR] = colour.extend(strength);
+    }
+  }
+  
+  /**
+  ** The values as the material of an instance, base colour factor, emissive
+  ** colour and strength, then metallic and roughness which share a vec4
+  ** with the hologram. Anything not set is -1 so VkModelInstanced.vert
+  ** keeps the uniform.
+  **/
+  pub(crate) fn instance_values(&self) -> (Vector4<f32>, Vector4<f32>, Vector2<f32>) {
+    let colour = self.base_colour_factor.unwrap_or_else(|| Vector4::new(-1.0, -1.0, -1.0, -1.0));
+    let emissive = match self.emissive {
+      Some((colour, strength)) => colour.extend(strength),
+      None => Vector4::new(-1.0, -1.0, -1.0, -1.0),
+    };
+    let factors = Vector2::new(self.metallic.unwrap_or(-1.0), self.roughness.unwrap_or(-1.0));
+    
+    (colour, emissive, factors)
+  }
+}
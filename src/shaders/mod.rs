@@ -2,8 +2,27 @@ pub use self::texture_shader::TextureShader;
 pub use self::model_shader::ModelShader;
 pub use self::final_shader::FinalShader;
 pub use self::final_shader::FinalVertex;
+pub use self::final_shader::Tonemapper;
+pub use self::shadows::ShadowSettings;
+pub use self::ambient_occlusion::AmbientOcclusionSettings;
+pub use self::anti_aliasing::AntiAliasing;
+pub use self::model_shader::DebugView;
+pub use self::debug_lines::DebugShape;
+pub(crate) use self::debug_lines::box_edges;
+pub use self::material_override::{MaterialOverride, TextureSlot};
 
 #[macro_use]
 mod texture_shader;
 mod model_shader;
+mod lights;
+mod shadows;
+mod environment;
+mod skybox;
+mod fog;
+mod debug_lines;
+mod material_override;
+mod instance_culling;
+mod ambient_occlusion;
+mod bloom;
+mod anti_aliasing;
 mod final_shader;
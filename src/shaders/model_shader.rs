@@ -5,54 +5,54 @@ use gltf::material::AlphaMode;
 use crate::math;
 use crate::camera::PerspectiveCamera;
 use crate::camera::PerspectiveCameraDirection;
-use crate::gltf_interpreter::ModelDetails;
+use crate::camera::Frustum;
+use crate::graphics::FrameStats;
+use crate::gltf_interpreter::{ModelDetails, ModelLight};
 
-use crate::vulkan::vkenums::{ImageType, ImageUsage, ImageViewType, SampleCount, ImageTiling, AttachmentLoadOp, AttachmentStoreOp, ImageLayout, ImageAspect, ShaderStage, VertexInputRate, AddressMode, MipmapMode, VkBool};
+use crate::vulkan::vkenums::{ImageType, ImageUsage, ImageViewType, SampleCount, ImageTiling, AttachmentLoadOp, AttachmentStoreOp, ImageLayout, ImageAspect, ShaderStage, VertexInputRate, AddressMode, MipmapMode, VkBool, BlendFactor, CompareOp};
 
 use crate::vulkan::{Instance, Device, RenderPass, Shader, Pipeline, PipelineBuilder, DescriptorSet, UpdateDescriptorSets, DescriptorSetBuilder, ImageAttachment, AttachmentInfo, SubpassInfo, RenderPassBuilder, Sampler, SamplerBuilder};
-use crate::vulkan::buffer::{Buffer, BufferUsage, UniformBufferBuilder, UniformData, Framebuffer, CommandBufferBuilder};
+use crate::vulkan::buffer::{Buffer, InstanceBuffer, BufferUsage, UniformBufferBuilder, UniformData, Framebuffer, CommandBufferBuilder};
 use crate::vulkan::pool::{DescriptorPool, CommandPool};
 use crate::shaders::FinalShader;
 use crate::shaders::FinalVertex;
+use crate::shaders::lights::{Light, LightClusters, ClusterView, LIGHT_SIZE, INITIAL_LIGHTS, CLUSTER_COUNT, INITIAL_LIGHT_INDICES, CLUSTER_NEAR, CLUSTER_FAR};
+use crate::shaders::shadows::{ShadowMap, ShadowSettings};
+use crate::shaders::environment::EnvironmentMap;
+use crate::shaders::skybox::Skybox;
+use crate::shaders::instance_culling::{InstanceCulling, MAX_LODS};
+use crate::shaders::ambient_occlusion::{AmbientOcclusion, AmbientOcclusionSettings};
+use crate::shaders::fog::Fog;
+use crate::shaders::debug_lines::{DebugLines, DebugShape};
+use crate::shaders::bloom::Bloom;
+use crate::shaders::anti_aliasing::{PostAntiAliasing, AntiAliasing};
+use crate::shaders::material_override::{MaterialOverride, TextureSlot};
 
-use cgmath::{Vector2, Vector3, Vector4};
+use cgmath::{Vector2, Vector3, Vector4, Matrix4, Matrix, Point3, EuclideanSpace, InnerSpace, Deg};
 
 use std::mem;
 use std::sync::Arc;
+use std::cmp::Ordering;
 
-const MAX_INSTANCES: usize = 2048;
-const _INSTANCED_SIZE: usize = 16;
-
-#[derive(Clone)]
-pub struct Light {
-  pos: Vector3<f32>,
-  colour: Vector3<f32>,
-  intensity: f32,
-}
-
-impl Light {
-  pub fn new() -> Light {
-    Light {
-      pos: Vector3::new(0.0, 0.0, 0.0),
-      colour: Vector3::new(1.0, 1.0, 1.0),
-      intensity: 100.0,
-    }
-  }
-  
-  pub fn _off() -> Light {
-    Light {
-      pos: Vector3::new(0.0, 0.0, 0.0),
-      colour: Vector3::new(0.0, 0.0, 0.0),
-      intensity: 0.0,
-    }
-  }
-  
-  pub fn update(&mut self, position: Vector3<f32>, colour: Vector3<f32>, intensity: f32) {
-    self.pos = position;
-    self.colour = colour;
-    self.intensity = intensity;
-  }
-}
+// vertical field of view in degrees of the camera projection, lods and culling are picked with it too
+const FOV: f32 = 60.0;
+// instances each lod of an instanced buffer has room for to start with, they grow as needed
+const INSTANCE_CAPACITY: usize = 256;
+// the buffer after the lods, instances the camera culled that can still cast a shadow into view
+const SHADOW_CASTERS: usize = MAX_LODS;
+const TEXTURES_PER_PRIMITIVE: [&str; 5] = ["base_colour", "metallic_roughness", "normal", "occlusion", "emissive"];
+// floats in a ModelInstanceData, the rows of the model matrix, colour, hologram, material and last frame's rows
+pub const INSTANCE_SIZE: usize = 40;
+// fraction of the screen height a model covers before dropping to the next lod
+pub const LOD_SCREEN_SIZES: [f32; 4] = [0.5, 0.25, 0.125, 0.0625];
+// the camera, light counts and debug view the forward subpass reads, 6 vec4s
+const FORWARD_CAMERA_SIZE: usize = 24;
+// this frame's and last frame's view projection the gbuffer vertex shaders read, 2 mat4s
+const CAMERA_SIZE: usize = 32;
+// the lit colour and the albedo it starts from are left unclamped for bloom and tonemapping
+const HDR_FORMAT: vk::Format = vk::FORMAT_R16G16B16A16_SFLOAT;
+// how far each pixel moved on screen since last frame, taa reprojects its history with it
+const VELOCITY_FORMAT: vk::Format = vk::FORMAT_R16G16_SFLOAT;
 
 #[derive(Clone)]
 pub struct ModelVertex {
@@ -61,24 +61,32 @@ pub struct ModelVertex {
   uvs: Vector2<f32>,
   colour: Vector4<f32>,
   tangent: Vector4<f32>,
+  uvs_1: Vector2<f32>,
 }
 
 #[derive(Clone)]
 pub struct ModelInstanceData {
-  model: Vector4<f32>,
-  rotation: Vector4<f32>,
+  model_x: Vector4<f32>, // the rows of the model matrix
+  model_y: Vector4<f32>,
+  model_z: Vector4<f32>,
   colour: Vector4<f32>,
-  hologram: Vector4<f32>,
+  hologram: Vector4<f32>, // hologram, scanline, then metallic and roughness of MaterialOverride::instance_values
+  material_colour: Vector4<f32>,
+  material_emissive: Vector4<f32>,
+  previous_x: Vector4<f32>, // last frame's rows of the model matrix
+  previous_y: Vector4<f32>,
+  previous_z: Vector4<f32>,
 }
 
 impl ModelVertex {
-  pub fn from(pos: Vector3<f32>, normal: Vector3<f32>, uvs: Vector2<f32>, colour: Vector4<f32>, tangent: Vector4<f32>) -> ModelVertex {
+  pub fn from(pos: Vector3<f32>, normal: Vector3<f32>, uvs: Vector2<f32>, colour: Vector4<f32>, tangent: Vector4<f32>, uvs_1: Vector2<f32>) -> ModelVertex {
     ModelVertex {
       pos,
       normal,
       uvs,
       colour,
       tangent,
+      uvs_1,
     }
   }
   
@@ -138,6 +146,16 @@ impl ModelVertex {
       }
     );
     
+    // 5 to 8 and 10 are taken by instance data
+    vertex_input_attribute_descriptions.push(
+      vk::VertexInputAttributeDescription {
+        location: 9,
+        binding: 0,
+        format: vk::FORMAT_R32G32_SFLOAT,
+        offset: offset_of!(ModelVertex, uvs_1) as u32,
+      }
+    );
+    
     vertex_input_attribute_descriptions
   }
 }
@@ -159,7 +177,7 @@ impl ModelInstanceData {
         location: 5,
         binding: 1,
         format: vk::FORMAT_R32G32B32A32_SFLOAT,
-        offset: offset_of!(ModelInstanceData, model) as u32,
+        offset: offset_of!(ModelInstanceData, model_x) as u32,
       }
     );
     
@@ -168,7 +186,7 @@ impl ModelInstanceData {
         location: 6,
         binding: 1,
         format: vk::FORMAT_R32G32B32A32_SFLOAT,
-        offset: offset_of!(ModelInstanceData, rotation) as u32,
+        offset: offset_of!(ModelInstanceData, model_y) as u32,
       }
     );
     
@@ -177,7 +195,7 @@ impl ModelInstanceData {
         location: 7,
         binding: 1,
         format: vk::FORMAT_R32G32B32A32_SFLOAT,
-        offset: offset_of!(ModelInstanceData, colour) as u32,
+        offset: offset_of!(ModelInstanceData, model_z) as u32,
       }
     );
     
@@ -186,14 +204,195 @@ impl ModelInstanceData {
         location: 8,
         binding: 1,
         format: vk::FORMAT_R32G32B32A32_SFLOAT,
+        offset: offset_of!(ModelInstanceData, colour) as u32,
+      }
+    );
+    
+    vertex_input_attribute_descriptions.push(
+      vk::VertexInputAttributeDescription {
+        location: 10,
+        binding: 1,
+        format: vk::FORMAT_R32G32B32A32_SFLOAT,
         offset: offset_of!(ModelInstanceData, hologram) as u32,
       }
     );
     
+    vertex_input_attribute_descriptions.push(
+      vk::VertexInputAttributeDescription {
+        location: 11,
+        binding: 1,
+        format: vk::FORMAT_R32G32B32A32_SFLOAT,
+        offset: offset_of!(ModelInstanceData, material_colour) as u32,
+      }
+    );
+    
+    vertex_input_attribute_descriptions.push(
+      vk::VertexInputAttributeDescription {
+        location: 12,
+        binding: 1,
+        format: vk::FORMAT_R32G32B32A32_SFLOAT,
+        offset: offset_of!(ModelInstanceData, material_emissive) as u32,
+      }
+    );
+    
+    vertex_input_attribute_descriptions.push(
+      vk::VertexInputAttributeDescription {
+        location: 13,
+        binding: 1,
+        format: vk::FORMAT_R32G32B32A32_SFLOAT,
+        offset: offset_of!(ModelInstanceData, previous_x) as u32,
+      }
+    );
+    
+    vertex_input_attribute_descriptions.push(
+      vk::VertexInputAttributeDescription {
+        location: 14,
+        binding: 1,
+        format: vk::FORMAT_R32G32B32A32_SFLOAT,
+        offset: offset_of!(ModelInstanceData, previous_y) as u32,
+      }
+    );
+    
+    vertex_input_attribute_descriptions.push(
+      vk::VertexInputAttributeDescription {
+        location: 15,
+        binding: 1,
+        format: vk::FORMAT_R32G32B32A32_SFLOAT,
+        offset: offset_of!(ModelInstanceData, previous_z) as u32,
+      }
+    );
+    
     vertex_input_attribute_descriptions
   }
 }
 
+/**
+** What the deffered pass shows. Lit is the normal shaded scene, the others
+** show a single gbuffer attachment or a heatmap as they are, without
+** exposure or tonemapping. Wireframe is lit and needs fillModeNonSolid.
+**/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+  Lit,
+  Albedo,
+  Mro,
+  Emissive,
+  Normal,
+  Position,
+  Depth,
+  Wireframe,
+  Overdraw, // gbuffer fragments per pixel
+  LightCount, // lights in each pixel's cluster
+}
+
+impl DebugView {
+  // must match the debug constants in VkModelDeffered.frag
+  fn to_index(self) -> f32 {
+    match self {
+      DebugView::Lit => 0.0,
+      DebugView::Albedo => 1.0,
+      DebugView::Mro => 2.0,
+      DebugView::Emissive => 3.0,
+      DebugView::Normal => 4.0,
+      DebugView::Position => 5.0,
+      DebugView::Depth => 6.0,
+      DebugView::Wireframe => 7.0,
+      DebugView::Overdraw => 8.0,
+      DebugView::LightCount => 9.0,
+    }
+  }
+}
+
+// a blended primitive waiting for the forward subpass
+struct TransparentDraw {
+  distance: f32,
+  model: usize,
+  primitive: usize,
+  lod: usize,
+  instance: TransparentInstance,
+  material: Option<String>, // material override reference
+}
+
+enum TransparentInstance {
+  Single(Matrix4<f32>, Vector4<f32>), // the model matrix and hologram of draw_model
+  Instanced(usize, usize, usize, u64), // instanced buffer, its lod, the frame of it and the offset of the instance in it
+}
+
+/**
+** The model matrices each reference was drawn with last frame, in the order
+** they were drawn, so a draw finds where it was by its order this frame. A
+** draw with nothing to match was where it is.
+**/
+struct MotionHistory {
+  previous: Vec<(String, Vec<Matrix4<f32>>)>,
+  current: Vec<(String, Vec<Matrix4<f32>>)>,
+}
+
+impl MotionHistory {
+  fn new() -> MotionHistory {
+    MotionHistory {
+      previous: Vec::new(),
+      current: Vec::new(),
+    }
+  }
+  
+  fn next_frame(&mut self) {
+    self.previous = mem::take(&mut self.current);
+  }
+  
+  // records a draw of the reference this frame, returns its model matrix last frame
+  fn push(&mut self, reference: &str, model: Matrix4<f32>) -> Matrix4<f32> {
+    let index = match self.current.iter().position(|(r, _)| r == reference) {
+      Some(index) => index,
+      None => {
+        self.current.push((reference.to_string(), Vec::new()));
+        self.current.len()-1
+      },
+    };
+    
+    let draw = self.current[index].1.len();
+    self.current[index].1.push(model);
+    
+    match self.previous.iter().find(|(r, _)| r == reference) {
+      Some((_, models)) => *models.get(draw).unwrap_or(&model),
+      None => model,
+    }
+  }
+}
+
+// a material override added by name, with a descriptor set for each primitive of its model
+struct ModelMaterial {
+  reference: String,
+  model_reference: String,
+  material: MaterialOverride,
+  texture_views: Vec<(TextureSlot, vk::ImageView)>,
+  frame_materials: Vec<Option<MaterialOverride>>, // what each frame's uniform buffers hold
+  descriptor_sets: Vec<DescriptorSet>,
+  uniform_buffers: Vec<Buffer<f32>>,
+}
+
+impl ModelMaterial {
+  fn free(&self, device: Arc<Device>, descriptor_set_pool: &DescriptorPool) {
+    for buffer in &self.uniform_buffers {
+      buffer.destroy(Arc::clone(&device));
+    }
+    
+    for descriptor in &self.descriptor_sets {
+      descriptor.free(Arc::clone(&device), descriptor_set_pool);
+    }
+  }
+  
+  fn destroy(&self, device: Arc<Device>) {
+    for buffer in &self.uniform_buffers {
+      buffer.destroy(Arc::clone(&device));
+    }
+    
+    for descriptor in &self.descriptor_sets {
+      descriptor.destroy(Arc::clone(&device));
+    }
+  }
+}
+
 struct Model {
   vertex_buffers: Vec<Buffer<ModelVertex>>,
   index_buffers: Vec<Buffer<u32>>,
@@ -201,14 +400,27 @@ struct Model {
   index_count: Vec<u32>,
   
   descriptor_sets: Vec<DescriptorSet>,
-  samplers: Vec<Sampler>,
+  samplers: Vec<Sampler>, // the model's own, destroyed with it
   reference: String,
   
+  // what each primitive's descriptor set was built from, for material overrides
+  materials: Vec<Vec<Vector4<f32>>>,
+  textures: Vec<Vec<ImageAttachment>>,
+  primitive_samplers: Vec<Sampler>,
+  
   _base_colour_factors: Vec<Vector4<f32>>,
-  _alpha_cutoffs: Vec<(f32, f32)>,
+  alpha_cutoffs: Vec<(f32, f32)>,
   double_sided: Vec<bool>,
   
   uniform_buffers: Vec<Buffer<f32>>,
+  
+  lod_index_buffers: Vec<Vec<(Buffer<u32>, u32)>>,
+  lod_levels: Vec<Option<u32>>,
+  num_lods: usize,
+  radius: f32,
+  
+  bounding_spheres: Vec<(Vector3<f32>, f32)>, // centre and radius of each primitive in model space
+  bounding_sphere: (Vector3<f32>, f32), // around every primitive
 }
 
 impl Model {
@@ -221,6 +433,9 @@ impl Model {
     let mut index_count = Vec::with_capacity(num_models);
     let mut descriptor_sets = Vec::with_capacity(num_models);
     let mut samplers: Vec<Sampler> = Vec::with_capacity(num_models);
+    let mut materials = Vec::with_capacity(num_models);
+    let mut primitive_textures = Vec::with_capacity(num_models);
+    let mut primitive_samplers = Vec::with_capacity(num_models);
     
     let mut base_colour_factors = Vec::with_capacity(num_models);
     let mut alpha_cutoffs = Vec::with_capacity(num_models);
@@ -228,16 +443,25 @@ impl Model {
     
     let mut uniform_buffers = Vec::with_capacity(num_models);
     
+    let mut lod_index_buffers = Vec::with_capacity(num_models);
+    let mut lod_levels = Vec::with_capacity(num_models);
+    
+    let mut bounding_spheres = Vec::with_capacity(num_models);
+    let mut model_min = Vector3::new(0.0, 0.0, 0.0);
+    let mut model_max = Vector3::new(0.0, 0.0, 0.0);
+    
     for i in 0..num_models {
       let position = model.vertex(i); //vec3
       let normal = model.normal(i); //vec3
       let uv = model.texcoords(i); // vec2
+      let uv_1 = model.texcoords_1(i); // vec2
       let colour = model.colours(i); // vec4 
       let tangent = model.tangent(i);//vec4
       
       let mut vertex = Vec::with_capacity(position.len());
       for j in 0..position.len() {
         let mut uvs = [0.0, 0.0];
+        let mut uvs_1 = [0.0, 0.0];
         let mut model_tangent = [0.0, 0.0, 0.0, 0.0];
         let mut model_colour = [1.0, 1.0, 1.0, 1.0];
         if j < colour.len() {
@@ -246,6 +470,9 @@ impl Model {
         if j < uv.len() {
           uvs = uv[j];
         }
+        if j < uv_1.len() {
+          uvs_1 = uv_1[j];
+        }
         if j < tangent.len() {
           model_tangent = tangent[j];
         }
@@ -256,7 +483,8 @@ impl Model {
                                       math::array3_to_vec3(normal[j]), 
                                       math::array2_to_vec2(uvs), 
                                       math::array4_to_vec4(model_colour), 
-                                      math::array4_to_vec4(model_tangent)));
+                                      math::array4_to_vec4(model_tangent),
+                                      math::array2_to_vec2(uvs_1)));
       }
       
       let index = model.index(i);  // Vec<u32>
@@ -265,6 +493,24 @@ impl Model {
       let v_buffer = Model::create_vertex_buffer(Arc::clone(&instance), Arc::clone(&device), vertex, command_pool, graphics_queue);
       let (i_buffer, indice) = Model::create_index_buffer(Arc::clone(&instance), Arc::clone(&device), index, command_pool, graphics_queue);
       
+      let mut lod_buffers = Vec::new();
+      for lod_index in model.lods(i) {
+        lod_buffers.push(Model::create_index_buffer(Arc::clone(&instance), Arc::clone(&device), lod_index, command_pool, graphics_queue));
+      }
+      lod_index_buffers.push(lod_buffers);
+      lod_levels.push(model.lod_level(i));
+      
+      // x is flipped along with the vertices
+      let (min, max) = model.bounding_box(i);
+      let (min, max) = (Vector3::new(-max.x, min.y, min.z), Vector3::new(-min.x, max.y, max.z));
+      bounding_spheres.push(((min+max)*0.5, (max-min).magnitude()*0.5));
+      if i == 0 {
+        model_min = min;
+        model_max = max;
+      }
+      model_min = Vector3::new(model_min.x.min(min.x), model_min.y.min(min.y), model_min.z.min(min.z));
+      model_max = Vector3::new(model_max.x.max(max.x), model_max.y.max(max.y), model_max.z.max(max.z));
+      
       let mut sampler = sampler;
       
       let temp_sampler;
@@ -285,8 +531,12 @@ impl Model {
       
       let base_colour_factor = model.base_colour(i);
       let alpha_cutoff = model.alphacutoff(i);
+      let transmission = model.transmission_factor(i);
       let alpha_mask = {
         match  model.alphamode(i) {
+          AlphaMode::Opaque if transmission > 0.0 => { // transmission is approximated as blending
+            0.0
+          },
           AlphaMode::Opaque => {
             1.0
           },
@@ -321,83 +571,47 @@ impl Model {
       let normal_scale = model.normal_texture_scale(i);
       let occlusion_strength = model.occlusion_texture_strength(i);
       let emissive_factor = math::array3_to_vec3(model.emissive_factor(i)); // vec3
+      let emissive_strength = model.emissive_strength(i);
+      let unlit = if model.unlit(i) { 1.0 } else { -1.0 };
       
-      let mut uniform_buffer = UniformBufferBuilder::new()
-         .set_binding(0)
-         .add_vector4()
-         .add_vector4()
-         .add_vector4()
-         .add_vector4()
-         .add_vector4()
-         .build(Arc::clone(&instance), Arc::clone(&device), 1);
-         
-      let uniform_data = UniformData::new()
-                           .add_vector4(Vector4::new(use_base_texture, use_metallic_roughness_texture,
-                                                     use_normal_texture, use_occlusion_texture))
-                           .add_vector4(Vector4::new(use_emissive_texture, normal_scale, alpha_cutoff, alpha_mask))
-                           .add_vector4(math::array4_to_vec4(base_colour_factor))
-                           .add_vector4(Vector4::new(metallic_factor, roughness_factor, occlusion_strength, 0.0))
-                           .add_vector4(Vector4::new(emissive_factor.x, emissive_factor.y, emissive_factor.z, 0.0))
-                           .build(Arc::clone(&device));
-      
-      uniform_buffer.fill_entire_buffer_all_frames(Arc::clone(&device), uniform_data);
-      
-      let descriptor_set;
-      descriptor_set = DescriptorSetBuilder::new()
-                           .vertex_uniform_buffer(0)
-                         //  .vertex_uniform_buffer(1)
-                           .fragment_combined_image_sampler(1)
-                           .fragment_combined_image_sampler(2)
-                           .fragment_combined_image_sampler(3)
-                           .fragment_combined_image_sampler(4)
-                           .fragment_combined_image_sampler(5)
-                           .build(Arc::clone(&device), &descriptor_set_pool, 1);
-      
-      let mut base_texture = dummy_texture;
-      let mut mro_texture = dummy_texture;
-      let mut normal_texture = dummy_texture;
-      let mut occlusion_texture = dummy_texture;
-      let mut emissive_texture = dummy_texture;
+      let mut material = vec!(Vector4::new(use_base_texture, use_metallic_roughness_texture,
+                                           use_normal_texture, use_occlusion_texture),
+                              Vector4::new(use_emissive_texture, normal_scale, alpha_cutoff, alpha_mask),
+                              math::array4_to_vec4(base_colour_factor),
+                              Vector4::new(metallic_factor, roughness_factor, occlusion_strength, 0.0),
+                              Vector4::new(emissive_factor.x, emissive_factor.y, emissive_factor.z, emissive_strength),
+                              Vector4::new(unlit, transmission, 0.0, 0.0));
       
-      if let Some(ref texture) = &base_textures[i*5 + 0] { // base texture
-        base_texture = texture;
-      }
-      if let Some(ref texture) = &base_textures[i*5 + 1] { // mro texture
-        mro_texture = texture;
+      // uv transforms as the first two rows of a 3x3 matrix, with the uv set in the spare w
+      for texture in &TEXTURES_PER_PRIMITIVE {
+        let transform = model.texture_transform(i, texture);
+        let tex_coord = model.texture_tex_coord(i, texture) as f32;
+        material.push(Vector4::new(transform[0], transform[4], transform[12], tex_coord));
+        material.push(Vector4::new(transform[1], transform[5], transform[13], 0.0));
       }
-      if let Some(ref texture) = &base_textures[i*5 + 2] { // normal texture
-        normal_texture = texture;
-      }
-      if let Some(ref texture) = &base_textures[i*5 + 3] { // occlusion texture
-        occlusion_texture = texture;
-      }
-      if let Some(ref texture) = &base_textures[i*5 + 4] { // emissive texture
-        emissive_texture = texture;
-      }
-      /* UpdateDescriptorSets::new()
-             .add_built_uniformbuffer(0, &mut uniform_buffer)
-             .add_sampled_image(1, &texture, ImageLayout::ShaderReadOnlyOptimal, &sampler)
-             .finish_update(Arc::clone(&device), &descriptor_set);
-      } else {
-        UpdateDescriptorSets::new()
-             .add_built_uniformbuffer(0, &mut uniform_buffer)
-             .add_sampled_image(1, &dummy_texture, ImageLayout::ShaderReadOnlyOptimal, &sampler)
-             .finish_update(Arc::clone(&device), &descriptor_set);
-      }*/
       
-      UpdateDescriptorSets::new()
-             .add_built_uniformbuffer(0, &mut uniform_buffer)
-             .add_sampled_image(1, base_texture, ImageLayout::ShaderReadOnlyOptimal, &sampler)
-             .add_sampled_image(2, mro_texture, ImageLayout::ShaderReadOnlyOptimal, &sampler)
-             .add_sampled_image(3, normal_texture, ImageLayout::ShaderReadOnlyOptimal, &sampler)
-             .add_sampled_image(4, occlusion_texture, ImageLayout::ShaderReadOnlyOptimal, &sampler)
-             .add_sampled_image(5, emissive_texture, ImageLayout::ShaderReadOnlyOptimal, &sampler)
-             .finish_update(Arc::clone(&device), &descriptor_set);
+      // base, mro, normal, occlusion and emissive, the dummy texture where there is none
+      let textures = (0..TEXTURES_PER_PRIMITIVE.len()).map(|slot| {
+        base_textures[i*5 + slot].as_ref().unwrap_or(dummy_texture).clone()
+      }).collect::<Vec<ImageAttachment>>();
+      
+      let (descriptor_set, uniform_buffer) = Model::create_descriptor_set(Arc::clone(&instance), Arc::clone(&device), &material, &textures, sampler, 1, descriptor_set_pool);
       
       uniform_buffers.push(uniform_buffer);
       descriptor_sets.push(descriptor_set);
+      materials.push(material);
+      primitive_textures.push(textures);
+      primitive_samplers.push(sampler.clone());
     }
     
+    // artist lods set the number of lods, so every level has its artist primitives
+    let max_artist_lod = lod_levels.iter().filter_map(|level| *level).max();
+    let num_lods = if let Some(max_artist_lod) = max_artist_lod {
+      max_artist_lod as usize + 1
+    } else {
+      1 + lod_index_buffers.iter().map(|lods| lods.len()).max().unwrap_or(0)
+    };
+    
     Model {
       vertex_buffers,
       index_buffers,
@@ -408,11 +622,135 @@ impl Model {
       samplers,
       reference: reference.to_string(),
       
+      materials,
+      textures: primitive_textures,
+      primitive_samplers,
+      
       _base_colour_factors: base_colour_factors,
-      _alpha_cutoffs: alpha_cutoffs,
+      alpha_cutoffs,
       double_sided,
       
       uniform_buffers,
+      
+      lod_index_buffers,
+      lod_levels,
+      num_lods,
+      radius: model.get_size().magnitude()*0.5,
+      
+      bounding_spheres,
+      bounding_sphere: ((model_min+model_max)*0.5, (model_max-model_min).magnitude()*0.5),
+    }
+  }
+  
+  /**
+  ** The material uniform and descriptor set of a primitive, textures are in
+  ** the order of TEXTURES_PER_PRIMITIVE.
+  **/
+  fn create_descriptor_set(instance: Arc<Instance>, device: Arc<Device>, material: &[Vector4<f32>], textures: &[ImageAttachment], sampler: &Sampler, num_sets: u32, descriptor_set_pool: &DescriptorPool) -> (DescriptorSet, Buffer<f32>) {
+    let mut uniform_buffer = UniformBufferBuilder::new().set_binding(0);
+    for _ in 0..material.len() {
+      uniform_buffer = uniform_buffer.add_vector4();
+    }
+    let mut uniform_buffer = uniform_buffer.build(Arc::clone(&instance), Arc::clone(&device), num_sets);
+    
+    uniform_buffer.fill_entire_buffer_all_frames(Arc::clone(&device), Model::material_data(Arc::clone(&device), material));
+    
+    let descriptor_set = DescriptorSetBuilder::new()
+                           .vertex_uniform_buffer(0)
+                           .fragment_combined_image_sampler(1)
+                           .fragment_combined_image_sampler(2)
+                           .fragment_combined_image_sampler(3)
+                           .fragment_combined_image_sampler(4)
+                           .fragment_combined_image_sampler(5)
+                           .build(Arc::clone(&device), descriptor_set_pool, num_sets);
+    
+    UpdateDescriptorSets::new()
+           .add_built_uniformbuffer(0, &mut uniform_buffer)
+           .add_sampled_image(1, &textures[0], ImageLayout::ShaderReadOnlyOptimal, sampler)
+           .add_sampled_image(2, &textures[1], ImageLayout::ShaderReadOnlyOptimal, sampler)
+           .add_sampled_image(3, &textures[2], ImageLayout::ShaderReadOnlyOptimal, sampler)
+           .add_sampled_image(4, &textures[3], ImageLayout::ShaderReadOnlyOptimal, sampler)
+           .add_sampled_image(5, &textures[4], ImageLayout::ShaderReadOnlyOptimal, sampler)
+           .finish_update(Arc::clone(&device), &descriptor_set);
+    
+    (descriptor_set, uniform_buffer)
+  }
+  
+  fn material_data(device: Arc<Device>, material: &[Vector4<f32>]) -> Vec<f32> {
+    let mut uniform_data = UniformData::new();
+    for vector in material {
+      uniform_data = uniform_data.add_vector4(*vector);
+    }
+    uniform_data.build(device)
+  }
+  
+  // a primitive's material with the override and its texture slots written over it
+  fn override_material(&self, primitive: usize, material: &MaterialOverride, texture_slots: &[TextureSlot]) -> Vec<Vector4<f32>> {
+    let mut primitive_material = self.materials[primitive].clone();
+    material.apply(&mut primitive_material);
+    for slot in texture_slots {
+      slot.enable(&mut primitive_material);
+    }
+    
+    primitive_material
+  }
+  
+  /**
+  ** A descriptor set for each primitive with the override written over its
+  ** material, swapped textures are used even where the primitive had none.
+  ** Each has a set and uniform buffer per frame so values can change while
+  ** other frames are in flight.
+  **/
+  fn create_override_descriptor_sets(&self, instance: Arc<Instance>, device: Arc<Device>, material: &MaterialOverride, textures: &[(TextureSlot, ImageAttachment)], num_frames: u32, descriptor_set_pool: &DescriptorPool) -> (Vec<DescriptorSet>, Vec<Buffer<f32>>) {
+    let mut descriptor_sets = Vec::with_capacity(self.materials.len());
+    let mut uniform_buffers = Vec::with_capacity(self.materials.len());
+    let texture_slots = textures.iter().map(|(slot, _)| *slot).collect::<Vec<TextureSlot>>();
+    
+    for i in 0..self.materials.len() {
+      let primitive_material = self.override_material(i, material, &texture_slots);
+      let mut primitive_textures = self.textures[i].clone();
+      for (slot, texture) in textures {
+        primitive_textures[slot.index()] = texture.clone();
+      }
+      
+      let (descriptor_set, uniform_buffer) = Model::create_descriptor_set(Arc::clone(&instance), Arc::clone(&device), &primitive_material, &primitive_textures, &self.primitive_samplers[i], num_frames, descriptor_set_pool);
+      descriptor_sets.push(descriptor_set);
+      uniform_buffers.push(uniform_buffer);
+    }
+    
+    (descriptor_sets, uniform_buffers)
+  }
+  
+  // blended primitives skip the gbuffer and are drawn in the forward subpass
+  fn blended(&self, primitive: usize) -> bool {
+    self.alpha_cutoffs[primitive].1 == 0.0
+  }
+  
+  fn masked(&self, primitive: usize) -> bool {
+    self.alpha_cutoffs[primitive].1 == 2.0
+  }
+  
+  /**
+  ** The index buffer and count to draw a primitive with at a lod, None if the
+  ** primitive belongs to a different artist lod. Primitives without a lod
+  ** suffix are drawn at every lod with their generated lods.
+  **/
+  fn lod_index_buffer(&self, primitive: usize, lod: usize) -> Option<(&Buffer<u32>, u32)> {
+    let lod = lod.min(self.num_lods-1);
+    
+    if let Some(level) = self.lod_levels[primitive] {
+      if level as usize != lod {
+        return None;
+      }
+      return Some((&self.index_buffers[primitive], self.index_count[primitive]));
+    }
+    
+    let lods = &self.lod_index_buffers[primitive];
+    if lod == 0 || lods.is_empty() {
+      Some((&self.index_buffers[primitive], self.index_count[primitive]))
+    } else {
+      let (buffer, count) = &lods[(lod-1).min(lods.len()-1)];
+      Some((buffer, *count))
     }
   }
   
@@ -436,6 +774,12 @@ impl Model {
     for sampler in &self.samplers {
       sampler.destroy(Arc::clone(&device));
     }
+    
+    for lods in &self.lod_index_buffers {
+      for (index, _) in lods {
+        index.destroy(Arc::clone(&device));
+      }
+    }
   }
   
   fn create_index_buffer(instance: Arc<Instance>, device: Arc<Device>, indexs: Vec<u32>, command_pool: &CommandPool, graphics_queue: &vk::Queue) -> (Buffer<u32>, u32) {
@@ -462,6 +806,7 @@ pub struct ModelShader {
   framebuffer_emissive_images: Vec<ImageAttachment>,
   framebuffer_normal_images: Vec<ImageAttachment>,
   framebuffer_position_images: Vec<ImageAttachment>,
+  framebuffer_velocity_images: Vec<ImageAttachment>,
   framebuffer_msaa_images: Vec<ImageAttachment>,
   framebuffer_depth_images: Vec<ImageAttachment>,
   framebuffer_msaa_depth_images: Vec<ImageAttachment>,
@@ -471,6 +816,8 @@ pub struct ModelShader {
   dummy_uniform_buffer: Buffer<f32>,
   
   models: Vec<Model>,
+  material_overrides: Vec<ModelMaterial>,
+  retired_material_overrides: Vec<(usize, ModelMaterial)>, // replaced or removed overrides and the last frame that could draw them
   
   vertex_buffer_deffered: Buffer<FinalVertex>,
   index_buffer_deffered: Buffer<u32>,
@@ -478,35 +825,87 @@ pub struct ModelShader {
   pipeline: Pipeline,
   pipeline_deffered: Pipeline,
   double_pipeline: Pipeline,
+  forward_pipeline: Pipeline,
+  forward_double_pipeline: Pipeline,
+  forward_instanced_pipeline: Pipeline,
+  forward_instanced_double_pipeline: Pipeline,
   instanced_pipeline: Pipeline,
   instanced_double_pipeline: Pipeline,
-  instanced_cpu_buffers: Vec<(String, Buffer<f32>)>,
-  instanced_cpu_data: Vec<UniformData>,
+  coverage_pipeline: Pipeline,
+  coverage_double_pipeline: Pipeline,
+  instanced_coverage_pipeline: Pipeline,
+  instanced_coverage_double_pipeline: Pipeline,
+  wireframe_pipelines: Option<(Pipeline, Pipeline)>, // single and instanced, when the device supports it
+  overdraw_pipelines: (Pipeline, Pipeline), // single and instanced
+  instanced_cpu_buffers: Vec<(String, Vec<InstanceBuffer>)>, // per lod then SHADOW_CASTERS
+  instance_culling: Option<InstanceCulling>, // instanced models are culled on the gpu when set
   
   vertex_shader: Shader,
   fragment_shader: Shader,
   vertex_shader_deffered: Shader,
   fragment_shader_deffered: Shader,
   vertex_shader_instanced: Shader,
+  fragment_shader_forward: Shader,
+  fragment_shader_overdraw: Shader,
   
   msaa: SampleCount,
+  alpha_to_coverage: bool, // only with msaa
   camera: PerspectiveCamera,
+  frustum: Frustum, // of the camera, set by begin_frame
+  view_projection: Matrix4<f32>, // of the camera, set by begin_frame
+  previous_view_projection: Matrix4<f32>, // of last frame's camera
+  frame: usize, // the current_buffer of begin_frame, the camera set of it is bound by the model pass
+  model_motion: MotionHistory, // of draw_model
+  instance_motion: MotionHistory, // of add_instanced_model
+  stats: FrameStats,
+  debug_view: DebugView,
   
   scanline: f32,
   light: Light,
+  lights: Vec<Light>, // cleared every frame
+  frame_lights: Vec<Light>, // set_light and lights with their shadow tiles, built by begin_shadow_pass
+  light_buffers: Vec<Buffer<f32>>, // per frame, so one can grow while the others are in flight
+  light_cluster_buffers: Vec<Buffer<f32>>,
+  retired_light_buffers: Vec<(usize, Buffer<f32>)>, // grown out of and the frame that replaced them
+  
+  forward_descriptor_set: DescriptorSet,
+  forward_camera_buffer: Buffer<f32>,
+  camera_descriptor_set: DescriptorSet,
+  camera_buffer: Buffer<f32>,
+  transparent_draws: Vec<TransparentDraw>, // cleared every frame
+  debug_lines: DebugLines,
+  
+  shadow_map: ShadowMap,
+  shadow_pipeline: Pipeline,
+  shadow_instanced_pipeline: Pipeline,
+  vertex_shader_shadow: Shader,
+  vertex_shader_shadow_instanced: Shader,
+  fragment_shader_shadow: Shader,
+  
+  environment_map: EnvironmentMap,
+  skybox: Skybox,
+  fog: Fog,
+  ambient_occlusion: AmbientOcclusion,
+  bloom: Bloom,
+  anti_aliasing: Option<PostAntiAliasing>, // fxaa or taa instead of msaa when set
 }
 
 impl ModelShader {
-  pub fn new(instance: Arc<Instance>, device: Arc<Device>, current_extent: &vk::Extent2D, format: &vk::Format, sampler: &Sampler, image_views: &Vec<vk::ImageView>, texture_image: &ImageAttachment, descriptor_set_pool: &DescriptorPool, command_pool: &CommandPool, graphics_queue: &vk::Queue, msaa: &SampleCount) -> ModelShader {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, current_extent: &vk::Extent2D, format: &vk::Format, sampler: &Sampler, image_views: &Vec<vk::ImageView>, texture_image: &ImageAttachment, descriptor_set_pool: &DescriptorPool, command_pool: &CommandPool, graphics_queue: &vk::Queue, msaa: &SampleCount, alpha_to_coverage: bool, gpu_culling: bool, occlusion_culling: bool, shadow_settings: ShadowSettings, ambient_occlusion_settings: AmbientOcclusionSettings, anti_aliasing: AntiAliasing) -> ModelShader {
     let vertex_shader_instanced = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelInstancedVert.spv"));
     let vertex_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelVert.spv"));
     
     let fragment_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelFrag.spv"));
     let vertex_shader_deffered = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelDefferedVert.spv"));
     let fragment_shader_deffered = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelDefferedFrag.spv"));
+    let fragment_shader_forward = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelForwardFrag.spv"));
+    let fragment_shader_overdraw = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelOverdrawFrag.spv"));
+    let vertex_shader_shadow = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelShadowVert.spv"));
+    let vertex_shader_shadow_instanced = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelShadowInstancedVert.spv"));
+    let fragment_shader_shadow = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkModelShadowFrag.spv"));
     
     let colour_attachment = AttachmentInfo::new()
-                                .format(*format)
+                                .format(HDR_FORMAT)
                                 .multisample(&SampleCount::OneBit)
                                 .load(AttachmentLoadOp::Clear)
                                 .store(AttachmentStoreOp::Store)
@@ -517,7 +916,7 @@ impl ModelShader {
                                 .image_usage(ImageLayout::ColourAttachmentOptimal);
     
     let albedo_colour_attachment = AttachmentInfo::new()
-                                .format(*format)
+                                .format(HDR_FORMAT)
                                 .multisample(&SampleCount::OneBit)
                                 .load(AttachmentLoadOp::DontCare)
                                 .store(AttachmentStoreOp::DontCare)
@@ -548,22 +947,39 @@ impl ModelShader {
                                 .final_layout(ImageLayout::ShaderReadOnlyOptimal)
                                 .image_usage(ImageLayout::ColourAttachmentOptimal);
                                 
+    // ambient occlusion is built from the normals and positions after the pass
+    let keep_normals = ambient_occlusion_settings.enabled();
     let normal_attachment = AttachmentInfo::new()
                                 .format(vk::FORMAT_R8G8B8A8_SNORM)
                                 .multisample(&SampleCount::OneBit)
-                                .load(AttachmentLoadOp::DontCare)
-                                .store(AttachmentStoreOp::DontCare)
+                                .load(if keep_normals { AttachmentLoadOp::Clear } else { AttachmentLoadOp::DontCare })
+                                .store(if keep_normals { AttachmentStoreOp::Store } else { AttachmentStoreOp::DontCare })
                                 .stencil_load(AttachmentLoadOp::DontCare)
                                 .stencil_store(AttachmentStoreOp::DontCare)
                                 .initial_layout(ImageLayout::Undefined)
                                 .final_layout(ImageLayout::ShaderReadOnlyOptimal)
                                 .image_usage(ImageLayout::ColourAttachmentOptimal);
     
+    // occlusion culling builds its depth pyramid from the positions after the pass
+    let keep_positions = (gpu_culling && occlusion_culling) || keep_normals;
     let position_attachment = AttachmentInfo::new()
                                 .format(vk::FORMAT_R16G16B16A16_SFLOAT)
                                 .multisample(&SampleCount::OneBit)
-                                .load(AttachmentLoadOp::DontCare)
-                                .store(AttachmentStoreOp::DontCare)
+                                .load(if keep_positions { AttachmentLoadOp::Clear } else { AttachmentLoadOp::DontCare })
+                                .store(if keep_positions { AttachmentStoreOp::Store } else { AttachmentStoreOp::DontCare })
+                                .stencil_load(AttachmentLoadOp::DontCare)
+                                .stencil_store(AttachmentStoreOp::DontCare)
+                                .initial_layout(ImageLayout::Undefined)
+                                .final_layout(ImageLayout::ShaderReadOnlyOptimal)
+                                .image_usage(ImageLayout::ColourAttachmentOptimal);
+    
+    // taa reprojects its history by the velocity after the pass
+    let keep_velocity = anti_aliasing == AntiAliasing::Taa;
+    let velocity_attachment = AttachmentInfo::new()
+                                .format(VELOCITY_FORMAT)
+                                .multisample(&SampleCount::OneBit)
+                                .load(if keep_velocity { AttachmentLoadOp::Clear } else { AttachmentLoadOp::DontCare })
+                                .store(if keep_velocity { AttachmentStoreOp::Store } else { AttachmentStoreOp::DontCare })
                                 .stencil_load(AttachmentLoadOp::DontCare)
                                 .stencil_store(AttachmentStoreOp::DontCare)
                                 .initial_layout(ImageLayout::Undefined)
@@ -582,7 +998,7 @@ impl ModelShader {
                                 .image_usage(ImageLayout::DepthStencilAttachmentOptimal);
     
     let msaa_attachment = AttachmentInfo::new()
-                                .format(*format)
+                                .format(HDR_FORMAT)
                                 .multisample(msaa)
                                 .load(AttachmentLoadOp::Clear)
                                 .store(AttachmentStoreOp::DontCare)
@@ -604,7 +1020,7 @@ impl ModelShader {
                                 .image_usage(ImageLayout::DepthStencilAttachmentOptimal);
     
     let msaa_albedo_colour_attachment = AttachmentInfo::new()
-                                .format(*format)
+                                .format(HDR_FORMAT)
                                 .multisample(msaa)
                                 .load(AttachmentLoadOp::Clear)
                                 .store(AttachmentStoreOp::DontCare)
@@ -658,13 +1074,25 @@ impl ModelShader {
                                 .final_layout(ImageLayout::ShaderReadOnlyOptimal)
                                 .image_usage(ImageLayout::ColourAttachmentOptimal);
     
+    let msaa_velocity_attachment = AttachmentInfo::new()
+                                .format(VELOCITY_FORMAT)
+                                .multisample(msaa)
+                                .load(AttachmentLoadOp::Clear)
+                                .store(AttachmentStoreOp::DontCare)
+                                .stencil_load(AttachmentLoadOp::DontCare)
+                                .stencil_store(AttachmentStoreOp::DontCare)
+                                .initial_layout(ImageLayout::Undefined)
+                                .final_layout(ImageLayout::ShaderReadOnlyOptimal)
+                                .image_usage(ImageLayout::ColourAttachmentOptimal);
+    
     let mut subpass = SubpassInfo::new().add_colour_attachment(if msaa == &SampleCount::OneBit { 0 } else { 7 })
                                     .add_colour_attachment(if msaa == &SampleCount::OneBit { 1 } else { 9 })
                                     .add_colour_attachment(if msaa == &SampleCount::OneBit { 2 } else { 10 })
                                     .add_colour_attachment(if msaa == &SampleCount::OneBit { 3 } else { 11 })
                                     .add_colour_attachment(if msaa == &SampleCount::OneBit { 4 } else { 12 })
                                     .add_depth_stencil(if msaa == &SampleCount::OneBit { 5 } else { 8 })
-                                    .add_colour_attachment(if msaa == &SampleCount::OneBit { 6 } else { 13 });
+                                    .add_colour_attachment(if msaa == &SampleCount::OneBit { 6 } else { 13 })
+                                    .add_colour_attachment(if msaa == &SampleCount::OneBit { 7 } else { 15 });
     
     if msaa != &SampleCount::OneBit {
       subpass = subpass.add_resolve_attachment(0)
@@ -673,17 +1101,27 @@ impl ModelShader {
                        .add_resolve_attachment(3)
                        .add_resolve_attachment(4)
                        .add_resolve_attachment(6)
+                       .add_resolve_attachment(14)
                        .add_resolve_attachment(5);
                        
     }
     
-    let second_subpass = SubpassInfo::new().add_colour_attachment(0)
+    let mut second_subpass = SubpassInfo::new().add_colour_attachment(0)
                                            .add_input_attachment(1)
                                            .add_input_attachment(2)
                                            .add_input_attachment(3)
                                            .add_input_attachment(4)
                                            .add_input_attachment(6);
     
+    // the multisampled depth can't be used with the single sampled colour so the deffered pass rebuilds it
+    if msaa != &SampleCount::OneBit {
+      second_subpass = second_subpass.add_depth_stencil(5);
+    }
+    
+    // blended primitives are drawn over the lit colour, tested against the opaque depth
+    let third_subpass = SubpassInfo::new().add_colour_attachment(0)
+                                          .add_depth_stencil(5);
+    
     let render_pass = RenderPassBuilder::new();
     let mut render_pass = render_pass.add_attachment(colour_attachment) // 0
                                      .add_attachment(albedo_colour_attachment) // 1
@@ -693,7 +1131,10 @@ impl ModelShader {
                                      .add_attachment(depth_attachment) // 5
                                      .add_attachment(position_attachment); // 6
     
-    if msaa != &SampleCount::OneBit {
+    // the velocity follows the position, or the multisampled attachments with msaa
+    if msaa == &SampleCount::OneBit {
+      render_pass = render_pass.add_attachment(velocity_attachment); // 7
+    } else {
       render_pass = render_pass.add_attachment(msaa_attachment); // 7
       render_pass = render_pass.add_attachment(msaa_depth_attachment); // 8
       render_pass = render_pass.add_attachment(msaa_albedo_colour_attachment); // 9
@@ -701,14 +1142,17 @@ impl ModelShader {
       render_pass = render_pass.add_attachment(msaa_emissive_attachment); // 11
       render_pass = render_pass.add_attachment(msaa_normal_attachment); // 12
       render_pass = render_pass.add_attachment(msaa_position_attachment); // 13
+      render_pass = render_pass.add_attachment(velocity_attachment); // 14
+      render_pass = render_pass.add_attachment(msaa_velocity_attachment); // 15
     }
     
     let render_pass = render_pass.add_subpass(subpass)
                                  .add_subpass(second_subpass)
+                                 .add_subpass(third_subpass)
                                  .build(Arc::clone(&device));
     
     let (framebuffer_colour_images, framebuffer_albedo_colour_images, framebuffer_mro_images,
-         framebuffer_emissive_images, framebuffer_normal_images, framebuffer_position_images, 
+         framebuffer_emissive_images, framebuffer_normal_images, framebuffer_position_images, framebuffer_velocity_images, 
          framebuffer_msaa_images, framebuffer_depth_images, framebuffer_msaa_depth_images, framebuffer_remaining_msaa_images, framebuffers) 
                       = ModelShader::create_frame_buffers(Arc::clone(&instance), Arc::clone(&device),
                                                            &render_pass, current_extent, format, 
@@ -759,22 +1203,113 @@ impl ModelShader {
           .fragment_input_attachment(3)
           .fragment_input_attachment(4)
           .fragment_input_attachment(5)
+          .fragment_storage_buffer(6)
+          .fragment_storage_buffer(7)
+          .fragment_storage_buffer(8)
+          .fragment_combined_image_sampler(9)
+          .fragment_combined_image_sampler(10)
+          .fragment_combined_image_sampler(11)
+          .fragment_combined_image_sampler(12)
+          .fragment_combined_image_sampler(13)
+          .fragment_storage_buffer(14)
+          .fragment_storage_buffer(15)
           .build(Arc::clone(&device), &descriptor_set_pool, image_views.len() as u32);
-      
+    
+    let light_buffers: Vec<Buffer<f32>> = (0..image_views.len()).map(|_| Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), 1, (INITIAL_LIGHTS*LIGHT_SIZE) as u64)).collect();
+    let light_cluster_buffers: Vec<Buffer<f32>> = (0..image_views.len()).map(|_| Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), 1, (CLUSTER_COUNT*2 + INITIAL_LIGHT_INDICES) as u64)).collect();
+    let shadow_map = ShadowMap::new(Arc::clone(&instance), Arc::clone(&device), shadow_settings, image_views.len() as u32);
+    let environment_map = EnvironmentMap::new(Arc::clone(&instance), Arc::clone(&device), descriptor_set_pool, command_pool, graphics_queue);
+    let fog = Fog::new(Arc::clone(&instance), Arc::clone(&device), image_views.len() as u32);
+    let ambient_occlusion = AmbientOcclusion::new(Arc::clone(&instance), Arc::clone(&device), ambient_occlusion_settings, &framebuffer_position_images, &framebuffer_normal_images, current_extent, descriptor_set_pool);
+    let bloom = Bloom::new(Arc::clone(&instance), Arc::clone(&device), &framebuffer_colour_images, current_extent, descriptor_set_pool);
+    let post_anti_aliasing = if anti_aliasing.post_process() {
+      Some(PostAntiAliasing::new(Arc::clone(&instance), Arc::clone(&device), anti_aliasing, &framebuffer_colour_images, &framebuffer_velocity_images, current_extent, descriptor_set_pool))
+    } else {
+      None
+    };
+    
     UpdateDescriptorSets::new()
       .add_input_attachment_image(1, &framebuffer_albedo_colour_images)
       .add_input_attachment_image(2, &framebuffer_mro_images)
       .add_input_attachment_image(3, &framebuffer_emissive_images)
       .add_input_attachment_image(4, &framebuffer_normal_images)
       .add_input_attachment_image(5, &framebuffer_position_images)
+      .add_storage_buffer(8, shadow_map.buffer())
+      .add_sampled_image(9, shadow_map.depth_image(), ImageLayout::ShaderReadOnlyOptimal, shadow_map.sampler())
+      .add_sampled_image(10, environment_map.irradiance(), ImageLayout::ShaderReadOnlyOptimal, environment_map.sampler())
+      .add_sampled_image(11, environment_map.prefiltered(), ImageLayout::ShaderReadOnlyOptimal, environment_map.sampler())
+      .add_sampled_image(12, environment_map.brdf_lut(), ImageLayout::ShaderReadOnlyOptimal, environment_map.sampler())
+      .add_sampled_image(13, ambient_occlusion.blurred_image(), ImageLayout::General, ambient_occlusion.sampler())
+      .add_storage_buffer(14, ambient_occlusion.view_buffer())
+      .add_storage_buffer(15, fog.buffer())
      .finish_update(Arc::clone(&device), &deffered_descriptor_set);
     
-    let (pipeline, pipeline_deffered, double_pipeline) = ModelShader::create_pipline(Arc::clone(&device), &vertex_shader, &fragment_shader, &vertex_shader_deffered, &fragment_shader_deffered, &render_pass, &descriptor_sets[0], &deffered_descriptor_set, msaa);
-    let (instanced_pipeline, instanced_double_pipeline) = ModelShader::create_instanced_pipline(Arc::clone(&device), &vertex_shader_instanced, &fragment_shader, &render_pass, &descriptor_sets[0], msaa);
+    let forward_descriptor_set = DescriptorSetBuilder::new()
+          .fragment_storage_buffer(0)
+          .fragment_storage_buffer(1)
+          .fragment_storage_buffer(2)
+          .fragment_combined_image_sampler(3)
+          .fragment_combined_image_sampler(4)
+          .fragment_combined_image_sampler(5)
+          .fragment_combined_image_sampler(6)
+          .fragment_storage_buffer(7)
+          .fragment_storage_buffer(8)
+          .build(Arc::clone(&device), descriptor_set_pool, image_views.len() as u32);
+    
+    let forward_camera_buffer = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), image_views.len() as u32, FORWARD_CAMERA_SIZE as u64);
+    
+    UpdateDescriptorSets::new()
+      .add_storage_buffer(2, shadow_map.buffer())
+      .add_sampled_image(3, shadow_map.depth_image(), ImageLayout::ShaderReadOnlyOptimal, shadow_map.sampler())
+      .add_sampled_image(4, environment_map.irradiance(), ImageLayout::ShaderReadOnlyOptimal, environment_map.sampler())
+      .add_sampled_image(5, environment_map.prefiltered(), ImageLayout::ShaderReadOnlyOptimal, environment_map.sampler())
+      .add_sampled_image(6, environment_map.brdf_lut(), ImageLayout::ShaderReadOnlyOptimal, environment_map.sampler())
+      .add_storage_buffer(7, &forward_camera_buffer)
+      .add_storage_buffer(8, fog.buffer())
+     .finish_update(Arc::clone(&device), &forward_descriptor_set);
+    
+    for i in 0..image_views.len() {
+      UpdateDescriptorSets::new()
+        .add_storage_buffer(6, &light_buffers[i])
+        .add_storage_buffer(7, &light_cluster_buffers[i])
+       .finish_update_single_set(Arc::clone(&device), &deffered_descriptor_set, i);
+      
+      UpdateDescriptorSets::new()
+        .add_storage_buffer(0, &light_buffers[i])
+        .add_storage_buffer(1, &light_cluster_buffers[i])
+       .finish_update_single_set(Arc::clone(&device), &forward_descriptor_set, i);
+    }
+    
+    // the second set of every model pipeline, the velocity is taken from the two view projections
+    let camera_descriptor_set = DescriptorSetBuilder::new()
+          .vertex_storage_buffer(0)
+          .build(Arc::clone(&device), descriptor_set_pool, image_views.len() as u32);
+    
+    let camera_buffer = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), image_views.len() as u32, CAMERA_SIZE as u64);
+    
+    UpdateDescriptorSets::new()
+      .add_storage_buffer(0, &camera_buffer)
+     .finish_update(Arc::clone(&device), &camera_descriptor_set);
+    
+    let (pipeline, pipeline_deffered, double_pipeline) = ModelShader::create_pipline(Arc::clone(&device), &vertex_shader, &fragment_shader, &vertex_shader_deffered, &fragment_shader_deffered, &render_pass, &descriptor_sets[0], &camera_descriptor_set, &deffered_descriptor_set, msaa);
+    let (instanced_pipeline, instanced_double_pipeline) = ModelShader::create_instanced_pipline(Arc::clone(&device), &vertex_shader_instanced, &fragment_shader, &render_pass, &descriptor_sets[0], &camera_descriptor_set, msaa);
+    let (coverage_pipeline, coverage_double_pipeline, instanced_coverage_pipeline, instanced_coverage_double_pipeline) = ModelShader::create_coverage_pipelines(Arc::clone(&device), &vertex_shader, &vertex_shader_instanced, &fragment_shader, &render_pass, &descriptor_sets[0], &camera_descriptor_set, msaa);
+    let (forward_pipeline, forward_double_pipeline, forward_instanced_pipeline, forward_instanced_double_pipeline) = ModelShader::create_forward_pipelines(Arc::clone(&device), &vertex_shader, &vertex_shader_instanced, &fragment_shader_forward, &render_pass, &descriptor_sets[0], &camera_descriptor_set, &forward_descriptor_set);
+    let (wireframe_pipelines, overdraw_pipelines) = ModelShader::create_debug_view_pipelines(Arc::clone(&device), &vertex_shader, &vertex_shader_instanced, &fragment_shader, &fragment_shader_overdraw, &render_pass, &descriptor_sets[0], &camera_descriptor_set, msaa);
+    let (shadow_pipeline, shadow_instanced_pipeline) = ModelShader::create_shadow_pipelines(Arc::clone(&device), &vertex_shader_shadow, &vertex_shader_shadow_instanced, &fragment_shader_shadow, shadow_map.get_render_pass(), &descriptor_sets[0]);
+    
+    let debug_lines = DebugLines::new(Arc::clone(&instance), Arc::clone(&device), &render_pass, 2, &descriptor_sets[0], image_views.len() as u32);
+    let skybox = Skybox::new(Arc::clone(&instance), Arc::clone(&device), &render_pass, msaa, descriptor_set_pool, command_pool, graphics_queue);
     
     let vertex_buffer_deffered = FinalShader::create_vertex_buffer(Arc::clone(&instance), Arc::clone(&device), &command_pool, graphics_queue);
     let index_buffer_deffered = FinalShader::create_index_buffer(Arc::clone(&instance), Arc::clone(&device), &command_pool, graphics_queue);
     
+    let instance_culling = if gpu_culling {
+      Some(InstanceCulling::new(Arc::clone(&instance), Arc::clone(&device), &framebuffer_position_images, occlusion_culling, current_extent, descriptor_set_pool))
+    } else {
+      None
+    };
+    
     ModelShader {
       renderpass: render_pass,
       framebuffers,
@@ -784,6 +1319,7 @@ impl ModelShader {
       framebuffer_emissive_images,
       framebuffer_normal_images,
       framebuffer_position_images,
+      framebuffer_velocity_images,
       framebuffer_msaa_images,
       framebuffer_depth_images,
       framebuffer_msaa_depth_images,
@@ -793,6 +1329,8 @@ impl ModelShader {
       dummy_uniform_buffer: uniform_buffer,
       
       models: Vec::new(),
+      material_overrides: Vec::new(),
+      retired_material_overrides: Vec::new(),
       
       vertex_buffer_deffered,
       index_buffer_deffered,
@@ -800,38 +1338,178 @@ impl ModelShader {
       pipeline,
       double_pipeline,
       pipeline_deffered,
+      forward_pipeline,
+      forward_double_pipeline,
+      forward_instanced_pipeline,
+      forward_instanced_double_pipeline,
       instanced_pipeline,
       instanced_double_pipeline,
+      coverage_pipeline,
+      coverage_double_pipeline,
+      instanced_coverage_pipeline,
+      instanced_coverage_double_pipeline,
+      wireframe_pipelines,
+      overdraw_pipelines,
       instanced_cpu_buffers: Vec::new(),
-      instanced_cpu_data: Vec::new(),
+      instance_culling,
       
       vertex_shader,
       fragment_shader,
       vertex_shader_deffered,
       fragment_shader_deffered,
       vertex_shader_instanced,
+      fragment_shader_forward,
+      fragment_shader_overdraw,
       
       msaa: *msaa,
+      alpha_to_coverage: alpha_to_coverage && msaa != &SampleCount::OneBit,
+      frustum: camera.get_frustum(FOV, current_extent.width as f32 / current_extent.height as f32, 0.1, 1080.0),
+      view_projection: ModelShader::view_projection(&camera, FOV, current_extent.width as f32 / current_extent.height as f32),
+      previous_view_projection: ModelShader::view_projection(&camera, FOV, current_extent.width as f32 / current_extent.height as f32),
+      frame: 0,
+      model_motion: MotionHistory::new(),
+      instance_motion: MotionHistory::new(),
+      stats: FrameStats::default(),
+      debug_view: DebugView::Lit,
       camera,
       
       scanline: 0.0,
-      light: Light::new(),
+      light: Light::off(),
+      lights: Vec::new(),
+      frame_lights: Vec::new(),
+      light_buffers,
+      light_cluster_buffers,
+      retired_light_buffers: Vec::new(),
+      
+      forward_descriptor_set,
+      forward_camera_buffer,
+      camera_descriptor_set,
+      camera_buffer,
+      transparent_draws: Vec::new(),
+      debug_lines,
+      
+      shadow_map,
+      shadow_pipeline,
+      shadow_instanced_pipeline,
+      vertex_shader_shadow,
+      vertex_shader_shadow_instanced,
+      fragment_shader_shadow,
+      
+      environment_map,
+      skybox,
+      fog,
+      ambient_occlusion,
+      bloom,
+      anti_aliasing: post_anti_aliasing,
     }
   }
   
-  pub fn update_scanline(&mut self, delta_time: f32) {
-    self.scanline += delta_time;
-    if self.scanline > 10000.0 {
-      self.scanline = 0.0;
+  /**
+  ** Lights the scene with the environment at location, or back to a flat
+  ** ambient with None. Waits for the device so it is slow, not per frame.
+  **/
+  pub fn set_environment_map(&mut self, instance: Arc<Instance>, device: Arc<Device>, location: Option<String>, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    if &location == self.environment_map.location() {
+      return;
     }
+    
+    device.wait();
+    self.environment_map.set(Arc::clone(&instance), Arc::clone(&device), location, command_pool, graphics_queue);
   }
   
-  pub fn set_light(&mut self, position: Vector3<f32>, colour: Vector3<f32>, intensity: f32) {
-    self.light.update(position, colour, intensity);
+  /**
+  ** Six square images for the sky in the order +x, -x, +y, -y, +z, -z.
+  ** Waits for the device when the images change.
+  **/
+  pub fn set_skybox_images(&mut self, instance: Arc<Instance>, device: Arc<Device>, locations: Vec<String>, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    if !self.skybox.images_changed(&locations) {
+      return;
+    }
+    
+    device.wait();
+    self.skybox.set_images(Arc::clone(&instance), Arc::clone(&device), locations, command_pool, graphics_queue);
   }
   
-  pub fn set_camera(&mut self, camera: PerspectiveCamera) {
-    self.camera = camera;
+  pub fn set_skybox_equirectangular(&mut self, instance: Arc<Instance>, device: Arc<Device>, location: String, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    if !self.skybox.equirectangular_changed(&location) {
+      return;
+    }
+    
+    device.wait();
+    self.skybox.set_equirectangular(Arc::clone(&instance), Arc::clone(&device), location, &self.environment_map, command_pool, graphics_queue);
+  }
+  
+  pub fn set_skybox_gradient(&mut self, top: Vector3<f32>, horizon: Vector3<f32>, bottom: Vector3<f32>) {
+    self.skybox.set_gradient(top, horizon, bottom);
+  }
+  
+  pub fn clear_skybox(&mut self) {
+    self.skybox.clear();
+  }
+  
+  pub fn set_fog(&mut self, colour: Vector3<f32>, density: f32) {
+    self.fog.set_distance(colour, density);
+  }
+  
+  pub fn set_height_fog(&mut self, density: f32, height: f32, falloff: f32) {
+    self.fog.set_height(density, height, falloff);
+  }
+  
+  pub fn clear_fog(&mut self) {
+    self.fog.clear();
+  }
+  
+  /**
+  ** Colours the fog with the sky lit by a sun shining along sun_direction,
+  ** and draws that sky when no skybox is set. None turns it back off.
+  **/
+  pub fn set_sky_scattering(&mut self, sun_direction: Option<Vector3<f32>>) {
+    self.fog.set_atmosphere(sun_direction);
+    self.skybox.set_atmosphere(self.fog.atmosphere());
+  }
+  
+  pub fn debug_view(&self) -> DebugView {
+    self.debug_view
+  }
+  
+  // takes effect from the next draw, blended primitives are only drawn in the lit view
+  pub fn set_debug_view(&mut self, view: DebugView) {
+    if view == DebugView::Wireframe && self.wireframe_pipelines.is_none() {
+      println!("Error: Wireframe debug view isn't supported by this device");
+      return;
+    }
+    
+    self.debug_view = view;
+  }
+  
+  pub fn update_scanline(&mut self, delta_time: f32) {
+    self.scanline += delta_time;
+    if self.scanline > 10000.0 {
+      self.scanline = 0.0;
+    }
+  }
+  
+  pub fn set_light(&mut self, position: Vector3<f32>, colour: Vector3<f32>, intensity: f32) {
+    self.light.update(position, colour, intensity);
+  }
+  
+  pub fn add_light(&mut self, light: ModelLight) {
+    self.lights.push(Light::from_model_light(&light));
+  }
+  
+  pub fn clear_lights(&mut self) {
+    self.lights.clear();
+  }
+  
+  pub fn set_camera(&mut self, camera: PerspectiveCamera) {
+    self.camera = camera;
+  }
+  
+  // outlined at the end of this frame's forward subpass
+  pub fn add_debug_shapes(&mut self, shapes: &[DebugShape], colour: Vector4<f32>, depth_tested: bool) {
+    for shape in shapes {
+      self.debug_lines.add_shape(shape, colour, depth_tested);
+    }
   }
   
   pub fn set_camera_move_speed(&mut self, speed: f32) {
@@ -851,7 +1529,10 @@ impl ModelShader {
   }
   
   pub fn get_texture(&self, current_buffer: usize) -> ImageAttachment {
-    self.framebuffer_colour_images[current_buffer].clone()
+    match &self.anti_aliasing {
+      Some(anti_aliasing) => anti_aliasing.output().clone(),
+      None => self.framebuffer_colour_images[current_buffer].clone(),
+    }
   }
   
   pub fn _get_texture_ref(&self, current_buffer: usize) -> &ImageAttachment {
@@ -870,6 +1551,91 @@ impl ModelShader {
         break;
       }
     }
+    
+    while let Some(i) = self.material_overrides.iter().position(|material| material.model_reference == reference) {
+      let material = self.material_overrides.remove(i);
+      self.retired_material_overrides.push((self.frame, material));
+    }
+  }
+  
+  // true once a model has been added, material overrides wait for it
+  pub fn has_model(&self, reference: &str) -> bool {
+    self.models.iter().any(|model| model.reference == reference)
+  }
+  
+  /**
+  ** Adds a material override for a loaded model that draws can pick by
+  ** reference, adding one with the same reference again replaces it.
+  ** Textures are the loaded textures of the override's texture slots.
+  **/
+  pub fn add_material_override(&mut self, instance: Arc<Instance>, device: Arc<Device>, reference: String, model_reference: String, material: &MaterialOverride, textures: Vec<(TextureSlot, ImageAttachment)>, descriptor_set_pool: &DescriptorPool) {
+    // overrides are usually sent every frame, when only the values changed
+    // begin_frame writes them into each frame's uniform buffers
+    let texture_views: Vec<(TextureSlot, vk::ImageView)> = textures.iter().map(|(slot, texture)| (*slot, texture.get_image_view())).collect();
+    if let Some(current) = self.material_overrides.iter_mut().find(|current| current.reference == reference && current.model_reference == model_reference && current.texture_views == texture_views) {
+      current.material = material.clone();
+      return;
+    }
+    
+    let model = match self.models.iter().find(|model| model.reference == model_reference) {
+      Some(model) => model,
+      None => return,
+    };
+    
+    let num_frames = self.framebuffers.len();
+    let (descriptor_sets, uniform_buffers) = model.create_override_descriptor_sets(Arc::clone(&instance), Arc::clone(&device), material, &textures, num_frames as u32, descriptor_set_pool);
+    
+    self.remove_material_override(reference.to_string());
+    self.material_overrides.push(ModelMaterial {
+      reference,
+      model_reference,
+      material: material.clone(),
+      texture_views,
+      frame_materials: vec!(Some(material.clone()); num_frames),
+      descriptor_sets,
+      uniform_buffers,
+    });
+  }
+  
+  pub fn remove_material_override(&mut self, reference: String) {
+    if let Some(i) = self.material_overrides.iter().position(|material| material.reference == reference) {
+      // frames in flight may still draw with it, it's freed once the last one's fence has signalled
+      let material = self.material_overrides.remove(i);
+      self.retired_material_overrides.push((self.frame, material));
+    }
+  }
+  
+  // writes overrides whose values changed into this frame's uniform buffers
+  fn write_material_overrides(&mut self, device: Arc<Device>, current_buffer: usize) {
+    for material in &mut self.material_overrides {
+      if material.frame_materials[current_buffer].as_ref() == Some(&material.material) {
+        continue;
+      }
+      
+      let model = match self.models.iter().find(|model| model.reference == material.model_reference) {
+        Some(model) => model,
+        None => continue,
+      };
+      
+      let texture_slots = material.texture_views.iter().map(|(slot, _)| *slot).collect::<Vec<TextureSlot>>();
+      for i in 0..material.uniform_buffers.len() {
+        let primitive_material = model.override_material(i, &material.material, &texture_slots);
+        material.uniform_buffers[i].fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, Model::material_data(Arc::clone(&device), &primitive_material));
+      }
+      material.frame_materials[current_buffer] = Some(material.material.clone());
+    }
+  }
+  
+  // the descriptor set to draw a primitive with, the model's own if the material override isn't for it
+  fn primitive_descriptor_set(&self, model: usize, primitive: usize, material: &Option<String>) -> vk::DescriptorSet {
+    let model = &self.models[model];
+    if let Some(reference) = material {
+      if let Some(material) = self.material_overrides.iter().find(|material| &material.reference == reference && material.model_reference == model.reference) {
+        return *material.descriptor_sets[primitive].set(self.frame);
+      }
+    }
+    
+    *model.descriptor_sets[primitive].set(0)
   }
   
   pub fn recreate(&mut self, instance: Arc<Instance>, device: Arc<Device>, format: &vk::Format, image_views: &Vec<vk::ImageView>, new_extent: &vk::Extent2D, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
@@ -882,6 +1648,7 @@ impl ModelShader {
       self.framebuffer_emissive_images[i].destroy(Arc::clone(&device));
       self.framebuffer_normal_images[i].destroy(Arc::clone(&device));
       self.framebuffer_position_images[i].destroy(Arc::clone(&device));
+      self.framebuffer_velocity_images[i].destroy(Arc::clone(&device));
       self.framebuffer_depth_images[i].destroy(Arc::clone(&device));
       
       if self.msaa != SampleCount::OneBit {
@@ -901,6 +1668,7 @@ impl ModelShader {
     self.framebuffer_emissive_images.clear();
     self.framebuffer_normal_images.clear();
     self.framebuffer_position_images.clear();
+    self.framebuffer_velocity_images.clear();
     self.framebuffer_msaa_images.clear();
     self.framebuffer_depth_images.clear();
     self.framebuffer_msaa_depth_images.clear();
@@ -912,6 +1680,7 @@ impl ModelShader {
          framebuffer_emissive_images,
          framebuffer_normal_images,
          framebuffer_position_images, 
+         framebuffer_velocity_images,
          framebuffer_msaa_images,
          framebuffer_depth_images,
          framebuffer_msaa_depth_images, 
@@ -928,36 +1697,44 @@ impl ModelShader {
     self.framebuffer_emissive_images = framebuffer_emissive_images;
     self.framebuffer_normal_images = framebuffer_normal_images;
     self.framebuffer_position_images = framebuffer_position_images;
+    self.framebuffer_velocity_images = framebuffer_velocity_images;
     self.framebuffer_msaa_images = framebuffer_msaa_images;
     self.framebuffer_depth_images = framebuffer_depth_images;
     self.framebuffer_msaa_depth_images = framebuffer_msaa_depth_images;
     self.framebuffer_remaining_msaa_images = framebuffer_remaining_msaa_images;
     
+    self.ambient_occlusion.recreate(Arc::clone(&instance), Arc::clone(&device), &self.framebuffer_position_images, &self.framebuffer_normal_images, new_extent);
+    self.bloom.recreate(Arc::clone(&instance), Arc::clone(&device), &self.framebuffer_colour_images, new_extent);
+    if let Some(anti_aliasing) = &mut self.anti_aliasing {
+      anti_aliasing.recreate(Arc::clone(&instance), Arc::clone(&device), &self.framebuffer_colour_images, &self.framebuffer_velocity_images, new_extent);
+    }
+    
     UpdateDescriptorSets::new()
       .add_input_attachment_image(1, &self.framebuffer_albedo_colour_images)
       .add_input_attachment_image(2, &self.framebuffer_mro_images)
       .add_input_attachment_image(3, &self.framebuffer_emissive_images)
       .add_input_attachment_image(4, &self.framebuffer_normal_images)
       .add_input_attachment_image(5, &self.framebuffer_position_images)
+      .add_sampled_image(13, self.ambient_occlusion.blurred_image(), ImageLayout::General, self.ambient_occlusion.sampler())
      .finish_update(Arc::clone(&device), &self.deffered_descriptor_set);
+    
+    if let Some(instance_culling) = &mut self.instance_culling {
+      let instance_buffers = self.instanced_cpu_buffers.iter().map(|(_, lods)| lods[0].buffer()).collect();
+      instance_culling.recreate(Arc::clone(&instance), Arc::clone(&device), &self.framebuffer_position_images, instance_buffers, new_extent);
+    }
   }
   
-  fn create_pipline(device: Arc<Device>, vertex_shader: &Shader, fragment_shader: &Shader, vertex_shader_deffered: &Shader, fragment_shader_deffered: &Shader, render_pass: &RenderPass, descriptor_set: &DescriptorSet, deffered_descriptor_set: &DescriptorSet, msaa: &SampleCount) -> (Pipeline, Pipeline, Pipeline) {
-    let push_constant_size = UniformData::new()
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .size_non_aligned();
+  fn create_pipline(device: Arc<Device>, vertex_shader: &Shader, fragment_shader: &Shader, vertex_shader_deffered: &Shader, fragment_shader_deffered: &Shader, render_pass: &RenderPass, descriptor_set: &DescriptorSet, camera_descriptor_set: &DescriptorSet, deffered_descriptor_set: &DescriptorSet, msaa: &SampleCount) -> (Pipeline, Pipeline, Pipeline) {
+    let push_constant_size = ModelShader::model_push_constant_size();
+    
+    let layouts = vec!(descriptor_set.layouts()[0], camera_descriptor_set.layouts()[0]);
     
     let pipeline = PipelineBuilder::new()
                   .vertex_shader(*vertex_shader.get_shader())
                   .fragment_shader(*fragment_shader.get_shader())
-                  .push_constants(ShaderStage::Vertex, push_constant_size as u32)
+                  .push_constants(ShaderStage::Vertex, push_constant_size)
                   .render_pass(render_pass.clone())
-                  .descriptor_set_layout(descriptor_set.layouts_clone())
+                  .descriptor_set_layout(layouts.clone())
                   .vertex_binding(vec!(ModelVertex::vertex_input_binding()))
                   .vertex_attributes(ModelVertex::vertex_input_attributes())
                   .multisample(msaa)
@@ -973,9 +1750,9 @@ impl ModelShader {
     let double_pipeline = PipelineBuilder::new()
                   .vertex_shader(*vertex_shader.get_shader())
                   .fragment_shader(*fragment_shader.get_shader())
-                  .push_constants(ShaderStage::Vertex, push_constant_size as u32)
+                  .push_constants(ShaderStage::Vertex, push_constant_size)
                   .render_pass(render_pass.clone())
-                  .descriptor_set_layout(descriptor_set.layouts_clone())
+                  .descriptor_set_layout(layouts)
                   .vertex_binding(vec!(ModelVertex::vertex_input_binding()))
                   .vertex_attributes(ModelVertex::vertex_input_attributes())
                   .multisample(msaa)
@@ -996,14 +1773,12 @@ impl ModelShader {
                                .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
                                .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
                                .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
                                .size_non_aligned();
     
-    let deffered_pipeline = PipelineBuilder::new()
+    let mut deffered_pipeline = PipelineBuilder::new()
                   .vertex_shader(*vertex_shader_deffered.get_shader())
                   .fragment_shader(*fragment_shader_deffered.get_shader())
-                  .push_constants(ShaderStage::Vertex, deffered_push_constant_size as u32)
+                  .push_constants(ShaderStage::Fragment, deffered_push_constant_size as u32)
                   .subpass(1)
                   .render_pass(render_pass.clone())
                   .descriptor_set_layout(deffered_descriptor_set.layouts_clone())
@@ -1014,18 +1789,22 @@ impl ModelShader {
                   .polygon_mode_fill()
                   //.polygon_mode_line()
                   .cull_mode_none()
-                  .front_face_counter_clockwise()
-                  .build(Arc::clone(&device));
+                  .front_face_counter_clockwise();
+    
+    // writes the depth the transparent subpass tests against
+    if msaa != &SampleCount::OneBit {
+      deffered_pipeline = deffered_pipeline.enable_depth_test()
+                                           .enable_depth_write()
+                                           .depth_compare_op(CompareOp::Always);
+    }
+    
+    let deffered_pipeline = deffered_pipeline.build(Arc::clone(&device));
     
     (pipeline, deffered_pipeline, double_pipeline)
   }
   
-  fn create_instanced_pipline(device: Arc<Device>, vertex_shader: &Shader, fragment_shader: &Shader, render_pass: &RenderPass, descriptor_set: &DescriptorSet, msaa: &SampleCount) -> (Pipeline, Pipeline) {
-    let push_constant_size = UniformData::new()
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
-                               .size(Arc::clone(&device));
+  fn create_instanced_pipline(device: Arc<Device>, vertex_shader: &Shader, fragment_shader: &Shader, render_pass: &RenderPass, descriptor_set: &DescriptorSet, camera_descriptor_set: &DescriptorSet, msaa: &SampleCount) -> (Pipeline, Pipeline) {
+    let layouts = vec!(descriptor_set.layouts()[0], camera_descriptor_set.layouts()[0]);
     
     let mut attributes = ModelVertex::vertex_input_attributes();
     attributes.append(&mut ModelInstanceData::vertex_input_attributes());
@@ -1033,9 +1812,8 @@ impl ModelShader {
     let pipeline = PipelineBuilder::new()
                   .vertex_shader(*vertex_shader.get_shader())
                   .fragment_shader(*fragment_shader.get_shader())
-                  .push_constants(ShaderStage::Vertex, push_constant_size as u32)
                   .render_pass(render_pass.clone())
-                  .descriptor_set_layout(descriptor_set.layouts_clone())
+                  .descriptor_set_layout(layouts.clone())
                   .vertex_binding(vec!(ModelVertex::vertex_input_binding(), ModelInstanceData::vertex_input_binding()))
                   .vertex_attributes(attributes)
                   .multisample(msaa)
@@ -1054,9 +1832,8 @@ impl ModelShader {
     let double_pipeline = PipelineBuilder::new()
                   .vertex_shader(*vertex_shader.get_shader())
                   .fragment_shader(*fragment_shader.get_shader())
-                  .push_constants(ShaderStage::Vertex, push_constant_size as u32)
                   .render_pass(render_pass.clone())
-                  .descriptor_set_layout(descriptor_set.layouts_clone())
+                  .descriptor_set_layout(layouts)
                   .vertex_binding(vec!(ModelVertex::vertex_input_binding(), ModelInstanceData::vertex_input_binding()))
                   .vertex_attributes(attributes)
                   .multisample(msaa)
@@ -1071,6 +1848,227 @@ impl ModelShader {
     
     (pipeline, double_pipeline)
   }
+  
+  /**
+  ** Gbuffer pipelines for masked primitives when alpha to coverage is on,
+  ** the fragment shader turns the alpha around the cutoff into coverage
+  ** instead of discarding.
+  **/
+  fn create_coverage_pipelines(device: Arc<Device>, vertex_shader: &Shader, vertex_shader_instanced: &Shader, fragment_shader: &Shader, render_pass: &RenderPass, descriptor_set: &DescriptorSet, camera_descriptor_set: &DescriptorSet, msaa: &SampleCount) -> (Pipeline, Pipeline, Pipeline, Pipeline) {
+    let push_constant_size = ModelShader::model_push_constant_size();
+    
+    let layouts = vec!(descriptor_set.layouts()[0], camera_descriptor_set.layouts()[0]);
+    
+    let mut pipelines = Vec::with_capacity(4);
+    for instanced in &[false, true] {
+      for double_sided in &[false, true] {
+        let mut pipeline = PipelineBuilder::new()
+                    .fragment_shader(*fragment_shader.get_shader())
+                    .add_fragment_specialisation_constant(0, UniformData::new().add_float(1.0), 0)
+                    .render_pass(render_pass.clone())
+                    .descriptor_set_layout(layouts.clone())
+                    .multisample(msaa)
+                    .alpha_to_coverage()
+                    .topology_triangle_list()
+                    .polygon_mode_fill()
+                    .enable_depth_write()
+                    .enable_depth_test()
+                    .front_face_counter_clockwise();
+        
+        if *instanced {
+          let mut attributes = ModelVertex::vertex_input_attributes();
+          attributes.append(&mut ModelInstanceData::vertex_input_attributes());
+          
+          pipeline = pipeline.vertex_shader(*vertex_shader_instanced.get_shader())
+                             .vertex_binding(vec!(ModelVertex::vertex_input_binding(), ModelInstanceData::vertex_input_binding()))
+                             .vertex_attributes(attributes);
+        } else {
+          pipeline = pipeline.vertex_shader(*vertex_shader.get_shader())
+                             .push_constants(ShaderStage::Vertex, push_constant_size)
+                             .vertex_binding(vec!(ModelVertex::vertex_input_binding()))
+                             .vertex_attributes(ModelVertex::vertex_input_attributes());
+        }
+        
+        pipeline = if *double_sided { pipeline.cull_mode_none() } else { pipeline.cull_mode_back() };
+        
+        pipelines.push(pipeline.build(Arc::clone(&device)));
+      }
+    }
+    
+    let instanced_double_pipeline = pipelines.remove(3);
+    let instanced_pipeline = pipelines.remove(2);
+    let double_pipeline = pipelines.remove(1);
+    let pipeline = pipelines.remove(0);
+    
+    (pipeline, double_pipeline, instanced_pipeline, instanced_double_pipeline)
+  }
+  
+  /**
+  ** Gbuffer pipelines for the wireframe and overdraw debug views. Wireframe
+  ** draws both sides of every primitive as lines, overdraw adds up every
+  ** fragment that lands on a pixel into the albedo without testing depth.
+  **/
+  fn create_debug_view_pipelines(device: Arc<Device>, vertex_shader: &Shader, vertex_shader_instanced: &Shader, fragment_shader: &Shader, fragment_shader_overdraw: &Shader, render_pass: &RenderPass, descriptor_set: &DescriptorSet, camera_descriptor_set: &DescriptorSet, msaa: &SampleCount) -> (Option<(Pipeline, Pipeline)>, (Pipeline, Pipeline)) {
+    let push_constant_size = ModelShader::model_push_constant_size();
+    
+    let layouts = vec!(descriptor_set.layouts()[0], camera_descriptor_set.layouts()[0]);
+    
+    let mut pipelines = Vec::with_capacity(4);
+    for overdraw in &[false, true] {
+      if !*overdraw && !device.supports_wireframe() {
+        continue;
+      }
+      
+      for instanced in &[false, true] {
+        let mut pipeline = PipelineBuilder::new()
+                    .render_pass(render_pass.clone())
+                    .descriptor_set_layout(layouts.clone())
+                    .multisample(msaa)
+                    .topology_triangle_list()
+                    .front_face_counter_clockwise();
+        
+        if *overdraw {
+          pipeline = pipeline.fragment_shader(*fragment_shader_overdraw.get_shader())
+                             .polygon_mode_fill()
+                             .colour_blend_factors(BlendFactor::One, BlendFactor::One)
+                             .alpha_blend_factors(BlendFactor::One, BlendFactor::One)
+                             .cull_mode_back();
+        } else {
+          pipeline = pipeline.fragment_shader(*fragment_shader.get_shader())
+                             .polygon_mode_line()
+                             .enable_depth_write()
+                             .enable_depth_test()
+                             .cull_mode_none();
+        }
+        
+        if *instanced {
+          let mut attributes = ModelVertex::vertex_input_attributes();
+          attributes.append(&mut ModelInstanceData::vertex_input_attributes());
+          
+          pipeline = pipeline.vertex_shader(*vertex_shader_instanced.get_shader())
+                             .vertex_binding(vec!(ModelVertex::vertex_input_binding(), ModelInstanceData::vertex_input_binding()))
+                             .vertex_attributes(attributes);
+        } else {
+          pipeline = pipeline.vertex_shader(*vertex_shader.get_shader())
+                             .push_constants(ShaderStage::Vertex, push_constant_size)
+                             .vertex_binding(vec!(ModelVertex::vertex_input_binding()))
+                             .vertex_attributes(ModelVertex::vertex_input_attributes());
+        }
+        
+        pipelines.push(pipeline.build(Arc::clone(&device)));
+      }
+    }
+    
+    let overdraw_instanced_pipeline = pipelines.pop().unwrap();
+    let overdraw_pipeline = pipelines.pop().unwrap();
+    let wireframe_pipelines = if pipelines.len() == 2 {
+      let wireframe_instanced_pipeline = pipelines.remove(1);
+      Some((pipelines.remove(0), wireframe_instanced_pipeline))
+    } else {
+      None
+    };
+    
+    (wireframe_pipelines, (overdraw_pipeline, overdraw_instanced_pipeline))
+  }
+  
+  /**
+  ** Pipelines for the blended primitives in the third subpass. They test
+  ** against the opaque depth without writing it as they're sorted instead,
+  ** and blend alpha over so the model image stays opaque behind them.
+  **/
+  fn create_forward_pipelines(device: Arc<Device>, vertex_shader: &Shader, vertex_shader_instanced: &Shader, fragment_shader: &Shader, render_pass: &RenderPass, descriptor_set: &DescriptorSet, camera_descriptor_set: &DescriptorSet, forward_descriptor_set: &DescriptorSet) -> (Pipeline, Pipeline, Pipeline, Pipeline) {
+    let push_constant_size = ModelShader::model_push_constant_size();
+    
+    let layouts = vec!(descriptor_set.layouts()[0], camera_descriptor_set.layouts()[0], forward_descriptor_set.layouts()[0]);
+    
+    let mut pipelines = Vec::with_capacity(4);
+    for instanced in &[false, true] {
+      for double_sided in &[false, true] {
+        let mut pipeline = PipelineBuilder::new()
+                    .fragment_shader(*fragment_shader.get_shader())
+                    .subpass(2)
+                    .render_pass(render_pass.clone())
+                    .descriptor_set_layout(layouts.clone())
+                    .topology_triangle_list()
+                    .polygon_mode_fill()
+                    .enable_depth_test()
+                    .alpha_blend_factors(BlendFactor::One, BlendFactor::OneMinusSrcAlpha)
+                    .front_face_counter_clockwise();
+        
+        if *instanced {
+          let mut attributes = ModelVertex::vertex_input_attributes();
+          attributes.append(&mut ModelInstanceData::vertex_input_attributes());
+          
+          pipeline = pipeline.vertex_shader(*vertex_shader_instanced.get_shader())
+                             .vertex_binding(vec!(ModelVertex::vertex_input_binding(), ModelInstanceData::vertex_input_binding()))
+                             .vertex_attributes(attributes);
+        } else {
+          pipeline = pipeline.vertex_shader(*vertex_shader.get_shader())
+                             .push_constants(ShaderStage::Vertex, push_constant_size)
+                             .vertex_binding(vec!(ModelVertex::vertex_input_binding()))
+                             .vertex_attributes(ModelVertex::vertex_input_attributes());
+        }
+        
+        pipeline = if *double_sided { pipeline.cull_mode_none() } else { pipeline.cull_mode_back() };
+        
+        pipelines.push(pipeline.build(Arc::clone(&device)));
+      }
+    }
+    
+    let instanced_double_pipeline = pipelines.remove(3);
+    let instanced_pipeline = pipelines.remove(2);
+    let double_pipeline = pipelines.remove(1);
+    let pipeline = pipelines.remove(0);
+    
+    (pipeline, double_pipeline, instanced_pipeline, instanced_double_pipeline)
+  }
+  
+  /**
+  ** Depth only pipelines for the shadow atlas, both sides are drawn so
+  ** thin and open meshes still cast shadows.
+  **/
+  fn create_shadow_pipelines(device: Arc<Device>, vertex_shader: &Shader, vertex_shader_instanced: &Shader, fragment_shader: &Shader, render_pass: &RenderPass, descriptor_set: &DescriptorSet) -> (Pipeline, Pipeline) {
+    let push_constant_size = ModelShader::shadow_push_constant_size();
+    
+    let instanced_push_constant_size = ModelShader::shadow_instanced_push_constant_size();
+    
+    let pipeline = PipelineBuilder::new()
+                  .vertex_shader(*vertex_shader.get_shader())
+                  .fragment_shader(*fragment_shader.get_shader())
+                  .push_constants(ShaderStage::Vertex, push_constant_size)
+                  .render_pass(render_pass.clone())
+                  .descriptor_set_layout(descriptor_set.layouts_clone())
+                  .vertex_binding(vec!(ModelVertex::vertex_input_binding()))
+                  .vertex_attributes(ModelVertex::vertex_input_attributes())
+                  .topology_triangle_list()
+                  .polygon_mode_fill()
+                  .enable_depth_write()
+                  .enable_depth_test()
+                  .cull_mode_none()
+                  .front_face_counter_clockwise()
+                  .build(Arc::clone(&device));
+    
+    let mut attributes = ModelVertex::vertex_input_attributes();
+    attributes.append(&mut ModelInstanceData::vertex_input_attributes());
+    
+    let instanced_pipeline = PipelineBuilder::new()
+                  .vertex_shader(*vertex_shader_instanced.get_shader())
+                  .fragment_shader(*fragment_shader.get_shader())
+                  .push_constants(ShaderStage::Vertex, instanced_push_constant_size)
+                  .render_pass(render_pass.clone())
+                  .descriptor_set_layout(descriptor_set.layouts_clone())
+                  .vertex_binding(vec!(ModelVertex::vertex_input_binding(), ModelInstanceData::vertex_input_binding()))
+                  .vertex_attributes(attributes)
+                  .topology_triangle_list()
+                  .polygon_mode_fill()
+                  .enable_depth_write()
+                  .enable_depth_test()
+                  .cull_mode_none()
+                  .front_face_counter_clockwise()
+                  .build(Arc::clone(&device));
+    
+    (pipeline, instanced_pipeline)
+  }
   /*
   pub fn create_index_buffer(instance: Arc<Instance>, device: Arc<Device>, command_pool: &CommandPool, graphics_queue: &vk::Queue) -> Buffer<u32> {
     let indices = vec!(0, 3, 2, 2, 1, 0, // back side
@@ -1116,7 +2114,7 @@ impl ModelShader {
     Buffer::<ModelVertex>::device_local_buffer_with_data(Arc::clone(&instance), Arc::clone(&device), command_pool, graphics_queue, usage, cube)
   }*/
   
-  fn create_frame_buffers(instance: Arc<Instance>, device: Arc<Device>, render_pass: &RenderPass, swapchain_extent: &vk::Extent2D, format: &vk::Format, msaa: &SampleCount, num_image_views: usize, command_pool: &CommandPool, graphics_queue: &vk::Queue) -> (Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<Framebuffer>) {
+  fn create_frame_buffers(instance: Arc<Instance>, device: Arc<Device>, render_pass: &RenderPass, swapchain_extent: &vk::Extent2D, format: &vk::Format, msaa: &SampleCount, num_image_views: usize, command_pool: &CommandPool, graphics_queue: &vk::Queue) -> (Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<ImageAttachment>, Vec<Framebuffer>) {
     
     let mut framebuffer_colour_images = Vec::with_capacity(num_image_views);
     let mut framebuffer_albedo_colour_images = Vec::with_capacity(num_image_views);
@@ -1124,6 +2122,7 @@ impl ModelShader {
     let mut framebuffer_emissive_images = Vec::with_capacity(num_image_views);
     let mut framebuffer_normal_images = Vec::with_capacity(num_image_views);
     let mut framebuffer_position_images = Vec::with_capacity(num_image_views);
+    let mut framebuffer_velocity_images = Vec::with_capacity(num_image_views);
     let mut framebuffer_msaa_images = Vec::with_capacity(num_image_views);
     let mut framebuffer_depth_images = Vec::with_capacity(num_image_views);
     let mut framebuffer_msaa_depth_images = Vec::with_capacity(num_image_views);
@@ -1132,11 +2131,12 @@ impl ModelShader {
     let mut framebuffer_msaa_emissive_images = Vec::with_capacity(num_image_views);
     let mut framebuffer_msaa_normal_images = Vec::with_capacity(num_image_views);
     let mut framebuffer_msaa_position_images = Vec::with_capacity(num_image_views);
+    let mut framebuffer_msaa_velocity_images = Vec::with_capacity(num_image_views);
     
     for _ in 0..num_image_views {
-      framebuffer_colour_images.push(ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::colour_attachment_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, format, swapchain_extent.width as u32, swapchain_extent.height as u32));
+      framebuffer_colour_images.push(ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::colour_attachment_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &HDR_FORMAT, swapchain_extent.width as u32, swapchain_extent.height as u32));
       
-      framebuffer_albedo_colour_images.push(ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::colour_input_attachment_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, format, swapchain_extent.width as u32, swapchain_extent.height as u32));
+      framebuffer_albedo_colour_images.push(ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::colour_input_attachment_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &HDR_FORMAT, swapchain_extent.width as u32, swapchain_extent.height as u32));
       
       framebuffer_mro_images.push(ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::colour_input_attachment_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, format, swapchain_extent.width as u32, swapchain_extent.height as u32));
       
@@ -1148,12 +2148,14 @@ impl ModelShader {
       
       framebuffer_position_images.push(ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::colour_input_attachment_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &vk::FORMAT_R16G16B16A16_SFLOAT, swapchain_extent.width as u32, swapchain_extent.height as u32));
       
+      framebuffer_velocity_images.push(ImageAttachment::create_image_colour_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::colour_input_attachment_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &VELOCITY_FORMAT, swapchain_extent.width as u32, swapchain_extent.height as u32));
+      
       if msaa != &SampleCount::OneBit {
-        framebuffer_msaa_images.push(ImageAttachment::create_image_msaa_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::transient_colour_input_attachment(), &ImageLayout::Undefined, &ImageLayout::ColourAttachmentOptimal, &ImageAspect::Colour, msaa, &ImageViewType::Type2D, format, command_pool, graphics_queue, swapchain_extent.width as u32, swapchain_extent.height as u32));
+        framebuffer_msaa_images.push(ImageAttachment::create_image_msaa_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::transient_colour_input_attachment(), &ImageLayout::Undefined, &ImageLayout::ColourAttachmentOptimal, &ImageAspect::Colour, msaa, &ImageViewType::Type2D, &HDR_FORMAT, command_pool, graphics_queue, swapchain_extent.width as u32, swapchain_extent.height as u32));
         
         framebuffer_msaa_depth_images.push(ImageAttachment::create_image_depth_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::depth_stencil_input_attachment(), &ImageLayout::Undefined, msaa, &ImageViewType::Type2D, &vk::FORMAT_D32_SFLOAT, swapchain_extent.width as u32, swapchain_extent.height as u32));
         
-        framebuffer_msaa_albedo_colour_images.push(ImageAttachment::create_image_msaa_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::transient_colour_input_attachment(), &ImageLayout::Undefined, &ImageLayout::ColourAttachmentOptimal, &ImageAspect::Colour, msaa, &ImageViewType::Type2D, format, command_pool, graphics_queue, swapchain_extent.width as u32, swapchain_extent.height as u32));
+        framebuffer_msaa_albedo_colour_images.push(ImageAttachment::create_image_msaa_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::transient_colour_input_attachment(), &ImageLayout::Undefined, &ImageLayout::ColourAttachmentOptimal, &ImageAspect::Colour, msaa, &ImageViewType::Type2D, &HDR_FORMAT, command_pool, graphics_queue, swapchain_extent.width as u32, swapchain_extent.height as u32));
         
         framebuffer_msaa_mro_images.push(ImageAttachment::create_image_msaa_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::transient_colour_input_attachment(), &ImageLayout::Undefined, &ImageLayout::ColourAttachmentOptimal, &ImageAspect::Colour, msaa, &ImageViewType::Type2D, format, command_pool, graphics_queue, swapchain_extent.width as u32, swapchain_extent.height as u32));
         
@@ -1162,6 +2164,8 @@ impl ModelShader {
         framebuffer_msaa_normal_images.push(ImageAttachment::create_image_msaa_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::transient_colour_input_attachment(), &ImageLayout::Undefined, &ImageLayout::ColourAttachmentOptimal, &ImageAspect::Colour, msaa, &ImageViewType::Type2D, &vk::FORMAT_R8G8B8A8_SNORM, command_pool, graphics_queue, swapchain_extent.width as u32, swapchain_extent.height as u32));
         
         framebuffer_msaa_position_images.push(ImageAttachment::create_image_msaa_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::transient_colour_input_attachment(), &ImageLayout::Undefined, &ImageLayout::ColourAttachmentOptimal, &ImageAspect::Colour, msaa, &ImageViewType::Type2D, &vk::FORMAT_R16G16B16A16_SFLOAT, command_pool, graphics_queue, swapchain_extent.width as u32, swapchain_extent.height as u32));
+        
+        framebuffer_msaa_velocity_images.push(ImageAttachment::create_image_msaa_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::transient_colour_input_attachment(), &ImageLayout::Undefined, &ImageLayout::ColourAttachmentOptimal, &ImageAspect::Colour, msaa, &ImageViewType::Type2D, &VELOCITY_FORMAT, command_pool, graphics_queue, swapchain_extent.width as u32, swapchain_extent.height as u32));
       }
     }
     
@@ -1187,7 +2191,9 @@ impl ModelShader {
       
       temp_image_views.push(framebuffer_position_images[i].get_image_view().clone());
       
-      if msaa != &SampleCount::OneBit {
+      if msaa == &SampleCount::OneBit {
+        temp_image_views.push(framebuffer_velocity_images[i].get_image_view().clone());
+      } else {
         temp_image_views.push(framebuffer_msaa_images[i].get_image_view().clone());
         temp_image_views.push(framebuffer_msaa_depth_images[i].get_image_view().clone());
         temp_image_views.push(framebuffer_msaa_albedo_colour_images[i].get_image_view().clone());
@@ -1195,6 +2201,8 @@ impl ModelShader {
         temp_image_views.push(framebuffer_msaa_emissive_images[i].get_image_view().clone());
         temp_image_views.push(framebuffer_msaa_normal_images[i].get_image_view().clone());
         temp_image_views.push(framebuffer_msaa_position_images[i].get_image_view().clone());
+        temp_image_views.push(framebuffer_velocity_images[i].get_image_view().clone());
+        temp_image_views.push(framebuffer_msaa_velocity_images[i].get_image_view().clone());
       }
       
       let framebuffer: Framebuffer = Framebuffer::new_with_imageviews(Arc::clone(&device), 
@@ -1213,19 +2221,71 @@ impl ModelShader {
        framebuffer_remaining_msaa_images.push(framebuffer_msaa_emissive_images[i].clone());
        framebuffer_remaining_msaa_images.push(framebuffer_msaa_normal_images[i].clone());
        framebuffer_remaining_msaa_images.push(framebuffer_msaa_position_images[i].clone());
+       framebuffer_remaining_msaa_images.push(framebuffer_msaa_velocity_images[i].clone());
       }
     }
     
     (framebuffer_colour_images, framebuffer_albedo_colour_images, framebuffer_mro_images, 
-     framebuffer_emissive_images, framebuffer_normal_images, framebuffer_position_images, 
+     framebuffer_emissive_images, framebuffer_normal_images, framebuffer_position_images, framebuffer_velocity_images, 
      framebuffer_msaa_images, framebuffer_depth_images, framebuffer_msaa_depth_images, framebuffer_remaining_msaa_images, framebuffers)
   }
   
+  // before any pass of the frame once the camera is set
+  pub fn begin_frame(&mut self, device: Arc<Device>, current_buffer: usize, window_width: f32, window_height: f32, descriptor_set_pool: &DescriptorPool) {
+    // same projection as VkModel.vert
+    let fov = FOV;
+    let aspect = window_width / window_height;
+    self.frustum = self.camera.get_frustum(fov, aspect, 0.1, 1080.0);
+    self.previous_view_projection = self.view_projection;
+    self.view_projection = ModelShader::view_projection(&self.camera, fov, aspect);
+    self.stats = FrameStats::default();
+    
+    let mut camera = UniformData::new()
+                   .add_matrix4(self.view_projection)
+                   .add_matrix4(self.previous_view_projection);
+    self.camera_buffer.fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, camera.build_non_aligned());
+    self.frame = current_buffer;
+    self.model_motion.next_frame();
+    self.instance_motion.next_frame();
+    
+    for (_, material) in self.retired_material_overrides.iter().filter(|(frame, _)| *frame == current_buffer) {
+      material.free(Arc::clone(&device), descriptor_set_pool);
+    }
+    self.retired_material_overrides.retain(|(frame, _)| *frame != current_buffer);
+    
+    for (_, buffer) in self.retired_light_buffers.iter().filter(|(frame, _)| *frame == current_buffer) {
+      buffer.destroy(Arc::clone(&device));
+    }
+    self.retired_light_buffers.retain(|(frame, _)| *frame != current_buffer);
+    self.write_material_overrides(Arc::clone(&device), current_buffer);
+    
+    for (_, lods) in self.instanced_cpu_buffers.iter_mut() {
+      for instances in lods {
+        instances.begin_frame(Arc::clone(&device), current_buffer);
+      }
+    }
+    
+    if let Some(instance_culling) = &mut self.instance_culling {
+      let (c_pos, c_center, c_up) = self.camera.get_look_at();
+      let view = ClusterView::new(c_pos, c_center, c_up, fov, aspect);
+      instance_culling.begin_frame(Arc::clone(&device), current_buffer, &self.frustum, view, fov);
+    }
+  }
+  
+  pub fn gpu_culling_enabled(&self) -> bool {
+    self.instance_culling.is_some()
+  }
+  
   pub fn begin_renderpass(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, clear_value: &Vec<vk::ClearValue>, window_size: &vk::Extent2D, current_buffer: usize) -> CommandBufferBuilder {
+    let cmd = self.ambient_occlusion.prepare(Arc::clone(&device), cmd);
+    let mut cmd = self.bloom.prepare(Arc::clone(&device), cmd);
+    if let Some(anti_aliasing) = &mut self.anti_aliasing {
+      cmd = anti_aliasing.prepare(Arc::clone(&device), cmd);
+    }
     cmd.begin_render_pass(Arc::clone(&device), clear_value, &self.renderpass, &self.framebuffers[current_buffer].internal_object(), &window_size)
   }
   
-  pub fn add_instanced_buffer(&mut self, instance: Arc<Instance>, device: Arc<Device>, image_views: u32, model_reference: String) {
+  pub fn add_instanced_buffer(&mut self, instance: Arc<Instance>, device: Arc<Device>, image_views: u32, model_reference: String, descriptor_set_pool: &DescriptorPool) {
     
     for i in 0..self.instanced_cpu_buffers.len() {
       if self.instanced_cpu_buffers[i].0 == model_reference.to_string() {
@@ -1233,72 +2293,239 @@ impl ModelShader {
       }
     }
     
-    let mut instanced_data = Vec::with_capacity(MAX_INSTANCES*16);
-    for _ in 0..(MAX_INSTANCES*16) {
-      instanced_data.push(0.0);
+    let usage = if self.instance_culling.is_some() { BufferUsage::vertex_storage_buffer() } else { BufferUsage::vertex_transfer_src_buffer() };
+    
+    // the gpu picks the lods of culled instances so they all go in the first
+    let instanced_cpu_buffers = (0..=SHADOW_CASTERS).map(|_| InstanceBuffer::new(Arc::clone(&instance), Arc::clone(&device), usage.clone(), image_views, INSTANCE_SIZE, INSTANCE_CAPACITY)).collect::<Vec<InstanceBuffer>>();
+    
+    if let Some(instance_culling) = &mut self.instance_culling {
+      instance_culling.add_buffer(Arc::clone(&instance), Arc::clone(&device), descriptor_set_pool, model_reference.to_string(), instanced_cpu_buffers[0].buffer());
     }
     
-    let usage = BufferUsage::vertex_transfer_src_buffer();
-    let instanced_cpu_buffer = Buffer::cpu_buffer_with_data(Arc::clone(&instance), Arc::clone(&device), usage, image_views, instanced_data);
+    self.instanced_cpu_buffers.push((model_reference, instanced_cpu_buffers));
+  }
+  
+  /**
+  ** Picks a lod from the fraction of the screen height the model's bounding
+  ** sphere covers, each entry in LOD_SCREEN_SIZES it falls below drops a level.
+  **/
+  fn lod(&self, model: &Model, position: Vector3<f32>, max_scale: f32, fov: f32) -> usize {
+    if model.num_lods <= 1 {
+      return 0;
+    }
+    
+    let (c_pos, _, _) = self.camera.get_look_at();
+    let distance = (position - c_pos).magnitude().max(0.0001);
+    let screen_size = model.radius*max_scale / (distance*(fov.to_radians()*0.5).tan());
     
-    self.instanced_cpu_buffers.push((model_reference, instanced_cpu_buffer));
-    self.instanced_cpu_data.push(UniformData::new());
+    let lod = LOD_SCREEN_SIZES.iter().filter(|size| screen_size < **size).count();
+    lod.min(model.num_lods-1)
+  }
+  
+  pub fn frame_stats(&self) -> FrameStats {
+    self.stats
   }
   
-  pub fn draw_model(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>, model_reference: String, hologram: bool, window_width: f32, window_height: f32, _delta_time: f32) -> CommandBufferBuilder {
+  /**
+  ** If a model space bounding sphere is inside the camera frustum once it is
+  ** placed by the model matrix.
+  **/
+  fn visible(&self, sphere: (Vector3<f32>, f32), model: &Matrix4<f32>) -> bool {
+    let (centre, radius) = sphere;
+    let (_, max_scale) = ModelShader::placement(model);
+    
+    self.frustum.contains_sphere((model * centre.extend(1.0)).truncate(), radius*max_scale)
+  }
+  
+  // the projection and view VkModel.vert places vertices with
+  fn view_projection(camera: &PerspectiveCamera, fov: f32, aspect: f32) -> Matrix4<f32> {
+    let (c_pos, c_center, c_up) = camera.get_look_at();
+    
+    cgmath::perspective(Deg(fov), aspect, 0.1, 1080.0) * Matrix4::look_at(Point3::from_vec(c_pos), Point3::from_vec(c_center), c_up)
+  }
+  
+  /**
+  ** Where a model matrix puts the model and the most it scales it by, the
+  ** largest length of its rows and columns. That is exact for a rotation and
+  ** a scale in either order, the same as instance_cull.comp.
+  **/
+  fn placement(model: &Matrix4<f32>) -> (Vector3<f32>, f32) {
+    let mut max_scale: f32 = 0.0;
+    for i in 0..3 {
+      max_scale = max_scale.max(model[i].truncate().magnitude()).max(model.row(i).truncate().magnitude());
+    }
+    
+    (model.w.truncate(), max_scale)
+  }
+  
+  // the rows of an affine model matrix, as VkModel.vert and the instances take them
+  fn model_rows(model: &Matrix4<f32>) -> [Vector4<f32>; 3] {
+    [model.row(0), model.row(1), model.row(2)]
+  }
+  
+  // the push constants of VkModel.vert and VkModelForward, every single model pipeline is made with their size
+  fn model_push_constants(model: &Matrix4<f32>, previous_model: &Matrix4<f32>, hologram: Vector4<f32>) -> UniformData {
+    let [model_x, model_y, model_z] = ModelShader::model_rows(model);
+    let [previous_x, previous_y, previous_z] = ModelShader::model_rows(previous_model);
+    UniformData::new()
+      .add_vector4(model_x)
+      .add_vector4(model_y)
+      .add_vector4(model_z)
+      .add_vector4(previous_x)
+      .add_vector4(previous_y)
+      .add_vector4(previous_z)
+      .add_vector4(hologram)
+  }
+  
+  // the push constants of VkModelShadow.vert
+  fn shadow_push_constants(light_view_projection: Matrix4<f32>, model: &Matrix4<f32>) -> UniformData {
+    let [model_x, model_y, model_z] = ModelShader::model_rows(model);
+    UniformData::new()
+      .add_matrix4(light_view_projection)
+      .add_vector4(model_x)
+      .add_vector4(model_y)
+      .add_vector4(model_z)
+  }
+  
+  // the push constants of VkModelShadowInstanced.vert, the model matrices are in the instances
+  fn shadow_instanced_push_constants(light_view_projection: Matrix4<f32>) -> UniformData {
+    UniformData::new()
+      .add_matrix4(light_view_projection)
+  }
+  
+  fn model_push_constant_size() -> u32 {
+    ModelShader::model_push_constants(&Matrix4::from_scale(1.0), &Matrix4::from_scale(1.0), Vector4::new(0.0, 0.0, 0.0, 0.0)).size_non_aligned() as u32
+  }
+  
+  fn shadow_push_constant_size() -> u32 {
+    ModelShader::shadow_push_constants(Matrix4::from_scale(1.0), &Matrix4::from_scale(1.0)).size_non_aligned() as u32
+  }
+  
+  fn shadow_instanced_push_constant_size() -> u32 {
+    ModelShader::shadow_instanced_push_constants(Matrix4::from_scale(1.0)).size_non_aligned() as u32
+  }
+  
+  // the model matrix of an instance written by push_instance
+  fn instance_model(values: &[f32]) -> Matrix4<f32> {
+    Matrix4::new(values[0], values[4], values[8],  0.0,
+                 values[1], values[5], values[9],  0.0,
+                 values[2], values[6], values[10], 0.0,
+                 values[3], values[7], values[11], 1.0)
+  }
+  
+  // the gbuffer pipeline a primitive is drawn with
+  fn primitive_pipeline(&self, model: &Model, primitive: usize, instanced: bool) -> &Pipeline {
+    match (self.debug_view, &self.wireframe_pipelines) {
+      (DebugView::Wireframe, Some((pipeline, instanced_pipeline))) => {
+        return if instanced { instanced_pipeline } else { pipeline };
+      },
+      (DebugView::Overdraw, _) => {
+        let (pipeline, instanced_pipeline) = &self.overdraw_pipelines;
+        return if instanced { instanced_pipeline } else { pipeline };
+      },
+      _ => {},
+    }
+    
+    let double_sided = model.double_sided[primitive];
+    let coverage = self.alpha_to_coverage && model.masked(primitive);
+    
+    match (instanced, coverage, double_sided) {
+      (false, false, false) => &self.pipeline,
+      (false, false, true) => &self.double_pipeline,
+      (false, true, false) => &self.coverage_pipeline,
+      (false, true, true) => &self.coverage_double_pipeline,
+      (true, false, false) => &self.instanced_pipeline,
+      (true, false, true) => &self.instanced_double_pipeline,
+      (true, true, false) => &self.instanced_coverage_pipeline,
+      (true, true, true) => &self.instanced_coverage_double_pipeline,
+    }
+  }
+  
+  // must be the first draw of the model pass as it doesn't test depth
+  pub fn draw_skybox(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, window_width: f32, window_height: f32) -> CommandBufferBuilder {
+    // the sky would count as a layer of overdraw
+    if self.debug_view == DebugView::Overdraw {
+      return cmd;
+    }
+    
+    let fov = FOV;
+    let aspect = window_width / window_height;
+    let (c_pos, c_center, c_up) = self.camera.get_look_at();
+    let view = ClusterView::new(c_pos, c_center, c_up, fov, aspect);
+    
+    self.skybox.draw(Arc::clone(&device), cmd, &view)
+  }
+  
+  pub fn draw_model(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, model: Matrix4<f32>, model_reference: String, hologram: bool, material: Option<String>, _window_width: f32, _window_height: f32, _delta_time: f32) -> CommandBufferBuilder {
     let mut cmd = cmd;
     
     if self.models.len() == 0 {
       return cmd;
     }
     
+    let previous_model = self.model_motion.push(&model_reference, model);
+    
     for i in 0..self.models.len() {
       if self.models[i].reference != model_reference {
         continue;
       }
       
-      let fov = 60.0;
-      let aspect = window_width / window_height;
-      let (c_pos, c_center, c_up) = self.camera.get_look_at();
-      
-      let camera_position    = Vector4::new(c_pos.x,    c_pos.y,    c_pos.z,    fov);
-      let camera_center      = Vector4::new(c_center.x, c_center.y, c_center.z, aspect);
-      let camera_up          = Vector4::new(c_up.x,     c_up.y,     c_up.z,     scale.x);
-      let model              = Vector4::new(position.x, position.y, position.z, scale.y);
-      let rotation           = Vector4::new(rotation.x, rotation.y, rotation.z, scale.z);
+      let fov = FOV;
+      let (c_pos, _, _) = self.camera.get_look_at();
+      let (position, max_scale) = ModelShader::placement(&model);
       let hologram           = Vector4::new(if hologram { 1.0 } else { -1.0 }, self.scanline, 0.0, 0.0);
       
+      let lod = self.lod(&self.models[i], position, max_scale, fov);
+      let distance = (position - c_pos).magnitude();
+      
       for j in 0..self.models[i].vertex_buffers.len() {
+        if !self.visible(self.models[i].bounding_spheres[j], &model) {
+          self.stats.primitives_culled += 1;
+          continue;
+        }
+        
+        if self.models[i].blended(j) {
+          self.stats.primitives_drawn += 1;
+          self.transparent_draws.push(TransparentDraw {
+            distance,
+            model: i,
+            primitive: j,
+            lod,
+            instance: TransparentInstance::Single(model, hologram),
+            material: material.clone(),
+          });
+          continue;
+        }
+        
         let vertex = &self.models[i].vertex_buffers[j];
         let vertex_count = self.models[i].vertex_count[j];
-        let index = &self.models[i].index_buffers[j];
-        let index_count = self.models[i].index_count[j];
+        let (index, index_count) = match self.models[i].lod_index_buffer(j, lod) {
+          Some(lod_index) => lod_index,
+          None => continue,
+        };
         
-        let descriptor = &self.models[i].descriptor_sets[j];
-       
-        let double_sided = self.models[i].double_sided[j];
+        self.stats.primitives_drawn += 1;
         
-        let push_constant_data = UniformData::new()
-                                 .add_vector4(camera_position)
-                                 .add_vector4(camera_center)
-                                 .add_vector4(camera_up)
-                                 .add_vector4(model)
-                                 .add_vector4(rotation)
-                                 .add_vector4(hologram);
+        let descriptor = self.primitive_descriptor_set(i, j, &material);
+        
+        let pipeline = self.primitive_pipeline(&self.models[i], j, false);
+        
+        let push_constant_data = ModelShader::model_push_constants(&model, &previous_model, hologram);
         
         cmd = cmd.push_constants(Arc::clone(&device), &self.pipeline, ShaderStage::Vertex, push_constant_data);
         
+        let descriptor_sets = vec!(descriptor, *self.camera_descriptor_set.set(self.frame));
         if index_count == 0 {
           cmd = cmd.draw(Arc::clone(&device), &vertex.internal_object(0), vertex_count, 
-                                 if double_sided { &self.double_pipeline } else { &self.pipeline },
-                                 vec!(*descriptor.set(0)),
+                                 pipeline,
+                                 descriptor_sets,
                                  Vec::with_capacity(0));
         } else {
           cmd = cmd.draw_indexed(Arc::clone(&device), &vertex.internal_object(0),
                                  &index.internal_object(0),
                                  index_count, 
-                                 if double_sided { &self.double_pipeline } else { &self.pipeline },
-                                 vec!(*descriptor.set(0)),
+                                 pipeline,
+                                 descriptor_sets,
                                  Vec::with_capacity(0));
         }
       }
@@ -1307,38 +2534,261 @@ impl ModelShader {
     cmd
   }
   
-  pub fn add_instanced_model(&mut self, position: Vector3<f32>, scale: Vector3<f32>, rotation: Vector3<f32>, colour: Vector4<f32>, model_reference: String, hologram: bool) {
+  /**
+  ** Material overrides of an instance are its numeric values only, texture
+  ** swaps need a named override on the draw_instanced of the buffer.
+  **/
+  pub fn add_instanced_model(&mut self, model: Matrix4<f32>, colour: Vector4<f32>, material: &MaterialOverride, model_reference: String, hologram: bool) {
     if self.models.len() == 0 {
       return;
     }
     
+    // before culling so the order instances are matched with last frame's doesn't change with the camera
+    let previous_model = self.instance_motion.push(&model_reference, model);
+    
+    // instances are bucketed by lod so each lod is one instanced draw, the gpu picks lods when culling
+    let (position, max_scale) = ModelShader::placement(&model);
+    let (lod, visible) = match self.models.iter().find(|m| m.reference == model_reference) {
+      Some(_) if self.instance_culling.is_some() => (0, true),
+      Some(details) => (self.lod(details, position, max_scale, FOV), self.visible(details.bounding_sphere, &model)),
+      None => (0, true),
+    };
+    
+    if !visible {
+      self.stats.instances_culled += 1;
+    }
+    if !visible && (hologram || !self.shadow_map.enabled()) {
+      return;
+    }
+    
+    let lod = if visible { lod } else { SHADOW_CASTERS };
     for i in 0..self.instanced_cpu_buffers.len() {
       if self.instanced_cpu_buffers[i].0 != model_reference {
         continue;
       }
       
-      let model              = Vector4::new(position.x, position.y, position.z, scale.x);
-      let rotation           = Vector4::new(rotation.x, rotation.y, rotation.z, scale.y);
-      let colour             = colour;
-      let hologram           = Vector4::new(if hologram { 1.0 } else { -1.0 }, self.scanline, 0.0, scale.z);
+      let [model_x, model_y, model_z] = ModelShader::model_rows(&model);
+      let [previous_x, previous_y, previous_z] = ModelShader::model_rows(&previous_model);
+      let (material_colour, material_emissive, material_factors) = material.instance_values();
+      let hologram           = Vector4::new(if hologram { 1.0 } else { -1.0 }, self.scanline, material_factors.x, material_factors.y);
+      
+      ModelShader::push_instance(&mut self.instanced_cpu_buffers[i].1, lod, [model_x, model_y, model_z, colour, hologram, material_colour, material_emissive, previous_x, previous_y, previous_z]);
+      if self.instance_culling.is_none() && visible {
+        self.stats.instances_drawn += 1;
+      }
+    }
+  }
+  
+  fn push_instance(lods: &mut [InstanceBuffer], lod: usize, instance: [Vector4<f32>; 10]) {
+    let mut values = [0.0; INSTANCE_SIZE];
+    for (i, vector) in instance.iter().enumerate() {
+      values[i*4..i*4+4].copy_from_slice(vector.as_ref() as &[f32; 4]);
+    }
       
-      let details = self.instanced_cpu_data[i].clone();
-      let data = details.clone();
-      self.instanced_cpu_data[i] = data
-                                    .add_vector4(model)
-                                    .add_vector4(rotation)
-                                    .add_vector4(colour)
-                                    .add_vector4(hologram);
+    lods[lod.min(SHADOW_CASTERS)].push(&values);
+  }
+  
+  /**
+  ** Grows the lods of an instanced buffer to fit the instances added this
+  ** frame, returns (lod, instance count) for each lod that has instances.
+  ** The culling pass reads the first lod so is pointed at it if it grew.
+  **/
+  fn upload_instances(&mut self, instance: Arc<Instance>, device: Arc<Device>, idx: usize) -> Vec<(usize, u32)> {
+    let (model_reference, lods) = &mut self.instanced_cpu_buffers[idx];
+    let mut lod_ranges = Vec::new();
+    for (lod, instances) in lods.iter_mut().enumerate() {
+      if instances.upload(Arc::clone(&instance), Arc::clone(&device)) && lod == 0 {
+        if let Some(instance_culling) = &self.instance_culling {
+          instance_culling.update_instance_buffer(Arc::clone(&device), model_reference, instances.buffer());
+        }
+      }
+      if !instances.is_empty() {
+        lod_ranges.push((lod, instances.len() as u32));
+      }
+    }
+      
+    lod_ranges
+  }
+  
+  /**
+  ** Uploads the instances added for a model this frame and culls them on the
+  ** gpu for draw_instanced, it must be recorded before the model pass starts.
+  ** Blended primitives are sorted on the cpu so their instances are culled
+  ** here and drawn in the forward subpass.
+  **/
+  pub fn cull_instances(&mut self, instance: Arc<Instance>, device: Arc<Device>, cmd: CommandBufferBuilder, model_reference: String, material: Option<String>) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    let idx = match self.instanced_cpu_buffers.iter().position(|(reference, _)| *reference == model_reference) {
+      Some(idx) => idx,
+      None => return cmd,
+    };
+    
+    self.upload_instances(Arc::clone(&instance), Arc::clone(&device), idx);
+    
+    let instances = &self.instanced_cpu_buffers[idx].1[0];
+    let data = instances.instances();
+    let current_buffer = instances.current_buffer();
+    
+    let i = match self.models.iter().position(|model| model.reference == model_reference) {
+      Some(i) => i,
+      None => return cmd,
+    };
+    
+    let model = &self.models[i];
+    let (c_pos, _, _) = self.camera.get_look_at();
+    
+    let mut transparent_draws = Vec::new();
+    for j in (0..model.vertex_buffers.len()).filter(|j| model.blended(*j)) {
+      for (instance, values) in data.chunks(INSTANCE_SIZE).enumerate() {
+        let instance_model = ModelShader::instance_model(values);
+        if !self.visible(model.bounding_spheres[j], &instance_model) {
+          continue;
+        }
+        
+        let (position, max_scale) = ModelShader::placement(&instance_model);
+        transparent_draws.push(TransparentDraw {
+          distance: (position - c_pos).magnitude(),
+          model: i,
+          primitive: j,
+          lod: self.lod(model, position, max_scale, FOV),
+          instance: TransparentInstance::Instanced(idx, 0, current_buffer, (instance * INSTANCE_SIZE * mem::size_of::<f32>()) as u64),
+          material: material.clone(),
+        });
+      }
+    }
+    
+    let lods = model.num_lods.min(MAX_LODS);
+    let primitives = model.vertex_buffers.len();
+    let mut index_counts = Vec::with_capacity(lods*primitives);
+    for lod in 0..lods {
+      for j in 0..primitives {
+        // blended primitives are left to the forward subpass and non indexed ones aren't drawn instanced
+        let index_count = match model.lod_index_buffer(j, lod) {
+          Some((_, count)) if !model.blended(j) => count,
+          _ => 0,
+        };
+        index_counts.push(index_count);
+      }
+    }
+    
+    let sphere = model.bounding_sphere;
+    let radius = model.radius;
+    let num_instances = (data.len() / INSTANCE_SIZE) as u32;
+    
+    self.transparent_draws.extend(transparent_draws);
+    self.instanced_cpu_buffers[idx].1[0].clear();
+    
+    if let Some(instance_culling) = &mut self.instance_culling {
+      let (culled_cmd, drawn, culled) = instance_culling.cull(Arc::clone(&instance), Arc::clone(&device), cmd, &model_reference, num_instances, sphere, radius, lods, index_counts);
+      cmd = culled_cmd;
+      self.stats.instances_drawn += drawn;
+      self.stats.instances_culled += culled;
+    }
+    
+    cmd
+  }
+  
+  // draws the instances cull_instances left with a draw for each lod and primitive
+  fn draw_instanced_indirect(&self, device: Arc<Device>, cmd: CommandBufferBuilder, model_reference: String, material: Option<String>) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    let (culled, commands, capacity) = match self.instance_culling.as_ref().and_then(|instance_culling| instance_culling.draw_buffers(&model_reference)) {
+      Some(buffers) => buffers,
+      None => return cmd,
+    };
+    
+    let i = match self.models.iter().position(|model| model.reference == model_reference) {
+      Some(i) => i,
+      None => return cmd,
+    };
+    
+    let model = &self.models[i];
+    let lods = model.num_lods.min(MAX_LODS);
+    let primitives = model.vertex_buffers.len();
+    for lod in 0..lods {
+      for j in 0..primitives {
+        if model.blended(j) {
+          continue;
+        }
+        
+        let (index, index_count) = match model.lod_index_buffer(j, lod) {
+          Some(lod_index) => lod_index,
+          None => continue,
+        };
+        
+        if index_count == 0 {
+          continue;
+        }
+        
+        let descriptor = self.primitive_descriptor_set(i, j, &material);
+        
+        let pipeline = self.primitive_pipeline(model, j, true);
+        
+        cmd = cmd.draw_instanced_indexed_indirect(Arc::clone(&device),
+                                                  model.vertex_buffers[j].internal_object(0),
+                                                  index.internal_object(0),
+                                                  culled,
+                                                  InstanceCulling::lod_offset(lod, capacity),
+                                                  commands,
+                                                  InstanceCulling::command_offset(lod, j, primitives),
+                                                  1,
+                                                  pipeline,
+                                                  vec!(descriptor, *self.camera_descriptor_set.set(self.frame)));
+      }
     }
+    
+    cmd
   }
   
-  pub fn draw_instanced(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, model_reference: String, window_width: f32, window_height: f32, _delta_time: f32) -> CommandBufferBuilder {
+  // after the model pass so the next frame's culls can test against this frame's depth
+  pub fn build_depth_pyramid(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize) -> CommandBufferBuilder {
+    match &mut self.instance_culling {
+      Some(instance_culling) => instance_culling.build_depth_pyramid(Arc::clone(&device), cmd, &self.framebuffer_position_images[current_buffer]),
+      None => cmd,
+    }
+  }
+  
+  // after the model pass, the next frame's deffered subpass reads it
+  pub fn build_ambient_occlusion(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize) -> CommandBufferBuilder {
+    self.ambient_occlusion.build(Arc::clone(&device), cmd, current_buffer, &self.framebuffer_position_images[current_buffer], &self.framebuffer_normal_images[current_buffer])
+  }
+  
+  pub fn build_anti_aliasing(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize) -> CommandBufferBuilder {
+    match &mut self.anti_aliasing {
+      Some(anti_aliasing) => anti_aliasing.build(Arc::clone(&device), cmd, current_buffer, &self.framebuffer_colour_images[current_buffer], &self.framebuffer_velocity_images[current_buffer]),
+      None => cmd,
+    }
+  }
+  
+  // sub pixel offset of the model pass viewport, only moves with taa
+  pub fn jitter(&self) -> Vector2<f32> {
+    match &self.anti_aliasing {
+      Some(anti_aliasing) => anti_aliasing.jitter(),
+      None => Vector2::new(0.0, 0.0),
+    }
+  }
+  
+  pub fn build_bloom(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize) -> CommandBufferBuilder {
+    self.bloom.build(Arc::clone(&device), cmd, current_buffer, &self.framebuffer_colour_images[current_buffer])
+  }
+  
+  // added over the model texture by the final shader, sampled in the general layout
+  pub fn get_bloom_texture(&self) -> ImageAttachment {
+    self.bloom.image().clone()
+  }
+  
+  pub fn draw_instanced(&mut self, instance: Arc<Instance>, device: Arc<Device>, cmd: CommandBufferBuilder, model_reference: String, material: Option<String>, _window_width: f32, _window_height: f32, _delta_time: f32) -> CommandBufferBuilder {
     let mut cmd = cmd;
     
     if self.models.len() == 0 || self.instanced_cpu_buffers.len() == 0 {
       return cmd;
     }
     
+    if self.instance_culling.is_some() {
+      return self.draw_instanced_indirect(Arc::clone(&device), cmd, model_reference, material);
+    }
+    
     let mut idx = 0;
     for j in 0..self.instanced_cpu_buffers.len() {
       if self.instanced_cpu_buffers[j].0 != model_reference {
@@ -1348,104 +2798,352 @@ impl ModelShader {
       idx = j
     };
     
-    let (model_reference, mut buffer) = self.instanced_cpu_buffers[idx].clone();
-    let mut instanced_data = self.instanced_cpu_data[idx].clone();
+    let model_reference = self.instanced_cpu_buffers[idx].0.to_string();
+    let lod_ranges = self.upload_instances(Arc::clone(&instance), Arc::clone(&device), idx);
     
-    let data = instanced_data.build(Arc::clone(&device));
-    let num_instances = (data.len() as f32 / 16.0) as u32;
-    
-    if num_instances == 0 {
+    if lod_ranges.is_empty() {
       return cmd;
     }
     
-    buffer.fill_entire_buffer_single_frame(Arc::clone(&device), 0, data);
+    for i in 0..self.models.len() {
+      if self.models[i].reference != model_reference {
+        continue;
+      }
+      
+      let (c_pos, _, _) = self.camera.get_look_at();
+      
+      for (lod, num_instances) in lod_ranges.iter().filter(|(lod, _)| *lod != SHADOW_CASTERS) {
+        let buffer = &self.instanced_cpu_buffers[idx].1[*lod];
+        let num_instances = *num_instances;
+        for j in 0..self.models[i].vertex_buffers.len() {
+          // each instance is sorted on its own so is drawn on its own
+          if self.models[i].blended(j) {
+            for (instance, values) in buffer.instances().chunks(INSTANCE_SIZE).enumerate() {
+              let position = Vector3::new(values[3], values[7], values[11]);
+              self.transparent_draws.push(TransparentDraw {
+                distance: (position - c_pos).magnitude(),
+                model: i,
+                primitive: j,
+                lod: *lod,
+                instance: TransparentInstance::Instanced(idx, *lod, buffer.current_buffer(), (instance * INSTANCE_SIZE * mem::size_of::<f32>()) as u64),
+                material: material.clone(),
+              });
+            }
+            continue;
+          }
+          
+          let vertex = &self.models[i].vertex_buffers[j];
+          let vertex_count = self.models[i].vertex_count[j];
+          let (index, index_count) = match self.models[i].lod_index_buffer(j, *lod) {
+            Some(lod_index) => lod_index,
+            None => continue,
+          };
+          
+          let descriptor = self.primitive_descriptor_set(i, j, &material);
+          
+          let pipeline = self.primitive_pipeline(&self.models[i], j, true);
+          
+          let descriptor_sets = vec!(descriptor, *self.camera_descriptor_set.set(self.frame));
+          if index_count == 0 {
+            
+            cmd = cmd.draw_instanced(Arc::clone(&device), 
+                                     &vertex.internal_object(0), 
+                                     buffer.internal_object(),
+                                     0,
+                                     vertex_count, 
+                                     num_instances,
+                                   pipeline,
+                                   descriptor_sets);
+            println!("Instanced draw Not indexed! Not Implemented!");
+          } else {
+            
+            cmd = cmd.draw_instanced_indexed(Arc::clone(&device), 
+                                         &vertex.internal_object(0),
+                                         &index.internal_object(0),
+                                         buffer.internal_object(),
+                                         0,
+                                         index_count,
+                                         num_instances,
+                                         pipeline,
+                                         descriptor_sets);
+          }
+        
+        }
+      }
+    }
+    
+    for instances in &mut self.instanced_cpu_buffers[idx].1 {
+      instances.clear();
+    }
+    
+    cmd
+  }
+  
+  /**
+  ** Fits the cascades to the camera, hands out the light shadow tiles,
+  ** uploads them for the deffered pass and starts the shadow render pass. The
+  ** pass always runs so the atlas is cleared and ready to sample even when
+  ** nothing casts a shadow.
+  **/
+  pub fn begin_shadow_pass(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize, window_width: f32, window_height: f32) -> CommandBufferBuilder {
+    let fov = FOV;
+    let aspect = window_width / window_height;
+    let (c_pos, c_center, c_up) = self.camera.get_look_at();
+    let view = ClusterView::new(c_pos, c_center, c_up, fov, aspect);
+    
+    let mut lights = self.lights.clone();
+    lights.push(self.light.clone());
+    self.shadow_map.update(&view, &mut lights);
+    self.frame_lights = lights;
+    self.shadow_map.fill_buffer(Arc::clone(&device), current_buffer);
+    
+    self.shadow_map.begin_renderpass(Arc::clone(&device), cmd)
+  }
+  
+  pub fn draw_model_shadow(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, model: Matrix4<f32>, model_reference: String, hologram: bool) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    if self.models.is_empty() || self.shadow_map.num_views() == 0 || hologram {
+      return cmd;
+    }
     
     for i in 0..self.models.len() {
       if self.models[i].reference != model_reference {
         continue;
       }
       
-      let fov = 60.0;
-      let aspect = window_width / window_height;
-      let (c_pos, c_center, c_up) = self.camera.get_look_at();
+      let (position, max_scale) = ModelShader::placement(&model);
       
-      let camera_position    = Vector4::new(c_pos.x,    c_pos.y,    c_pos.z,    fov);
-      let camera_center      = Vector4::new(c_center.x, c_center.y, c_center.z, aspect);
-      let camera_up          = Vector4::new(c_up.x,     c_up.y,     c_up.z,     0.0); // x, y, z, 
+      let lod = self.lod(&self.models[i], position, max_scale, FOV);
+      // the origin can sit anywhere in the mesh so twice the radius bounds it
+      let radius = self.models[i].radius*2.0*max_scale;
       
-      for j in 0..self.models[i].vertex_buffers.len() {
-        let vertex = &self.models[i].vertex_buffers[j];
-        let vertex_count = self.models[i].vertex_count[j];
-        let index = &self.models[i].index_buffers[j];
-        let index_count = self.models[i].index_count[j];
+      for view in 0..self.shadow_map.num_views() {
+        if !self.shadow_map.view_reaches(view, position, radius) {
+          continue;
+        }
         
-        let descriptor = &self.models[i].descriptor_sets[j];
-       
-        let double_sided = self.models[i].double_sided[j];
+        cmd = self.shadow_map.set_view_viewport(Arc::clone(&device), cmd, view);
         
-        let push_constant_data = UniformData::new()
-                                 .add_vector4(camera_position)
-                                 .add_vector4(camera_center)
-                                 .add_vector4(camera_up);
+        let push_constant_data = ModelShader::shadow_push_constants(self.shadow_map.view_matrix(view), &model);
         
-        cmd = cmd.push_constants(Arc::clone(&device), &self.pipeline, ShaderStage::Vertex, push_constant_data);
+        cmd = cmd.push_constants(Arc::clone(&device), &self.shadow_pipeline, ShaderStage::Vertex, push_constant_data);
         
-        if index_count == 0 {
+        for j in 0..self.models[i].vertex_buffers.len() {
+          // blended primitives would cast solid shadows
+          if self.models[i].blended(j) {
+            continue;
+          }
           
-          cmd = cmd.draw_instanced(Arc::clone(&device), 
-                                   &vertex.internal_object(0), 
-                                   &buffer.internal_object(0),
-                                   vertex_count, 
-                                   num_instances,
-                                 if double_sided { &self.instanced_double_pipeline } else { &self.instanced_pipeline },
-                                 vec!(*descriptor.set(0)));
-          println!("Instanced draw Not indexed! Not Implemented!");
-        } else {
+          let vertex = &self.models[i].vertex_buffers[j];
+          let vertex_count = self.models[i].vertex_count[j];
+          let (index, index_count) = match self.models[i].lod_index_buffer(j, lod) {
+            Some(lod_index) => lod_index,
+            None => continue,
+          };
+          
+          let descriptor = &self.models[i].descriptor_sets[j];
           
-          cmd = cmd.draw_instanced_indexed(Arc::clone(&device), 
-                                       &vertex.internal_object(0),
-                                       &index.internal_object(0),
-                                       &buffer.internal_object(0),
-                                       index_count,
+          if index_count == 0 {
+            cmd = cmd.draw(Arc::clone(&device), vertex.internal_object(0), vertex_count, 
+                                   &self.shadow_pipeline,
+                                   vec!(*descriptor.set(0)),
+                                   Vec::with_capacity(0));
+          } else {
+            cmd = cmd.draw_indexed(Arc::clone(&device), vertex.internal_object(0),
+                                   index.internal_object(0),
+                                   index_count, 
+                                   &self.shadow_pipeline,
+                                   vec!(*descriptor.set(0)),
+                                   Vec::with_capacity(0));
+          }
+        }
+      }
+    }
+    
+    cmd
+  }
+  
+  /**
+  ** Draws a model's instances into each shadow view from the instance
+  ** buffers of the main pass. Every instance is tested against each view on
+  ** the cpu and the ones that reach it are drawn in runs that share a lod.
+  **/
+  pub fn draw_instanced_shadow(&mut self, instance: Arc<Instance>, device: Arc<Device>, cmd: CommandBufferBuilder, model_reference: String) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    if self.models.is_empty() || self.shadow_map.num_views() == 0 {
+      return cmd;
+    }
+    
+    let idx = match self.instanced_cpu_buffers.iter().position(|(reference, _)| *reference == model_reference) {
+      Some(idx) => idx,
+      None => return cmd,
+    };
+    
+    let i = match self.models.iter().position(|model| model.reference == model_reference) {
+      Some(i) => i,
+      None => return cmd,
+    };
+    
+    let lod_ranges = self.upload_instances(Arc::clone(&instance), Arc::clone(&device), idx);
+    
+    let model = &self.models[i];
+    for (bucket, _) in &lod_ranges {
+      let buffer = &self.instanced_cpu_buffers[idx].1[*bucket];
+      
+      // lod, position and radius of each instance, holograms don't cast shadows
+      let casters: Vec<Option<(usize, Vector3<f32>, f32)>> = buffer.instances().chunks(INSTANCE_SIZE).map(|values| {
+        if values[16] > 0.0 {
+          return None;
+        }
+        
+        let (position, max_scale) = ModelShader::placement(&ModelShader::instance_model(values));
+        // the origin can sit anywhere in the mesh so twice the radius bounds it
+        Some((self.lod(model, position, max_scale, FOV), position, model.radius*2.0*max_scale))
+      }).collect();
+      
+      for view in 0..self.shadow_map.num_views() {
+        let mut runs: Vec<(usize, u32, usize)> = Vec::new(); // first instance, count, lod
+        for (instance, caster) in casters.iter().enumerate() {
+          let lod = match caster {
+            Some((lod, position, radius)) if self.shadow_map.view_reaches(view, *position, *radius) => *lod,
+            _ => continue,
+          };
+          
+          match runs.last_mut() {
+            Some((first, count, run_lod)) if *first + *count as usize == instance && *run_lod == lod => *count += 1,
+            _ => runs.push((instance, 1, lod)),
+          }
+        }
+        
+        if runs.is_empty() {
+          continue;
+        }
+        
+        cmd = self.shadow_map.set_view_viewport(Arc::clone(&device), cmd, view);
+        
+        let push_constant_data = ModelShader::shadow_instanced_push_constants(self.shadow_map.view_matrix(view));
+        
+        cmd = cmd.push_constants(Arc::clone(&device), &self.shadow_instanced_pipeline, ShaderStage::Vertex, push_constant_data);
+        
+        for (first, num_instances, lod) in runs {
+          let offset = (first * INSTANCE_SIZE * mem::size_of::<f32>()) as u64;
+          for j in 0..model.vertex_buffers.len() {
+            if model.blended(j) {
+              continue;
+            }
+            
+            let vertex = &model.vertex_buffers[j];
+            let vertex_count = model.vertex_count[j];
+            let (index, index_count) = match model.lod_index_buffer(j, lod) {
+              Some(lod_index) => lod_index,
+              None => continue,
+            };
+            
+            let descriptor = &model.descriptor_sets[j];
+            
+            if index_count == 0 {
+              cmd = cmd.draw_instanced(Arc::clone(&device), 
+                                       vertex.internal_object(0), 
+                                       buffer.internal_object(),
+                                       offset,
+                                       vertex_count, 
                                        num_instances,
-                                       if double_sided { &self.instanced_double_pipeline } else { &self.instanced_pipeline },
+                                       &self.shadow_instanced_pipeline,
                                        vec!(*descriptor.set(0)));
+            } else {
+              cmd = cmd.draw_instanced_indexed(Arc::clone(&device), 
+                                               vertex.internal_object(0),
+                                               index.internal_object(0),
+                                               buffer.internal_object(),
+                                               offset,
+                                               index_count,
+                                               num_instances,
+                                               &self.shadow_instanced_pipeline,
+                                               vec!(*descriptor.set(0)));
+            }
+          }
         }
-        
       }
     }
     
-    self.instanced_cpu_data[idx] = UniformData::new();
-    
     cmd
   }
   
-    pub fn draw_deffered(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize) -> CommandBufferBuilder {
+  /**
+  ** Recreates a light buffer at the next power of two when this frame's lights
+  ** or cluster indices don't fit, and points the deffered and forward
+  ** descriptor sets at it. Waits on the device so no frame still reads it.
+  **/
+  /**
+  ** Grows this frame's light buffers when the clusters don't fit. The frame's
+  ** fence has been waited on so only its sets are rewritten, the replaced
+  ** buffers are destroyed once the fence has signalled again.
+  **/
+  fn grow_light_buffers(&mut self, instance: Arc<Instance>, device: Arc<Device>, current_buffer: usize, clusters: &LightClusters) {
+    let fits = |buffer: &Buffer<f32>, len: usize| (len*mem::size_of::<f32>()) as u64 <= buffer.max_size();
+    if fits(&self.light_buffers[current_buffer], clusters.lights.len()) && fits(&self.light_cluster_buffers[current_buffer], clusters.clusters.len()) {
+      return;
+    }
+    
+    if !fits(&self.light_buffers[current_buffer], clusters.lights.len()) {
+      let buffer = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), 1, clusters.lights.len().next_power_of_two() as u64);
+      let old_buffer = mem::replace(&mut self.light_buffers[current_buffer], buffer);
+      self.retired_light_buffers.push((current_buffer, old_buffer));
+    }
+    if !fits(&self.light_cluster_buffers[current_buffer], clusters.clusters.len()) {
+      let buffer = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), 1, clusters.clusters.len().next_power_of_two() as u64);
+      let old_buffer = mem::replace(&mut self.light_cluster_buffers[current_buffer], buffer);
+      self.retired_light_buffers.push((current_buffer, old_buffer));
+    }
+    
+    UpdateDescriptorSets::new()
+      .add_storage_buffer(6, &self.light_buffers[current_buffer])
+      .add_storage_buffer(7, &self.light_cluster_buffers[current_buffer])
+     .finish_update_single_set(Arc::clone(&device), &self.deffered_descriptor_set, current_buffer);
+    
+    UpdateDescriptorSets::new()
+      .add_storage_buffer(0, &self.light_buffers[current_buffer])
+      .add_storage_buffer(1, &self.light_cluster_buffers[current_buffer])
+     .finish_update_single_set(Arc::clone(&device), &self.forward_descriptor_set, current_buffer);
+  }
+  
+    pub fn draw_deffered(&mut self, instance: Arc<Instance>, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize, window_width: f32, window_height: f32) -> CommandBufferBuilder {
       let mut cmd = cmd;
       
-      let (c_pos, _, _) = self.camera.get_look_at();
+      let fov = FOV;
+      let aspect = window_width / window_height;
+      let (c_pos, c_center, c_up) = self.camera.get_look_at();
+      let view = ClusterView::new(c_pos, c_center, c_up, fov, aspect);
       
-      let light1_position     = Vector4::new(0.0, 0.0, 0.0, 10.0); // x y z, intensity
-      let light1_colour       = Vector4::new(1.0, 0.0, 0.0, c_pos.x); // r, g, b, cam_x
-      let light2_position     = Vector4::new(0.0, 0.0, 0.0, 10.0); // x2 y2 z2 intensity2
-      let light2_colour       = Vector4::new(0.0, 1.0, 0.0, c_pos.y); // r2 g2 b2, cam_y
-      let light3_position     = Vector4::new(0.0, 0.0, 0.0, 10.0);//x3 y3 z3, intensity3
-      let light3_colour       = Vector4::new(0.0, 0.0, 1.0, c_pos.z); // r3, g3, b3, cam_z
+      let clusters = LightClusters::new(&self.frame_lights, &view);
+      // the lights are only known once begin_shadow_pass has given them their shadow tiles
+      self.grow_light_buffers(Arc::clone(&instance), Arc::clone(&device), current_buffer, &clusters);
+      
+      if clusters.num_lights > 0 {
+        self.light_buffers[current_buffer].fill_entire_buffer_single_frame(Arc::clone(&device), 0, clusters.lights);
+      }
+      self.light_cluster_buffers[current_buffer].fill_entire_buffer_single_frame(Arc::clone(&device), 0, clusters.clusters);
       
-      let sun_direction       = Vector4::new(0.0, 0.0, 0.0, 0.0);
-      let sun_colour          = Vector4::new(0.0, 0.0, 0.0, 0.0);
+      let camera_position     = view.position.extend(CLUSTER_NEAR); // x, y, z, near
+      let camera_forward      = view.forward.extend(CLUSTER_FAR); // x, y, z, far
+      let environment         = if self.environment_map.enabled() { 1.0 } else { 0.0 };
+      let light_counts        = Vector4::new(clusters.num_lights as f32, clusters.num_directional as f32, environment, self.environment_map.max_lod());
       
       let push_constant_data = UniformData::new()
-                                 .add_vector4(light1_position)
-                                 .add_vector4(light1_colour)
-                                 .add_vector4(light2_position)
-                                 .add_vector4(light2_colour)
-                                 .add_vector4(light3_position)
-                                 .add_vector4(light3_colour)
-                                 .add_vector4(sun_direction)
-                                 .add_vector4(sun_colour);
+                                 .add_vector4(camera_position)
+                                 .add_vector4(camera_forward)
+                                 .add_vector4(view.right)
+                                 .add_vector4(view.up)
+                                 .add_vector4(light_counts)
+                                 .add_vector4(Vector4::new(self.debug_view.to_index(), 0.0, 0.0, 0.0));
+      
+      self.forward_camera_buffer.fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, push_constant_data.clone().build_non_aligned());
+      self.ambient_occlusion.update_view(Arc::clone(&device), current_buffer, view);
+      self.fog.update(Arc::clone(&device), current_buffer);
     
-      cmd = cmd.push_constants(Arc::clone(&device), &self.pipeline_deffered, ShaderStage::Vertex, push_constant_data);
+      cmd = cmd.push_constants(Arc::clone(&device), &self.pipeline_deffered, ShaderStage::Fragment, push_constant_data);
       
       let index_count = 6;
       
@@ -1459,37 +3157,192 @@ impl ModelShader {
      cmd
   }
   
+  /**
+  ** Draws the blended primitives queued by draw_model and draw_instanced in
+  ** the forward subpass, furthest from the camera first so each one blends
+  ** over everything behind it.
+  **/
+  pub fn draw_transparent(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize, _window_width: f32, _window_height: f32) -> CommandBufferBuilder {
+    let mut cmd = cmd;
+    
+    let mut draws = mem::take(&mut self.transparent_draws);
+    if self.debug_view != DebugView::Lit {
+      return cmd;
+    }
+    
+    draws.sort_by(|a, b| b.distance.partial_cmp(&a.distance).unwrap_or(Ordering::Equal));
+    
+    for draw in &draws {
+      if draw.model >= self.models.len() {
+        continue;
+      }
+      
+      let model = &self.models[draw.model];
+      let vertex = &model.vertex_buffers[draw.primitive];
+      let vertex_count = model.vertex_count[draw.primitive];
+      let (index, index_count) = match model.lod_index_buffer(draw.primitive, draw.lod) {
+        Some(lod_index) => lod_index,
+        None => continue,
+      };
+      
+      let descriptor_sets = vec!(self.primitive_descriptor_set(draw.model, draw.primitive, &draw.material), *self.camera_descriptor_set.set(self.frame), *self.forward_descriptor_set.set(current_buffer));
+      let double_sided = model.double_sided[draw.primitive];
+      
+      match draw.instance {
+        TransparentInstance::Single(model, hologram) => {
+          let pipeline = if double_sided { &self.forward_double_pipeline } else { &self.forward_pipeline };
+          
+          // the forward subpass writes no velocity
+          let push_constant_data = ModelShader::model_push_constants(&model, &model, hologram);
+          
+          cmd = cmd.push_constants(Arc::clone(&device), pipeline, ShaderStage::Vertex, push_constant_data);
+          
+          if index_count == 0 {
+            cmd = cmd.draw(Arc::clone(&device), vertex.internal_object(0), vertex_count, 
+                                   pipeline,
+                                   descriptor_sets,
+                                   Vec::with_capacity(0));
+          } else {
+            cmd = cmd.draw_indexed(Arc::clone(&device), vertex.internal_object(0),
+                                   index.internal_object(0),
+                                   index_count, 
+                                   pipeline,
+                                   descriptor_sets,
+                                   Vec::with_capacity(0));
+          }
+        },
+        TransparentInstance::Instanced(buffer, lod, frame, instance_offset) => {
+          let pipeline = if double_sided { &self.forward_instanced_double_pipeline } else { &self.forward_instanced_pipeline };
+          let buffer = self.instanced_cpu_buffers[buffer].1[lod].buffer();
+          
+          if index_count == 0 {
+            cmd = cmd.draw_instanced(Arc::clone(&device), 
+                                     vertex.internal_object(0), 
+                                     buffer.internal_object(frame),
+                                     instance_offset,
+                                     vertex_count, 
+                                     1,
+                                     pipeline,
+                                     descriptor_sets);
+          } else {
+            cmd = cmd.draw_instanced_indexed(Arc::clone(&device), 
+                                             vertex.internal_object(0),
+                                             index.internal_object(0),
+                                             buffer.internal_object(frame),
+                                             instance_offset,
+                                             index_count,
+                                             1,
+                                             pipeline,
+                                             descriptor_sets);
+          }
+        },
+      }
+    }
+     
+    cmd
+  }
+  
+  // the debug shapes added this frame, after draw_transparent in the forward subpass
+  pub fn draw_debug(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize, window_width: f32, window_height: f32) -> CommandBufferBuilder {
+    let fov = FOV;
+    let aspect = window_width / window_height;
+    let (c_pos, c_center, c_up) = self.camera.get_look_at();
+    
+    let push_constant_data = UniformData::new()
+                               .add_vector4(Vector4::new(c_pos.x,    c_pos.y,    c_pos.z,    fov))
+                               .add_vector4(Vector4::new(c_center.x, c_center.y, c_center.z, aspect))
+                               .add_vector4(Vector4::new(c_up.x,     c_up.y,     c_up.z,     0.0));
+    
+    self.debug_lines.draw(Arc::clone(&device), cmd, current_buffer, push_constant_data, &self.descriptor_sets[0])
+  }
+  
   pub fn destroy(&mut self, device: Arc<Device>) {
     self.index_buffer_deffered.destroy(Arc::clone(&device));
     self.vertex_buffer_deffered.destroy(Arc::clone(&device));
     
-    for (_data, buffer) in &self.instanced_cpu_buffers {
-      buffer.destroy(Arc::clone(&device));
+    for (_reference, lods) in self.instanced_cpu_buffers.iter() {
+      for instances in lods {
+        instances.destroy(Arc::clone(&device));
+      }
+    }
+    
+    if let Some(instance_culling) = &self.instance_culling {
+      instance_culling.destroy(Arc::clone(&device));
     }
     
     for model in &self.models {
       model.destroy(Arc::clone(&device));
     }
     
+    for material in &self.material_overrides {
+      material.destroy(Arc::clone(&device));
+    }
+    
+    for (_, material) in &self.retired_material_overrides {
+      material.destroy(Arc::clone(&device));
+    }
+    
     self.dummy_uniform_buffer.destroy(Arc::clone(&device));
+    for buffer in self.light_buffers.iter().chain(self.light_cluster_buffers.iter()) {
+      buffer.destroy(Arc::clone(&device));
+    }
+    
+    for (_, buffer) in &self.retired_light_buffers {
+      buffer.destroy(Arc::clone(&device));
+    }
+    self.forward_camera_buffer.destroy(Arc::clone(&device));
+    self.camera_buffer.destroy(Arc::clone(&device));
+    self.debug_lines.destroy(Arc::clone(&device));
     
     self.pipeline.destroy(Arc::clone(&device));
     self.double_pipeline.destroy(Arc::clone(&device));
     self.pipeline_deffered.destroy(Arc::clone(&device));
+    self.forward_pipeline.destroy(Arc::clone(&device));
+    self.forward_double_pipeline.destroy(Arc::clone(&device));
+    self.forward_instanced_pipeline.destroy(Arc::clone(&device));
+    self.forward_instanced_double_pipeline.destroy(Arc::clone(&device));
     self.instanced_pipeline.destroy(Arc::clone(&device));
     self.instanced_double_pipeline.destroy(Arc::clone(&device));
+    self.coverage_pipeline.destroy(Arc::clone(&device));
+    self.coverage_double_pipeline.destroy(Arc::clone(&device));
+    self.instanced_coverage_pipeline.destroy(Arc::clone(&device));
+    self.instanced_coverage_double_pipeline.destroy(Arc::clone(&device));
+    if let Some((wireframe_pipeline, wireframe_instanced_pipeline)) = &self.wireframe_pipelines {
+      wireframe_pipeline.destroy(Arc::clone(&device));
+      wireframe_instanced_pipeline.destroy(Arc::clone(&device));
+    }
+    self.overdraw_pipelines.0.destroy(Arc::clone(&device));
+    self.overdraw_pipelines.1.destroy(Arc::clone(&device));
+    self.shadow_pipeline.destroy(Arc::clone(&device));
+    self.shadow_instanced_pipeline.destroy(Arc::clone(&device));
+    self.shadow_map.destroy(Arc::clone(&device));
+    self.environment_map.destroy(Arc::clone(&device));
+    self.skybox.destroy(Arc::clone(&device));
+    self.fog.destroy(Arc::clone(&device));
+    self.ambient_occlusion.destroy(Arc::clone(&device));
+    self.bloom.destroy(Arc::clone(&device));
+    if let Some(anti_aliasing) = &self.anti_aliasing {
+      anti_aliasing.destroy(Arc::clone(&device));
+    }
     
     for descriptor in &self.descriptor_sets {
       descriptor.destroy(Arc::clone(&device));
     }
     
     self.deffered_descriptor_set.destroy(Arc::clone(&device));
+    self.forward_descriptor_set.destroy(Arc::clone(&device));
+    self.camera_descriptor_set.destroy(Arc::clone(&device));
     
     self.vertex_shader.destroy(Arc::clone(&device));
     self.fragment_shader.destroy(Arc::clone(&device));
     self.vertex_shader_deffered.destroy(Arc::clone(&device));
     self.fragment_shader_deffered.destroy(Arc::clone(&device));
     self.vertex_shader_instanced.destroy(Arc::clone(&device));
+    self.fragment_shader_forward.destroy(Arc::clone(&device));
+    self.fragment_shader_overdraw.destroy(Arc::clone(&device));
+    self.vertex_shader_shadow.destroy(Arc::clone(&device));
+    self.vertex_shader_shadow_instanced.destroy(Arc::clone(&device));
+    self.fragment_shader_shadow.destroy(Arc::clone(&device));
     
     for framebuffer in &self.framebuffers {
      framebuffer.destroy(Arc::clone(&device));
@@ -1519,6 +3372,10 @@ impl ModelShader {
       images.destroy(Arc::clone(&device)); 
     }
     
+    for images in &self.framebuffer_velocity_images {
+      images.destroy(Arc::clone(&device));
+    }
+    
     for images in &self.framebuffer_msaa_images {
       images.destroy(Arc::clone(&device));
     }
@@ -0,0 +1,437 @@
+use vk;
+
+use crate::vulkan::vkenums::{ImageType, ImageUsage, ImageViewType, SampleCount, ImageTiling, AttachmentLoadOp, AttachmentStoreOp, ImageLayout, Filter, AddressMode, MipmapMode, VkBool};
+
+use crate::vulkan::{Instance, Device, RenderPass, ImageAttachment, AttachmentInfo, SubpassInfo, RenderPassBuilder, Sampler, SamplerBuilder};
+use crate::vulkan::buffer::{Buffer, BufferUsage, Framebuffer, CommandBufferBuilder};
+use crate::shaders::lights::{Light, ClusterView, CLUSTER_NEAR, sun_direction};
+use crate::math;
+
+use cgmath::{Vector3, Vector4, Matrix4, InnerSpace};
+
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+pub const MAX_CASCADES: usize = 4;
+pub const MAX_SHADOWED_LIGHTS: usize = 16;
+// cascades first then up to six cube faces per shadowed light
+pub const MAX_SHADOW_TILES: usize = MAX_CASCADES + MAX_SHADOWED_LIGHTS*6;
+const TILE_SIZE: usize = 20; // matrix and atlas rect
+// split depths, info and the tiles, must match Shadows in VkModelDeffered.frag
+pub const SHADOW_DATA_SIZE: usize = 8 + MAX_SHADOW_TILES*TILE_SIZE;
+
+const MIN_RESOLUTION: u32 = 256;
+const MAX_RESOLUTION: u32 = 4096;
+const MIN_LIGHT_RESOLUTION: u32 = 128;
+const MAX_LIGHT_RESOLUTION: u32 = 2048;
+const MAX_ATLAS_SIZE: u32 = 16384;
+// blend between logarithmic (1.0) and uniform (0.0) cascade splits
+const SPLIT_LAMBDA: f32 = 0.5;
+const LIGHT_SHADOW_NEAR: f32 = 0.05;
+
+#[derive(Clone, Copy)]
+pub struct ShadowSettings {
+  cascades: u32,
+  resolution: u32,
+  distance: f32,
+  light_budget: u32,
+  light_resolution: u32,
+}
+
+impl ShadowSettings {
+  pub fn new(cascades: u32, resolution: u32, distance: f32, light_budget: u32, light_resolution: u32) -> ShadowSettings {
+    let mut settings = ShadowSettings {
+      cascades: cascades.min(MAX_CASCADES as u32),
+      resolution: resolution.clamp(MIN_RESOLUTION, MAX_RESOLUTION),
+      distance: distance.max(CLUSTER_NEAR*2.0),
+      light_budget: light_budget.min(MAX_SHADOWED_LIGHTS as u32),
+      light_resolution: light_resolution.clamp(MIN_LIGHT_RESOLUTION, MAX_LIGHT_RESOLUTION),
+    };
+    
+    // a big budget gives up detail rather than outgrowing the atlas
+    loop {
+      let (width, height, _) = settings.atlas_layout();
+      if (width <= MAX_ATLAS_SIZE && height <= MAX_ATLAS_SIZE) || settings.light_resolution <= MIN_LIGHT_RESOLUTION {
+        break;
+      }
+      settings.light_resolution /= 2;
+    }
+    
+    settings
+  }
+  
+  pub fn enabled(&self) -> bool {
+    self.sun_enabled() || self.lights_enabled()
+  }
+  
+  pub fn sun_enabled(&self) -> bool {
+    self.cascades > 0
+  }
+  
+  pub fn lights_enabled(&self) -> bool {
+    self.light_budget > 0
+  }
+  
+  /**
+  ** Width, height and the number of light tiles per row. The cascades sit
+  ** along the top with the light tiles in rows beneath them.
+  **/
+  fn atlas_layout(&self) -> (u32, u32, u32) {
+    let (sun_width, sun_height) = if self.sun_enabled() {
+      (self.resolution*self.cascades, self.resolution)
+    } else {
+      (0, 0)
+    };
+    
+    if !self.lights_enabled() {
+      return (sun_width.max(1), sun_height.max(1), 0);
+    }
+    
+    let tiles = self.light_budget*6;
+    let columns = if sun_width >= self.light_resolution {
+      sun_width / self.light_resolution
+    } else {
+      (tiles as f32).sqrt().ceil() as u32
+    };
+    let rows = math::div_round_up(tiles, columns);
+    
+    (sun_width.max(columns*self.light_resolution), sun_height + rows*self.light_resolution, columns)
+  }
+}
+
+/**
+** One square of the atlas drawn from a single view, the cascades of the sun
+** or a face of a point or spot light. Lights only reach casters within their range.
+**/
+#[derive(Clone)]
+struct ShadowView {
+  matrix: Matrix4<f32>,
+  tile: usize,
+  offset: (u32, u32),
+  size: u32,
+  reach: Option<(Vector3<f32>, f32)>,
+}
+
+/**
+** Depth atlas for the sun and the lights given shadows this frame. Each
+** cascade is a resolution*resolution square laid out left to right along the
+** top, point lights take six light tiles below them and spot lights take one.
+** With shadows turned off the atlas is a single texel that is only ever
+** cleared so the deffered pass always has something to sample.
+**/
+pub struct ShadowMap {
+  settings: ShadowSettings,
+  render_pass: RenderPass,
+  depth_image: ImageAttachment,
+  framebuffer: Framebuffer,
+  sampler: Sampler,
+  views: Vec<ShadowView>,
+  splits: Vec<f32>,
+  shadow_buffer: Buffer<f32>,
+}
+
+impl ShadowMap {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, settings: ShadowSettings, num_sets: u32) -> ShadowMap {
+    let depth_attachment = AttachmentInfo::new()
+                                .format(vk::FORMAT_D32_SFLOAT)
+                                .multisample(&SampleCount::OneBit)
+                                .load(AttachmentLoadOp::Clear)
+                                .store(AttachmentStoreOp::Store)
+                                .stencil_load(AttachmentLoadOp::DontCare)
+                                .stencil_store(AttachmentStoreOp::DontCare)
+                                .initial_layout(ImageLayout::Undefined)
+                                .final_layout(ImageLayout::ShaderReadOnlyOptimal)
+                                .image_usage(ImageLayout::DepthStencilAttachmentOptimal);
+    
+    let subpass = SubpassInfo::new().add_depth_stencil(0);
+    
+    let render_pass = RenderPassBuilder::new()
+                        .add_attachment(depth_attachment)
+                        .add_subpass(subpass)
+                        .build(Arc::clone(&device));
+    
+    let (width, height, _) = settings.atlas_layout();
+    
+    let depth_image = ImageAttachment::create_image_depth_attachment(Arc::clone(&instance), Arc::clone(&device), &ImageType::Type2D, &ImageTiling::Optimal, &ImageUsage::depth_stencil_sampled(), &ImageLayout::Undefined, &SampleCount::OneBit, &ImageViewType::Type2D, &vk::FORMAT_D32_SFLOAT, width, height);
+    
+    let extent = vk::Extent2D { width, height };
+    let framebuffer = Framebuffer::new_with_imageviews(Arc::clone(&device), &render_pass, &extent, vec!(depth_image.get_image_view()));
+    
+    let sampler = SamplerBuilder::new()
+                    .min_filter(Filter::Nearest)
+                    .mag_filter(Filter::Nearest)
+                    .address_mode(AddressMode::ClampToEdge)
+                    .mipmap_mode(MipmapMode::Nearest)
+                    .anisotropy(VkBool::False)
+                    .max_anisotropy(1.0)
+                    .build(Arc::clone(&device));
+    
+    let shadow_buffer = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), BufferUsage::storage_buffer(), num_sets, SHADOW_DATA_SIZE as u64);
+    
+    ShadowMap {
+      settings,
+      render_pass,
+      depth_image,
+      framebuffer,
+      sampler,
+      views: Vec::new(),
+      splits: Vec::new(),
+      shadow_buffer,
+    }
+  }
+  
+  pub fn enabled(&self) -> bool {
+    self.settings.enabled()
+  }
+  
+  pub fn num_views(&self) -> usize {
+    self.views.len()
+  }
+  
+  pub fn view_matrix(&self, view: usize) -> Matrix4<f32> {
+    self.views[view].matrix
+  }
+  
+  /**
+  ** Whether a caster bounded by the sphere can land in a view,
+  ** the cascades take everything.
+  **/
+  pub fn view_reaches(&self, view: usize, centre: Vector3<f32>, radius: f32) -> bool {
+    match self.views[view].reach {
+      Some((position, range)) => (centre - position).magnitude() < range + radius,
+      None => true,
+    }
+  }
+  
+  pub fn depth_image(&self) -> &ImageAttachment {
+    &self.depth_image
+  }
+  
+  pub fn sampler(&self) -> &Sampler {
+    &self.sampler
+  }
+  
+  pub fn buffer(&self) -> &Buffer<f32> {
+    &self.shadow_buffer
+  }
+  
+  /**
+  ** Lays out this frame's views, the sun's cascades then the lights closest
+  ** to the camera that cast shadows up to the budget. Each chosen light is
+  ** given the tile its depth maps start at, the rest are left unshadowed.
+  **/
+  pub fn update(&mut self, view: &ClusterView, lights: &mut [Light]) {
+    self.views.clear();
+    self.splits.clear();
+    
+    self.update_cascades(view, sun_direction(lights));
+    
+    for light in lights.iter_mut() {
+      light.set_shadow_tile(None);
+    }
+    
+    let mut candidates: Vec<(usize, f32)> = lights.iter().enumerate().filter(|(_, light)| light.casts_shadows()).filter_map(|(i, light)| {
+      let (centre, radius) = light.bounding_sphere();
+      view.distance_to(centre, radius).map(|distance| (i, distance))
+    }).collect();
+    candidates.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+    candidates.truncate(self.settings.light_budget as usize);
+    
+    let (_, _, columns) = self.settings.atlas_layout();
+    let sun_height = if self.settings.sun_enabled() { self.settings.resolution } else { 0 };
+    let size = self.settings.light_resolution;
+    
+    let mut next_tile = 0;
+    for (i, _) in candidates {
+      let light = &mut lights[i];
+      light.set_shadow_tile(Some(MAX_CASCADES + next_tile as usize));
+      
+      let far = light.range().max(LIGHT_SHADOW_NEAR*2.0);
+      for (direction, fov) in light.shadow_faces() {
+        self.views.push(ShadowView {
+          matrix: ShadowMap::perspective(light.position(), direction, fov, LIGHT_SHADOW_NEAR, far),
+          tile: MAX_CASCADES + next_tile as usize,
+          offset: ((next_tile % columns)*size, sun_height + (next_tile / columns)*size),
+          size,
+          reach: Some((light.position(), light.range())),
+        });
+        
+        next_tile += 1;
+      }
+    }
+  }
+  
+  /**
+  ** Perspective view from a light with depth from 0 at near to 1 at far.
+  **/
+  fn perspective(position: Vector3<f32>, direction: Vector3<f32>, fov: f32, near: f32, far: f32) -> Matrix4<f32> {
+    let direction = direction.normalize();
+    let up = if direction.y.abs() > 0.99 { Vector3::new(0.0, 0.0, 1.0) } else { Vector3::new(0.0, 1.0, 0.0) };
+    let right = direction.cross(up).normalize();
+    let up = right.cross(direction);
+    
+    let f = 1.0 / (fov*0.5).tan();
+    let a = far / (far - near);
+    let b = -far*near / (far - near);
+    
+    Matrix4::from_cols(Vector4::new(f*right.x,               f*up.x,               a*direction.x,                   direction.x),
+                       Vector4::new(f*right.y,               f*up.y,               a*direction.y,                   direction.y),
+                       Vector4::new(f*right.z,               f*up.z,               a*direction.z,                   direction.z),
+                       Vector4::new(-f*right.dot(position), -f*up.dot(position), -a*direction.dot(position) + b, -direction.dot(position)))
+  }
+  
+  /**
+  ** Splits the view between CLUSTER_NEAR and the shadow distance and fits an
+  ** orthographic projection from the sun around each slice. Each slice is
+  ** bounded by a sphere snapped to whole texels so the shadows don't shimmer
+  ** as the camera turns or moves.
+  **/
+  fn update_cascades(&mut self, view: &ClusterView, sun_direction: Option<Vector3<f32>>) {
+    let sun_direction = match sun_direction {
+      Some(direction) if self.settings.sun_enabled() && direction.magnitude2() > 0.0 => direction.normalize(),
+      _ => return,
+    };
+    
+    let up = if sun_direction.y.abs() > 0.99 { Vector3::new(0.0, 0.0, 1.0) } else { Vector3::new(0.0, 1.0, 0.0) };
+    let light_right = sun_direction.cross(up).normalize();
+    let light_up = light_right.cross(sun_direction);
+    
+    let near = CLUSTER_NEAR;
+    let far = self.settings.distance;
+    let num_cascades = self.settings.cascades as usize;
+    let texels = self.settings.resolution as f32;
+    
+    let mut split_near = near;
+    for i in 0..num_cascades {
+      let p = (i+1) as f32 / num_cascades as f32;
+      let log_split = near*(far/near).powf(p);
+      let uniform_split = near + (far-near)*p;
+      let split_far = SPLIT_LAMBDA*log_split + (1.0-SPLIT_LAMBDA)*uniform_split;
+      
+      let corners = ShadowMap::slice_corners(view, split_near, split_far);
+      let mut centre = Vector3::new(0.0, 0.0, 0.0);
+      for corner in &corners {
+        centre += *corner;
+      }
+      centre /= corners.len() as f32;
+      
+      let mut radius: f32 = 0.0;
+      for corner in &corners {
+        radius = radius.max((*corner - centre).magnitude());
+      }
+      radius = (radius*16.0).ceil() / 16.0;
+      
+      let texel_size = 2.0*radius / texels;
+      let x = (centre.dot(light_right) / texel_size).floor() * texel_size;
+      let y = (centre.dot(light_up) / texel_size).floor() * texel_size;
+      
+      // casters up to the shadow distance behind the slice still land in the map
+      let z = centre.dot(sun_direction);
+      let z_near = z - radius - far;
+      let z_range = (z + radius) - z_near;
+      
+      let matrix = Matrix4::from_cols(Vector4::new(light_right.x/radius, light_up.x/radius, sun_direction.x/z_range, 0.0),
+                                      Vector4::new(light_right.y/radius, light_up.y/radius, sun_direction.y/z_range, 0.0),
+                                      Vector4::new(light_right.z/radius, light_up.z/radius, sun_direction.z/z_range, 0.0),
+                                      Vector4::new(-x/radius,            -y/radius,         -z_near/z_range,         1.0));
+      
+      self.views.push(ShadowView {
+        matrix,
+        tile: i,
+        offset: (i as u32*self.settings.resolution, 0),
+        size: self.settings.resolution,
+        reach: None,
+      });
+      self.splits.push(split_far);
+      
+      split_near = split_far;
+    }
+  }
+  
+  fn slice_corners(view: &ClusterView, near: f32, far: f32) -> Vec<Vector3<f32>> {
+    let right = view.right.truncate();
+    let up = view.up.truncate();
+    
+    let mut corners = Vec::with_capacity(8);
+    for depth in &[near, far] {
+      let centre = view.position + view.forward*(*depth);
+      let half_width = right*(depth*view.right.w);
+      let half_height = up*(depth*view.up.w);
+      
+      corners.push(centre - half_width - half_height);
+      corners.push(centre + half_width - half_height);
+      corners.push(centre - half_width + half_height);
+      corners.push(centre + half_width + half_height);
+    }
+    
+    corners
+  }
+  
+  /**
+  ** Layout matches Shadows in VkModelDeffered.frag, info is cascade count,
+  ** texel size in the atlas, _. Each tile is its matrix then its rect in the
+  ** atlas as uv offset and uv scale.
+  **/
+  pub fn fill_buffer(&mut self, device: Arc<Device>, current_buffer: usize) {
+    let (width, height, _) = self.settings.atlas_layout();
+    
+    let mut splits = [0.0; MAX_CASCADES];
+    for (split, cascade_split) in splits.iter_mut().zip(&self.splits) {
+      *split = *cascade_split;
+    }
+    
+    let mut tiles = vec!((Matrix4::from_scale(1.0), Vector4::new(0.0, 0.0, 0.0, 0.0)); MAX_SHADOW_TILES);
+    for view in &self.views {
+      let rect = Vector4::new(view.offset.0 as f32 / width as f32, view.offset.1 as f32 / height as f32,
+                              view.size as f32 / width as f32,     view.size as f32 / height as f32);
+      tiles[view.tile] = (view.matrix, rect);
+    }
+    
+    let mut data = Vec::with_capacity(SHADOW_DATA_SIZE);
+    data.extend_from_slice(&splits);
+    data.extend_from_slice(&[self.splits.len() as f32, 1.0 / width as f32, 1.0 / height as f32, 0.0]);
+    
+    for (matrix, rect) in tiles {
+      let matrix: [[f32; 4]; 4] = matrix.into();
+      for column in &matrix {
+        data.extend_from_slice(column);
+      }
+      data.extend_from_slice(&[rect.x, rect.y, rect.z, rect.w]);
+    }
+    
+    self.shadow_buffer.fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, data);
+  }
+  
+  pub fn begin_renderpass(&self, device: Arc<Device>, cmd: CommandBufferBuilder) -> CommandBufferBuilder {
+    let clear_value = vec!(vk::ClearValue {
+      depthStencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 }
+    });
+    
+    let (width, height, _) = self.settings.atlas_layout();
+    let extent = vk::Extent2D { width, height };
+    
+    cmd.begin_render_pass(Arc::clone(&device), &clear_value, &self.render_pass, self.framebuffer.internal_object(), &extent)
+  }
+  
+  /**
+  ** Points the viewport and scissor at a view's square of the atlas.
+  **/
+  pub fn set_view_viewport(&self, device: Arc<Device>, cmd: CommandBufferBuilder, view: usize) -> CommandBufferBuilder {
+    let ShadowView { offset: (x, y), size, .. } = self.views[view];
+    
+    let cmd = cmd.set_viewport(Arc::clone(&device), x as f32, y as f32, size as f32, size as f32);
+    cmd.set_scissor(Arc::clone(&device), x as i32, y as i32, size, size)
+  }
+  
+  pub fn get_render_pass(&self) -> &RenderPass {
+    &self.render_pass
+  }
+  
+  pub fn destroy(&mut self, device: Arc<Device>) {
+    self.shadow_buffer.destroy(Arc::clone(&device));
+    self.sampler.destroy(Arc::clone(&device));
+    self.framebuffer.destroy(Arc::clone(&device));
+    self.depth_image.destroy(Arc::clone(&device));
+    self.render_pass.destroy(Arc::clone(&device));
+  }
+}
@@ -0,0 +1,255 @@
+use vk;
+
+use crate::vulkan::vkenums::{ShaderStage, ImageLayout, SampleCount, Filter, AddressMode, MipmapMode, VkBool};
+
+use crate::vulkan::{Instance, Device, RenderPass, Shader, Pipeline, PipelineBuilder, DescriptorSet, UpdateDescriptorSets, DescriptorSetBuilder, ImageAttachment, Sampler, SamplerBuilder};
+use crate::vulkan::buffer::{Buffer, UniformData, CommandBufferBuilder};
+use crate::vulkan::pool::{DescriptorPool, CommandPool};
+use crate::shaders::FinalShader;
+use crate::shaders::FinalVertex;
+use crate::shaders::lights::{ClusterView, CLUSTER_FAR};
+use crate::shaders::environment::{EnvironmentMap, ENVIRONMENT_FORMAT, srgb_to_linear, rgb_to_half_floats};
+
+use cgmath::{Vector3, Vector4};
+
+use std::sync::Arc;
+
+// must match SKY_CUBE, SKY_GRADIENT and SKY_ATMOSPHERE in VkSkybox.frag
+const SKY_CUBE: f32 = 0.0;
+const SKY_GRADIENT: f32 = 1.0;
+const SKY_ATMOSPHERE: f32 = 2.0;
+
+#[derive(Clone, PartialEq)]
+enum SkySource {
+  Nothing,
+  Images(Vec<String>),
+  Equirectangular(String),
+  Gradient(Vector3<f32>, Vector3<f32>, Vector3<f32>),
+}
+
+/**
+** Background of the model pass, drawn before any models on the far plane.
+** The sky is a cube from six images or an equirectangular image, or a
+** gradient from the top of the sky through the horizon to the ground.
+** Without one the atmosphere lit by the sun is drawn when it is on, and
+** otherwise the model clear colour shows through.
+**/
+pub struct Skybox {
+  source: SkySource,
+  loaded: bool, // false when the source failed to load, so it isn't retried every frame
+  atmosphere: Option<Vector3<f32>>, // the way the sunlight travels
+  pipeline: Pipeline,
+  vertex_shader: Shader,
+  fragment_shader: Shader,
+  descriptor_set: DescriptorSet,
+  vertex_buffer: Buffer<FinalVertex>,
+  sampler: Sampler,
+  cube: ImageAttachment,
+}
+
+impl Skybox {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, render_pass: &RenderPass, msaa: &SampleCount, descriptor_set_pool: &DescriptorPool, command_pool: &CommandPool, graphics_queue: &vk::Queue) -> Skybox {
+    let vertex_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkSkyboxVert.spv"));
+    let fragment_shader = Shader::new(Arc::clone(&device), include_bytes!("./sprv/VkSkyboxFrag.spv"));
+    
+    let descriptor_set = DescriptorSetBuilder::new()
+                           .fragment_combined_image_sampler(0)
+                           .build(Arc::clone(&device), descriptor_set_pool, 1);
+    
+    let push_constant_size = UniformData::new()
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .add_vector4(Vector4::new(0.0, 0.0, 0.0, 0.0))
+                               .size_non_aligned();
+    
+    let pipeline = PipelineBuilder::new()
+                  .vertex_shader(*vertex_shader.get_shader())
+                  .fragment_shader(*fragment_shader.get_shader())
+                  .push_constants(ShaderStage::Fragment, push_constant_size as u32)
+                  .render_pass(render_pass.clone())
+                  .descriptor_set_layout(descriptor_set.layouts_clone())
+                  .vertex_binding(vec!(FinalVertex::vertex_input_binding()))
+                  .vertex_attributes(FinalVertex::vertex_input_attributes())
+                  .multisample(msaa)
+                  .topology_triangle_list()
+                  .polygon_mode_fill()
+                  .cull_mode_none()
+                  .front_face_counter_clockwise()
+                  .disable_blend()
+                  .build(Arc::clone(&device));
+    
+    let vertex_buffer = FinalShader::create_vertex_buffer(Arc::clone(&instance), Arc::clone(&device), command_pool, graphics_queue);
+    
+    let sampler = SamplerBuilder::new()
+                    .min_filter(Filter::Linear)
+                    .mag_filter(Filter::Linear)
+                    .address_mode(AddressMode::ClampToEdge)
+                    .mipmap_mode(MipmapMode::Nearest)
+                    .anisotropy(VkBool::False)
+                    .max_anisotropy(1.0)
+                    .build(Arc::clone(&device));
+    
+    // a black texel per face so the descriptor set is never empty
+    let black = rgb_to_half_floats(&[[0.0; 3]; 6]);
+    let cube = ImageAttachment::create_cube_texture_from_pixels(Arc::clone(&instance), Arc::clone(&device), black, 1, ENVIRONMENT_FORMAT, command_pool, graphics_queue);
+    
+    let skybox = Skybox {
+      source: SkySource::Nothing,
+      loaded: false,
+      atmosphere: None,
+      pipeline,
+      vertex_shader,
+      fragment_shader,
+      descriptor_set,
+      vertex_buffer,
+      sampler,
+      cube,
+    };
+    
+    skybox.update_descriptor_set(Arc::clone(&device));
+    
+    skybox
+  }
+  
+  fn source_enabled(&self) -> bool {
+    self.loaded && self.source != SkySource::Nothing
+  }
+  
+  pub fn enabled(&self) -> bool {
+    self.source_enabled() || self.atmosphere.is_some()
+  }
+  
+  // whether setting these would replace the cube, which needs the device to be idle
+  pub fn images_changed(&self, locations: &[String]) -> bool {
+    self.source != SkySource::Images(locations.to_vec())
+  }
+  
+  pub fn equirectangular_changed(&self, location: &str) -> bool {
+    self.source != SkySource::Equirectangular(location.to_string())
+  }
+  
+  /**
+  ** Six square images of the same size in the order +x, -x, +y, -y, +z, -z.
+  ** The device must be idle as the old cube is destroyed.
+  **/
+  pub fn set_images(&mut self, instance: Arc<Instance>, device: Arc<Device>, locations: Vec<String>, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    if !self.images_changed(&locations) {
+      return;
+    }
+    
+    self.source = SkySource::Images(locations.clone());
+    self.loaded = false;
+    
+    if locations.len() != 6 {
+      println!("Error: A skybox needs 6 images, {} were given", locations.len());
+      return;
+    }
+    
+    let mut size = 0;
+    let mut faces = Vec::new();
+    for location in &locations {
+      let image = match image::open(location) {
+        Ok(image) => image.to_rgba(),
+        Err(e) => {
+          println!("Error: Failed to load skybox image {}: {}", location, e);
+          return;
+        }
+      };
+      
+      let (width, height) = image.dimensions();
+      if width != height || (size != 0 && width != size) {
+        println!("Error: Skybox image {} isn't square or a different size to the others", location);
+        return;
+      }
+      size = width;
+      
+      faces.extend(image.pixels().map(|pixel| {
+        [srgb_to_linear(pixel.data[0]), srgb_to_linear(pixel.data[1]), srgb_to_linear(pixel.data[2])]
+      }));
+    }
+    
+    let cube = ImageAttachment::create_cube_texture_from_pixels(Arc::clone(&instance), Arc::clone(&device), rgb_to_half_floats(&faces), size, ENVIRONMENT_FORMAT, command_pool, graphics_queue);
+    self.replace_cube(Arc::clone(&device), cube);
+    self.loaded = true;
+  }
+  
+  // the device must be idle as the old cube is destroyed
+  pub fn set_equirectangular(&mut self, instance: Arc<Instance>, device: Arc<Device>, location: String, environment_map: &EnvironmentMap, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    if !self.equirectangular_changed(&location) {
+      return;
+    }
+    
+    self.loaded = false;
+    if let Some(cube) = environment_map.create_cube_from_equirectangular(Arc::clone(&instance), Arc::clone(&device), &location, command_pool, graphics_queue) {
+      self.replace_cube(Arc::clone(&device), cube);
+      self.loaded = true;
+    }
+    self.source = SkySource::Equirectangular(location);
+  }
+  
+  // colours are as they should appear on screen
+  pub fn set_gradient(&mut self, top: Vector3<f32>, horizon: Vector3<f32>, bottom: Vector3<f32>) {
+    self.source = SkySource::Gradient(top, horizon, bottom);
+    self.loaded = true;
+  }
+  
+  pub fn clear(&mut self) {
+    self.source = SkySource::Nothing;
+  }
+  
+  // drawn behind everything when no other sky is set
+  pub fn set_atmosphere(&mut self, sun_direction: Option<Vector3<f32>>) {
+    self.atmosphere = sun_direction;
+  }
+  
+  pub fn draw(&self, device: Arc<Device>, cmd: CommandBufferBuilder, view: &ClusterView) -> CommandBufferBuilder {
+    if !self.enabled() {
+      return cmd;
+    }
+    
+    let black = Vector3::new(0.0, 0.0, 0.0);
+    let (sky_type, top, horizon, bottom) = match (&self.source, self.atmosphere) {
+      (_, Some(sun_direction)) if !self.source_enabled() => (SKY_ATMOSPHERE, -sun_direction, black, black),
+      (SkySource::Gradient(top, horizon, bottom), _) => (SKY_GRADIENT, *top, *horizon, *bottom),
+      _ => (SKY_CUBE, black, black, black),
+    };
+    
+    let push_constant_data = UniformData::new()
+                               .add_vector4(view.position.extend(CLUSTER_FAR))
+                               .add_vector4(view.forward.extend(sky_type))
+                               .add_vector4(view.right)
+                               .add_vector4(view.up)
+                               .add_vector4(top.extend(0.0))
+                               .add_vector4(horizon.extend(0.0))
+                               .add_vector4(bottom.extend(0.0));
+    
+    let cmd = cmd.push_constants(Arc::clone(&device), &self.pipeline, ShaderStage::Fragment, push_constant_data);
+    cmd.draw(Arc::clone(&device), self.vertex_buffer.internal_object(0), 3, &self.pipeline, vec!(*self.descriptor_set.set(0)), Vec::with_capacity(0))
+  }
+  
+  fn replace_cube(&mut self, device: Arc<Device>, cube: ImageAttachment) {
+    self.cube.destroy(Arc::clone(&device));
+    self.cube = cube;
+    self.update_descriptor_set(Arc::clone(&device));
+  }
+  
+  fn update_descriptor_set(&self, device: Arc<Device>) {
+    UpdateDescriptorSets::new()
+      .add_sampled_image(0, &self.cube, ImageLayout::ShaderReadOnlyOptimal, &self.sampler)
+     .finish_update(Arc::clone(&device), &self.descriptor_set);
+  }
+  
+  pub fn destroy(&self, device: Arc<Device>) {
+    self.cube.destroy(Arc::clone(&device));
+    self.sampler.destroy(Arc::clone(&device));
+    self.vertex_buffer.destroy(Arc::clone(&device));
+    self.descriptor_set.destroy(Arc::clone(&device));
+    self.pipeline.destroy(Arc::clone(&device));
+    self.vertex_shader.destroy(Arc::clone(&device));
+    self.fragment_shader.destroy(Arc::clone(&device));
+  }
+}
@@ -7,7 +7,7 @@ use crate::camera::OrthoCamera;
 use crate::vulkan::vkenums::{ImageType, ImageUsage, ImageViewType, SampleCount, ImageTiling, AttachmentLoadOp, AttachmentStoreOp, ImageLayout, ImageAspect, ShaderStage, VertexInputRate};
 
 use crate::vulkan::{Instance, Device, RenderPass, Shader, Pipeline, PipelineBuilder, DescriptorSet, UpdateDescriptorSets, DescriptorSetBuilder, ImageAttachment, AttachmentInfo, SubpassInfo, RenderPassBuilder, Sampler};
-use crate::vulkan::buffer::{Buffer, BufferUsage, UniformBufferBuilder, UniformData, Framebuffer, CommandBufferBuilder};
+use crate::vulkan::buffer::{Buffer, InstanceBuffer, BufferUsage, UniformBufferBuilder, UniformData, Framebuffer, CommandBufferBuilder};
 use crate::vulkan::pool::{DescriptorPool, CommandPool};
 
 use cgmath::{Vector2, Vector3, Vector4};
@@ -16,7 +16,8 @@ use std::mem;
 use std::sync::Arc;
 use std::collections::HashMap;
 
-const MAX_INSTANCES: usize = 8096;
+// instances each instanced buffer has room for to start with, they grow as needed
+const INSTANCE_CAPACITY: usize = 1024;
 
 // Simple offset_of macro akin to C++ offsetof
 #[macro_export]
@@ -24,7 +25,7 @@ macro_rules! offset_of {
     ($base:path, $field:ident) => {{
         #[allow(unused_unsafe)]
         unsafe {
-            let b: $base = unsafe { mem::MaybeUninit::uninit().assume_init() };
+            let b: $base = unsafe { mem::zeroed() };
             (&b.$field as *const _ as isize) - (&b as *const _ as isize)
         }
     }};
@@ -145,7 +146,7 @@ pub struct TextureShader {
   camera: OrthoCamera,
   
   vertex_shader_instanced: Shader,
-  instanced_cpu_buffers: HashMap<String, (UniformData, Buffer<f32>, String)>,
+  instanced_cpu_buffers: HashMap<String, (InstanceBuffer, String)>,
   instanced_pipeline: Pipeline,
 }
 
@@ -358,14 +359,15 @@ impl TextureShader {
   }
   
   pub fn add_instanced_buffer(&mut self, instance: Arc<Instance>, device: Arc<Device>, image_views: u32, buffer_reference: String, texture_reference: String) {
-    let mut instanced_data = Vec::with_capacity(MAX_INSTANCES*12);
-    for _ in 0..(MAX_INSTANCES*12) {
-      instanced_data.push(0.0);
+    // the buffer is kept if it already exists, only drawing with a different texture
+    if let Some((_, texture)) = self.instanced_cpu_buffers.get_mut(&buffer_reference) {
+      *texture = texture_reference;
+      return;
     }
     
     let usage = BufferUsage::vertex_transfer_src_buffer();
-    let instanced_cpu_buffer = Buffer::cpu_buffer_with_data(Arc::clone(&instance), Arc::clone(&device), usage, image_views, instanced_data);
-    self.instanced_cpu_buffers.insert(buffer_reference, (UniformData::with_capacity(MAX_INSTANCES*12), instanced_cpu_buffer, texture_reference));
+    let instanced_cpu_buffer = InstanceBuffer::new(Arc::clone(&instance), Arc::clone(&device), usage, image_views, 12, INSTANCE_CAPACITY);
+    self.instanced_cpu_buffers.insert(buffer_reference, (instanced_cpu_buffer, texture_reference));
   }
   
   pub fn add_texture(&mut self, device: Arc<Device>, descriptor_set_pool: &DescriptorPool, texture_reference: String, texture_image: &ImageAttachment, sampler: &Sampler) {
@@ -595,22 +597,18 @@ impl TextureShader {
     }
     
     let draw_colour = colour;
-   // let mut details = self.instanced_cpu_buffers.get_mut(&buffer_reference).unwrap();
-    if let Some(details) = &mut self.instanced_cpu_buffers.get_mut(&buffer_reference) {
-      let data = details.0.clone();
-      details.0 = data
-                        .add_vector4(model)
-                        .add_vector4(draw_colour)
-                        .add_vector4(sprite);
+    if let Some((instances, _)) = self.instanced_cpu_buffers.get_mut(&buffer_reference) {
+      instances.push(&[model.x, model.y, model.z, model.w,
+                       draw_colour.x, draw_colour.y, draw_colour.z, draw_colour.w,
+                       sprite.x, sprite.y, sprite.z, sprite.w]);
     }
   }
   
-  pub fn draw_instanced(&mut self, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize, buffer_reference: String) -> CommandBufferBuilder {
+  pub fn draw_instanced(&mut self, instance: Arc<Instance>, device: Arc<Device>, cmd: CommandBufferBuilder, buffer_reference: String) -> CommandBufferBuilder {
     let mut cmd = cmd;
     
-    if let Some((instanced_data, buffer, texture_reference)) = self.instanced_cpu_buffers.get_mut(&buffer_reference) {
-      let data = instanced_data.build(Arc::clone(&device));
-      let num_instances = data.len() as u32 / 12;
+    if let Some((instances, texture_reference)) = self.instanced_cpu_buffers.get_mut(&buffer_reference) {
+      let num_instances = instances.len() as u32;
       
       if num_instances == 0 {
         return cmd;
@@ -619,7 +617,7 @@ impl TextureShader {
         return cmd
       }
       
-      buffer.fill_entire_buffer_single_frame(Arc::clone(&device), current_buffer, data);
+      instances.upload(Arc::clone(&instance), Arc::clone(&device));
       
       let descriptor: &DescriptorSet = self.descriptor_sets.get(&texture_reference.to_string()).unwrap();
       
@@ -640,19 +638,25 @@ impl TextureShader {
       cmd = cmd.draw_instanced_indexed(Arc::clone(&device), 
                                        &self.vertex_buffer.internal_object(0),
                                        &self.index_buffer.internal_object(0),
-                                       &buffer.internal_object(current_buffer),
+                                       instances.internal_object(),
+                                       0,
                                        index_count,
                                        num_instances,
                                        &self.instanced_pipeline,
                                        vec!(*descriptor.set(0)));
       
-      instanced_data.clear();
+      instances.clear();
     }
     
     cmd
   }
   
-  pub fn fill_buffers(&mut self, _instance: Arc<Instance>, _device: Arc<Device>, cmd: CommandBufferBuilder, _current_buffer: usize) -> CommandBufferBuilder {
+  pub fn fill_buffers(&mut self, _instance: Arc<Instance>, device: Arc<Device>, cmd: CommandBufferBuilder, current_buffer: usize) -> CommandBufferBuilder {
+    // instances are written into this frame's buffers from here on
+    for (instances, _texture) in self.instanced_cpu_buffers.values_mut() {
+      instances.begin_frame(Arc::clone(&device), current_buffer);
+    }
+    
     /*
     let mut cmd = cmd;
     
@@ -682,7 +686,7 @@ impl TextureShader {
     
     for instance_details in self.instanced_cpu_buffers.iter() {
       match instance_details {
-        (_reference, (_data, buffer, _texture)) => {
+        (_reference, (buffer, _texture)) => {
           buffer.destroy(Arc::clone(&device));
         }
       }
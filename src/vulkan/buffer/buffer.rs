@@ -233,6 +233,57 @@ impl<T: Clone> Buffer<T> {
     }
   }
   
+  // reads back what the gpu wrote to a host visible buffer, the caller makes sure it is done writing
+  pub fn read_buffer(&self, device: Arc<Device>, current_buffer: usize) -> Vec<T> {
+    let len = self.size as usize / mem::size_of::<T>();
+    let mut data: Vec<T> = Vec::with_capacity(len);
+    
+    let mut host_visible_data = ptr::null_mut();
+    
+    unsafe {
+      let vk = device.pointers();
+      let device = device.internal_object();
+      
+      check_errors(vk.MapMemory(*device, self.memory[current_buffer], 0, self.size, 0, &mut host_visible_data));
+      let mapped_memory_range = vk::MappedMemoryRange {
+        sType: vk::STRUCTURE_TYPE_MAPPED_MEMORY_RANGE,
+        pNext: ptr::null(),
+        memory: self.memory[current_buffer],
+        offset: 0 as vk::DeviceSize,
+        size: vk::WHOLE_SIZE,
+      };
+      vk.InvalidateMappedMemoryRanges(*device, 1, &mapped_memory_range);
+      memcpy(data.as_mut_ptr() as *mut _, host_visible_data, self.size as usize);
+      data.set_len(len);
+      vk.UnmapMemory(*device, self.memory[current_buffer]);
+    }
+    
+    data
+  }
+  
+  // maps a host visible set until unmap_memory, it must be unmapped before it is destroyed
+  pub fn map_memory(&self, device: Arc<Device>, current_buffer: usize) -> *mut T {
+    let mut host_visible_data = ptr::null_mut();
+    
+    unsafe {
+      let vk = device.pointers();
+      let device = device.internal_object();
+      
+      check_errors(vk.MapMemory(*device, self.memory[current_buffer], 0, vk::WHOLE_SIZE, 0, &mut host_visible_data));
+    }
+    
+    host_visible_data as *mut T
+  }
+  
+  pub fn unmap_memory(&self, device: Arc<Device>, current_buffer: usize) {
+    unsafe {
+      let vk = device.pointers();
+      let device = device.internal_object();
+      
+      vk.UnmapMemory(*device, self.memory[current_buffer]);
+    }
+  }
+  
   pub fn internal_object(&self, current_buffer: usize) -> &vk::Buffer {
     &self.buffer[current_buffer]
   }
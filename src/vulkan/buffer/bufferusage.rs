@@ -140,4 +140,20 @@ impl BufferUsage {
       .. BufferUsage::none()
     }
   }
+  
+  pub fn vertex_storage_buffer() -> BufferUsage {
+    BufferUsage {
+      vertex_buffer: true,
+      storage_buffer: true,
+      .. BufferUsage::none()
+    }
+  }
+
+  pub fn indirect_storage_buffer() -> BufferUsage {
+    BufferUsage {
+      indirect_buffer: true,
+      storage_buffer: true,
+      .. BufferUsage::none()
+    }
+  }
 }
@@ -13,7 +13,7 @@ use crate::vulkan::check_errors;
 use crate::vulkan::buffer::UniformData;
 
 use crate::vulkan::vkenums::{PipelineStage, ImageAspect, ImageLayout, ShaderStage, CommandBufferLevel,
-                             PipelineBindPoint, SubpassContents, IndexType, Access, SampleCount};
+                             PipelineBindPoint, SubpassContents, IndexType, Access, SampleCount, Filter};
 
 use std::mem;
 use std::ptr;
@@ -307,6 +307,14 @@ impl CommandBuffer {
     }
   }
   
+  pub fn draw_indexed_indirect(&self, device: Arc<Device>, buffer: &vk::Buffer, offset: u64, draw_count: u32, stride: u32) {
+    let vk = device.pointers();
+    
+    unsafe {
+      vk.CmdDrawIndexedIndirect(self.command_buffer, *buffer, offset, draw_count, stride);
+    }
+  }
+  
   pub fn dispatch(&self, device: Arc<Device>, x: u32, y: u32, z: u32) {
     let vk = device.pointers();
     
@@ -345,6 +353,13 @@ impl CommandBuffer {
     }
   }
   
+  pub fn blit_image(&self, device: Arc<Device>, src_image: vk::Image, src_layout: ImageLayout, dst_image: vk::Image, dst_layout: ImageLayout, region: vk::ImageBlit, filter: Filter) {
+    unsafe {
+      let vk = device.pointers();
+      vk.CmdBlitImage(self.command_buffer, src_image, src_layout.to_bits(), dst_image, dst_layout.to_bits(), 1, &region, filter.to_bits());
+    }
+  }
+  
   pub fn copy_buffer<T: Clone, U: Clone>(&self, device: Arc<Device>, src_buffer: &Buffer<T>, dst_buffer: &Buffer<U>, current_buffer: usize) 
 {
     let buffer_copy = {
@@ -362,12 +377,17 @@ impl CommandBuffer {
   }
   
   pub fn copy_buffer_to_image<T: Clone>(&self, device: Arc<Device>, src_buffer: &Buffer<T>, dst_image: vk::Image, image_aspect: ImageAspect, width: u32, height: u32, current_buffer: usize) {
+    self.copy_buffer_to_image_layers(device, src_buffer, dst_image, image_aspect, width, height, 1, current_buffer);
+  }
+  
+  // layers are packed one after another in the buffer
+  pub fn copy_buffer_to_image_layers<T: Clone>(&self, device: Arc<Device>, src_buffer: &Buffer<T>, dst_image: vk::Image, image_aspect: ImageAspect, width: u32, height: u32, layers: u32, current_buffer: usize) {
     
     let image_subresource_layers = vk::ImageSubresourceLayers {
       aspectMask: image_aspect.to_bits(),
       mipLevel: 0,
       baseArrayLayer: 0,
-      layerCount: 1,
+      layerCount: layers,
     };
     
     let region = vk::BufferImageCopy {
@@ -410,6 +430,20 @@ impl CommandBuffer {
     }
   }
   
+  pub fn memory_barrier(&self, device: Arc<Device>, src_stage: PipelineStage, dst_stage: PipelineStage, src_access_flags: &Access, dst_access_flags: &Access) {
+    let barrier = vk::MemoryBarrier {
+      sType: vk::STRUCTURE_TYPE_MEMORY_BARRIER,
+      pNext: ptr::null(),
+      srcAccessMask: src_access_flags.to_bits(),
+      dstAccessMask: dst_access_flags.to_bits(),
+    };
+    
+    unsafe {
+      let vk = device.pointers();
+      vk.CmdPipelineBarrier(self.command_buffer, src_stage.to_bits(), dst_stage.to_bits(), 0, 1, &barrier, 0, ptr::null(), 0, ptr::null());
+    }
+  }
+  
   pub fn pipeline_barrier(&self, device: Arc<Device>, src_stage: PipelineStage, dst_stage: PipelineStage, barrier: vk::ImageMemoryBarrier) {
     unsafe {
       let vk = device.pointers();
@@ -8,6 +8,7 @@ use crate::vulkan::vkenums::{ShaderStage, CommandBufferUsage, Access, ImageLayou
 use cgmath::Vector4;
 
 use std::sync::Arc;
+use std::mem;
 
 pub struct CommandBufferBuilder {
   flags: u32,
@@ -85,6 +86,18 @@ impl CommandBufferBuilder {
     self
   }
   
+  // offset is in bytes from the start of the vertex buffer
+  pub fn draw_with_offset(self, device: Arc<Device>, vertex_buffer: &vk::Buffer, offset: u64, vertex_count: u32, pipeline: &Pipeline, descriptor_set: Vec<vk::DescriptorSet>) -> CommandBufferBuilder {
+    self.command_buffer.bind_graphics_pipeline(Arc::clone(&device), pipeline);
+    
+    self.command_buffer.bind_graphics_descriptor_set(Arc::clone(&device), pipeline, descriptor_set, Vec::with_capacity(0));
+    
+    self.command_buffer.bind_vertex_buffer(Arc::clone(&device), 0, offset, vertex_buffer);
+    self.command_buffer.draw(Arc::clone(&device), vertex_count, 1);
+    
+    self
+  }
+  
   pub fn draw_indexed(self, device: Arc<Device>, vertex_buffer: &vk::Buffer, index_buffer: &vk::Buffer, index_count: u32, pipeline: &Pipeline, descriptor_set: Vec<vk::DescriptorSet>, dynamic_uniform_buffers_offsets: Vec<u32>) -> CommandBufferBuilder {
     self.command_buffer.bind_graphics_pipeline(Arc::clone(&device), pipeline);
     
@@ -139,25 +152,25 @@ impl CommandBufferBuilder {
     self
   }
   
-  pub fn draw_instanced(self, device: Arc<Device>, vertex_buffer: &vk::Buffer, instance_buffer: &vk::Buffer, vertex_count: u32, instance_count: u32, pipeline: &Pipeline, descriptor_set: Vec<vk::DescriptorSet>) -> CommandBufferBuilder {
+  pub fn draw_instanced(self, device: Arc<Device>, vertex_buffer: &vk::Buffer, instance_buffer: &vk::Buffer, instance_offset: u64, vertex_count: u32, instance_count: u32, pipeline: &Pipeline, descriptor_set: Vec<vk::DescriptorSet>) -> CommandBufferBuilder {
     self.command_buffer.bind_graphics_pipeline(Arc::clone(&device), pipeline);
     
     self.command_buffer.bind_graphics_descriptor_set(Arc::clone(&device), pipeline, descriptor_set, Vec::with_capacity(0));
     
     self.command_buffer.bind_vertex_buffer(Arc::clone(&device), 0, 0, vertex_buffer);
-    self.command_buffer.bind_vertex_buffer(Arc::clone(&device), 1, 0, instance_buffer);
+    self.command_buffer.bind_vertex_buffer(Arc::clone(&device), 1, instance_offset, instance_buffer);
     self.command_buffer.draw(Arc::clone(&device), vertex_count, instance_count);
     
     self
   }
   
-  pub fn draw_instanced_indexed(self, device: Arc<Device>, vertex_buffer: &vk::Buffer, index_buffer: &vk::Buffer, instance_buffer: &vk::Buffer, index_count: u32, instance_count: u32, pipeline: &Pipeline, descriptor_set: Vec<vk::DescriptorSet>) -> CommandBufferBuilder {
+  pub fn draw_instanced_indexed(self, device: Arc<Device>, vertex_buffer: &vk::Buffer, index_buffer: &vk::Buffer, instance_buffer: &vk::Buffer, instance_offset: u64, index_count: u32, instance_count: u32, pipeline: &Pipeline, descriptor_set: Vec<vk::DescriptorSet>) -> CommandBufferBuilder {
     self.command_buffer.bind_graphics_pipeline(Arc::clone(&device), pipeline);
     
     self.command_buffer.bind_graphics_descriptor_set(Arc::clone(&device), pipeline, descriptor_set, Vec::with_capacity(0));
     
     self.command_buffer.bind_vertex_buffer(Arc::clone(&device), 0, 0, vertex_buffer);
-    self.command_buffer.bind_vertex_buffer(Arc::clone(&device), 1, 0, instance_buffer);
+    self.command_buffer.bind_vertex_buffer(Arc::clone(&device), 1, instance_offset, instance_buffer);
     self.command_buffer.bind_index_buffer(Arc::clone(&device), 0, index_buffer);
     
     self.command_buffer.draw_indexed(Arc::clone(&device), index_count, 0, 0, instance_count);
@@ -165,6 +178,21 @@ impl CommandBufferBuilder {
     self
   }
   
+  // draws the commands written to indirect_buffer, each drawing from instance_buffer at instance_offset
+  pub fn draw_instanced_indexed_indirect(self, device: Arc<Device>, vertex_buffer: &vk::Buffer, index_buffer: &vk::Buffer, instance_buffer: &vk::Buffer, instance_offset: u64, indirect_buffer: &vk::Buffer, indirect_offset: u64, draw_count: u32, pipeline: &Pipeline, descriptor_set: Vec<vk::DescriptorSet>) -> CommandBufferBuilder {
+    self.command_buffer.bind_graphics_pipeline(Arc::clone(&device), pipeline);
+    
+    self.command_buffer.bind_graphics_descriptor_set(Arc::clone(&device), pipeline, descriptor_set, Vec::with_capacity(0));
+    
+    self.command_buffer.bind_vertex_buffer(Arc::clone(&device), 0, 0, vertex_buffer);
+    self.command_buffer.bind_vertex_buffer(Arc::clone(&device), 1, instance_offset, instance_buffer);
+    self.command_buffer.bind_index_buffer(Arc::clone(&device), 0, index_buffer);
+    
+    self.command_buffer.draw_indexed_indirect(Arc::clone(&device), indirect_buffer, indirect_offset, draw_count, 5*mem::size_of::<u32>() as u32);
+    
+    self
+  }
+  
   pub fn compute_dispatch(self, device: Arc<Device>, pipeline: &Pipeline, descriptor_set: Vec<vk::DescriptorSet>, x: u32, y: u32, z: u32) -> CommandBufferBuilder {
     self.command_buffer.bind_compute_pipeline(Arc::clone(&device), pipeline);
     self.command_buffer.bind_compute_descriptor_set(Arc::clone(&device), pipeline, descriptor_set);
@@ -179,6 +207,11 @@ impl CommandBufferBuilder {
     self
   }
   
+  pub fn memory_barrier(self, device: Arc<Device>, src_stage: PipelineStage, dst_stage: PipelineStage, src_mask: &Access, dst_mask: &Access) -> CommandBufferBuilder {
+    self.command_buffer.memory_barrier(Arc::clone(&device), src_stage, dst_stage, src_mask, dst_mask);
+    self
+  }
+  
   pub fn image_barrier(self, device: Arc<Device>, src_mask: &Access, dst_mask: &Access, old_layout: &ImageLayout, new_layout: &ImageLayout, aspect: &ImageAspect, src_stage: PipelineStage, dst_stage: PipelineStage, src_queue_family: u32, dst_queue_family: u32, image: &ImageAttachment) -> CommandBufferBuilder {
     self.command_buffer.image_barrier(Arc::clone(&device), src_mask, dst_mask, old_layout, new_layout, aspect, src_stage, dst_stage, src_queue_family, dst_queue_family, image);
     self
@@ -0,0 +1,180 @@
+use vk;
+
+use crate::vulkan::Instance;
+use crate::vulkan::Device;
+use crate::vulkan::buffer::{Buffer, BufferUsage};
+
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+
+/**
+** Instance data for instanced draws that grows as it is needed. Each frame
+** has its own host visible buffer that stays mapped, instances are written
+** straight into the current frame's memory. Instances past the capacity
+** wait on the cpu until upload, which recreates the buffers at least twice
+** as large and has to wait for the device to be idle to do so. The command
+** buffer being recorded can still use the old buffers so they are only
+** destroyed once that frame's fence has been waited on again.
+**/
+pub struct InstanceBuffer {
+  usage: BufferUsage,
+  instance_size: usize, // floats in an instance
+  buffer: Buffer<f32>,
+  mapped: Vec<*mut f32>, // per frame
+  capacity: usize, // instances per frame
+  len: usize, // instances in the current frame's memory
+  overflow: Vec<f32>, // instances past the capacity until the next upload
+  current_buffer: usize,
+  retired: Vec<(usize, Buffer<f32>)>, // buffers replaced by upload and the frame that replaced them
+}
+
+impl InstanceBuffer {
+  pub fn new(instance: Arc<Instance>, device: Arc<Device>, usage: BufferUsage, num_frames: u32, instance_size: usize, capacity: usize) -> InstanceBuffer {
+    let capacity = capacity.max(1);
+    let (buffer, mapped) = InstanceBuffer::create_buffer(Arc::clone(&instance), Arc::clone(&device), &usage, num_frames, instance_size*capacity);
+    
+    InstanceBuffer {
+      usage,
+      instance_size,
+      buffer,
+      mapped,
+      capacity,
+      len: 0,
+      overflow: Vec::new(),
+      current_buffer: 0,
+      retired: Vec::new(),
+    }
+  }
+  
+  fn create_buffer(instance: Arc<Instance>, device: Arc<Device>, usage: &BufferUsage, num_frames: u32, data_len: usize) -> (Buffer<f32>, Vec<*mut f32>) {
+    let buffer: Buffer<f32> = Buffer::cpu_buffer(Arc::clone(&instance), Arc::clone(&device), usage.clone(), num_frames, data_len as u64);
+    let mapped = (0..num_frames as usize).map(|i| buffer.map_memory(Arc::clone(&device), i)).collect();
+    
+    (buffer, mapped)
+  }
+  
+  fn destroy_buffer(device: Arc<Device>, buffer: &Buffer<f32>, num_frames: usize) {
+    for i in 0..num_frames {
+      buffer.unmap_memory(Arc::clone(&device), i);
+    }
+    
+    buffer.destroy(Arc::clone(&device));
+  }
+  
+  /**
+  ** Starts the instances of a frame over, the frame's fence must have been
+  ** waited on. Buffers retired while this frame was last recorded are no
+  ** longer used and are destroyed.
+  **/
+  pub fn begin_frame(&mut self, device: Arc<Device>, current_buffer: usize) {
+    let num_frames = self.mapped.len();
+    for (_, buffer) in self.retired.iter().filter(|(frame, _)| *frame == current_buffer) {
+      InstanceBuffer::destroy_buffer(Arc::clone(&device), buffer, num_frames);
+    }
+    self.retired.retain(|(frame, _)| *frame != current_buffer);
+    
+    self.current_buffer = current_buffer;
+    self.clear();
+  }
+  
+  pub fn clear(&mut self) {
+    self.len = 0;
+    self.overflow.clear();
+  }
+  
+  // values is a single instance
+  pub fn push(&mut self, values: &[f32]) {
+    if values.len() != self.instance_size {
+      println!("Error: Instance of {} floats pushed to an instance buffer of {}", values.len(), self.instance_size);
+      return;
+    }
+    
+    if self.len < self.capacity {
+      unsafe {
+        let instance = self.mapped[self.current_buffer].add(self.len*self.instance_size);
+        ptr::copy_nonoverlapping(values.as_ptr(), instance, self.instance_size);
+      }
+      self.len += 1;
+    } else {
+      self.overflow.extend_from_slice(values);
+    }
+  }
+  
+  // instances pushed since the frame began or the buffer was cleared
+  pub fn len(&self) -> usize {
+    self.len + self.overflow.len() / self.instance_size
+  }
+  
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+  
+  /**
+  ** Grows the buffers to fit the instances that overflowed. Returns true if
+  ** they were recreated, anything holding on to them like descriptor sets
+  ** has to be updated.
+  **/
+  pub fn upload(&mut self, instance: Arc<Instance>, device: Arc<Device>) -> bool {
+    if self.overflow.is_empty() {
+      return false;
+    }
+    
+    let num_instances = self.len();
+    let mut capacity = self.capacity*2;
+    while capacity < num_instances {
+      capacity *= 2;
+    }
+    
+    device.wait();
+    
+    let num_frames = self.mapped.len() as u32;
+    let (buffer, mapped) = InstanceBuffer::create_buffer(Arc::clone(&instance), Arc::clone(&device), &self.usage, num_frames, self.instance_size*capacity);
+    
+    unsafe {
+      let old = self.mapped[self.current_buffer];
+      let new = mapped[self.current_buffer];
+      ptr::copy_nonoverlapping(old, new, self.len*self.instance_size);
+      ptr::copy_nonoverlapping(self.overflow.as_ptr(), new.add(self.len*self.instance_size), self.overflow.len());
+    }
+    
+    let old_buffer = mem::replace(&mut self.buffer, buffer);
+    self.retired.push((self.current_buffer, old_buffer));
+    self.mapped = mapped;
+    self.capacity = capacity;
+    self.len = num_instances;
+    self.overflow.clear();
+    
+    true
+  }
+  
+  // the instances in the current frame's memory, everything after upload
+  pub fn instances(&self) -> &[f32] {
+    unsafe {
+      slice::from_raw_parts(self.mapped[self.current_buffer], self.len*self.instance_size)
+    }
+  }
+  
+  pub fn current_buffer(&self) -> usize {
+    self.current_buffer
+  }
+  
+  pub fn buffer(&self) -> &Buffer<f32> {
+    &self.buffer
+  }
+  
+  // the current frame's buffer
+  pub fn internal_object(&self) -> &vk::Buffer {
+    self.buffer.internal_object(self.current_buffer)
+  }
+  
+  pub fn destroy(&self, device: Arc<Device>) {
+    let num_frames = self.mapped.len();
+    for (_, buffer) in &self.retired {
+      InstanceBuffer::destroy_buffer(Arc::clone(&device), buffer, num_frames);
+    }
+    
+    InstanceBuffer::destroy_buffer(Arc::clone(&device), &self.buffer, num_frames);
+  }
+}
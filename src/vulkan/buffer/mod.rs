@@ -2,11 +2,13 @@ pub use self::bufferusage::BufferUsage;
 pub use self::commandbuffer::CommandBuffer;
 pub use self::commandbufferbuilder::CommandBufferBuilder;
 pub use self::buffer::Buffer;
+pub use self::instancebuffer::InstanceBuffer;
 pub use self::uniformbuffer::UniformData;
 pub use self::uniformbuffer::UniformBufferBuilder;
 pub use self::framebuffer::Framebuffer;
 
 mod buffer;
+mod instancebuffer;
 mod bufferusage;
 mod framebuffer;
 mod uniformbuffer;
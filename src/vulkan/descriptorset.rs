@@ -88,112 +88,122 @@ impl<'a> UpdateDescriptorSets<'a> {
   }
   
   pub fn finish_update(self, device: Arc<Device>, descriptor_set: &DescriptorSet) {
-    let sets = descriptor_set.all_sets();
+    for (j, set) in descriptor_set.all_sets().iter().enumerate() {
+      self.write_set(Arc::clone(&device), *set, j, j);
+    }
+  }
+  
+  /**
+  ** Writes only one frame's set, buffers are single frame buffers that belong
+  ** to it. The other frames' sets can still be in use by the device.
+  **/
+  pub fn finish_update_single_set(self, device: Arc<Device>, descriptor_set: &DescriptorSet, frame: usize) {
+    self.write_set(device, *descriptor_set.set(frame), frame, 0);
+  }
+  
+  fn write_set(&self, device: Arc<Device>, set: vk::DescriptorSet, frame: usize, buffer_frame: usize) {
+    for i in 0..self.buffers.len() {
+      let (binding, buffer_type, buffer) = &self.buffers[i];
+      let descriptor_buffer_info = vk::DescriptorBufferInfo {
+        buffer: *buffer.internal_object(buffer_frame),
+        offset: 0,
+        range: vk::WHOLE_SIZE,
+      };
+      
+      let write_descriptor_set = 
+        vk::WriteDescriptorSet {
+          sType: vk::STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+          pNext: ptr::null(),
+          dstSet: set,
+          dstBinding: *binding,
+          dstArrayElement: 0,
+          descriptorCount: 1,
+          descriptorType: buffer_type.to_bits(),
+          pImageInfo: ptr::null(),
+          pBufferInfo: &descriptor_buffer_info,
+          pTexelBufferView: ptr::null(),
+        };
+      
+      let vk = device.pointers();
+      let device = device.internal_object();
+      unsafe {
+        vk.UpdateDescriptorSets(*device, 1, &write_descriptor_set, 0, ptr::null());
+      }
+    }
+    
+    for i in 0..self.input_images.len() {
+      let (binding, ref images, ref layout, ref descriptor_type) = self.input_images[i];
+      
+      let descriptor_image_info;
 
-    for j in 0..sets.len() {
-     for i in 0..self.buffers.len() {
-       let (binding, buffer_type, buffer) = &self.buffers[i];
-       let descriptor_buffer_info = vk::DescriptorBufferInfo {
-            buffer: *buffer.internal_object(j),
-            offset: 0,
-            range: vk::WHOLE_SIZE,
-         };
-        
-        let write_descriptor_set = 
-          vk::WriteDescriptorSet {
-            sType: vk::STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
-            pNext: ptr::null(),
-            dstSet: sets[j],
-            dstBinding: *binding,
-            dstArrayElement: 0,
-            descriptorCount: 1,
-            descriptorType: buffer_type.to_bits(),
-            pImageInfo: ptr::null(),
-            pBufferInfo: &descriptor_buffer_info,
-            pTexelBufferView: ptr::null(),
-          };
-        
-        let vk = device.pointers();
-        let device = device.internal_object();
-        unsafe {
-          vk.UpdateDescriptorSets(*device, 1, &write_descriptor_set, 0, ptr::null());
-        }
+      descriptor_image_info =
+        vk::DescriptorImageInfo {
+          sampler: 0,
+          imageView: images[frame].get_image_view(),
+          imageLayout: layout.to_bits(),
+      };
+      
+      let write_descriptor_set = 
+        vk::WriteDescriptorSet {
+          sType: vk::STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+          pNext: ptr::null(),
+          dstSet: set,
+          dstBinding: binding,
+          dstArrayElement: 0,
+          descriptorCount: 1,
+          descriptorType: descriptor_type.to_bits(),
+          pImageInfo: &descriptor_image_info,
+          pBufferInfo: ptr::null(),
+          pTexelBufferView: ptr::null(),
+      };
+      
+      let vk = device.pointers();
+      let device = device.internal_object();
+      unsafe {
+        vk.UpdateDescriptorSets(*device, 1, &write_descriptor_set, 0, ptr::null());
       }
+    }
+    
+    for i in 0..self.images.len() {
+      let (binding, ref image, ref layout, ref sampler, ref descriptor_type) = self.images[i];
+      
+      let descriptor_image_info;
       
-       for i in 0..self.input_images.len() {
-        let (binding, ref images, ref layout, ref descriptor_type) = self.input_images[i];
+      if sampler.is_some() {
+        descriptor_image_info = 
+          vk::DescriptorImageInfo {
+            sampler: sampler.unwrap().internal_object(),
+            imageView: image.get_image_view(),
+            imageLayout: layout.to_bits(),
+          };
         
-        let descriptor_image_info;
-
+      } else {
         descriptor_image_info =
           vk::DescriptorImageInfo {
             sampler: 0,
-            imageView: images[j].get_image_view(),
+            imageView: image.get_image_view(),
             imageLayout: layout.to_bits(),
-        };
-        
-        let write_descriptor_set = 
-          vk::WriteDescriptorSet {
-            sType: vk::STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
-            pNext: ptr::null(),
-            dstSet: sets[j],
-            dstBinding: binding,
-            dstArrayElement: 0,
-            descriptorCount: 1,
-            descriptorType: descriptor_type.to_bits(),
-            pImageInfo: &descriptor_image_info,
-            pBufferInfo: ptr::null(),
-            pTexelBufferView: ptr::null(),
-        };
-        
-        let vk = device.pointers();
-        let device = device.internal_object();
-        unsafe {
-          vk.UpdateDescriptorSets(*device, 1, &write_descriptor_set, 0, ptr::null());
-        }
+          };
       }
       
-      for i in 0..self.images.len() {
-        let (binding, ref image, ref layout, ref sampler, ref descriptor_type) = self.images[i];
-        
-        let descriptor_image_info;
-        
-        if sampler.is_some() {
-          descriptor_image_info = 
-            vk::DescriptorImageInfo {
-              sampler: sampler.unwrap().internal_object(),
-              imageView: image.get_image_view(),
-              imageLayout: layout.to_bits(),
-            };
-          
-        } else {
-          descriptor_image_info =
-            vk::DescriptorImageInfo {
-              sampler: 0,
-              imageView: image.get_image_view(),
-              imageLayout: layout.to_bits(),
-            };
-        }
-        
-        let write_descriptor_set = 
-          vk::WriteDescriptorSet {
-            sType: vk::STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
-            pNext: ptr::null(),
-            dstSet: sets[j],
-            dstBinding: binding,
-            dstArrayElement: 0,
-            descriptorCount: 1,
-            descriptorType: descriptor_type.to_bits(),
-            pImageInfo: &descriptor_image_info,
-            pBufferInfo: ptr::null(),
-            pTexelBufferView: ptr::null(),
-        };
-        
-        let vk = device.pointers();
-        let device = device.internal_object();
-        unsafe {
-          vk.UpdateDescriptorSets(*device, 1, &write_descriptor_set, 0, ptr::null());
-        }
+      let write_descriptor_set = 
+        vk::WriteDescriptorSet {
+          sType: vk::STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+          pNext: ptr::null(),
+          dstSet: set,
+          dstBinding: binding,
+          dstArrayElement: 0,
+          descriptorCount: 1,
+          descriptorType: descriptor_type.to_bits(),
+          pImageInfo: &descriptor_image_info,
+          pBufferInfo: ptr::null(),
+          pTexelBufferView: ptr::null(),
+      };
+      
+      let vk = device.pointers();
+      let device = device.internal_object();
+      unsafe {
+        vk.UpdateDescriptorSets(*device, 1, &write_descriptor_set, 0, ptr::null());
       }
     }
   }
@@ -240,6 +250,28 @@ impl DescriptorSetBuilder {
     self
   }
   
+  pub fn vertex_storage_buffer(mut self, binding_location: u32) -> DescriptorSetBuilder {
+    self.descriptor_set_layout_info.push(
+      DescriptorSetLayoutInfo {
+        binding: binding_location,
+        descriptor_type: DescriptorType::StorageBuffer,
+        shader_stage: ShaderStage::Vertex,
+      }
+    );
+    self
+  }
+  
+  pub fn fragment_storage_buffer(mut self, binding_location: u32) -> DescriptorSetBuilder {
+    self.descriptor_set_layout_info.push(
+      DescriptorSetLayoutInfo {
+        binding: binding_location,
+        descriptor_type: DescriptorType::StorageBuffer,
+        shader_stage: ShaderStage::Fragment,
+      }
+    );
+    self
+  }
+  
   pub fn vertex_dynamic_uniform_buffer(mut self, binding_location: u32) -> DescriptorSetBuilder {
     self.descriptor_set_layout_info.push(
       DescriptorSetLayoutInfo {
@@ -424,4 +456,18 @@ impl DescriptorSet {
       }
     }
   }
+  
+  // returns the sets to a pool built with free_descriptor_sets, then destroys the layouts
+  pub fn free(&self, device: Arc<Device>, set_pool: &DescriptorPool) {
+    {
+      let vk = device.pointers();
+      let device = device.internal_object();
+      
+      unsafe {
+        check_errors(vk.FreeDescriptorSets(*device, *set_pool.local_pool(), self.sets.len() as u32, self.sets.as_ptr()));
+      }
+    }
+    
+    self.destroy(device);
+  }
 }
@@ -17,12 +17,13 @@ pub struct Device {
   phys_device: vk::PhysicalDevice,
   min_uniformbuffer_offset_alignment: u64,
   non_coherent_atom_size: u64,
+  wireframe: bool, // fillModeNonSolid is enabled
   _extensions: Vec<CString>,
 }
 
 impl Device {
   pub fn new(instance: Arc<Instance>, surface: &vk::SurfaceKHR, logs: &mut Logs) -> Arc<Device> {
-    let (device, phys_device, min_uniformbuffer_offset_alignment, non_coherent_atom_size, extensions) = Device::create_suitable_device(Arc::clone(&instance), surface, logs);
+    let (device, phys_device, min_uniformbuffer_offset_alignment, non_coherent_atom_size, wireframe, extensions) = Device::create_suitable_device(Arc::clone(&instance), surface, logs);
     let vk = Device::create_device_instance(Arc::clone(&instance), &device);
     
     Arc::new(Device {
@@ -31,6 +32,7 @@ impl Device {
       phys_device,
       min_uniformbuffer_offset_alignment,
       non_coherent_atom_size,
+      wireframe,
       _extensions: extensions,
     })
   }
@@ -51,6 +53,11 @@ impl Device {
     self.non_coherent_atom_size
   }
   
+  // if pipelines can draw with polygon_mode_line
+  pub fn supports_wireframe(&self) -> bool {
+    self.wireframe
+  }
+  
   pub fn min_buffer_align(&self, _buffer: &vk::Buffer) -> u64 {
 //    let mem_req: Vec<> = unsafe { mem::MaybeUninit::uninit().assume_init() }; 
   //  self.vk.GetBufferMemoryRequirements(self.device, *buffer, mem_req.as_mut_ptr());
@@ -128,7 +135,7 @@ impl Device {
     vk_device
   }
   
-  fn create_suitable_device(instance: Arc<Instance>, surface: &vk::SurfaceKHR, logs: &mut Logs) -> (vk::Device, vk::PhysicalDevice, u64, u64, Vec<CString>) {
+  fn create_suitable_device(instance: Arc<Instance>, surface: &vk::SurfaceKHR, logs: &mut Logs) -> (vk::Device, vk::PhysicalDevice, u64, u64, bool, Vec<CString>) {
     let layer_names = instance.get_layers();
     let layers_names_raw: Vec<*const i8> = layer_names.iter().map(|raw_name| raw_name.as_ptr()).collect();
     
@@ -139,6 +146,7 @@ impl Device {
     let mut device: vk::Device = unsafe { mem::MaybeUninit::uninit().assume_init() };
     let mut device_available_extensions = Vec::new();
     let mut physical_device_index = 0;
+    let mut wireframe = false;
     
     for i in 0..physical_devices.len() {
       let family_properties = instance.get_device_queue_family_properties(&physical_devices[i]);
@@ -216,6 +224,7 @@ impl Device {
         }
         
         logs.system_msg(&format!("feature alpha to one {}", device_features.alphaToOne));
+        wireframe = device_features.fillModeNonSolid == vk::TRUE;
         
         // Need to fix
         let features = vk::PhysicalDeviceFeatures {
@@ -232,7 +241,7 @@ impl Device {
           drawIndirectFirstInstance: VkBool::False.to_bits(),
           depthClamp: VkBool::False.to_bits(),
           depthBiasClamp: VkBool::False.to_bits(),
-          fillModeNonSolid: VkBool::False.to_bits(),
+          fillModeNonSolid: device_features.fillModeNonSolid,
           depthBounds: VkBool::False.to_bits(),
           wideLines: VkBool::False.to_bits(),
           largePoints: VkBool::False.to_bits(),
@@ -310,7 +319,7 @@ impl Device {
     logs.system_msg(&format!("Max fragment shader outputs: {}", device_prop.limits.maxFragmentOutputAttachments));
     logs.system_msg(&format!("Max fragment shader inputs: {}", device_prop.limits.maxDescriptorSetInputAttachments));
     
-    (device, physical_devices[physical_device_index], min_uniformbuffer_offset_alignment, non_coherent_atom_size, device_available_extensions)
+    (device, physical_devices[physical_device_index], min_uniformbuffer_offset_alignment, non_coherent_atom_size, wireframe, device_available_extensions)
   }
   
   fn print_physical_device_details(vk_instance: &vk::InstancePointers, physical_devices: &Vec<vk::PhysicalDevice>, logs: &mut Logs) {
@@ -4,7 +4,7 @@ use crate::vulkan::{Instance, Device};
 use crate::vulkan::buffer::{Buffer, BufferUsage, CommandBuffer, CommandBufferBuilder};
 use crate::vulkan::pool::{CommandPool};
 use crate::vulkan::vkenums::{ImageType, ImageViewType, ImageLayout, ImageTiling, ImageAspect, SampleCount, 
-                             ImageUsage, SharingMode, PipelineStage, Access, MemoryProperty, ComponentSwizzle};
+                             ImageUsage, SharingMode, PipelineStage, Access, MemoryProperty, ComponentSwizzle, Filter};
 use crate::vulkan::check_errors;
 
 use image;
@@ -21,6 +21,7 @@ pub struct ImageAttachment {
   format: vk::Format,
   width: u32,
   height: u32,
+  mip_levels: u32,
 }
 
 impl ImageAttachment {
@@ -39,6 +40,7 @@ impl ImageAttachment {
       format: *format,
       width,
       height,
+      mip_levels: 1,
     }
   }
   
@@ -56,6 +58,7 @@ impl ImageAttachment {
       format: *format,
       width,
       height,
+      mip_levels: 1,
     }
   }
   
@@ -76,6 +79,7 @@ impl ImageAttachment {
       format: *format,
       width,
       height,
+      mip_levels: 1,
     }
   }
   
@@ -120,6 +124,7 @@ impl ImageAttachment {
       format: format,
       width,
       height,
+      mip_levels: 1,
     }
   }
   
@@ -154,6 +159,7 @@ impl ImageAttachment {
       format: format,
       width,
       height,
+      mip_levels: 1,
     }
   }
   
@@ -193,6 +199,7 @@ impl ImageAttachment {
       format: format,
       width,
       height,
+      mip_levels: 1,
     }
   }
   
@@ -228,9 +235,161 @@ impl ImageAttachment {
       format: format,
       width,
       height,
+      mip_levels: 1,
     })
   }
   
+  /**
+  ** Six square layers viewed as a cube with every mip level, faces are
+  ** drawn to through views from create_face_view.
+  **/
+  pub fn create_cube_attachment(instance: Arc<Instance>, device: Arc<Device>, usage: &ImageUsage, format: &vk::Format, size: u32, mip_levels: u32) -> ImageAttachment {
+    let memory_property = MemoryProperty::DeviceLocal;
+    let (image, memory) = ImageAttachment::create_image_with_layers(Arc::clone(&instance), Arc::clone(&device), &memory_property, &ImageType::Type2D, &ImageTiling::Optimal, usage, &ImageLayout::Undefined, &SampleCount::OneBit, format, size, size, mip_levels, 6, vk::IMAGE_CREATE_CUBE_COMPATIBLE_BIT);
+    let image_view = ImageAttachment::create_image_view_range(Arc::clone(&device), &image, format, &ImageAspect::Colour, &ImageViewType::TypeCube, 0, mip_levels, 0, 6);
+    
+    ImageAttachment {
+      image,
+      image_view,
+      memory,
+      format: *format,
+      width: size,
+      height: size,
+      mip_levels,
+    }
+  }
+  
+  /**
+  ** Cube texture from six square faces packed one after another in the
+  ** order +x, -x, +y, -y, +z, -z.
+  **/
+  pub fn create_cube_texture_from_pixels(instance: Arc<Instance>, device: Arc<Device>, image_data: std::vec::Vec<u8>, size: u32, format: vk::Format, command_pool: &CommandPool, graphics_queue: &vk::Queue) -> ImageAttachment {
+    let staging_usage = BufferUsage::transfer_src_buffer();
+    let image_usage = ImageUsage::transfer_dst_sampled();
+    
+    let staging_buffer = Buffer::cpu_buffer_with_data(Arc::clone(&instance), Arc::clone(&device), staging_usage, 1, image_data);
+    
+    let memory_property = MemoryProperty::DeviceLocal;
+    let (image, memory) = ImageAttachment::create_image_with_layers(Arc::clone(&instance), Arc::clone(&device), &memory_property, &ImageType::Type2D, &ImageTiling::Optimal, &image_usage, &ImageLayout::Undefined, &SampleCount::OneBit, &format, size, size, 1, 6, vk::IMAGE_CREATE_CUBE_COMPATIBLE_BIT);
+    let image_view = ImageAttachment::create_image_view_range(Arc::clone(&device), &image, &format, &ImageAspect::Colour, &ImageViewType::TypeCube, 0, 1, 0, 6);
+    
+    let texture = ImageAttachment {
+      image,
+      image_view,
+      memory,
+      format,
+      width: size,
+      height: size,
+      mip_levels: 1,
+    };
+    
+    let cmd = CommandBuffer::begin_single_time_command(Arc::clone(&device), command_pool);
+    cmd.pipeline_barrier(Arc::clone(&device), PipelineStage::TopOfPipe, PipelineStage::Transfer, 
+                         texture.cube_barrier(0, ImageLayout::Undefined, ImageLayout::TransferDstOptimal, None, Access::TransferWrite));
+    cmd.copy_buffer_to_image_layers(Arc::clone(&device), &staging_buffer, texture.image, ImageAspect::Colour, size, size, 6, 0);
+    cmd.pipeline_barrier(Arc::clone(&device), PipelineStage::Transfer, PipelineStage::FragmentShader, 
+                         texture.cube_barrier(0, ImageLayout::TransferDstOptimal, ImageLayout::ShaderReadOnlyOptimal, Some(Access::TransferWrite), Access::ShaderRead));
+    cmd.end_single_time_command(Arc::clone(&device), command_pool, graphics_queue);
+    
+    staging_buffer.destroy(Arc::clone(&device));
+    
+    texture
+  }
+  
+  // view of a single face and mip level, destroyed with destroy_view
+  pub fn create_face_view(&self, device: Arc<Device>, face: u32, mip_level: u32) -> vk::ImageView {
+    ImageAttachment::create_image_view_range(Arc::clone(&device), &self.image, &self.format, &ImageAspect::Colour, &ImageViewType::Type2D, mip_level, 1, face, 1)
+  }
+  
+  pub fn destroy_view(device: Arc<Device>, image_view: vk::ImageView) {
+    unsafe {
+      let vk = device.pointers();
+      let device = device.internal_object();
+      
+      vk.DestroyImageView(*device, image_view, ptr::null());
+    }
+  }
+  
+  /**
+  ** Fills every mip level of a cube from the one above it by blitting, the
+  ** first level must already be drawn and in ShaderReadOnlyOptimal. Every
+  ** level ends up in ShaderReadOnlyOptimal.
+  **/
+  pub fn generate_cube_mipmaps(&self, device: Arc<Device>, command_pool: &CommandPool, graphics_queue: &vk::Queue) {
+    let cmd = CommandBuffer::begin_single_time_command(Arc::clone(&device), command_pool);
+    
+    for mip in 1..self.mip_levels {
+      let src_size = (self.width >> (mip-1)).max(1) as i32;
+      let dst_size = (self.width >> mip).max(1) as i32;
+      
+      let (src_layout, src_access, src_stage) = if mip == 1 {
+        (ImageLayout::ShaderReadOnlyOptimal, Access::ShaderRead, PipelineStage::FragmentShader)
+      } else {
+        (ImageLayout::TransferDstOptimal, Access::TransferWrite, PipelineStage::Transfer)
+      };
+      
+      cmd.pipeline_barrier(Arc::clone(&device), src_stage, PipelineStage::Transfer, 
+                           self.cube_barrier(mip-1, src_layout, ImageLayout::TransferSrcOptimal, Some(src_access), Access::TransferRead));
+      cmd.pipeline_barrier(Arc::clone(&device), PipelineStage::TopOfPipe, PipelineStage::Transfer, 
+                           self.cube_barrier(mip, ImageLayout::Undefined, ImageLayout::TransferDstOptimal, None, Access::TransferWrite));
+      
+      let blit = vk::ImageBlit {
+        srcSubresource: vk::ImageSubresourceLayers {
+          aspectMask: ImageAspect::Colour.to_bits(),
+          mipLevel: mip-1,
+          baseArrayLayer: 0,
+          layerCount: 6,
+        },
+        srcOffsets: [vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: src_size, y: src_size, z: 1 }],
+        dstSubresource: vk::ImageSubresourceLayers {
+          aspectMask: ImageAspect::Colour.to_bits(),
+          mipLevel: mip,
+          baseArrayLayer: 0,
+          layerCount: 6,
+        },
+        dstOffsets: [vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: dst_size, y: dst_size, z: 1 }],
+      };
+      
+      cmd.blit_image(Arc::clone(&device), self.image, ImageLayout::TransferSrcOptimal, self.image, ImageLayout::TransferDstOptimal, blit, Filter::Linear);
+      
+      cmd.pipeline_barrier(Arc::clone(&device), PipelineStage::Transfer, PipelineStage::FragmentShader, 
+                           self.cube_barrier(mip-1, ImageLayout::TransferSrcOptimal, ImageLayout::ShaderReadOnlyOptimal, Some(Access::TransferRead), Access::ShaderRead));
+    }
+    
+    if self.mip_levels > 1 {
+      cmd.pipeline_barrier(Arc::clone(&device), PipelineStage::Transfer, PipelineStage::FragmentShader, 
+                           self.cube_barrier(self.mip_levels-1, ImageLayout::TransferDstOptimal, ImageLayout::ShaderReadOnlyOptimal, Some(Access::TransferWrite), Access::ShaderRead));
+    }
+    
+    cmd.end_single_time_command(Arc::clone(&device), command_pool, graphics_queue);
+  }
+  
+  // all six faces of one mip level
+  fn cube_barrier(&self, mip_level: u32, old_layout: ImageLayout, new_layout: ImageLayout, src_access: Option<Access>, dst_access: Access) -> vk::ImageMemoryBarrier {
+    vk::ImageMemoryBarrier {
+      sType: vk::STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
+      pNext: ptr::null(),
+      srcAccessMask: src_access.map(|access| access.to_bits()).unwrap_or(0),
+      dstAccessMask: dst_access.to_bits(),
+      oldLayout: old_layout.to_bits(),
+      newLayout: new_layout.to_bits(),
+      srcQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+      dstQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+      image: self.image,
+      subresourceRange: vk::ImageSubresourceRange {
+        aspectMask: ImageAspect::Colour.to_bits(),
+        baseMipLevel: mip_level,
+        levelCount: 1,
+        baseArrayLayer: 0,
+        layerCount: 6,
+      },
+    }
+  }
+  
+  pub fn get_mip_levels(&self) -> u32 {
+    self.mip_levels
+  }
+  
   pub fn get_size(&self) -> (u32, u32) {
     (self.width, self.height)
   }
@@ -382,6 +541,10 @@ impl ImageAttachment {
   }
   
   fn create_image(instance: Arc<Instance>, device: Arc<Device>, memory_property: &MemoryProperty, image_type: &ImageType, tiling: &ImageTiling, usage: &ImageUsage, initial_layout: &ImageLayout,  samples: &SampleCount, format: &vk::Format, width: u32, height: u32) -> (vk::Image, vk::DeviceMemory) {
+    ImageAttachment::create_image_with_layers(instance, device, memory_property, image_type, tiling, usage, initial_layout, samples, format, width, height, 1, 1, 0)
+  }
+  
+  fn create_image_with_layers(instance: Arc<Instance>, device: Arc<Device>, memory_property: &MemoryProperty, image_type: &ImageType, tiling: &ImageTiling, usage: &ImageUsage, initial_layout: &ImageLayout,  samples: &SampleCount, format: &vk::Format, width: u32, height: u32, mip_levels: u32, array_layers: u32, flags: u32) -> (vk::Image, vk::DeviceMemory) {
     let mut image: vk::Image = unsafe { mem::MaybeUninit::uninit().assume_init() };
     let mut memory: vk::DeviceMemory = unsafe { mem::MaybeUninit::uninit().assume_init() };
     
@@ -394,12 +557,12 @@ impl ImageAttachment {
       vk::ImageCreateInfo {
         sType: vk::STRUCTURE_TYPE_IMAGE_CREATE_INFO,
         pNext: ptr::null(),
-        flags: 0,
+        flags,
         imageType: image_type.to_bits(),
         format: *format,
         extent: vk::Extent3D { width: width, height: height, depth: 1 },
-        mipLevels: 1,
-        arrayLayers: 1,
+        mipLevels: mip_levels,
+        arrayLayers: array_layers,
         samples: samples.to_bits(),
         tiling: tiling.to_bits(),
         usage: usage.to_bits(),
@@ -458,6 +621,10 @@ impl ImageAttachment {
   }
   
   fn create_image_view(device: Arc<Device>, image: &vk::Image, format: &vk::Format, image_aspect: &ImageAspect, image_view_type: &ImageViewType) -> vk::ImageView {
+    ImageAttachment::create_image_view_range(device, image, format, image_aspect, image_view_type, 0, 1, 0, 1)
+  }
+  
+  fn create_image_view_range(device: Arc<Device>, image: &vk::Image, format: &vk::Format, image_aspect: &ImageAspect, image_view_type: &ImageViewType, base_mip_level: u32, mip_levels: u32, base_layer: u32, layers: u32) -> vk::ImageView {
     let mut image_view: vk::ImageView = unsafe { mem::MaybeUninit::uninit().assume_init() };
     
     let vk = device.pointers();
@@ -472,10 +639,10 @@ impl ImageAttachment {
     
     let subresource = vk::ImageSubresourceRange {
       aspectMask: image_aspect.to_bits(),
-      baseMipLevel: 0,
-      levelCount: 1,
-      baseArrayLayer: 0,
-      layerCount: 1,
+      baseMipLevel: base_mip_level,
+      levelCount: mip_levels,
+      baseArrayLayer: base_layer,
+      layerCount: layers,
     };
     
     let image_view_create_info = vk::ImageViewCreateInfo {
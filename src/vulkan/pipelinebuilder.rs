@@ -27,6 +27,7 @@ pub struct PipelineBuilder {
   front_face: FrontFace,
   depth_test: u32,
   depth_write: u32,
+  depth_compare_op: CompareOp,
   depth_clamp: u32,
   depth_bias: u32,
   rasterizer_discard: u32,
@@ -37,6 +38,10 @@ pub struct PipelineBuilder {
   alpha_to_coverage: u32,
   alpha_to_one: u32,
   blend_enabled: VkBool,
+  src_colour_blend_factor: BlendFactor,
+  dst_colour_blend_factor: BlendFactor,
+  src_alpha_blend_factor: BlendFactor,
+  dst_alpha_blend_factor: BlendFactor,
   has_push_constant: bool,
   push_constant_size: u32,
   push_constant_shader_stage: ShaderStage,
@@ -60,6 +65,7 @@ impl PipelineBuilder {
       front_face: FrontFace::Clockwise,
       depth_test: vk::FALSE,
       depth_write: vk::FALSE,
+      depth_compare_op: CompareOp::Less,
       depth_clamp: vk::FALSE,
       depth_bias: vk::FALSE,
       rasterizer_discard: vk::FALSE,
@@ -70,6 +76,10 @@ impl PipelineBuilder {
       alpha_to_coverage: vk::FALSE,
       alpha_to_one: vk::FALSE,
       blend_enabled: VkBool::True, 
+      src_colour_blend_factor: BlendFactor::SrcAlpha,
+      dst_colour_blend_factor: BlendFactor::OneMinusSrcAlpha,
+      src_alpha_blend_factor: BlendFactor::SrcAlpha,
+      dst_alpha_blend_factor: BlendFactor::Zero,
       has_push_constant: false,
       push_constant_size: 0,
       push_constant_shader_stage: ShaderStage::Vertex,
@@ -85,6 +95,18 @@ impl PipelineBuilder {
     self
   }
   
+  pub fn colour_blend_factors(mut self, src: BlendFactor, dst: BlendFactor) -> PipelineBuilder {
+    self.src_colour_blend_factor = src;
+    self.dst_colour_blend_factor = dst;
+    self
+  }
+  
+  pub fn alpha_blend_factors(mut self, src: BlendFactor, dst: BlendFactor) -> PipelineBuilder {
+    self.src_alpha_blend_factor = src;
+    self.dst_alpha_blend_factor = dst;
+    self
+  }
+  
   pub fn push_constants(mut self, shader_stage: ShaderStage, size: u32) -> PipelineBuilder {
     self.has_push_constant = true;
     self.push_constant_size = size;
@@ -257,6 +279,11 @@ impl PipelineBuilder {
     self
   }
   
+  pub fn depth_compare_op(mut self, compare_op: CompareOp) -> PipelineBuilder {
+    self.depth_compare_op = compare_op;
+    self
+  }
+  
   pub fn enable_depth_clamp(mut self) -> PipelineBuilder {
     self.depth_clamp = vk::TRUE;
     self
@@ -462,18 +489,22 @@ impl PipelineBuilder {
       }
     }
     
+    // kept alive until the pipeline is created
+    let vertex_specialisation_data = vertex_specialisation_data.build_non_aligned();
+    let fragment_specialisation_data = fragment_specialisation_data.build_non_aligned();
+    
     vertex_specialisation_constants = vk::SpecializationInfo {
                                         mapEntryCount: vertex_specialisation_map_entry.len() as u32,
                                         pMapEntries: vertex_specialisation_map_entry.as_ptr(),
-                                        dataSize: vertex_specialisation_data.size_non_aligned() as usize,
-                                        pData: vertex_specialisation_data.build_non_aligned().as_ptr() as *const _,
+                                        dataSize: vertex_specialisation_data.len() * mem::size_of::<f32>(),
+                                        pData: vertex_specialisation_data.as_ptr() as *const _,
                                       };
                                       
     fragment_specialisation_constants = vk::SpecializationInfo {
                                         mapEntryCount: fragment_specialisation_map_entry.len() as u32,
                                         pMapEntries: fragment_specialisation_map_entry.as_ptr(),
-                                        dataSize: fragment_specialisation_data.size_non_aligned() as usize,
-                                        pData: fragment_specialisation_data.build_non_aligned().as_ptr() as *const _,
+                                        dataSize: fragment_specialisation_data.len() * mem::size_of::<f32>(),
+                                        pData: fragment_specialisation_data.as_ptr() as *const _,
                                       };
     
     shader_stages.push(
@@ -618,7 +649,7 @@ impl PipelineBuilder {
         flags: 0,
         depthTestEnable: self.depth_test,
         depthWriteEnable: self.depth_write,
-        depthCompareOp: CompareOp::Less.to_bits(),
+        depthCompareOp: self.depth_compare_op.to_bits(),
         depthBoundsTestEnable: vk::FALSE,
         stencilTestEnable: vk::FALSE,
         front: front_stencil_op_state,
@@ -637,11 +668,11 @@ impl PipelineBuilder {
       pipeline_colour_blend_attachments.push(
         vk::PipelineColorBlendAttachmentState {
           blendEnable: self.blend_enabled.to_bits(),
-          srcColorBlendFactor: BlendFactor::SrcAlpha.to_bits(),
-          dstColorBlendFactor: BlendFactor::OneMinusSrcAlpha.to_bits(),
+          srcColorBlendFactor: self.src_colour_blend_factor.to_bits(),
+          dstColorBlendFactor: self.dst_colour_blend_factor.to_bits(),
           colorBlendOp: BlendOp::Add.to_bits(),
-          srcAlphaBlendFactor: BlendFactor::SrcAlpha.to_bits(),
-          dstAlphaBlendFactor: BlendFactor::Zero.to_bits(),
+          srcAlphaBlendFactor: self.src_alpha_blend_factor.to_bits(),
+          dstAlphaBlendFactor: self.dst_alpha_blend_factor.to_bits(),
           alphaBlendOp: BlendOp::Add.to_bits(),
           colorWriteMask: ColourComponent::R.to_bits() | ColourComponent::G.to_bits() | ColourComponent::B.to_bits() | ColourComponent::A.to_bits(),
         }
@@ -17,6 +17,7 @@ pub struct DescriptorPoolBuilder {
   num_uniform_buffers: u32,
   num_storage_buffers: u32,
   num_input_attachments: u32,
+  free_descriptor_sets: bool,
 }
 
 impl DescriptorPoolBuilder {
@@ -28,6 +29,7 @@ impl DescriptorPoolBuilder {
       num_uniform_buffers: 0,
       num_storage_buffers: 0,
       num_input_attachments: 0,
+      free_descriptor_sets: false,
     }
   }
   
@@ -61,6 +63,12 @@ impl DescriptorPoolBuilder {
     self
   }
   
+  // lets sets be given back to the pool with DescriptorSet::free
+  pub fn free_descriptor_sets(mut self) -> DescriptorPoolBuilder {
+    self.free_descriptor_sets = true;
+    self
+  }
+  
   pub fn build(&self, device: Arc<Device>, num_sets: u32) -> DescriptorPool {
     let max_sets = num_sets * self.num_uniform_buffers
                                 .max(self.num_sampled_images)
@@ -130,7 +138,7 @@ impl DescriptorPoolBuilder {
       vk::DescriptorPoolCreateInfo {
         sType: vk::STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
         pNext: ptr::null(),
-        flags: 0,
+        flags: if self.free_descriptor_sets { vk::DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT } else { 0 },
         maxSets: descriptor_pool_size.len() as u32*max_sets,
         poolSizeCount: descriptor_pool_size.len() as u32,
         pPoolSizes: descriptor_pool_size.as_ptr(),
@@ -357,7 +357,28 @@ impl RenderPassBuilder {
     let mut subpass_dependency: Vec<vk::SubpassDependency> = Vec::with_capacity(self.subpasses.len());
     
     
-    if self.subpasses.len() == 1 {
+    if self.subpasses.len() == 1 && self.subpasses[0].num_colour_attachments() == 0 {
+      // Depth only, written here and sampled by a later pass
+      subpass_dependency.push(vk::SubpassDependency {
+          srcSubpass: vk::SUBPASS_EXTERNAL,
+          dstSubpass: 0,
+          srcStageMask: PipelineStage::FragmentShader.to_bits(),
+          dstStageMask: PipelineStage::EarlyFragmentTests.to_bits(),
+          srcAccessMask: Access::ShaderRead.to_bits(),
+          dstAccessMask: Access::DepthStencilAttachmentWrite.to_bits(),
+          dependencyFlags: 0, // other passes sample any texel
+        });
+      
+      subpass_dependency.push(vk::SubpassDependency {
+          srcSubpass: 0,
+          dstSubpass: vk::SUBPASS_EXTERNAL,
+          srcStageMask: PipelineStage::LateFragementTests.to_bits(),
+          dstStageMask: PipelineStage::FragmentShader.to_bits(),
+          srcAccessMask: Access::DepthStencilAttachmentWrite.to_bits(),
+          dstAccessMask: Access::ShaderRead.to_bits(),
+          dependencyFlags: 0, // other passes sample any texel
+        });
+    } else if self.subpasses.len() == 1 {
       subpass_dependency.push(vk::SubpassDependency {
           srcSubpass: vk::SUBPASS_EXTERNAL,
           dstSubpass: 0,
@@ -389,8 +410,23 @@ impl RenderPassBuilder {
               dependencyFlags: Dependency::ByRegion.to_bits(),
         });
       
+      // later subpasses draw over the colour and test against the depth of the earlier ones
+      for i in 2..self.subpasses.len() as u32 {
+        for j in 0..i {
+          subpass_dependency.push(vk::SubpassDependency {
+                  srcSubpass: j,
+                  dstSubpass: i,
+                  srcStageMask: PipelineStage::ColorAttachmentOutput.to_bits() | PipelineStage::LateFragementTests.to_bits(),
+                  dstStageMask: PipelineStage::EarlyFragmentTests.to_bits() | PipelineStage::ColorAttachmentOutput.to_bits(),
+                  srcAccessMask: Access::ColourAttachmentWrite.to_bits() | Access::DepthStencilAttachmentWrite.to_bits(),
+                  dstAccessMask: Access::ColourAttachmentReadAndWrite.to_bits() | Access::DepthStencilAttachmentRead.to_bits(),
+                  dependencyFlags: Dependency::ByRegion.to_bits(),
+            });
+        }
+      }
+      
       subpass_dependency.push(vk::SubpassDependency {
-              srcSubpass: 1,
+              srcSubpass: self.subpasses.len() as u32 - 1,
               dstSubpass: vk::SUBPASS_EXTERNAL,
               srcStageMask: PipelineStage::ColorAttachmentOutput.to_bits(),
               dstStageMask: PipelineStage::BottomOfPipe.to_bits(),
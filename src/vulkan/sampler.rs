@@ -109,6 +109,11 @@ impl SamplerBuilder {
     self
   }
   
+  pub fn max_lod(mut self, lod: f32) -> SamplerBuilder {
+    self.max_lod = lod;
+    self
+  }
+  
   pub fn anisotropy(mut self, enabled: VkBool) -> SamplerBuilder {
     self.anisotropy = enabled;
     self
@@ -845,6 +845,13 @@ impl ImageUsage {
     }
   }
   
+  pub fn depth_stencil_sampled() -> ImageUsage {
+    ImageUsage {
+      sampled: true,
+      .. ImageUsage::depth_stencil_attachment()
+    }
+  }
+  
   pub fn transfer_src() -> ImageUsage {
     ImageUsage {
       transfer_src: true,
@@ -908,6 +915,14 @@ impl ImageUsage {
     }
   }
   
+  pub fn colour_attachment_transfer_sampled() -> ImageUsage {
+    ImageUsage {
+      transfer_src: true,
+      transfer_dst: true,
+      .. ImageUsage::colour_attachment_sampled()
+    }
+  }
+  
   pub fn colour_input_attachment_sampled() -> ImageUsage {
     ImageUsage {
       input_attachment: true,
@@ -915,6 +930,14 @@ impl ImageUsage {
     }
   }
   
+  pub fn storage_sampled() -> ImageUsage {
+    ImageUsage {
+      sampled: true,
+      storage: true,
+      .. ImageUsage::none()
+    }
+  }
+  
   pub fn colour_attachment_storage_sampled() -> ImageUsage {
     ImageUsage {
       sampled: true,